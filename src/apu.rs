@@ -0,0 +1,805 @@
+use std::cell::Cell;
+
+/// CPU cycles per second of the NTSC and PAL consoles
+pub const NTSC_CPU_RATE: f64 = 1_789_773.0;
+pub const PAL_CPU_RATE: f64 = 1_662_607.0;
+
+/// Length counter values, indexed by the top 5 bits of a channel's last register
+const LENGTH_TABLE: [u8; 32] = [
+	10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14,
+	12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30
+];
+
+/// Pulse waveforms for each duty cycle setting
+const DUTY_TABLE: [[u8; 8]; 4] = [
+	[0, 1, 0, 0, 0, 0, 0, 0],
+	[0, 1, 1, 0, 0, 0, 0, 0],
+	[0, 1, 1, 1, 1, 0, 0, 0],
+	[1, 0, 0, 1, 1, 1, 1, 1]
+];
+
+const TRIANGLE_SEQUENCE: [u8; 32] = [
+	15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0,
+	0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15
+];
+
+/// Noise and DMC timer periods in CPU cycles (NTSC)
+const NOISE_PERIODS: [u16; 16] = [
+	4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068
+];
+const DMC_PERIODS: [u16; 16] = [
+	428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54
+];
+
+/// CPU cycles into the frame counter's sequence at which it clocks the envelopes and linear
+/// counter (quarter frames) and the length counters and sweeps (half frames). The 4 step sequence
+/// also asserts IRQ on its last step.
+const FOUR_STEP: [(u32, bool); 4] = [(7457, false), (14913, true), (22371, false), (29829, true)];
+const FIVE_STEP: [(u32, bool); 4] = [(7457, false), (14913, true), (22371, false), (37281, true)];
+const FOUR_STEP_PERIOD: u32 = 29830;
+const FIVE_STEP_PERIOD: u32 = 37282;
+
+/// NES APU: two pulse channels, a triangle, noise and the delta modulation channel (DMC)
+///     http://wiki.nesdev.com/w/index.php/APU
+///
+/// Registers:
+///     0x4000 -> 0x4003: Pulse 1 duty and envelope, sweep, timer low, length and timer high
+///     0x4004 -> 0x4007: Pulse 2, as pulse 1
+///     0x4008 -> 0x400B: Triangle linear counter, unused, timer low, length and timer high
+///     0x400C -> 0x400F: Noise envelope, unused, mode and period, length
+///     0x4010 -> 0x4013: DMC IRQ, loop and rate, output level, sample address, sample length
+///     0x4015:           Channel enables when written, length counter and IRQ status when read
+///     0x4017:           Frame counter mode and IRQ inhibit
+///
+/// The DMC reads its samples from the CPU's bus, so the CPU polls dmc_request() and answers with
+/// dmc_fill(). The cycles the CPU is stalled for while it does aren't emulated.
+pub struct Apu {
+	pulses: [Pulse; 2],
+	triangle: Triangle,
+	noise: Noise,
+	dmc: Dmc,
+	odd_cycle: bool,
+	frame_cycle: u32,
+	five_step: bool,
+	frame_irq_inhibit: bool,
+	frame_irq: Cell<bool> // Cleared by reading 0x4015
+}
+
+impl Apu {
+	pub fn new() -> Apu {
+		Apu {
+			pulses: [Pulse::new(1), Pulse::new(0)],
+			triangle: Triangle::default(),
+			noise: Noise::new(),
+			dmc: Dmc::new(),
+			odd_cycle: false,
+			frame_cycle: 0,
+			five_step: false,
+			frame_irq_inhibit: false,
+			frame_irq: Cell::new(false)
+		}
+	}
+
+	pub fn write(&mut self, address: u16, value: u8) {
+		match address {
+			0x4000 ..= 0x4003 => self.pulses[0].write(address & 0x03, value),
+			0x4004 ..= 0x4007 => self.pulses[1].write(address & 0x03, value),
+			0x4008 ..= 0x400B => self.triangle.write(address & 0x03, value),
+			0x400C ..= 0x400F => self.noise.write(address & 0x03, value),
+			0x4010 ..= 0x4013 => self.dmc.write(address & 0x03, value),
+			0x4015 => {
+				self.pulses[0].length.set_enabled(value & 0x01 != 0);
+				self.pulses[1].length.set_enabled(value & 0x02 != 0);
+				self.triangle.length.set_enabled(value & 0x04 != 0);
+				self.noise.length.set_enabled(value & 0x08 != 0);
+				self.dmc.set_enabled(value & 0x10 != 0);
+			},
+			0x4017 => {
+				self.five_step = value & 0x80 != 0;
+				self.frame_irq_inhibit = value & 0x40 != 0;
+				if self.frame_irq_inhibit {
+					self.frame_irq.set(false);
+				}
+				self.frame_cycle = 0;
+				// The 5 step sequence clocks everything straight away
+				if self.five_step {
+					self.quarter_frame();
+					self.half_frame();
+				}
+			},
+			_ => ()
+		}
+	}
+
+	/// Read 0x4015, which acknowledges the frame IRQ
+	pub fn read_status(&self) -> u8 {
+		let mut status = 0;
+		for (bit, length) in [&self.pulses[0].length, &self.pulses[1].length, &self.triangle.length,
+				&self.noise.length].iter().enumerate() {
+			if length.value > 0 {
+				status |= 1 << bit;
+			}
+		}
+		if self.dmc.remaining > 0 {
+			status |= 0x10;
+		}
+		if self.frame_irq.get() {
+			status |= 0x40;
+		}
+		if self.dmc.irq {
+			status |= 0x80;
+		}
+		self.frame_irq.set(false);
+		status
+	}
+
+	/// Level of the frame counter's IRQ output
+	pub fn frame_irq(&self) -> bool {
+		self.frame_irq.get()
+	}
+
+	/// Level of the DMC's IRQ output
+	pub fn dmc_irq(&self) -> bool {
+		self.dmc.irq
+	}
+
+	/// Address of the next sample byte, when the DMC's buffer is empty
+	pub fn dmc_request(&self) -> Option<u16> {
+		self.dmc.request()
+	}
+
+	/// Hand the DMC the byte it asked for
+	pub fn dmc_fill(&mut self, value: u8) {
+		self.dmc.fill(value);
+	}
+
+	/// Advance by one CPU cycle
+	pub fn clock(&mut self) {
+		// Pulse timers count APU cycles, every other CPU cycle
+		self.odd_cycle = !self.odd_cycle;
+		if !self.odd_cycle {
+			self.pulses[0].clock_timer();
+			self.pulses[1].clock_timer();
+		}
+		self.triangle.clock_timer();
+		self.noise.clock_timer();
+		self.dmc.clock_timer();
+		self.clock_frame_counter();
+	}
+
+	fn clock_frame_counter(&mut self) {
+		self.frame_cycle += 1;
+		let (steps, period) = if self.five_step {
+			(&FIVE_STEP, FIVE_STEP_PERIOD)
+		} else {
+			(&FOUR_STEP, FOUR_STEP_PERIOD)
+		};
+		if let Some(&(_, half)) = steps.iter().find(|step| step.0 == self.frame_cycle) {
+			self.quarter_frame();
+			if half {
+				self.half_frame();
+			}
+		}
+		if !self.five_step && !self.frame_irq_inhibit && self.frame_cycle >= FOUR_STEP[3].0 {
+			self.frame_irq.set(true);
+		}
+		if self.frame_cycle >= period {
+			self.frame_cycle = 0;
+		}
+	}
+
+	fn quarter_frame(&mut self) {
+		self.pulses[0].envelope.clock();
+		self.pulses[1].envelope.clock();
+		self.triangle.clock_linear_counter();
+		self.noise.envelope.clock();
+	}
+
+	fn half_frame(&mut self) {
+		for pulse in self.pulses.iter_mut() {
+			pulse.length.clock();
+			pulse.clock_sweep();
+		}
+		self.triangle.length.clock();
+		self.noise.length.clock();
+	}
+
+	/// Mixed output of all channels, from 0.0 to about 1.0
+	///     http://wiki.nesdev.com/w/index.php/APU_Mixer
+	pub fn output(&self) -> f32 {
+		let pulses = (self.pulses[0].output() + self.pulses[1].output()) as f32;
+		let pulse_out = if pulses == 0.0 { 0.0 } else { 95.88 / (8128.0 / pulses + 100.0) };
+		let tnd = self.triangle.output() as f32 / 8227.0 + self.noise.output() as f32 / 12241.0
+			+ self.dmc.level as f32 / 22638.0;
+		let tnd_out = if tnd == 0.0 { 0.0 } else { 159.79 / (1.0 / tnd + 100.0) };
+		pulse_out + tnd_out
+	}
+}
+
+impl Default for Apu {
+	fn default() -> Apu {
+		Apu::new()
+	}
+}
+
+/// Silences a channel once its note has played for long enough
+#[derive(Default)]
+struct LengthCounter {
+	enabled: bool,
+	halted: bool,
+	value: u8
+}
+
+impl LengthCounter {
+	fn load(&mut self, value: u8) {
+		if self.enabled {
+			self.value = LENGTH_TABLE[(value >> 3) as usize];
+		}
+	}
+
+	fn set_enabled(&mut self, enabled: bool) {
+		self.enabled = enabled;
+		if !enabled {
+			self.value = 0;
+		}
+	}
+
+	fn clock(&mut self) {
+		if !self.halted && self.value > 0 {
+			self.value -= 1;
+		}
+	}
+}
+
+/// Volume of a pulse or noise channel, either constant or a decaying sawtooth
+#[derive(Default)]
+struct Envelope {
+	start: bool,
+	looping: bool,
+	constant: bool,
+	period: u8, // Also the constant volume
+	divider: u8,
+	decay: u8
+}
+
+impl Envelope {
+	fn write(&mut self, value: u8) {
+		self.looping = value & 0x20 != 0;
+		self.constant = value & 0x10 != 0;
+		self.period = value & 0x0F;
+	}
+
+	fn clock(&mut self) {
+		if self.start {
+			self.start = false;
+			self.decay = 15;
+			self.divider = self.period;
+		} else if self.divider == 0 {
+			self.divider = self.period;
+			if self.decay > 0 {
+				self.decay -= 1;
+			} else if self.looping {
+				self.decay = 15;
+			}
+		} else {
+			self.divider -= 1;
+		}
+	}
+
+	fn volume(&self) -> u8 {
+		if self.constant { self.period } else { self.decay }
+	}
+}
+
+struct Pulse {
+	negate_adjust: u16, // Pulse 1 negates with one's complement, so its sweep goes 1 lower
+	duty: u8,
+	step: u8,
+	timer: u16,
+	period: u16,
+	envelope: Envelope,
+	length: LengthCounter,
+	sweep_enabled: bool,
+	sweep_period: u8,
+	sweep_negate: bool,
+	sweep_shift: u8,
+	sweep_divider: u8,
+	sweep_reload: bool
+}
+
+impl Pulse {
+	fn new(negate_adjust: u16) -> Pulse {
+		Pulse {
+			negate_adjust,
+			duty: 0,
+			step: 0,
+			timer: 0,
+			period: 0,
+			envelope: Envelope::default(),
+			length: LengthCounter::default(),
+			sweep_enabled: false,
+			sweep_period: 0,
+			sweep_negate: false,
+			sweep_shift: 0,
+			sweep_divider: 0,
+			sweep_reload: false
+		}
+	}
+
+	fn write(&mut self, register: u16, value: u8) {
+		match register {
+			0 => {
+				self.duty = value >> 6;
+				self.length.halted = value & 0x20 != 0;
+				self.envelope.write(value);
+			},
+			1 => {
+				self.sweep_enabled = value & 0x80 != 0;
+				self.sweep_period = (value >> 4) & 0x07;
+				self.sweep_negate = value & 0x08 != 0;
+				self.sweep_shift = value & 0x07;
+				self.sweep_reload = true;
+			},
+			2 => self.period = (self.period & 0x0700) | value as u16,
+			_ => {
+				self.period = (self.period & 0x00FF) | ((value & 0x07) as u16) << 8;
+				self.length.load(value);
+				self.step = 0;
+				self.envelope.start = true;
+			}
+		}
+	}
+
+	fn clock_timer(&mut self) {
+		if self.timer == 0 {
+			self.timer = self.period;
+			self.step = (self.step + 1) & 0x07;
+		} else {
+			self.timer -= 1;
+		}
+	}
+
+	fn sweep_target(&self) -> u16 {
+		let change = self.period >> self.sweep_shift;
+		if self.sweep_negate {
+			self.period.saturating_sub(change + self.negate_adjust)
+		} else {
+			self.period + change
+		}
+	}
+
+	// Periods too short for the timer, or that the sweep would take out of range, mute the channel
+	fn muted(&self) -> bool {
+		self.period < 8 || self.sweep_target() > 0x7FF
+	}
+
+	fn clock_sweep(&mut self) {
+		if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && !self.muted() {
+			self.period = self.sweep_target();
+		}
+		if self.sweep_divider == 0 || self.sweep_reload {
+			self.sweep_divider = self.sweep_period;
+			self.sweep_reload = false;
+		} else {
+			self.sweep_divider -= 1;
+		}
+	}
+
+	fn output(&self) -> u8 {
+		let high = DUTY_TABLE[self.duty as usize][self.step as usize] != 0;
+		if self.muted() || self.length.value == 0 || !high {
+			0
+		} else {
+			self.envelope.volume()
+		}
+	}
+}
+
+#[derive(Default)]
+struct Triangle {
+	step: u8,
+	timer: u16,
+	period: u16,
+	length: LengthCounter,
+	control: bool, // Also halts the length counter
+	linear_period: u8,
+	linear_counter: u8,
+	linear_reload: bool
+}
+
+impl Triangle {
+	fn write(&mut self, register: u16, value: u8) {
+		match register {
+			0 => {
+				self.control = value & 0x80 != 0;
+				self.length.halted = self.control;
+				self.linear_period = value & 0x7F;
+			},
+			1 => (),
+			2 => self.period = (self.period & 0x0700) | value as u16,
+			_ => {
+				self.period = (self.period & 0x00FF) | ((value & 0x07) as u16) << 8;
+				self.length.load(value);
+				self.linear_reload = true;
+			}
+		}
+	}
+
+	fn clock_timer(&mut self) {
+		if self.timer == 0 {
+			self.timer = self.period;
+			if self.length.value > 0 && self.linear_counter > 0 {
+				self.step = (self.step + 1) & 0x1F;
+			}
+		} else {
+			self.timer -= 1;
+		}
+	}
+
+	fn clock_linear_counter(&mut self) {
+		if self.linear_reload {
+			self.linear_counter = self.linear_period;
+		} else if self.linear_counter > 0 {
+			self.linear_counter -= 1;
+		}
+		if !self.control {
+			self.linear_reload = false;
+		}
+	}
+
+	// Stopping the sequencer holds the output where it was rather than silencing it
+	fn output(&self) -> u8 {
+		TRIANGLE_SEQUENCE[self.step as usize]
+	}
+}
+
+struct Noise {
+	short_mode: bool,
+	timer: u16,
+	period: u16,
+	shift: u16, // 15 bit linear feedback shift register
+	envelope: Envelope,
+	length: LengthCounter
+}
+
+impl Noise {
+	fn new() -> Noise {
+		Noise {
+			short_mode: false,
+			timer: 0,
+			period: NOISE_PERIODS[0],
+			shift: 1,
+			envelope: Envelope::default(),
+			length: LengthCounter::default()
+		}
+	}
+
+	fn write(&mut self, register: u16, value: u8) {
+		match register {
+			0 => {
+				self.length.halted = value & 0x20 != 0;
+				self.envelope.write(value);
+			},
+			1 => (),
+			2 => {
+				self.short_mode = value & 0x80 != 0;
+				self.period = NOISE_PERIODS[(value & 0x0F) as usize];
+			},
+			_ => {
+				self.length.load(value);
+				self.envelope.start = true;
+			}
+		}
+	}
+
+	fn clock_timer(&mut self) {
+		if self.timer == 0 {
+			self.timer = self.period - 1;
+			let tap = if self.short_mode { 6 } else { 1 };
+			let feedback = (self.shift ^ (self.shift >> tap)) & 1;
+			self.shift = (self.shift >> 1) | feedback << 14;
+		} else {
+			self.timer -= 1;
+		}
+	}
+
+	fn output(&self) -> u8 {
+		if self.shift & 1 != 0 || self.length.value == 0 { 0 } else { self.envelope.volume() }
+	}
+}
+
+struct Dmc {
+	irq_enabled: bool,
+	irq: bool,
+	looping: bool,
+	timer: u16,
+	period: u16,
+	level: u8,
+	sample_address: u16,
+	sample_length: u16,
+	address: u16,
+	remaining: u16,
+	buffer: Option<u8>,
+	shift: u8,
+	bits: u8,
+	silent: bool
+}
+
+impl Dmc {
+	fn new() -> Dmc {
+		Dmc {
+			irq_enabled: false,
+			irq: false,
+			looping: false,
+			timer: 0,
+			period: DMC_PERIODS[0],
+			level: 0,
+			sample_address: 0xC000,
+			sample_length: 1,
+			address: 0xC000,
+			remaining: 0,
+			buffer: None,
+			shift: 0,
+			bits: 8,
+			silent: true
+		}
+	}
+
+	fn write(&mut self, register: u16, value: u8) {
+		match register {
+			0 => {
+				self.irq_enabled = value & 0x80 != 0;
+				if !self.irq_enabled {
+					self.irq = false;
+				}
+				self.looping = value & 0x40 != 0;
+				self.period = DMC_PERIODS[(value & 0x0F) as usize];
+			},
+			1 => self.level = value & 0x7F,
+			2 => self.sample_address = 0xC000 | (value as u16) << 6,
+			_ => self.sample_length = (value as u16) << 4 | 1
+		}
+	}
+
+	fn set_enabled(&mut self, enabled: bool) {
+		self.irq = false;
+		if !enabled {
+			self.remaining = 0;
+		} else if self.remaining == 0 {
+			self.restart();
+		}
+	}
+
+	fn restart(&mut self) {
+		self.address = self.sample_address;
+		self.remaining = self.sample_length;
+	}
+
+	fn request(&self) -> Option<u16> {
+		if self.buffer.is_none() && self.remaining > 0 { Some(self.address) } else { None }
+	}
+
+	fn fill(&mut self, value: u8) {
+		self.buffer = Some(value);
+		// Samples wrap around from the end of the address space to 0x8000
+		self.address = if self.address == 0xFFFF { 0x8000 } else { self.address + 1 };
+		self.remaining -= 1;
+		if self.remaining == 0 {
+			if self.looping {
+				self.restart();
+			} else if self.irq_enabled {
+				self.irq = true;
+			}
+		}
+	}
+
+	fn clock_timer(&mut self) {
+		if self.timer > 0 {
+			self.timer -= 1;
+			return;
+		}
+		self.timer = self.period - 1;
+		if !self.silent {
+			if self.shift & 1 != 0 {
+				if self.level <= 125 {
+					self.level += 2;
+				}
+			} else if self.level >= 2 {
+				self.level -= 2;
+			}
+		}
+		self.shift >>= 1;
+		self.bits -= 1;
+		if self.bits == 0 {
+			self.bits = 8;
+			match self.buffer.take() {
+				Some(value) => {
+					self.silent = false;
+					self.shift = value;
+				},
+				None => self.silent = true
+			}
+		}
+	}
+}
+
+/// Turns the output level at every CPU cycle into samples at an audio sample rate, averaging the
+/// levels over each sample
+pub struct Resampler {
+	cycles_per_sample: f64,
+	cycles: f64,
+	sum: f64,
+	count: u32,
+	samples: Vec<f32>
+}
+
+impl Resampler {
+	pub fn new(cpu_rate: f64, sample_rate: u32) -> Resampler {
+		Resampler {
+			cycles_per_sample: cpu_rate / sample_rate as f64,
+			cycles: 0.0,
+			sum: 0.0,
+			count: 0,
+			samples: Vec::new()
+		}
+	}
+
+	/// Add the level for one CPU cycle
+	pub fn add(&mut self, level: f32) {
+		self.sum += level as f64;
+		self.count += 1;
+		self.cycles += 1.0;
+		if self.cycles >= self.cycles_per_sample {
+			self.cycles -= self.cycles_per_sample;
+			self.samples.push((self.sum / self.count as f64) as f32);
+			self.sum = 0.0;
+			self.count = 0;
+		}
+	}
+
+	/// Samples finished since the last call
+	pub fn take_samples(&mut self) -> Vec<f32> {
+		std::mem::take(&mut self.samples)
+	}
+}
+
+#[cfg(test)]
+fn clock(apu: &mut Apu, cycles: u32) {
+	for _ in 0..cycles {
+		apu.clock();
+	}
+}
+
+#[test]
+fn test_pulse() {
+	let mut apu = Apu::new();
+	apu.write(0x4015, 0x01);
+	// 50% duty at constant volume 15, with a period of 9 APU cycles per step
+	apu.write(0x4000, 0xBF);
+	apu.write(0x4002, 0x08);
+	apu.write(0x4003, 0x00);
+	assert_eq!(apu.read_status() & 0x01, 0x01);
+	let mut levels = Vec::new();
+	for _ in 0..8 {
+		levels.push(apu.pulses[0].output());
+		clock(&mut apu, 18);
+	}
+	assert_eq!(levels, vec![0, 15, 15, 15, 15, 0, 0, 0]);
+	// The triangle rests at 15 until it's played
+	clock(&mut apu, 18 * 2);
+	let triangle = 159.79 / (8227.0 / 15.0 + 100.0);
+	assert!((apu.output() - (95.88 / (8128.0 / 15.0 + 100.0) + triangle)).abs() < 1e-6);
+
+	// Periods below 8 are silent
+	apu.write(0x4002, 0x06);
+	assert_eq!(apu.pulses[0].output(), 0);
+
+	// Disabling the channel clears its length counter
+	apu.write(0x4015, 0x00);
+	assert_eq!(apu.read_status() & 0x01, 0x00);
+}
+
+#[test]
+fn test_length_counter() {
+	let mut apu = Apu::new();
+	apu.write(0x4015, 0x02);
+	// Length index 1 is 254, so use index 3, a length of 2
+	apu.write(0x4004, 0x1F);
+	apu.write(0x4006, 0x40);
+	apu.write(0x4007, 3 << 3);
+	assert_eq!(apu.pulses[1].length.value, 2);
+	clock(&mut apu, FOUR_STEP[1].0);
+	assert_eq!(apu.read_status() & 0x02, 0x02);
+	clock(&mut apu, FOUR_STEP[3].0 - FOUR_STEP[1].0);
+	assert_eq!(apu.read_status() & 0x02, 0x00);
+}
+
+#[test]
+fn test_frame_irq() {
+	let mut apu = Apu::new();
+	clock(&mut apu, FOUR_STEP[3].0 - 1);
+	assert!(!apu.frame_irq());
+	clock(&mut apu, 1);
+	assert!(apu.frame_irq());
+	// Reading the status acknowledges it
+	assert_eq!(apu.read_status() & 0x40, 0x40);
+	assert!(!apu.frame_irq());
+
+	// Inhibited, and the 5 step sequence never asserts it
+	apu.write(0x4017, 0x40);
+	clock(&mut apu, FOUR_STEP_PERIOD * 2);
+	assert!(!apu.frame_irq());
+	apu.write(0x4017, 0x80);
+	clock(&mut apu, FIVE_STEP_PERIOD * 2);
+	assert!(!apu.frame_irq());
+}
+
+#[test]
+fn test_triangle() {
+	let mut apu = Apu::new();
+	apu.write(0x4015, 0x04);
+	apu.write(0x4008, 0x81);
+	apu.write(0x400A, 0x00);
+	apu.write(0x400B, 0x08);
+	// Nothing moves until the linear counter is loaded by a quarter frame
+	clock(&mut apu, 10);
+	assert_eq!(apu.triangle.output(), 15);
+	clock(&mut apu, FOUR_STEP[0].0);
+	clock(&mut apu, 3);
+	assert_ne!(apu.triangle.output(), 15);
+}
+
+#[test]
+fn test_noise() {
+	let mut apu = Apu::new();
+	apu.write(0x4015, 0x08);
+	apu.write(0x400C, 0x1F);
+	apu.write(0x400E, 0x00);
+	apu.write(0x400F, 0x08);
+	let mut levels = Vec::new();
+	for _ in 0..64 {
+		clock(&mut apu, 4);
+		levels.push(apu.noise.output());
+	}
+	assert!(levels.contains(&0));
+	assert!(levels.contains(&15));
+}
+
+#[test]
+fn test_dmc() {
+	let mut apu = Apu::new();
+	// Sample at 0xC040, 17 bytes long, with IRQ when it ends
+	apu.write(0x4010, 0x8F);
+	apu.write(0x4011, 0x40);
+	apu.write(0x4012, 0x01);
+	apu.write(0x4013, 0x01);
+	apu.write(0x4015, 0x10);
+	assert_eq!(apu.read_status() & 0x10, 0x10);
+	let mut addresses = Vec::new();
+	for _ in 0..17 * 8 * DMC_PERIODS[15] as u32 + 100 {
+		if let Some(address) = apu.dmc_request() {
+			addresses.push(address);
+			apu.dmc_fill(0xFF);
+		}
+		apu.clock();
+	}
+	assert_eq!(addresses, (0xC040..0xC051).collect::<Vec<u16>>());
+	assert!(apu.dmc_irq());
+	assert_eq!(apu.read_status() & 0x90, 0x80);
+	// All ones ramps the level up as far as it goes in steps of 2
+	assert_eq!(apu.dmc.level, 126);
+
+	// Writing the enable acknowledges the IRQ
+	apu.write(0x4015, 0x00);
+	assert!(!apu.dmc_irq());
+}
+
+#[test]
+fn test_resampler() {
+	let mut resampler = Resampler::new(100.0, 10);
+	for i in 0..25 {
+		resampler.add(if i < 10 { 1.0 } else { 0.0 });
+	}
+	assert_eq!(resampler.take_samples(), vec![1.0, 0.0]);
+	assert!(resampler.take_samples().is_empty());
+}
@@ -1,9 +1,28 @@
+use apu::{ Apu, Resampler };
+use error::{ Access, EmuError, Result };
+use mapper::Cartridge;
+#[cfg(test)]
+use mapper::{ Mapper, Mirroring };
 use memory::{ Memory, ReadWriteMemory };
+use opcode::{ Opcode, CMOS_OPCODES, OPCODES };
+#[cfg(test)]
+use std::cell::RefCell;
 use std::fmt;
+#[cfg(test)]
+use std::rc::Rc;
+use trace::Tracer;
+#[cfg(test)]
+use trace::format_line;
 
-const NMI_VECTOR:   u16 = 0xFFFA;
-const RESET_VECTOR: u16 = 0xFFFC; // Location of first instruction in memory
-const IRQ_VECTOR:   u16 = 0xFFFE;
+pub const NMI_VECTOR:   u16 = 0xFFFA;
+pub const RESET_VECTOR: u16 = 0xFFFC; // Location of first instruction in memory
+pub const IRQ_VECTOR:   u16 = 0xFFFE;
+
+/// The reset sequence takes 7 cycles before the first instruction is fetched
+const RESET_CYCLES: u64 = 7;
+
+/// NMI and IRQ sequences take 7 cycles, just like BRK
+const INTERRUPT_CYCLES: u64 = 7;
 
 /// CPU Status Flags
 enum Flag {
@@ -19,49 +38,172 @@ enum Flag {
 	/// has no effect, but also says "The only way for an IRQ handler to distinguish IRQ from BRK
 	/// is to read the flags byte from the stack and test bit 4"
 	Break    = 1 << 4,
+	/// Unused bit, always reads back as 1 when the status is pushed to the stack
+	Unused   = 1 << 5,
 	/// Set if last ADC or SBC resulted in signed overflow
 	Overflow = 1 << 6,
 	/// Set if set bit 7 of last operation
 	Negative = 1 << 7,
 }
 
+/// Member of the 6502 family to emulate
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum CpuVariant {
+	/// Ricoh 2A03 used by the NES. Its decimal mode is disconnected, so the D flag can be set but
+	/// ADC and SBC always work in binary.
+	#[default]
+	Ricoh2A03,
+	/// Stock NMOS 6502 with decimal mode
+	Nmos6502,
+	/// CMOS 65C02, which fixes the JMP ($xxFF) bug and adds instructions such as BRA, PHX/PLX,
+	/// STZ and TRB/TSB. Opcodes left undocumented on the NMOS 6502 are NOPs.
+	Cmos65C02
+}
+
+impl CpuVariant {
+	/// Opcode metadata for this variant
+	pub fn opcodes(self) -> &'static [Opcode; 256] {
+		match self {
+			CpuVariant::Cmos65C02 => &CMOS_OPCODES,
+			_ => &OPCODES
+		}
+	}
+
+	/// Whether ADC and SBC do BCD arithmetic when the decimal flag is set
+	pub fn has_decimal_mode(self) -> bool {
+		self != CpuVariant::Ricoh2A03
+	}
+
+	/// Whether JMP ($xxFF) reads the high byte of its target from $xx00
+	pub fn has_jmp_indirect_bug(self) -> bool {
+		self != CpuVariant::Cmos65C02
+	}
+
+}
+
+/// Hardware interrupts
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interrupt {
+	Nmi,
+	Irq
+}
+
+/// Devices that can hold the IRQ line. The line stays asserted until every source releases it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IrqSource {
+	/// The APU's frame counter and DMC, followed after every instruction
+	FrameCounter = 1,
+	Dmc          = 1 << 1,
+	/// Follows the cartridge's Mapper::irq output before every instruction
+	Mapper       = 1 << 2,
+	External     = 1 << 3
+}
+
+/// How to handle the unstable unofficial opcodes (SHA, SHX, SHY, TAS, LAS, XAA and LXA). Their
+/// results depend on analog effects and vary between chips.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnstablePolicy {
+	/// Report them as illegal opcodes
+	Reject,
+	/// Emulate their most common behavior. XAA and LXA use the given magic constant, usually 0xEE
+	/// or 0xFF, as A = (A | magic) & X & M and A, X = (A | magic) & M.
+	Emulate { magic: u8 }
+}
+
 /// CPU Registers
-#[derive(Debug, Default)]
-struct Registers {
-	a: u8,     // Accumulator register used by the ALU
-	x: u8,     // Indexing register
-	y: u8,     // ''
-	s: u8,     // Stack pointer
-	pc: u16,   // Program counter (2 bytes wide)
-	status: u8 // Status register used by various instructions and the ALU
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Registers {
+	pub a: u8,     // Accumulator register used by the ALU
+	pub x: u8,     // Indexing register
+	pub y: u8,     // ''
+	pub s: u8,     // Stack pointer
+	pub pc: u16,   // Program counter (2 bytes wide)
+	pub status: u8 // Status register used by various instructions and the ALU
+}
+
+/// Record of a single instruction executed by CPU::step
+#[derive(Clone, Debug)]
+pub struct StepRecord {
+	/// Address the opcode was fetched from
+	pub pc: u16,
+	pub opcode: u8,
+	/// Instruction length in bytes, including the opcode
+	pub length: u8,
+	operand_bytes: [u8; 2],
+	/// Address the instruction read, wrote or jumped to. None for implied, accumulator and
+	/// immediate instructions
+	pub address: Option<u16>,
+	/// Interrupt that was serviced before this instruction. The instruction is the first one of the
+	/// handler, and the 7 cycles of the interrupt sequence are included in cycles.
+	pub interrupt: Option<Interrupt>,
+	pub cycles: u32,
+	pub before: Registers,
+	pub after: Registers
+}
+
+impl StepRecord {
+	/// Operand bytes following the opcode
+	pub fn operands(&self) -> &[u8] {
+		&self.operand_bytes[..self.length as usize - 1]
+	}
 }
 
 /// Model for the 6502 Microprocessor
+#[allow(clippy::upper_case_acronyms)]
 pub struct CPU {
+	variant: CpuVariant,
 	registers: Registers,
 	ram: ReadWriteMemory,
-	cartridge: Box<Memory>
+	apu: Apu,
+	resampler: Option<Resampler>, // Collects audio samples when set
+	cartridge: Cartridge,    // Shared with the PPU
+	cycles: u64,            // Total cycles executed since power up
+	page_crossed: bool,     // Set by indexed addressing modes when the effective address crosses a page
+	address: Option<u16>,   // Effective address of the current instruction
+	tracer: Option<Tracer>,
+	nmi_line: bool,         // Current level of the NMI line (true when asserted)
+	nmi_pending: bool,      // Set on the NMI line's rising edge until the NMI is serviced
+	irq_lines: u8,          // IrqSource bits currently asserting IRQ
+	irq_inhibit: bool,      // Value of the I flag when interrupts were last polled
+	unstable_policy: UnstablePolicy
 }
 
 impl CPU {
 
-	pub fn new(cartridge: Box<Memory>) -> CPU {
+	pub fn new(cartridge: Cartridge, variant: CpuVariant) -> CPU {
 		CPU {
+			variant,
 			registers: Registers::default(),
 			ram: ReadWriteMemory::new(0x800),
-			cartridge: cartridge
+			apu: Apu::new(),
+			resampler: None,
+			cartridge,
+			cycles: 0,
+			page_crossed: false,
+			address: None,
+			tracer: None,
+			nmi_line: false,
+			nmi_pending: false,
+			irq_lines: 0,
+			irq_inhibit: true,
+			unstable_policy: UnstablePolicy::Emulate { magic: 0xEE }
 		}
 	}
 
 	/// Emulate CPU power up
-	///		http://wiki.nesdev.com/w/index.php/CPU_power_up_state#At_power-up
-	pub fn power_up(&mut self) {
+	///     http://wiki.nesdev.com/w/index.php/CPU_power_up_state#At_power-up
+	pub fn power_up(&mut self) -> Result<()> {
 		self.registers.a = 0;
 		self.registers.x = 0;
 		self.registers.y = 0;
 		self.registers.s = 0xFD;
-		self.registers.pc = self.cartridge.loadw(RESET_VECTOR);
+		self.registers.pc = self.loadw(RESET_VECTOR)?;
 		self.set_status(Flag::Irq, true);
+		self.set_status(Flag::Unused, true);
+		self.cycles = RESET_CYCLES;
+		self.irq_inhibit = true;
+		self.nmi_pending = false;
+		Ok(())
 	}
 
 	pub fn power_up_with_pc_override(&mut self, pc: u16) {
@@ -71,21 +213,237 @@ impl CPU {
 		self.registers.s = 0xFD;
 		self.registers.pc = pc;
 		self.set_status(Flag::Irq, true);
+		self.set_status(Flag::Unused, true);
+		self.cycles = RESET_CYCLES;
+		self.irq_inhibit = true;
+		self.nmi_pending = false;
 	}
 
 	/// Emulate CPU reset
-	///		http://wiki.nesdev.com/w/index.php/CPU_power_up_state#After_reset
-	pub fn reset(&mut self) {
-		// TODO: Reset state
+	///     http://wiki.nesdev.com/w/index.php/CPU_power_up_state#After_reset
+	///
+	/// A, X and Y are left alone, and the stack pointer is decremented by 3 as if the reset sequence
+	/// pushed PC and P, but nothing is written to the stack.
+	pub fn reset(&mut self) -> Result<()> {
+		self.registers.s = self.registers.s.wrapping_sub(3);
+		// Reset silences the APU
+		self.apu.write(0x4015, 0x00);
+		self.registers.pc = self.loadw(RESET_VECTOR)?;
+		self.set_status(Flag::Irq, true);
+		self.cycles += RESET_CYCLES;
+		self.irq_inhibit = true;
+		self.nmi_pending = false;
+		Ok(())
 	}
 
-	pub fn run(&mut self) {
-		println!("Running!");
+	/// Choose how the unstable unofficial opcodes are handled
+	pub fn set_unstable_policy(&mut self, policy: UnstablePolicy) {
+		self.unstable_policy = policy;
+	}
 
+	/// Set the level of the NMI line. NMI is edge triggered, so it fires once each time the line
+	/// goes from released to asserted.
+	pub fn set_nmi_line(&mut self, asserted: bool) {
+		if asserted && !self.nmi_line {
+			self.nmi_pending = true;
+		}
+		self.nmi_line = asserted;
+	}
+
+	/// Assert or release the IRQ line on behalf of a device. IRQ is level triggered, so it keeps
+	/// firing while any device holds the line and interrupts aren't disabled.
+	pub fn set_irq_line(&mut self, source: IrqSource, asserted: bool) {
+		if asserted {
+			self.irq_lines |= source as u8;
+		} else {
+			self.irq_lines &= !(source as u8);
+		}
+	}
+
+	/// Replace the registers, e.g. to call a routine directly
+	pub fn set_registers(&mut self, registers: Registers) {
+		self.registers = registers;
+	}
+
+	/// Collect audio samples, or stop collecting them with None
+	pub fn set_resampler(&mut self, resampler: Option<Resampler>) {
+		self.resampler = resampler;
+	}
+
+	/// Audio samples collected since the last call
+	pub fn take_samples(&mut self) -> Vec<f32> {
+		self.resampler.as_mut().map(Resampler::take_samples).unwrap_or_default()
+	}
+
+	/// Let cycles pass without executing anything. The APU and the cartridge still run.
+	pub fn stall(&mut self, cycles: u32) {
+		self.cycles += cycles as u64;
+		self.clock_devices(cycles);
+	}
+
+	/// Total number of cycles executed since power up
+	pub fn cycles(&self) -> u64 {
+		self.cycles
+	}
+
+	/// Member of the 6502 family being emulated
+	pub fn variant(&self) -> CpuVariant {
+		self.variant
+	}
+
+	/// Snapshot of the current register values
+	pub fn registers(&self) -> Registers {
+		self.registers
+	}
+
+	/// Write a trace line for every instruction before it executes, or stop tracing with None
+	/// A trace line that can't be written fails that step() with EmuError::Io
+	pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+		self.tracer = tracer;
+	}
+
+	/// Read memory without triggering side effects of reading I/O registers. Meant for debuggers
+	/// and tracers; I/O registers and unmapped addresses always read as 0xFF.
+	pub fn peek(&self, address: u16) -> u8 {
+		match address {
+			0x2000 ..= 0x401F => 0xFF,
+			_ => self.load(address).unwrap_or(0xFF)
+		}
+	}
+
+	/// Run until an error stops the CPU
+	pub fn run(&mut self) -> EmuError {
 		loop {
-			// Get instruction from prg
-			let instruction = self.load_pc();
-			self.execute(instruction);
+			if let Err(e) = self.step() {
+				return e;
+			}
+		}
+	}
+
+	/// Fetch and execute a single instruction
+	pub fn step(&mut self) -> Result<StepRecord> {
+		let start = self.cycles;
+		let mapper_irq = self.cartridge.borrow().irq();
+		self.set_irq_line(IrqSource::Mapper, mapper_irq);
+		let interrupt = self.poll_interrupt();
+		if let Some(interrupt) = interrupt {
+			self.interrupt(interrupt)?;
+		}
+
+		if let Some(mut tracer) = self.tracer.take() {
+			let traced = tracer.trace(self);
+			self.tracer = Some(tracer);
+			traced?;
+		}
+
+		let before = self.registers;
+		self.address = None;
+
+		// Get instruction from prg
+		let opcode = self.peek(before.pc);
+		let length = self.variant.opcodes()[opcode as usize].length();
+		let mut operand_bytes = [0; 2];
+		for i in 1..length {
+			operand_bytes[i as usize - 1] = self.peek(before.pc.wrapping_add(i as u16));
+		}
+
+		let opcode = self.load_pc()?;
+		self.execute(opcode)?;
+		let cycles = (self.cycles - start) as u32;
+		self.clock_devices(cycles);
+
+		Ok(StepRecord {
+			pc: before.pc,
+			opcode,
+			length,
+			operand_bytes,
+			address: self.address,
+			interrupt,
+			cycles,
+			before,
+			after: self.registers
+		})
+	}
+
+	/// Run whole instructions until at least the given number of cycles have passed, returning the
+	/// number of cycles actually run
+	pub fn run_for_cycles(&mut self, cycles: u64) -> Result<u64> {
+		let start = self.cycles;
+		while self.cycles - start < cycles {
+			self.step()?;
+		}
+		Ok(self.cycles - start)
+	}
+
+	/// Run until the predicate returns true for an executed instruction, returning its record
+	pub fn run_until<F: FnMut(&StepRecord) -> bool>(&mut self, mut predicate: F) -> Result<StepRecord> {
+		loop {
+			let record = self.step()?;
+			if predicate(&record) {
+				return Ok(record);
+			}
+		}
+	}
+
+	// Run the APU and the cartridge for the cycles an instruction took, serving the DMC's sample
+	// fetches and mixing the cartridge's expansion audio in with the APU. The cycles the DMC steals
+	// from the CPU aren't emulated.
+	fn clock_devices(&mut self, cycles: u32) {
+		let expansion_audio = self.cartridge.borrow().audio();
+		for _ in 0..cycles {
+			self.apu.clock();
+			if let Some(address) = self.apu.dmc_request() {
+				let value = self.load(address).unwrap_or(0xFF);
+				self.apu.dmc_fill(value);
+			}
+			if let Some(ref mut resampler) = self.resampler {
+				resampler.add(self.apu.output() + expansion_audio);
+			}
+		}
+		self.set_irq_line(IrqSource::FrameCounter, self.apu.frame_irq());
+		self.set_irq_line(IrqSource::Dmc, self.apu.dmc_irq());
+		self.cartridge.borrow_mut().clock(cycles);
+	}
+
+	// Interrupt operations
+
+	// Interrupt to service before the next instruction, if any. NMI takes priority over IRQ.
+	fn poll_interrupt(&self) -> Option<Interrupt> {
+		if self.nmi_pending {
+			Some(Interrupt::Nmi)
+		} else if self.irq_lines != 0 && !self.irq_inhibit {
+			Some(Interrupt::Irq)
+		} else {
+			None
+		}
+	}
+
+	// Push PC and P (with the break bit clear) and jump through the interrupt's vector
+	fn interrupt(&mut self, interrupt: Interrupt) -> Result<()> {
+		let vector = match interrupt {
+			Interrupt::Nmi => {
+				self.nmi_pending = false;
+				NMI_VECTOR
+			},
+			Interrupt::Irq => IRQ_VECTOR
+		};
+
+		let pc = self.registers.pc;
+		self.pushw(pc)?;
+		let sr = (self.registers.status & !(Flag::Break as u8)) | Flag::Unused as u8;
+		self.push(sr)?;
+		self.set_status(Flag::Irq, true);
+		self.clear_decimal_on_interrupt();
+		self.registers.pc = self.loadw(vector)?;
+		self.cycles += INTERRUPT_CYCLES;
+		self.irq_inhibit = true;
+		Ok(())
+	}
+
+	// The 65C02 clears the decimal flag when entering an interrupt handler or BRK
+	fn clear_decimal_on_interrupt(&mut self) {
+		if self.variant == CpuVariant::Cmos65C02 {
+			self.set_status(Flag::Decimal, false);
 		}
 	}
 
@@ -106,24 +464,19 @@ impl CPU {
 
 	// Program Counter operations
 
-	fn load_pc(&mut self) -> u8 {
-		let value = self.cartridge.load(self.registers.pc);
-		self.registers.pc += 1;
-		value
+	fn load_pc(&mut self) -> Result<u8> {
+		let address = self.get_pc();
+		self.load(address)
 	}
 
 	fn get_pc(&mut self) -> u16 {
 		let address = self.registers.pc;
-		self.registers.pc += 1;
+		self.registers.pc = self.registers.pc.wrapping_add(1);
 		address
 	}
 
-	fn loadw_pc(&mut self) -> u16 {
-		self.load_pc() as u16 | (self.load_pc() as u16) << 8
-	}
-
-	fn getw_pc(&mut self) -> u16 {
-		self.get_pc() as u16 | (self.get_pc() as u16) << 8
+	fn loadw_pc(&mut self) -> Result<u16> {
+		Ok(self.load_pc()? as u16 | (self.load_pc()? as u16) << 8)
 	}
 
 
@@ -132,133 +485,213 @@ impl CPU {
 	//        Hardcoded to Page 1 (0x100-0x1FF)
 
 	// Push a value to the stack
-	fn push(&mut self, value: u8) {
+	fn push(&mut self, value: u8) -> Result<()> {
 		let address = (self.registers.s as u16) | 0x100;
-		self.store(address, value);
-		self.registers.s -= 1;
+		self.store(address, value)?;
+		self.registers.s = self.registers.s.wrapping_sub(1);
+		Ok(())
 	}
 
 	// Push a word onto the stack
-	fn pushw(&mut self, value: u16) {
-		self.push((value >> 8) as u8);
-		self.push(value as u8);
+	fn pushw(&mut self, value: u16) -> Result<()> {
+		self.push((value >> 8) as u8)?;
+		self.push(value as u8)
 	}
 
 	// Pull a value from the stack
-	fn pull(&mut self) -> u8 {
+	fn pull(&mut self) -> Result<u8> {
+		self.registers.s = self.registers.s.wrapping_add(1);
 		let address = (self.registers.s as u16) | 0x100;
-		self.registers.s += 1;
 		self.load(address)
 	}
 
 	// Pull a word from the stack
-	fn pullw(&mut self) -> u16 {
-		let word = self.pull();
-		(word | self.pull()) as u16
+	fn pullw(&mut self) -> Result<u16> {
+		Ok(self.pull()? as u16 | (self.pull()? as u16) << 8)
 	}
 
 
 	// Addressing modes
 
-	fn immediate_mode(&mut self) -> u16 {
-		self.get_pc()
+	fn immediate_mode(&mut self) -> Result<u16> {
+		Ok(self.get_pc())
 	}
 
-	fn absolute_mode(&mut self) -> u16 {
-		self.getw_pc()
+	fn absolute_mode(&mut self) -> Result<u16> {
+		let address = self.loadw_pc()?;
+		Ok(self.effective(address))
 	}
 
-	fn absolute_x_mode(&mut self) -> u16 {
-		self.getw_pc() + self.registers.x as u16
+	fn absolute_x_mode(&mut self) -> Result<u16> {
+		let base = self.loadw_pc()?;
+		Ok(self.indexed(base, self.registers.x))
 	}
 
-	fn absolute_y_mode(&mut self) -> u16 {
-		self.getw_pc() + self.registers.y as u16
+	fn absolute_y_mode(&mut self) -> Result<u16> {
+		let base = self.loadw_pc()?;
+		Ok(self.indexed(base, self.registers.y))
 	}
 
-	fn zero_page_mode(&mut self) -> u16 {
-		self.load_pc() as u16
+	fn zero_page_mode(&mut self) -> Result<u16> {
+		let address = self.load_pc()? as u16;
+		Ok(self.effective(address))
 	}
 
-	fn zero_page_x_mode(&mut self) -> u16 {
-		(self.load_pc() + self.registers.x) as u16
+	// Zero page indexing wraps around within the zero page
+	fn zero_page_x_mode(&mut self) -> Result<u16> {
+		let address = self.load_pc()?.wrapping_add(self.registers.x) as u16;
+		Ok(self.effective(address))
 	}
 
-	fn zero_page_y_mode(&mut self) -> u16 {
-		(self.load_pc() + self.registers.y) as u16
+	fn zero_page_y_mode(&mut self) -> Result<u16> {
+		let address = self.load_pc()?.wrapping_add(self.registers.y) as u16;
+		Ok(self.effective(address))
 	}
 
-	fn indirect_x_mode(&mut self) -> u16 {
-		let address = self.load_pc() + self.registers.x; // Zero page address
-		self.loadw(address as u16) // Indirect address
+	fn indirect_x_mode(&mut self) -> Result<u16> {
+		let address = self.load_pc()?.wrapping_add(self.registers.x); // Zero page address
+		let address = self.loadw_zero_page(address)?; // Indirect address
+		Ok(self.effective(address))
 	}
 
-	fn indirect_y_mode(&mut self) -> u16 {
-		let address = self.load_pc(); // Zero page address
-		self.loadw(address as u16) + self.registers.y as u16 // Indirect address
+	fn indirect_y_mode(&mut self) -> Result<u16> {
+		let address = self.load_pc()?; // Zero page address
+		let base = self.loadw_zero_page(address)?; // Indirect address
+		Ok(self.indexed(base, self.registers.y))
 	}
 
-	fn relative_mode(&mut self) -> u16 {
-		let address = self.load_pc();
-		if address & 0x80 == 0x80 {
-			self.registers.pc - (address & 0x7F) as u16
-		} else {
-			self.registers.pc + address as u16
+	// 65C02 only
+	fn zero_page_indirect_mode(&mut self) -> Result<u16> {
+		let address = self.load_pc()?; // Zero page address
+		let address = self.loadw_zero_page(address)?; // Indirect address
+		Ok(self.effective(address))
+	}
+
+	// Operand is a signed offset from the address of the next instruction
+	fn relative_mode(&mut self) -> Result<u16> {
+		let offset = self.load_pc()? as i8;
+		let address = self.registers.pc.wrapping_add(offset as u16);
+		Ok(self.effective(address))
+	}
+
+	// Add an index register to a base address, noting if the result lands on a different page
+	fn indexed(&mut self, base: u16, index: u8) -> u16 {
+		let address = base.wrapping_add(index as u16);
+		self.page_crossed = (base & 0xFF00) != (address & 0xFF00);
+		self.effective(address)
+	}
+
+	// Record the effective address of the current instruction for CPU::step
+	fn effective(&mut self, address: u16) -> u16 {
+		self.address = Some(address);
+		address
+	}
+
+	// Load a word from the zero page; the high byte wraps to 0x00 rather than crossing into page 1
+	fn loadw_zero_page(&self, address: u8) -> Result<u16> {
+		Ok(self.load(address as u16)? as u16 | (self.load(address.wrapping_add(1) as u16)? as u16) << 8)
+	}
+
+	// Load an operand for a read instruction. Reads through an indexed address that crossed a page
+	// take an extra cycle to fix up the high byte; writes and read-modify-writes always pay for it,
+	// so it's already part of their base cycle count.
+	fn load_operand(&mut self, address: u16) -> Result<u8> {
+		self.page_cross_penalty();
+		self.load(address)
+	}
+
+	fn page_cross_penalty(&mut self) {
+		if self.page_crossed {
+			self.cycles += 1;
 		}
 	}
 
+	// Store the result of a read-modify-write instruction. The NMOS 6502 writes the unmodified value
+	// back on the cycle before writing the result, which mappers can see; the 65C02 reads it twice
+	// instead.
+	fn store_modified(&mut self, address: u16, old: u8, new: u8) -> Result<()> {
+		if self.variant != CpuVariant::Cmos65C02 {
+			self.store(address, old)?;
+		}
+		self.store(address, new)
+	}
+
 	fn set_zn(&mut self, value: u8) {
 		self.set_status(Flag::Zero, value == 0);
 		self.set_status(Flag::Negative, value & 0x80 != 0);
 	}
 
-	pub fn execute(&mut self, instruction: u8) {
-		println!("Executing instruction: {:#X}", instruction);
+	/// Execute a single instruction, returning the number of cycles it took
+	pub fn execute(&mut self, instruction: u8) -> Result<u32> {
+		let start = self.cycles;
+		let irq_inhibit = self.get_status(Flag::Irq);
+		self.page_crossed = false;
+		self.cycles += self.variant.opcodes()[instruction as usize].cycles as u64;
+
+		match self.variant {
+			CpuVariant::Cmos65C02 => self.execute_cmos(instruction)?,
+			_ => self.execute_nmos(instruction)?
+		}
+
+		// Interrupts are polled before the last cycle of an instruction. CLI, SEI and PLP change the
+		// I flag after that point, so their effect on IRQs is delayed by one instruction.
+		self.irq_inhibit = match instruction {
+			0x58 | 0x78 | 0x28 => irq_inhibit,
+			_ => self.get_status(Flag::Irq)
+		};
+
+		Ok((self.cycles - start) as u32)
+	}
+
+	// Decode and run an instruction of the NMOS 6502 and 2A03
+	fn execute_nmos(&mut self, instruction: u8) -> Result<()> {
 		match instruction {
 
+			// OFFICIAL Instructions
+
 			// ADC
-			0x69 => { let address = self.immediate_mode(); self.adc(address); },
-			0x65 => { let address = self.zero_page_mode(); self.adc(address); },
-			0x75 => { let address = self.zero_page_x_mode(); self.adc(address); },
-			0x6D => { let address = self.absolute_mode(); self.adc(address); },
-			0x7D => { let address = self.absolute_x_mode(); self.adc(address); },
-			0x79 => { let address = self.absolute_y_mode(); self.adc(address); },
-			0x61 => { let address = self.indirect_x_mode(); self.adc(address); },
-			0x71 => { let address = self.indirect_y_mode(); self.adc(address); },
+			0x69 => { let address = self.immediate_mode()?; self.adc(address)?; },
+			0x65 => { let address = self.zero_page_mode()?; self.adc(address)?; },
+			0x75 => { let address = self.zero_page_x_mode()?; self.adc(address)?; },
+			0x6D => { let address = self.absolute_mode()?; self.adc(address)?; },
+			0x7D => { let address = self.absolute_x_mode()?; self.adc(address)?; },
+			0x79 => { let address = self.absolute_y_mode()?; self.adc(address)?; },
+			0x61 => { let address = self.indirect_x_mode()?; self.adc(address)?; },
+			0x71 => { let address = self.indirect_y_mode()?; self.adc(address)?; },
 
 			// AND
-			0x29 => { let address = self.immediate_mode(); self.and(address); },
-			0x25 => { let address = self.zero_page_mode(); self.and(address); },
-			0x35 => { let address = self.zero_page_x_mode(); self.and(address); },
-			0x2D => { let address = self.absolute_mode(); self.and(address); },
-			0x3D => { let address = self.absolute_x_mode(); self.and(address); },
-			0x39 => { let address = self.absolute_y_mode(); self.and(address); },
-			0x21 => { let address = self.indirect_x_mode(); self.and(address); },
-			0x31 => { let address = self.indirect_y_mode(); self.and(address); },
+			0x29 => { let address = self.immediate_mode()?; self.and(address)?; },
+			0x25 => { let address = self.zero_page_mode()?; self.and(address)?; },
+			0x35 => { let address = self.zero_page_x_mode()?; self.and(address)?; },
+			0x2D => { let address = self.absolute_mode()?; self.and(address)?; },
+			0x3D => { let address = self.absolute_x_mode()?; self.and(address)?; },
+			0x39 => { let address = self.absolute_y_mode()?; self.and(address)?; },
+			0x21 => { let address = self.indirect_x_mode()?; self.and(address)?; },
+			0x31 => { let address = self.indirect_y_mode()?; self.and(address)?; },
 
 			// ASL
-			0x06 => { let address = self.zero_page_mode(); self.asl(address); },
-			0x16 => { let address = self.zero_page_x_mode(); self.asl(address); },
-			0x0E => { let address = self.absolute_mode(); self.asl(address); },
-			0x1E => { let address = self.absolute_x_mode(); self.asl(address); },
+			0x06 => { let address = self.zero_page_mode()?; self.asl(address)?; },
+			0x16 => { let address = self.zero_page_x_mode()?; self.asl(address)?; },
+			0x0E => { let address = self.absolute_mode()?; self.asl(address)?; },
+			0x1E => { let address = self.absolute_x_mode()?; self.asl(address)?; },
 			0x0A => self.asla(),
 
 			// BRANCH Instructions
-			0x90 => self.bcc(),
-			0xb0 => self.bcs(),
-			0xF0 => self.beq(),
-			0x30 => self.bmi(),
-			0xD0 => self.bne(),
-			0x10 => self.bpl(),
-			0x50 => self.bvc(),
-			0x70 => self.bvs(),
+			0x90 => self.bcc()?,
+			0xB0 => self.bcs()?,
+			0xF0 => self.beq()?,
+			0x30 => self.bmi()?,
+			0xD0 => self.bne()?,
+			0x10 => self.bpl()?,
+			0x50 => self.bvc()?,
+			0x70 => self.bvs()?,
 
 			// BIT
-			0x24 => { let address = self.zero_page_mode(); self.bit(address); },
-			0x2C => { let address = self.absolute_mode(); self.bit(address); },
+			0x24 => { let address = self.zero_page_mode()?; self.bit(address)?; },
+			0x2C => { let address = self.absolute_mode()?; self.bit(address)?; },
 
 			// BREAK
-			0x00 => self.brk(),
+			0x00 => self.brk()?,
 
 			// CLEAR Instructions
 			0xD8 => self.cld(),
@@ -267,263 +700,559 @@ impl CPU {
 			0xB8 => self.clv(),
 
 			// CMP
-			0xC9 => { let address = self.immediate_mode(); self.cmp(address); },
-			0xC5 => { let address = self.zero_page_mode(); self.cmp(address); },
-			0xD5 => { let address = self.zero_page_x_mode(); self.cmp(address); },
-			0xCD => { let address = self.absolute_mode(); self.cmp(address); },
-			0xDD => { let address = self.absolute_x_mode(); self.cmp(address); },
-			0xD9 => { let address = self.absolute_y_mode(); self.cmp(address); },
-			0xC1 => { let address = self.indirect_x_mode(); self.cmp(address); },
-			0xD1 => { let address = self.indirect_y_mode(); self.cmp(address); },
+			0xC9 => { let address = self.immediate_mode()?; self.cmp(address)?; },
+			0xC5 => { let address = self.zero_page_mode()?; self.cmp(address)?; },
+			0xD5 => { let address = self.zero_page_x_mode()?; self.cmp(address)?; },
+			0xCD => { let address = self.absolute_mode()?; self.cmp(address)?; },
+			0xDD => { let address = self.absolute_x_mode()?; self.cmp(address)?; },
+			0xD9 => { let address = self.absolute_y_mode()?; self.cmp(address)?; },
+			0xC1 => { let address = self.indirect_x_mode()?; self.cmp(address)?; },
+			0xD1 => { let address = self.indirect_y_mode()?; self.cmp(address)?; },
 
 			// CPX
-			0xE0 => { let address = self.immediate_mode(); self.cpx(address) },
-			0xE4 => { let address = self.zero_page_mode(); self.cpx(address) },
-			0xEC => { let address = self.absolute_mode(); self.cpx(address) },
+			0xE0 => { let address = self.immediate_mode()?; self.cpx(address)?; },
+			0xE4 => { let address = self.zero_page_mode()?; self.cpx(address)?; },
+			0xEC => { let address = self.absolute_mode()?; self.cpx(address)?; },
 
 			// CPY
-			0xC0 => { let address = self.immediate_mode(); self.cpy(address) },
-			0xC4 => { let address = self.zero_page_mode(); self.cpy(address) },
-			0xCC => { let address = self.absolute_mode(); self.cpy(address) },
+			0xC0 => { let address = self.immediate_mode()?; self.cpy(address)?; },
+			0xC4 => { let address = self.zero_page_mode()?; self.cpy(address)?; },
+			0xCC => { let address = self.absolute_mode()?; self.cpy(address)?; },
 
 			// DECREMENT Instructions
-			0xC6 => { let address = self.zero_page_mode(); self.dec(address); },
-			0xD6 => { let address = self.zero_page_x_mode(); self.dec(address); },
-			0xCE => { let address = self.absolute_mode(); self.dec(address); },
-			0xDE => { let address = self.absolute_x_mode(); self.dec(address); },
+			0xC6 => { let address = self.zero_page_mode()?; self.dec(address)?; },
+			0xD6 => { let address = self.zero_page_x_mode()?; self.dec(address)?; },
+			0xCE => { let address = self.absolute_mode()?; self.dec(address)?; },
+			0xDE => { let address = self.absolute_x_mode()?; self.dec(address)?; },
 			0xCA => self.dex(),
 			0x88 => self.dey(),
 
 			// EOR
-			0x49 => { let address = self.immediate_mode(); self.eor(address); },
-			0x45 => { let address = self.zero_page_mode(); self.eor(address); },
-			0x55 => { let address = self.zero_page_x_mode(); self.eor(address); },
-			0x4D => { let address = self.absolute_mode(); self.eor(address); },
-			0x5D => { let address = self.absolute_x_mode(); self.eor(address); },
-			0x59 => { let address = self.absolute_y_mode(); self.eor(address); },
-			0x41 => { let address = self.indirect_x_mode(); self.eor(address); },
-			0x51 => { let address = self.indirect_y_mode(); self.eor(address); },
+			0x49 => { let address = self.immediate_mode()?; self.eor(address)?; },
+			0x45 => { let address = self.zero_page_mode()?; self.eor(address)?; },
+			0x55 => { let address = self.zero_page_x_mode()?; self.eor(address)?; },
+			0x4D => { let address = self.absolute_mode()?; self.eor(address)?; },
+			0x5D => { let address = self.absolute_x_mode()?; self.eor(address)?; },
+			0x59 => { let address = self.absolute_y_mode()?; self.eor(address)?; },
+			0x41 => { let address = self.indirect_x_mode()?; self.eor(address)?; },
+			0x51 => { let address = self.indirect_y_mode()?; self.eor(address)?; },
 
 			// INCREMENT Instructions
-			0xE6 => { let address = self.zero_page_mode(); self.inc(address); },
-			0xF6 => { let address = self.zero_page_x_mode(); self.inc(address); },
-			0xEE => { let address = self.absolute_mode(); self.inc(address); },
-			0xFE => { let address = self.absolute_x_mode(); self.inc(address); },
+			0xE6 => { let address = self.zero_page_mode()?; self.inc(address)?; },
+			0xF6 => { let address = self.zero_page_x_mode()?; self.inc(address)?; },
+			0xEE => { let address = self.absolute_mode()?; self.inc(address)?; },
+			0xFE => { let address = self.absolute_x_mode()?; self.inc(address)?; },
 			0xE8 => self.inx(),
 			0xC8 => self.iny(),
 
 			// JUMP Instructions
-			0x4C => self.jmpa(),
-			0x6C => self.jmpi(),
-			0x20 => self.jsr(),
+			0x4C => self.jmpa()?,
+			0x6C => self.jmpi()?,
+			0x20 => self.jsr()?,
 
 			// LDA
-			0xA9 => { let address = self.immediate_mode(); self.lda(address); },
-			0xA5 => { let address = self.zero_page_mode(); self.lda(address); },
-			0xB5 => { let address = self.zero_page_x_mode(); self.lda(address); },
-			0xAD => { let address = self.absolute_mode(); self.lda(address); },
-			0xBD => { let address = self.absolute_x_mode(); self.lda(address); },
-			0xB9 => { let address = self.absolute_y_mode(); self.lda(address); },
-			0xA1 => { let address = self.indirect_x_mode(); self.lda(address); },
-			0xB1 => { let address = self.indirect_y_mode(); self.lda(address); }
+			0xA9 => { let address = self.immediate_mode()?; self.lda(address)?; },
+			0xA5 => { let address = self.zero_page_mode()?; self.lda(address)?; },
+			0xB5 => { let address = self.zero_page_x_mode()?; self.lda(address)?; },
+			0xAD => { let address = self.absolute_mode()?; self.lda(address)?; },
+			0xBD => { let address = self.absolute_x_mode()?; self.lda(address)?; },
+			0xB9 => { let address = self.absolute_y_mode()?; self.lda(address)?; },
+			0xA1 => { let address = self.indirect_x_mode()?; self.lda(address)?; },
+			0xB1 => { let address = self.indirect_y_mode()?; self.lda(address)?; }
 
 			// LDX
-			0xA2 => { let address = self.immediate_mode(); self.ldx(address); },
-			0xA6 => { let address = self.zero_page_mode(); self.ldx(address); },
-			0xB6 => { let address = self.zero_page_y_mode(); self.ldx(address); },
-			0xAE => { let address = self.absolute_mode(); self.ldx(address); },
-			0xBE => { let address = self.absolute_y_mode(); self.lda(address); }
+			0xA2 => { let address = self.immediate_mode()?; self.ldx(address)?; },
+			0xA6 => { let address = self.zero_page_mode()?; self.ldx(address)?; },
+			0xB6 => { let address = self.zero_page_y_mode()?; self.ldx(address)?; },
+			0xAE => { let address = self.absolute_mode()?; self.ldx(address)?; },
+			0xBE => { let address = self.absolute_y_mode()?; self.ldx(address)?; }
 
 			// LDY
-			0xA0 => { let address = self.immediate_mode(); self.ldy(address); },
-			0xA4 => { let address = self.zero_page_mode(); self.ldy(address); },
-			0xB4 => { let address = self.zero_page_x_mode(); self.ldy(address); },
-			0xAC => { let address = self.absolute_mode(); self.ldy(address); },
-			0xBC => { let address = self.absolute_x_mode(); self.ldy(address); }
+			0xA0 => { let address = self.immediate_mode()?; self.ldy(address)?; },
+			0xA4 => { let address = self.zero_page_mode()?; self.ldy(address)?; },
+			0xB4 => { let address = self.zero_page_x_mode()?; self.ldy(address)?; },
+			0xAC => { let address = self.absolute_mode()?; self.ldy(address)?; },
+			0xBC => { let address = self.absolute_x_mode()?; self.ldy(address)?; }
 
 			// LSR
-			0x46 => { let address = self.zero_page_mode(); self.lsr(address); },
-			0x56 => { let address = self.zero_page_x_mode(); self.lsr(address); },
-			0x4E => { let address = self.absolute_mode(); self.lsr(address); },
-			0x5E => { let address = self.absolute_x_mode(); self.lsr(address); },
+			0x46 => { let address = self.zero_page_mode()?; self.lsr(address)?; },
+			0x56 => { let address = self.zero_page_x_mode()?; self.lsr(address)?; },
+			0x4E => { let address = self.absolute_mode()?; self.lsr(address)?; },
+			0x5E => { let address = self.absolute_x_mode()?; self.lsr(address)?; },
 			0x4A => self.lsra(),
 
 			// NOP
 			0xEA => self.nop(),
 
 			// ORA
-			0x09 => { let address = self.immediate_mode(); self.ora(address); },
-			0x05 => { let address = self.zero_page_mode(); self.ora(address); },
-			0x15 => { let address = self.zero_page_x_mode(); self.ora(address); },
-			0x0D => { let address = self.absolute_mode(); self.ora(address); },
-			0x1D => { let address = self.absolute_x_mode(); self.ora(address); },
-			0x19 => { let address = self.absolute_y_mode(); self.ora(address); },
-			0x01 => { let address = self.indirect_x_mode(); self.ora(address); },
-			0x11 => { let address = self.indirect_y_mode(); self.ora(address); }
+			0x09 => { let address = self.immediate_mode()?; self.ora(address)?; },
+			0x05 => { let address = self.zero_page_mode()?; self.ora(address)?; },
+			0x15 => { let address = self.zero_page_x_mode()?; self.ora(address)?; },
+			0x0D => { let address = self.absolute_mode()?; self.ora(address)?; },
+			0x1D => { let address = self.absolute_x_mode()?; self.ora(address)?; },
+			0x19 => { let address = self.absolute_y_mode()?; self.ora(address)?; },
+			0x01 => { let address = self.indirect_x_mode()?; self.ora(address)?; },
+			0x11 => { let address = self.indirect_y_mode()?; self.ora(address)?; }
 
 			// Push & Pull Instructions
-			0x48 => self.pha(),
-			0x08 => self.php(),
-			0x68 => self.pla(),
-			0x28 => self.plp(),
+			0x48 => self.pha()?,
+			0x08 => self.php()?,
+			0x68 => self.pla()?,
+			0x28 => self.plp()?,
+
+			// ROL
+			0x26 => { let address = self.zero_page_mode()?; self.rol(address)?; },
+			0x36 => { let address = self.zero_page_x_mode()?; self.rol(address)?; },
+			0x2E => { let address = self.absolute_mode()?; self.rol(address)?; },
+			0x3E => { let address = self.absolute_x_mode()?; self.rol(address)?; },
+			0x2A => self.rola(),
+
+			// ROR
+			0x66 => { let address = self.zero_page_mode()?; self.ror(address)?; },
+			0x76 => { let address = self.zero_page_x_mode()?; self.ror(address)?; },
+			0x6E => { let address = self.absolute_mode()?; self.ror(address)?; },
+			0x7E => { let address = self.absolute_x_mode()?; self.ror(address)?; },
+			0x6A => self.rora(),
 
 			// Return Instructions
-			0x40 => self.rti(),
-			0x60 => self.rts(),
+			0x40 => self.rti()?,
+			0x60 => self.rts()?,
 
 			// SBC
-			0xE9 => { let address = self.immediate_mode(); self.sbc(address); },
-			0xE5 => { let address = self.zero_page_mode(); self.sbc(address); },
-			0xF5 => { let address = self.zero_page_x_mode(); self.sbc(address); },
-			0xED => { let address = self.absolute_mode(); self.sbc(address); },
-			0xFD => { let address = self.absolute_x_mode(); self.sbc(address); },
-			0xF9 => { let address = self.absolute_y_mode(); self.sbc(address); },
-			0xE1 => { let address = self.indirect_x_mode(); self.sbc(address); },
-			0xF1 => { let address = self.indirect_y_mode(); self.sbc(address); }
+			0xE9 => { let address = self.immediate_mode()?; self.sbc(address)?; },
+			0xE5 => { let address = self.zero_page_mode()?; self.sbc(address)?; },
+			0xF5 => { let address = self.zero_page_x_mode()?; self.sbc(address)?; },
+			0xED => { let address = self.absolute_mode()?; self.sbc(address)?; },
+			0xFD => { let address = self.absolute_x_mode()?; self.sbc(address)?; },
+			0xF9 => { let address = self.absolute_y_mode()?; self.sbc(address)?; },
+			0xE1 => { let address = self.indirect_x_mode()?; self.sbc(address)?; },
+			0xF1 => { let address = self.indirect_y_mode()?; self.sbc(address)?; }
 
 			// SET Instructions
 			0x38 => self.sec(),
 			0xF8 => self.sed(),
 			0x78 => self.sei(),
 
-			_ => panic!("Unsupported instruction: {:#X}", instruction)
+			// STA
+			0x85 => { let address = self.zero_page_mode()?; self.sta(address)?; },
+			0x95 => { let address = self.zero_page_x_mode()?; self.sta(address)?; },
+			0x8D => { let address = self.absolute_mode()?; self.sta(address)?; },
+			0x9D => { let address = self.absolute_x_mode()?; self.sta(address)?; },
+			0x99 => { let address = self.absolute_y_mode()?; self.sta(address)?; },
+			0x81 => { let address = self.indirect_x_mode()?; self.sta(address)?; },
+			0x91 => { let address = self.indirect_y_mode()?; self.sta(address)?; },
+
+			// STX
+			0x86 => { let address = self.zero_page_mode()?; self.stx(address)?; },
+			0x96 => { let address = self.zero_page_y_mode()?; self.stx(address)?; },
+			0x8E => { let address = self.absolute_mode()?; self.stx(address)?; },
+
+			// STY
+			0x84 => { let address = self.zero_page_mode()?; self.sty(address)?; },
+			0x94 => { let address = self.zero_page_x_mode()?; self.sty(address)?; },
+			0x8C => { let address = self.absolute_mode()?; self.sty(address)?; },
+
+			// TRANSFER Instructions
+			0xAA => self.tax(),
+			0xA8 => self.tay(),
+			0xBA => self.tsx(),
+			0x8A => self.txa(),
+			0x9A => self.txs(),
+			0x98 => self.tya(),
+
+
+			// UNOFFICIAL Instructions
+			//     http://wiki.nesdev.com/w/index.php/CPU_unofficial_opcodes
+
+			// NOP
+			0x1A | 0x3A | 0x5A | 0x7A | 0xDA | 0xFA => self.nop(),
+			0x80 | 0x82 | 0x89 | 0xC2 | 0xE2 => { let address = self.immediate_mode()?; self.nopr(address)?; },
+			0x04 | 0x44 | 0x64 => { let address = self.zero_page_mode()?; self.nopr(address)?; },
+			0x14 | 0x34 | 0x54 | 0x74 | 0xD4 | 0xF4 => { let address = self.zero_page_x_mode()?; self.nopr(address)?; },
+			0x0C => { let address = self.absolute_mode()?; self.nopr(address)?; },
+			0x1C | 0x3C | 0x5C | 0x7C | 0xDC | 0xFC => { let address = self.absolute_x_mode()?; self.nopr(address)?; },
+
+			// LAX
+			0xA7 => { let address = self.zero_page_mode()?; self.lax(address)?; },
+			0xB7 => { let address = self.zero_page_y_mode()?; self.lax(address)?; },
+			0xAF => { let address = self.absolute_mode()?; self.lax(address)?; },
+			0xBF => { let address = self.absolute_y_mode()?; self.lax(address)?; },
+			0xA3 => { let address = self.indirect_x_mode()?; self.lax(address)?; },
+			0xB3 => { let address = self.indirect_y_mode()?; self.lax(address)?; },
+
+			// SAX
+			0x87 => { let address = self.zero_page_mode()?; self.sax(address)?; },
+			0x97 => { let address = self.zero_page_y_mode()?; self.sax(address)?; },
+			0x8F => { let address = self.absolute_mode()?; self.sax(address)?; },
+			0x83 => { let address = self.indirect_x_mode()?; self.sax(address)?; },
+
+			// SBC
+			0xEB => { let address = self.immediate_mode()?; self.sbc(address)?; },
+
+			// DCP
+			0xC7 => { let address = self.zero_page_mode()?; self.dcp(address)?; },
+			0xD7 => { let address = self.zero_page_x_mode()?; self.dcp(address)?; },
+			0xCF => { let address = self.absolute_mode()?; self.dcp(address)?; },
+			0xDF => { let address = self.absolute_x_mode()?; self.dcp(address)?; },
+			0xDB => { let address = self.absolute_y_mode()?; self.dcp(address)?; },
+			0xC3 => { let address = self.indirect_x_mode()?; self.dcp(address)?; },
+			0xD3 => { let address = self.indirect_y_mode()?; self.dcp(address)?; },
+
+			// ISC
+			0xE7 => { let address = self.zero_page_mode()?; self.isc(address)?; },
+			0xF7 => { let address = self.zero_page_x_mode()?; self.isc(address)?; },
+			0xEF => { let address = self.absolute_mode()?; self.isc(address)?; },
+			0xFF => { let address = self.absolute_x_mode()?; self.isc(address)?; },
+			0xFB => { let address = self.absolute_y_mode()?; self.isc(address)?; },
+			0xE3 => { let address = self.indirect_x_mode()?; self.isc(address)?; },
+			0xF3 => { let address = self.indirect_y_mode()?; self.isc(address)?; },
+
+			// SLO
+			0x07 => { let address = self.zero_page_mode()?; self.slo(address)?; },
+			0x17 => { let address = self.zero_page_x_mode()?; self.slo(address)?; },
+			0x0F => { let address = self.absolute_mode()?; self.slo(address)?; },
+			0x1F => { let address = self.absolute_x_mode()?; self.slo(address)?; },
+			0x1B => { let address = self.absolute_y_mode()?; self.slo(address)?; },
+			0x03 => { let address = self.indirect_x_mode()?; self.slo(address)?; },
+			0x13 => { let address = self.indirect_y_mode()?; self.slo(address)?; },
+
+			// RLA
+			0x27 => { let address = self.zero_page_mode()?; self.rla(address)?; },
+			0x37 => { let address = self.zero_page_x_mode()?; self.rla(address)?; },
+			0x2F => { let address = self.absolute_mode()?; self.rla(address)?; },
+			0x3F => { let address = self.absolute_x_mode()?; self.rla(address)?; },
+			0x3B => { let address = self.absolute_y_mode()?; self.rla(address)?; },
+			0x23 => { let address = self.indirect_x_mode()?; self.rla(address)?; },
+			0x33 => { let address = self.indirect_y_mode()?; self.rla(address)?; },
+
+			// SRE
+			0x47 => { let address = self.zero_page_mode()?; self.sre(address)?; },
+			0x57 => { let address = self.zero_page_x_mode()?; self.sre(address)?; },
+			0x4F => { let address = self.absolute_mode()?; self.sre(address)?; },
+			0x5F => { let address = self.absolute_x_mode()?; self.sre(address)?; },
+			0x5B => { let address = self.absolute_y_mode()?; self.sre(address)?; },
+			0x43 => { let address = self.indirect_x_mode()?; self.sre(address)?; },
+			0x53 => { let address = self.indirect_y_mode()?; self.sre(address)?; },
+
+			// RRA
+			0x67 => { let address = self.zero_page_mode()?; self.rra(address)?; },
+			0x77 => { let address = self.zero_page_x_mode()?; self.rra(address)?; },
+			0x6F => { let address = self.absolute_mode()?; self.rra(address)?; },
+			0x7F => { let address = self.absolute_x_mode()?; self.rra(address)?; },
+			0x7B => { let address = self.absolute_y_mode()?; self.rra(address)?; },
+			0x63 => { let address = self.indirect_x_mode()?; self.rra(address)?; },
+			0x73 => { let address = self.indirect_y_mode()?; self.rra(address)?; },
+
+			// Immediate combinations
+			0x0B | 0x2B => { let address = self.immediate_mode()?; self.anc(address)?; },
+			0x4B => { let address = self.immediate_mode()?; self.alr(address)?; },
+			0x6B => { let address = self.immediate_mode()?; self.arr(address)?; },
+			0xCB => { let address = self.immediate_mode()?; self.axs(address)?; },
+
+			// Unstable Instructions
+			0x93 => { self.check_unstable()?; let address = self.indirect_y_mode()?; self.sha(address)?; },
+			0x9F => { self.check_unstable()?; let address = self.absolute_y_mode()?; self.sha(address)?; },
+			0x9E => { self.check_unstable()?; let address = self.absolute_y_mode()?; self.shx(address)?; },
+			0x9C => { self.check_unstable()?; let address = self.absolute_x_mode()?; self.shy(address)?; },
+			0x9B => { self.check_unstable()?; let address = self.absolute_y_mode()?; self.tas(address)?; },
+			0xBB => { self.check_unstable()?; let address = self.absolute_y_mode()?; self.las(address)?; },
+			0x8B => { self.check_unstable()?; let address = self.immediate_mode()?; self.xaa(address)?; },
+			0xAB => { self.check_unstable()?; let address = self.immediate_mode()?; self.lxa(address)?; },
+
+			// KIL
+			0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xB2 | 0xD2 | 0xF2 => self.kil()?,
 		}
+		Ok(())
+	}
+
+	// Decode and run an instruction of the 65C02. Opcodes that work the same as on the NMOS 6502 are
+	// passed on to execute_nmos.
+	//     http://www.6502.org/tutorials/65c02opcodes.html
+	fn execute_cmos(&mut self, instruction: u8) -> Result<()> {
+		match instruction {
+
+			// (zp) addressing
+			0x12 => { let address = self.zero_page_indirect_mode()?; self.ora(address)?; },
+			0x32 => { let address = self.zero_page_indirect_mode()?; self.and(address)?; },
+			0x52 => { let address = self.zero_page_indirect_mode()?; self.eor(address)?; },
+			0x72 => { let address = self.zero_page_indirect_mode()?; self.adc(address)?; },
+			0x92 => { let address = self.zero_page_indirect_mode()?; self.sta(address)?; },
+			0xB2 => { let address = self.zero_page_indirect_mode()?; self.lda(address)?; },
+			0xD2 => { let address = self.zero_page_indirect_mode()?; self.cmp(address)?; },
+			0xF2 => { let address = self.zero_page_indirect_mode()?; self.sbc(address)?; },
+
+			// BIT
+			0x89 => { let address = self.immediate_mode()?; self.biti(address)?; },
+			0x34 => { let address = self.zero_page_x_mode()?; self.bit(address)?; },
+			0x3C => { let address = self.absolute_x_mode()?; self.bit(address)?; },
+
+			// BRA
+			0x80 => self.bra()?,
+
+			// DEC, INC
+			0x3A => self.deca(),
+			0x1A => self.inca(),
+
+			// JMP
+			0x7C => self.jmpx()?,
+
+			// PUSH, PULL Instructions
+			0xDA => self.phx()?,
+			0x5A => self.phy()?,
+			0xFA => self.plx()?,
+			0x7A => self.ply()?,
+
+			// Shifts and rotates of abs,X only take an extra cycle when the page is crossed
+			0x1E => { let address = self.absolute_x_mode()?; self.page_cross_penalty(); self.asl(address)?; },
+			0x5E => { let address = self.absolute_x_mode()?; self.page_cross_penalty(); self.lsr(address)?; },
+			0x3E => { let address = self.absolute_x_mode()?; self.page_cross_penalty(); self.rol(address)?; },
+			0x7E => { let address = self.absolute_x_mode()?; self.page_cross_penalty(); self.ror(address)?; },
+
+			// STZ
+			0x64 => { let address = self.zero_page_mode()?; self.stz(address)?; },
+			0x74 => { let address = self.zero_page_x_mode()?; self.stz(address)?; },
+			0x9C => { let address = self.absolute_mode()?; self.stz(address)?; },
+			0x9E => { let address = self.absolute_x_mode()?; self.stz(address)?; },
+
+			// TRB, TSB
+			0x14 => { let address = self.zero_page_mode()?; self.trb(address)?; },
+			0x1C => { let address = self.absolute_mode()?; self.trb(address)?; },
+			0x04 => { let address = self.zero_page_mode()?; self.tsb(address)?; },
+			0x0C => { let address = self.absolute_mode()?; self.tsb(address)?; },
+
+			// NOP
+			0x02 | 0x22 | 0x42 | 0x62 | 0x82 | 0xC2 | 0xE2 => { let address = self.immediate_mode()?; self.nopr(address)?; },
+			0x44 => { let address = self.zero_page_mode()?; self.nopr(address)?; },
+			0x54 | 0xD4 | 0xF4 => { let address = self.zero_page_x_mode()?; self.nopr(address)?; },
+			0x5C | 0xDC | 0xFC => { let address = self.absolute_mode()?; self.nopr(address)?; },
+			_ if instruction & 0x03 == 0x03 => self.nop(),
+
+			_ => self.execute_nmos(instruction)?
+		}
+		Ok(())
 	}
 
 	// ADC - Add memory to accumulator with carry
 	// A + M + C -> C, A
-	fn adc(&mut self, address: u16) {
-		let value = self.load(address);
-		let mut new_a = self.registers.a as u16 + value as u16;
+	fn adc(&mut self, address: u16) -> Result<()> {
+		let value = self.load_operand(address)?;
+		self.add(value);
+		Ok(())
+	}
 
-		if self.get_status(Flag::Carry) {
-			new_a += 1;
+	// Shared by ADC and RRA
+	fn add(&mut self, value: u8) {
+		if self.decimal_mode() {
+			self.add_decimal(value);
+		} else {
+			self.add_with_carry(value);
 		}
-		self.set_status(Flag::Carry, new_a > 0xFF);
+	}
 
-		if (self.registers.a ^ value) & 0x80 == 0 {
-			if (self.registers.a ^ new_a as u8) & 0x80 == 0x80 {
-				self.set_status(Flag::Overflow, true);
-			}
+	// Shared by SBC and ISC
+	fn subtract(&mut self, value: u8) {
+		if self.decimal_mode() {
+			self.subtract_decimal(value);
+		} else {
+			self.add_with_carry(!value);
+		}
+	}
+
+	fn decimal_mode(&self) -> bool {
+		self.variant.has_decimal_mode() && self.get_status(Flag::Decimal)
+	}
+
+	// Binary addition; SBC is ADC of the one's complement of the operand
+	fn add_with_carry(&mut self, value: u8) {
+		let a = self.registers.a;
+		let carry = self.get_status(Flag::Carry) as u16;
+		let result = a as u16 + value as u16 + carry;
+		let new_a = result as u8;
+
+		self.set_status(Flag::Carry, result > 0xFF);
+		// Overflow if both operands share a sign that differs from the result's sign
+		self.set_status(Flag::Overflow, !(a ^ value) & (a ^ new_a) & 0x80 != 0);
+		self.set_zn(new_a);
+		self.registers.a = new_a;
+	}
+
+	// BCD addition. The NMOS 6502 sets N and V from the sum before the high digit is adjusted and Z
+	// from the binary sum. The 65C02 sets N and Z from the result and takes an extra cycle.
+	//     http://www.6502.org/tutorials/decimal_mode.html
+	fn add_decimal(&mut self, value: u8) {
+		let a = self.registers.a;
+		let carry = self.get_status(Flag::Carry) as u16;
+		let mut low = (a & 0x0F) as u16 + (value & 0x0F) as u16 + carry;
+		if low >= 0x0A {
+			low = ((low + 0x06) & 0x0F) + 0x10;
+		}
+		let mut result = (a & 0xF0) as u16 + (value & 0xF0) as u16 + low;
+		let unadjusted = result as u8;
+		self.set_status(Flag::Overflow, !(a ^ value) & (a ^ unadjusted) & 0x80 != 0);
+		if result >= 0xA0 {
+			result += 0x60;
+		}
+		self.set_status(Flag::Carry, result > 0xFF);
+
+		let new_a = result as u8;
+		if self.variant == CpuVariant::Cmos65C02 {
+			self.set_zn(new_a);
+			self.cycles += 1;
+		} else {
+			self.set_status(Flag::Zero, (a as u16 + value as u16 + carry) as u8 == 0);
+			self.set_status(Flag::Negative, unadjusted & 0x80 != 0);
 		}
-		self.set_zn(new_a as u8);
-		self.registers.a = new_a as u8;
+		self.registers.a = new_a;
+	}
+
+	// BCD subtraction. C and V, and N and Z on the NMOS 6502, are the same as binary subtraction.
+	fn subtract_decimal(&mut self, value: u8) {
+		let a = self.registers.a;
+		let borrow = (!self.get_status(Flag::Carry)) as i16;
+		self.add_with_carry(!value);
+
+		let mut low = (a & 0x0F) as i16 - (value & 0x0F) as i16 - borrow;
+		if low < 0 {
+			low = ((low - 0x06) & 0x0F) - 0x10;
+		}
+		let mut result = (a & 0xF0) as i16 - (value & 0xF0) as i16 + low;
+		if result < 0 {
+			result -= 0x60;
+		}
+
+		let new_a = result as u8;
+		if self.variant == CpuVariant::Cmos65C02 {
+			self.set_zn(new_a);
+			self.cycles += 1;
+		}
+		self.registers.a = new_a;
 	}
 
 	// AND - Apply bitwise AND to accumulator with memory
 	// A & M -> A
-	fn and(&mut self, address: u16) {
-		let value = self.load(address);
+	fn and(&mut self, address: u16) -> Result<()> {
+		let value = self.load_operand(address)?;
 		let new_a = self.registers.a & value;
 		self.set_zn(new_a);
 		self.registers.a = new_a;
+		Ok(())
 	}
 
 	// ASL - Shift memory left one bit
 	// M << 1 -> M
-	fn asl(&mut self, address: u16) {
-		let mut value = self.load(address);
-		self.set_status(Flag::Carry, value & 0x80 == 0x80);
-		value <<= 1;
-		self.set_zn(value);
-		self.store(address, value);
+	fn asl(&mut self, address: u16) -> Result<()> {
+		let old = self.load(address)?;
+		let value = self.shift_left(old);
+		self.store_modified(address, old, value)
 	}
 
 	// ASL - Shift accumulator left one bit
 	// A << 1 -> A
 	fn asla(&mut self) {
 		let a = self.registers.a;
-		self.set_status(Flag::Carry, a & 0x80 == 0x80);
-		let new_a = self.registers.a << 1;
-		self.set_zn(new_a);
-		self.registers.a = new_a;
+		self.registers.a = self.shift_left(a);
+	}
+
+	fn shift_left(&mut self, value: u8) -> u8 {
+		self.set_status(Flag::Carry, value & 0x80 == 0x80);
+		let result = value << 1;
+		self.set_zn(result);
+		result
+	}
+
+	// Branch Helper
+	// Uses relative addressing mode; the offset is always consumed, even if the branch isn't taken.
+	// Taking the branch costs an extra cycle, and another if the target is on a different page.
+	fn branch(&mut self, condition: bool) -> Result<()> {
+		let address = self.relative_mode()?;
+		if condition {
+			self.cycles += 1;
+			if (self.registers.pc & 0xFF00) != (address & 0xFF00) {
+				self.cycles += 1;
+			}
+			self.registers.pc = address;
+		}
+		Ok(())
 	}
 
 	// BCC - Branch on carry clear
 	// Branch on Carry == 0
-	// Uses relative addressing mode; PC + value @ address
-	fn bcc(&mut self) {
-		if !self.get_status(Flag::Carry) {
-			self.registers.pc = self.relative_mode();
-		}
+	fn bcc(&mut self) -> Result<()> {
+		let condition = !self.get_status(Flag::Carry);
+		self.branch(condition)
 	}
 
 	// BCS - Branch on carry set
 	// Branch on Carry == 1
-	fn bcs(&mut self) {
-		if self.get_status(Flag::Carry) {
-			self.registers.pc = self.relative_mode();
-		}
+	fn bcs(&mut self) -> Result<()> {
+		let condition = self.get_status(Flag::Carry);
+		self.branch(condition)
 	}
 
 	// BEQ - Branch on Zero
 	// Branch on Zero == 1
-	fn beq(&mut self) {
-		if self.get_status(Flag::Zero) {
-			self.registers.pc = self.relative_mode();
-		}
+	fn beq(&mut self) -> Result<()> {
+		let condition = self.get_status(Flag::Zero);
+		self.branch(condition)
 	}
 
 	// BIT - Test bits in memory with accumulator
 	// A & M, M7 -> N, M6 -> V
-	fn bit(&mut self, address: u16) {
-		let value = self.load(address);
+	fn bit(&mut self, address: u16) -> Result<()> {
+		let value = self.load_operand(address)?;
 		self.set_status(Flag::Negative, value & 0x80 == 0x80);
 		self.set_status(Flag::Overflow, value & 0x40 == 0x40);
 		let a = self.registers.a;
 		self.set_status(Flag::Zero, a & value == 0);
+		Ok(())
 	}
 
 	// BMI - Branch on result minus
 	// Branch on Negative == 1
-	fn bmi(&mut self) {
-		if self.get_status(Flag::Negative) {
-			self.registers.pc = self.relative_mode();
-		}
+	fn bmi(&mut self) -> Result<()> {
+		let condition = self.get_status(Flag::Negative);
+		self.branch(condition)
 	}
 
 	// BNE - Branch on result not zero
 	// Branch on Zero == 0
-	fn bne(&mut self) {
-		if !self.get_status(Flag::Zero)  {
-			self.registers.pc = self.relative_mode();
-		}
+	fn bne(&mut self) -> Result<()> {
+		let condition = !self.get_status(Flag::Zero);
+		self.branch(condition)
 	}
 
 	// BPL - Branch on result plus
 	// Branch on Negative == 0
-	fn bpl(&mut self) {
-		if !self.get_status(Flag::Negative)  {
-			self.registers.pc = self.relative_mode();
-		}
+	fn bpl(&mut self) -> Result<()> {
+		let condition = !self.get_status(Flag::Negative);
+		self.branch(condition)
 	}
 
 	// BRK - Fork break
 	// Forced Interrupt PC + 2 toS P toS
-	fn brk(&mut self) {
+	fn brk(&mut self) -> Result<()> {
 		let pc = self.registers.pc;
-		self.pushw(pc + 1);
-		self.set_status(Flag::Break, true);
-		let sr = self.registers.status;
-		self.push(sr);
+		self.pushw(pc.wrapping_add(1))?;
+		let sr = self.registers.status | Flag::Break as u8 | Flag::Unused as u8;
+		self.push(sr)?;
 		self.set_status(Flag::Irq, true);
-		self.registers.pc = self.loadw(IRQ_VECTOR);
+		self.clear_decimal_on_interrupt();
+		self.registers.pc = self.loadw(IRQ_VECTOR)?;
+		Ok(())
 	}
 
 	// BVC - Branch on overflow clear
 	// Branch on Overflow == 0
-	fn bvc(&mut self) {
-		if !self.get_status(Flag::Overflow)  {
-			self.registers.pc = self.relative_mode();
-		}
+	fn bvc(&mut self) -> Result<()> {
+		let condition = !self.get_status(Flag::Overflow);
+		self.branch(condition)
 	}
 
 	// BVS - Branch on overflow set
 	// Branch on Overflow == 1
-	fn bvs(&mut self) {
-		if self.get_status(Flag::Overflow)  {
-			self.registers.pc = self.relative_mode();
-		}
+	fn bvs(&mut self) -> Result<()> {
+		let condition = self.get_status(Flag::Overflow);
+		self.branch(condition)
 	}
 
 	/// CLD - Clear decimal status
@@ -549,78 +1278,94 @@ impl CPU {
 	// Compare Helper
 	fn compare(&mut self, a: u8, b: u8) {
 		self.set_status(Flag::Carry, a >= b);
-		self.set_zn(a - b);
+		self.set_zn(a.wrapping_sub(b));
 	}
 
 	// CMP - Compare memory and accumulator
 	// A - M
-	fn cmp(&mut self, address: u16) {
+	fn cmp(&mut self, address: u16) -> Result<()> {
 		let a = self.registers.a;
-		let b = self.load(address);
+		let b = self.load_operand(address)?;
 		self.compare(a, b);
+		Ok(())
 	}
 
 	// CPX - Compare memory and x
 	// X - M
-	fn cpx(&mut self, address: u16) {
+	fn cpx(&mut self, address: u16) -> Result<()> {
 		let a = self.registers.x;
-		let b = self.load(address);
+		let b = self.load_operand(address)?;
 		self.compare(a, b);
+		Ok(())
 	}
 
 	// CPY - Compare memory and y
 	// Y - M
-	fn cpy(&mut self, address: u16) {
+	fn cpy(&mut self, address: u16) -> Result<()> {
 		let a = self.registers.y;
-		let b = self.load(address);
+		let b = self.load_operand(address)?;
 		self.compare(a, b);
+		Ok(())
 	}
 
 	// DEC - Decrement memory by one
 	// M - 1 -> M
-	fn dec(&mut self, address: u16) {
-		let value = self.load(address);
-		self.store(address, value - 1);
+	fn dec(&mut self, address: u16) -> Result<()> {
+		let old = self.load(address)?;
+		let value = old.wrapping_sub(1);
+		self.set_zn(value);
+		self.store_modified(address, old, value)
 	}
 
 	// DEX - Decrement X by one
 	// X - 1 -> X
 	fn dex(&mut self) {
-		self.registers.x -= 1;
+		let new_x = self.registers.x.wrapping_sub(1);
+		self.set_zn(new_x);
+		self.registers.x = new_x;
 	}
 
 	// DEY - Decrement Y by one
 	// Y - 1 -> Y
 	fn dey(&mut self) {
-		self.registers.y -= 1;
+		let new_y = self.registers.y.wrapping_sub(1);
+		self.set_zn(new_y);
+		self.registers.y = new_y;
 	}
 
 	// EOR - Exclusive OR memory with accumulator
 	// A ^ M -> A
-	fn eor(&mut self, address: u16) {
-		let value = self.load(address);
-	 	let new_a = self.registers.a ^ value;
+	fn eor(&mut self, address: u16) -> Result<()> {
+		let value = self.load_operand(address)?;
+		let new_a = self.registers.a ^ value;
 		self.set_zn(new_a);
 		self.registers.a = new_a;
+		Ok(())
 	}
 
 	// INC - Increment memory by one
 	// M + 1 -> M
-	fn inc(&mut self, address: u16) {
-		let value = self.load(address);
-		self.store(address, value + 1);
+	fn inc(&mut self, address: u16) -> Result<()> {
+		let old = self.load(address)?;
+		let value = old.wrapping_add(1);
+		self.set_zn(value);
+		self.store_modified(address, old, value)
 	}
 
 	// INX - Increment X by one
 	// X + 1 -> X
 	fn inx(&mut self) {
-		self.registers.x += 1;
+		let new_x = self.registers.x.wrapping_add(1);
+		self.set_zn(new_x);
+		self.registers.x = new_x;
 	}
 
 	// INY - Increment Y by one
 	// Y + 1 -> Y
 	fn iny(&mut self) {
-		self.registers.y += 1;
+		let new_y = self.registers.y.wrapping_add(1);
+		self.set_zn(new_y);
+		self.registers.y = new_y;
 	}
 
 	// JMP - Load PC in absolute mode
@@ -628,67 +1373,87 @@ impl CPU {
 	// to it by setting PC.
 	// (PC + 1) -> PC Low
 	// (PC + 2) -> PC High
-	fn jmpa(&mut self) {
-		let value = self.loadw_pc();
-		println!("JMP {:#X}", value);
-		self.registers.pc = value;
+	fn jmpa(&mut self) -> Result<()> {
+		let value = self.loadw_pc()?;
+		self.registers.pc = self.effective(value);
+		Ok(())
 	}
 
 	// JMP - Load PC in indirect mode
 	// Indirect mode for this instruction; instead of loading the value at PC + 1, PC + 2, we take
 	// load the word starting at PC + 1 and jump to it by setting PC
-	fn jmpi(&mut self) {
-		let address = self.loadw_pc();
-		let value = self.loadw(address);
-		println!("JMP {:#X}", value);
-		self.registers.pc = value;
+	// Note: The NMOS 6502 never carries into the high byte of the pointer, so JMP ($xxFF) reads
+	// its high byte from $xx00. The 65C02 fixed this.
+	fn jmpi(&mut self) -> Result<()> {
+		let address = self.loadw_pc()?;
+		let high_address = if self.variant.has_jmp_indirect_bug() {
+			(address & 0xFF00) | (address as u8).wrapping_add(1) as u16
+		} else {
+			address.wrapping_add(1)
+		};
+		let value = self.load(address)? as u16 | (self.load(high_address)? as u16) << 8;
+		self.registers.pc = self.effective(value);
+		Ok(())
 	}
 
 	// JSR - Jump to new location saving return address
 	// PC + 2 toS, (PC + 1) -> PCL
 	//             (PC + 2) -> PCH
-	fn jsr(&mut self) {
-		let address = self.loadw_pc();
+	fn jsr(&mut self) -> Result<()> {
+		let address = self.loadw_pc()?;
 		let pc = self.registers.pc;
-		self.pushw(pc - 1);
-		self.registers.pc = address;
+		self.pushw(pc.wrapping_sub(1))?;
+		self.registers.pc = self.effective(address);
+		Ok(())
 	}
 
 	// LDA - Load Accumulator with memory
 	// Operation: M -> A
-	fn lda(&mut self, address: u16) {
-		let new_a = self.load(address);
+	fn lda(&mut self, address: u16) -> Result<()> {
+		let new_a = self.load_operand(address)?;
 		self.registers.a = new_a;
 		self.set_zn(new_a);
+		Ok(())
 	}
 
 	// LDX - Load X with memory
 	// Operation: M -> X
-	fn ldx(&mut self, address: u16) {
-		let new_x = self.load(address);
+	fn ldx(&mut self, address: u16) -> Result<()> {
+		let new_x = self.load_operand(address)?;
 		self.registers.x = new_x;
 		self.set_zn(new_x);
+		Ok(())
 	}
 
 	// LDY - Load Y with memory
 	// Operation M -> Y
-	fn ldy(&mut self, address: u16) {
-		let new_y = self.load(address);
+	fn ldy(&mut self, address: u16) -> Result<()> {
+		let new_y = self.load_operand(address)?;
 		self.registers.y = new_y;
 		self.set_zn(new_y);
+		Ok(())
 	}
 
 	// LSR - Shift memory right one bit
 	// M >> 1 -> M
-	fn lsr(&mut self, address: u16) {
-		let value = self.load(address);
-		self.store(address, value >> 1);
+	fn lsr(&mut self, address: u16) -> Result<()> {
+		let old = self.load(address)?;
+		let value = self.shift_right(old);
+		self.store_modified(address, old, value)
 	}
 
 	// LSR - Shift accumulator right one bit
 	// A >> 1 -> A
 	fn lsra(&mut self) {
-		self.registers.a = self.registers.a >> 1;
+		let a = self.registers.a;
+		self.registers.a = self.shift_right(a);
+	}
+
+	fn shift_right(&mut self, value: u8) -> u8 {
+		self.set_status(Flag::Carry, value & 0x1 == 0x1);
+		let result = value >> 1;
+		self.set_zn(result);
+		result
 	}
 
 	// NOP - No Operation
@@ -696,75 +1461,119 @@ impl CPU {
 
 	// ORA - OR memory with accumulator
 	// A | M -> A
-	fn ora(&mut self, address: u16) {
-		let new_a = self.registers.a | self.load(address);;
+	fn ora(&mut self, address: u16) -> Result<()> {
+		let new_a = self.registers.a | self.load_operand(address)?;
 		self.registers.a = new_a;
 		self.set_zn(new_a);
+		Ok(())
 	}
 
 	// PHA - Push accumulator onto stack
 	// A -> toS
-	fn pha(&mut self) {
+	fn pha(&mut self) -> Result<()> {
 		let a = self.registers.a;
-		self.push(a);
+		self.push(a)
 	}
 
 	// PHP - Push processor status onto stack
 	// P -> toS
-	fn php(&mut self) {
-		let p = self.registers.status;
-		self.push(p);
+	// The pushed copy always has the break and unused bits set
+	fn php(&mut self) -> Result<()> {
+		let p = self.registers.status | Flag::Break as u8 | Flag::Unused as u8;
+		self.push(p)
 	}
 
 	// PLA - Pull accumulator from stack
 	// toS -> A
-	fn pla(&mut self) {
-		self.registers.a = self.pull();
+	fn pla(&mut self) -> Result<()> {
+		let new_a = self.pull()?;
+		self.registers.a = new_a;
+		self.set_zn(new_a);
+		Ok(())
 	}
 
 	// PLP - Pull processor status from stack
 	// toS -> P
-	fn plp(&mut self) {
-		self.registers.status = self.pull();
+	fn plp(&mut self) -> Result<()> {
+		let p = self.pull()?;
+		self.set_status_from_stack(p);
+		Ok(())
+	}
+
+	// The break bit only exists in copies of P pushed to the stack, and the unused bit always reads
+	// back as 1, so neither is taken from the pulled value
+	fn set_status_from_stack(&mut self, p: u8) {
+		self.registers.status = (p & !(Flag::Break as u8)) | Flag::Unused as u8;
 	}
 
 	// ROL - Rotate memory one bit left
+	// M << 1 | C -> M
+	fn rol(&mut self, address: u16) -> Result<()> {
+		let old = self.load(address)?;
+		let value = self.rotate_left(old);
+		self.store_modified(address, old, value)
+	}
 
 	// ROL - Rotate accumulator one bit left
+	// A << 1 | C -> A
+	fn rola(&mut self) {
+		let a = self.registers.a;
+		self.registers.a = self.rotate_left(a);
+	}
+
+	fn rotate_left(&mut self, value: u8) -> u8 {
+		let carry = self.get_status(Flag::Carry) as u8;
+		self.set_status(Flag::Carry, value & 0x80 == 0x80);
+		let result = (value << 1) | carry;
+		self.set_zn(result);
+		result
+	}
 
 	// ROR - Rotate memory one bit right
+	// C << 7 | M >> 1 -> M
+	fn ror(&mut self, address: u16) -> Result<()> {
+		let old = self.load(address)?;
+		let value = self.rotate_right(old);
+		self.store_modified(address, old, value)
+	}
 
 	// ROR - Rotate accumulator one bit right
+	// C << 7 | A >> 1 -> A
+	fn rora(&mut self) {
+		let a = self.registers.a;
+		self.registers.a = self.rotate_right(a);
+	}
+
+	fn rotate_right(&mut self, value: u8) -> u8 {
+		let carry = self.get_status(Flag::Carry) as u8;
+		self.set_status(Flag::Carry, value & 0x1 == 0x1);
+		let result = (carry << 7) | (value >> 1);
+		self.set_zn(result);
+		result
+	}
 
 	// RTI - Return from interrupt
-	fn rti(&mut self) {
-		self.registers.s = self.pull();
-		self.registers.pc = self.pullw();
+	// toS -> P, toS -> PC
+	fn rti(&mut self) -> Result<()> {
+		let p = self.pull()?;
+		self.set_status_from_stack(p);
+		self.registers.pc = self.pullw()?;
+		Ok(())
 	}
 
 	// RTS - Return from subroutine
 	// toS -> PC, PC + 1 -> PC
-	fn rts(&mut self) {
-		self.registers.pc = self.pullw() + 1;
+	fn rts(&mut self) -> Result<()> {
+		self.registers.pc = self.pullw()?.wrapping_add(1);
+		Ok(())
 	}
 
 	// SBC - Subtract memory from accumulator with borrow
 	// A - M - C -> A
-	fn sbc(&mut self, address: u16) {
-		let a = self.registers.a;
-		let value = self.load(address);
-        let mut new_a = a as u32 - value as u32;
-        if !self.get_status(Flag::Carry) {
-            new_a -= 1;
-        }
-        self.set_status(Flag::Carry, (new_a & 0x100) == 0);
-
-        let new_a = new_a as u8;
-		if (a ^ new_a) & 0x80 != 0 && (a ^ value) & 0x80 == 0x80 {
-			self.set_status(Flag::Carry, true);
-		}
-		self.set_zn(new_a);
-		self.registers.a = new_a;
+	fn sbc(&mut self, address: u16) -> Result<()> {
+		let value = self.load_operand(address)?;
+		self.subtract(value);
+		Ok(())
 	}
 
 	// SEC - Set carry
@@ -784,6 +1593,403 @@ impl CPU {
 	fn sei(&mut self) {
 		self.set_status(Flag::Irq, true);
 	}
+
+	// STA - Store accumulator in memory
+	// A -> M
+	fn sta(&mut self, address: u16) -> Result<()> {
+		let a = self.registers.a;
+		self.store(address, a)
+	}
+
+	// STX - Store X in memory
+	// X -> M
+	fn stx(&mut self, address: u16) -> Result<()> {
+		let x = self.registers.x;
+		self.store(address, x)
+	}
+
+	// STY - Store Y in memory
+	// Y -> M
+	fn sty(&mut self, address: u16) -> Result<()> {
+		let y = self.registers.y;
+		self.store(address, y)
+	}
+
+	// TAX - Transfer accumulator to X
+	// A -> X
+	fn tax(&mut self) {
+		let new_x = self.registers.a;
+		self.registers.x = new_x;
+		self.set_zn(new_x);
+	}
+
+	// TAY - Transfer accumulator to Y
+	// A -> Y
+	fn tay(&mut self) {
+		let new_y = self.registers.a;
+		self.registers.y = new_y;
+		self.set_zn(new_y);
+	}
+
+	// TSX - Transfer stack pointer to X
+	// S -> X
+	fn tsx(&mut self) {
+		let new_x = self.registers.s;
+		self.registers.x = new_x;
+		self.set_zn(new_x);
+	}
+
+	// TXA - Transfer X to accumulator
+	// X -> A
+	fn txa(&mut self) {
+		let new_a = self.registers.x;
+		self.registers.a = new_a;
+		self.set_zn(new_a);
+	}
+
+	// TXS - Transfer X to stack pointer
+	// X -> S (Flags are not affected)
+	fn txs(&mut self) {
+		self.registers.s = self.registers.x;
+	}
+
+	// TYA - Transfer Y to accumulator
+	// Y -> A
+	fn tya(&mut self) {
+		let new_a = self.registers.y;
+		self.registers.a = new_a;
+		self.set_zn(new_a);
+	}
+
+	// 65C02 instructions
+
+	// BIT - Test bits in memory with accumulator, immediate mode only changes Z
+	// A & M
+	fn biti(&mut self, address: u16) -> Result<()> {
+		let value = self.load(address)?;
+		let a = self.registers.a;
+		self.set_status(Flag::Zero, a & value == 0);
+		Ok(())
+	}
+
+	// BRA - Branch always
+	fn bra(&mut self) -> Result<()> {
+		self.branch(true)
+	}
+
+	// DEC - Decrement accumulator by one
+	// A - 1 -> A
+	fn deca(&mut self) {
+		let new_a = self.registers.a.wrapping_sub(1);
+		self.set_zn(new_a);
+		self.registers.a = new_a;
+	}
+
+	// INC - Increment accumulator by one
+	// A + 1 -> A
+	fn inca(&mut self) {
+		let new_a = self.registers.a.wrapping_add(1);
+		self.set_zn(new_a);
+		self.registers.a = new_a;
+	}
+
+	// JMP - Jump through a pointer indexed by X
+	// (PC + 1, PC + 2) + X -> pointer, (pointer) -> PC
+	fn jmpx(&mut self) -> Result<()> {
+		let base = self.loadw_pc()?;
+		let pointer = base.wrapping_add(self.registers.x as u16);
+		let value = self.loadw(pointer)?;
+		self.registers.pc = self.effective(value);
+		Ok(())
+	}
+
+	// PHX - Push index X on stack
+	// X toS
+	fn phx(&mut self) -> Result<()> {
+		let x = self.registers.x;
+		self.push(x)
+	}
+
+	// PHY - Push index Y on stack
+	// Y toS
+	fn phy(&mut self) -> Result<()> {
+		let y = self.registers.y;
+		self.push(y)
+	}
+
+	// PLX - Pull index X from stack
+	// X fromS
+	fn plx(&mut self) -> Result<()> {
+		let new_x = self.pull()?;
+		self.registers.x = new_x;
+		self.set_zn(new_x);
+		Ok(())
+	}
+
+	// PLY - Pull index Y from stack
+	// Y fromS
+	fn ply(&mut self) -> Result<()> {
+		let new_y = self.pull()?;
+		self.registers.y = new_y;
+		self.set_zn(new_y);
+		Ok(())
+	}
+
+	// STZ - Store zero in memory
+	// 0 -> M
+	fn stz(&mut self, address: u16) -> Result<()> {
+		self.store(address, 0)
+	}
+
+	// TRB - Test and reset memory bits with accumulator
+	// A & M -> Z, !A & M -> M
+	fn trb(&mut self, address: u16) -> Result<()> {
+		let value = self.load(address)?;
+		let a = self.registers.a;
+		self.set_status(Flag::Zero, a & value == 0);
+		self.store(address, value & !a)
+	}
+
+	// TSB - Test and set memory bits with accumulator
+	// A & M -> Z, A | M -> M
+	fn tsb(&mut self, address: u16) -> Result<()> {
+		let value = self.load(address)?;
+		let a = self.registers.a;
+		self.set_status(Flag::Zero, a & value == 0);
+		self.store(address, value | a)
+	}
+
+	// Unofficial instructions
+
+	// Unstable instructions are only run if the policy allows it
+	fn check_unstable(&self) -> Result<()> {
+		match self.unstable_policy {
+			UnstablePolicy::Reject => Err(EmuError::IllegalOpcode {
+				opcode: self.load(self.registers.pc.wrapping_sub(1))?,
+				address: self.registers.pc.wrapping_sub(1)
+			}),
+			UnstablePolicy::Emulate { .. } => Ok(())
+		}
+	}
+
+	fn magic(&self) -> u8 {
+		match self.unstable_policy {
+			UnstablePolicy::Emulate { magic } => magic,
+			UnstablePolicy::Reject => 0xFF
+		}
+	}
+
+	// NOP - No Operation, but still read memory
+	// Indexed reads pay for crossing a page like any other read
+	fn nopr(&mut self, address: u16) -> Result<()> {
+		self.load_operand(address)?;
+		Ok(())
+	}
+
+	// LAX - Load accumulator and X with memory
+	// M -> A, X
+	fn lax(&mut self, address: u16) -> Result<()> {
+		let value = self.load_operand(address)?;
+		self.registers.a = value;
+		self.registers.x = value;
+		self.set_zn(value);
+		Ok(())
+	}
+
+	// SAX - Store accumulator AND X in memory
+	// A & X -> M
+	fn sax(&mut self, address: u16) -> Result<()> {
+		let value = self.registers.a & self.registers.x;
+		self.store(address, value)
+	}
+
+	// DCP - Decrement memory by one then compare with accumulator
+	// M - 1 -> M, A - M
+	fn dcp(&mut self, address: u16) -> Result<()> {
+		let old = self.load(address)?;
+		let value = old.wrapping_sub(1);
+		self.store_modified(address, old, value)?;
+		let a = self.registers.a;
+		self.compare(a, value);
+		Ok(())
+	}
+
+	// ISC - Increment memory by one then subtract from accumulator with borrow
+	// M + 1 -> M, A - M - C -> A
+	fn isc(&mut self, address: u16) -> Result<()> {
+		let old = self.load(address)?;
+		let value = old.wrapping_add(1);
+		self.store_modified(address, old, value)?;
+		self.subtract(value);
+		Ok(())
+	}
+
+	// SLO - Shift memory left one bit then OR with accumulator
+	// M << 1 -> M, A | M -> A
+	fn slo(&mut self, address: u16) -> Result<()> {
+		let old = self.load(address)?;
+		let value = self.shift_left(old);
+		self.store_modified(address, old, value)?;
+		let new_a = self.registers.a | value;
+		self.set_zn(new_a);
+		self.registers.a = new_a;
+		Ok(())
+	}
+
+	// RLA - Rotate memory one bit left then AND with accumulator
+	// M << 1 | C -> M, A & M -> A
+	fn rla(&mut self, address: u16) -> Result<()> {
+		let old = self.load(address)?;
+		let value = self.rotate_left(old);
+		self.store_modified(address, old, value)?;
+		let new_a = self.registers.a & value;
+		self.set_zn(new_a);
+		self.registers.a = new_a;
+		Ok(())
+	}
+
+	// SRE - Shift memory right one bit then exclusive OR with accumulator
+	// M >> 1 -> M, A ^ M -> A
+	fn sre(&mut self, address: u16) -> Result<()> {
+		let old = self.load(address)?;
+		let value = self.shift_right(old);
+		self.store_modified(address, old, value)?;
+		let new_a = self.registers.a ^ value;
+		self.set_zn(new_a);
+		self.registers.a = new_a;
+		Ok(())
+	}
+
+	// RRA - Rotate memory one bit right then add to accumulator with carry
+	// C << 7 | M >> 1 -> M, A + M + C -> A
+	fn rra(&mut self, address: u16) -> Result<()> {
+		let old = self.load(address)?;
+		let value = self.rotate_right(old);
+		self.store_modified(address, old, value)?;
+		self.add(value);
+		Ok(())
+	}
+
+	// ANC - AND memory with accumulator, then copy the negative flag to carry
+	// A & M -> A, N -> C
+	fn anc(&mut self, address: u16) -> Result<()> {
+		self.and(address)?;
+		let negative = self.get_status(Flag::Negative);
+		self.set_status(Flag::Carry, negative);
+		Ok(())
+	}
+
+	// ALR - AND memory with accumulator then shift accumulator right one bit
+	// (A & M) >> 1 -> A
+	fn alr(&mut self, address: u16) -> Result<()> {
+		let value = self.registers.a & self.load(address)?;
+		self.registers.a = self.shift_right(value);
+		Ok(())
+	}
+
+	// ARR - AND memory with accumulator then rotate accumulator right one bit
+	// C << 7 | (A & M) >> 1 -> A, A6 -> C, A6 ^ A5 -> V
+	fn arr(&mut self, address: u16) -> Result<()> {
+		let value = self.registers.a & self.load(address)?;
+		let new_a = self.rotate_right(value);
+		self.set_status(Flag::Carry, new_a & 0x40 != 0);
+		self.set_status(Flag::Overflow, ((new_a >> 6) ^ (new_a >> 5)) & 0x1 != 0);
+		self.registers.a = new_a;
+		Ok(())
+	}
+
+	// AXS - Subtract memory from accumulator AND X, without borrow
+	// (A & X) - M -> X
+	fn axs(&mut self, address: u16) -> Result<()> {
+		let a = self.registers.a & self.registers.x;
+		let value = self.load(address)?;
+		self.compare(a, value);
+		self.registers.x = a.wrapping_sub(value);
+		Ok(())
+	}
+
+	// Shared by SHA, SHX, SHY and TAS. The value is ANDed with the high byte of the base address
+	// plus one, and when indexing crosses a page that value also replaces the high byte of the
+	// address written to.
+	fn store_high_and(&mut self, address: u16, index: u8, value: u8) -> Result<()> {
+		let base = address.wrapping_sub(index as u16);
+		let value = value & ((base >> 8) as u8).wrapping_add(1);
+		let address = if self.page_crossed {
+			(value as u16) << 8 | (address & 0xFF)
+		} else {
+			address
+		};
+		self.store(address, value)
+	}
+
+	// SHA - Store accumulator AND X AND high address byte + 1
+	// A & X & (H + 1) -> M
+	fn sha(&mut self, address: u16) -> Result<()> {
+		let (value, y) = (self.registers.a & self.registers.x, self.registers.y);
+		self.store_high_and(address, y, value)
+	}
+
+	// SHX - Store X AND high address byte + 1
+	// X & (H + 1) -> M
+	fn shx(&mut self, address: u16) -> Result<()> {
+		let (value, y) = (self.registers.x, self.registers.y);
+		self.store_high_and(address, y, value)
+	}
+
+	// SHY - Store Y AND high address byte + 1
+	// Y & (H + 1) -> M
+	fn shy(&mut self, address: u16) -> Result<()> {
+		let (value, x) = (self.registers.y, self.registers.x);
+		self.store_high_and(address, x, value)
+	}
+
+	// TAS - Transfer accumulator AND X to the stack pointer, then store it like SHA
+	// A & X -> S, S & (H + 1) -> M
+	fn tas(&mut self, address: u16) -> Result<()> {
+		let s = self.registers.a & self.registers.x;
+		self.registers.s = s;
+		let y = self.registers.y;
+		self.store_high_and(address, y, s)
+	}
+
+	// LAS - AND memory with the stack pointer, then load accumulator, X and stack pointer
+	// M & S -> A, X, S
+	fn las(&mut self, address: u16) -> Result<()> {
+		let value = self.load_operand(address)? & self.registers.s;
+		self.registers.a = value;
+		self.registers.x = value;
+		self.registers.s = value;
+		self.set_zn(value);
+		Ok(())
+	}
+
+	// XAA - Transfer X to accumulator then AND with memory, mixed with the magic constant
+	// (A | magic) & X & M -> A
+	fn xaa(&mut self, address: u16) -> Result<()> {
+		let new_a = (self.registers.a | self.magic()) & self.registers.x & self.load(address)?;
+		self.set_zn(new_a);
+		self.registers.a = new_a;
+		Ok(())
+	}
+
+	// LXA - Load accumulator and X with memory, mixed with the magic constant
+	// (A | magic) & M -> A, X
+	fn lxa(&mut self, address: u16) -> Result<()> {
+		let value = (self.registers.a | self.magic()) & self.load(address)?;
+		self.registers.a = value;
+		self.registers.x = value;
+		self.set_zn(value);
+		Ok(())
+	}
+
+	// KIL - Halt the CPU
+	// PC is left on the opcode, so the CPU stays halted until it's reset
+	fn kil(&mut self) -> Result<()> {
+		let address = self.registers.pc.wrapping_sub(1);
+		let opcode = self.load(address)?;
+		self.registers.pc = address;
+		Err(EmuError::Halted { opcode, address })
+	}
 }
 
 impl fmt::Display for CPU {
@@ -802,42 +2008,1040 @@ impl fmt::Display for CPU {
 //
 // CPU Memory (2 Byte addressing)
 //
-//   0x0000 -> 0x7FFF : 2KB RAM
+//   0x0000 -> 0x07FF : 2KB RAM
 //   0x0800 -> 0x1FFF : Mirrored sections of RAM
 //   0x2000 -> 0x2007 : PPU Registers
 //   0x2008 -> 0x3FFF : Mirrored sections of PPU Registers
-//   0x4000 -> 0x401F : APU and IO Registers
+//   0x4000 -> 0x4013 : APU Registers
+//   0x4014           : OAM DMA
+//   0x4015           : APU Status
+//   0x4016 -> 0x4017 : Controllers, and the APU Frame Counter when 0x4017 is written
+//   0x4018 -> 0x401F : Normally disabled APU and IO test registers
 //   0x4020 -> 0xFFFF : Cartirdge Space
 //
 
 impl Memory for CPU {
-	fn load(&self, address: u16) -> u8 {
-		println!("CPU Load: {:#X}", address);
+	fn load(&self, address: u16) -> Result<u8> {
 		match address {
-			0x0000 ... 0x1FFF => self.ram.load(address & 0x7FF),
-			0x2000 ... 0x2007 => unimplemented!(),
-			0x2008 ... 0x3FFF => unimplemented!(),
-			0x4000 ... 0x401F => unimplemented!(),
-			0x4020 ... 0xFFFF => {
-				println!("Accessing Cartridge");
-				return self.cartridge.load(address);
-			},
-			_ => unreachable!()
+			0x0000 ..= 0x1FFF => self.ram.load(address & 0x7FF),
+			0x2000 ..= 0x3FFF => Err(EmuError::UnmappedRegister { address, access: Access::Read }),
+			0x4015 => Ok(self.apu.read_status()),
+			0x4000 ..= 0x401F => Err(EmuError::UnmappedRegister { address, access: Access::Read }),
+			0x4020 ..= 0xFFFF => self.cartridge.borrow().cpu_read(address)
 		}
 	}
 
-	fn store(&mut self, address: u16, value: u8) {
-		println!("CPU Store: {:#X} = {:#X}", address, value);
+	fn store(&mut self, address: u16, value: u8) -> Result<()> {
 		match address {
-			0x0000 ... 0x07FF => self.ram.store(address & 0x7FF, value),
-			0x2000 ... 0x2007 => unimplemented!(),
-			0x2008 ... 0x3FFF => unimplemented!(),
-			0x4000 ... 0x401F => unimplemented!(),
-			0x4020 ... 0xFFFF => {
-				println!("Accessing Cartridge");
-				return self.cartridge.store(address, value);
+			0x0000 ..= 0x1FFF => self.ram.store(address & 0x7FF, value),
+			0x4000 ..= 0x4013 | 0x4015 | 0x4017 => {
+				self.cartridge.borrow_mut().snoop_write(address, value);
+				self.apu.write(address, value);
+				Ok(())
 			},
-			_ => unreachable!()
+			0x2000 ..= 0x401F => {
+				self.cartridge.borrow_mut().snoop_write(address, value);
+				Err(EmuError::UnmappedRegister { address, access: Access::Write })
+			},
+			0x4020 ..= 0xFFFF => self.cartridge.borrow_mut().cpu_write(address, value)
 		}
 	}
 }
+
+/// Flat 64KB cartridge used to feed programs to the CPU in tests
+#[cfg(test)]
+pub struct TestCartridge {
+	pub data: Vec<u8>,
+	pub irq: bool,
+	pub writes: Vec<(u16, u8)>
+}
+
+#[cfg(test)]
+impl TestCartridge {
+	pub fn new(data: Vec<u8>) -> Rc<RefCell<TestCartridge>> {
+		Rc::new(RefCell::new(TestCartridge { data, irq: false, writes: Vec::new() }))
+	}
+}
+
+#[cfg(test)]
+impl Mapper for TestCartridge {
+	fn cpu_read(&self, address: u16) -> Result<u8> {
+		Ok(self.data[address as usize])
+	}
+
+	fn cpu_write(&mut self, address: u16, value: u8) -> Result<()> {
+		self.data[address as usize] = value;
+		self.writes.push((address, value));
+		Ok(())
+	}
+
+	fn ppu_read(&mut self, address: u16) -> Result<u8> {
+		Err(EmuError::BusFault { address, access: Access::Read })
+	}
+
+	fn ppu_write(&mut self, address: u16, _value: u8) -> Result<()> {
+		Err(EmuError::BusFault { address, access: Access::Write })
+	}
+
+	fn mirroring(&self) -> Mirroring {
+		Mirroring::Horizontal
+	}
+
+	fn irq(&self) -> bool {
+		self.irq
+	}
+}
+
+/// Build a CPU with the program loaded at 0x8000 and the reset vector pointing to it
+#[cfg(test)]
+fn test_cpu(program: &[u8]) -> CPU {
+	test_cpu_variant(program, CpuVariant::Ricoh2A03)
+}
+
+#[cfg(test)]
+fn test_cpu_variant(program: &[u8], variant: CpuVariant) -> CPU {
+	let mut data = vec![0; 0x10000];
+	data[0x8000..0x8000 + program.len()].copy_from_slice(program);
+	data[RESET_VECTOR as usize] = 0x00;
+	data[RESET_VECTOR as usize + 1] = 0x80;
+	data[IRQ_VECTOR as usize] = 0x00;
+	data[IRQ_VECTOR as usize + 1] = 0x90;
+	data[NMI_VECTOR as usize] = 0x00;
+	data[NMI_VECTOR as usize + 1] = 0x91;
+
+	let mut cpu = CPU::new(TestCartridge::new(data), variant);
+	cpu.power_up().unwrap();
+	cpu
+}
+
+/// Execute the next n instructions
+#[cfg(test)]
+fn run_instructions(cpu: &mut CPU, n: usize) {
+	for _ in 0..n {
+		cpu.step().unwrap();
+	}
+}
+
+#[test]
+fn test_load_instructions() {
+	// LDA #$80, LDX $10, LDY $0F,X
+	let mut cpu = test_cpu(&[0xA9, 0x80, 0xA6, 0x10, 0xB4, 0x0F]);
+	cpu.store(0x10, 0x01).unwrap();
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.registers.a, 0x80);
+	assert!(cpu.get_status(Flag::Negative));
+	assert!(!cpu.get_status(Flag::Zero));
+
+	run_instructions(&mut cpu, 2);
+	assert_eq!(cpu.registers.x, 0x01);
+	assert_eq!(cpu.registers.y, 0x01);
+	assert!(!cpu.get_status(Flag::Negative));
+}
+
+#[test]
+fn test_load_indexed_modes() {
+	// LDX #$02, LDA $0200,X, LDY #$03, LDX $0200,Y, LDA ($10),Y, LDA ($0E,X)
+	let mut cpu = test_cpu(&[0xA2, 0x02, 0xBD, 0x00, 0x02, 0xA0, 0x03, 0xBE, 0x00, 0x02,
+	                         0xB1, 0x10, 0xA1, 0x0E]);
+	cpu.store(0x0202, 0x22).unwrap();
+	cpu.store(0x0203, 0x33).unwrap();
+	cpu.store(0x0303, 0x44).unwrap();
+	cpu.storew(0x10, 0x0300).unwrap();
+	cpu.storew(0x13, 0x0202).unwrap();
+
+	run_instructions(&mut cpu, 2);
+	assert_eq!(cpu.registers.a, 0x22);
+	run_instructions(&mut cpu, 2);
+	assert_eq!(cpu.registers.x, 0x33);
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.registers.a, 0x44);
+	// Pointer comes from 0x0E + X = 0x13
+	cpu.registers.x = 0x05;
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.registers.a, 0x22);
+}
+
+#[test]
+fn test_zero_page_wrapping() {
+	// LDX #$FF, LDA $02,X, LDA ($FF),Y
+	let mut cpu = test_cpu(&[0xA2, 0xFF, 0xB5, 0x02, 0xB1, 0xFF]);
+	cpu.store(0x01, 0x11).unwrap();
+	cpu.store(0xFF, 0x00).unwrap();
+	cpu.store(0x00, 0x03).unwrap();
+	cpu.store(0x0300, 0x55).unwrap();
+
+	run_instructions(&mut cpu, 2);
+	assert_eq!(cpu.registers.a, 0x11);
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.registers.a, 0x55);
+}
+
+#[test]
+fn test_store_instructions() {
+	// LDA #$12, LDX #$34, LDY #$56, STA $20, STX $0300, STY $21,X
+	let mut cpu = test_cpu(&[0xA9, 0x12, 0xA2, 0x34, 0xA0, 0x56, 0x85, 0x20, 0x8E, 0x00, 0x03,
+	                         0x94, 0x21]);
+	run_instructions(&mut cpu, 6);
+	assert_eq!(cpu.load(0x20).unwrap(), 0x12);
+	assert_eq!(cpu.load(0x0300).unwrap(), 0x34);
+	assert_eq!(cpu.load(0x55).unwrap(), 0x56);
+}
+
+#[test]
+fn test_store_indirect() {
+	// LDA #$AB, LDY #$01, STA ($10),Y, LDX #$02, STA ($0E,X), STX $30,Y
+	let mut cpu = test_cpu(&[0xA9, 0xAB, 0xA0, 0x01, 0x91, 0x10, 0xA2, 0x02, 0x81, 0x0E, 0x96,
+	                         0x30]);
+	cpu.storew(0x10, 0x0400).unwrap();
+	run_instructions(&mut cpu, 3);
+	assert_eq!(cpu.load(0x0401).unwrap(), 0xAB);
+	run_instructions(&mut cpu, 2);
+	assert_eq!(cpu.load(0x0400).unwrap(), 0xAB);
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.load(0x31).unwrap(), 0x02);
+}
+
+#[test]
+fn test_ram_mirroring() {
+	// LDA #$42, STA $0805, LDX $1805
+	let mut cpu = test_cpu(&[0xA9, 0x42, 0x8D, 0x05, 0x08, 0xAE, 0x05, 0x18]);
+	run_instructions(&mut cpu, 3);
+	assert_eq!(cpu.load(0x0005).unwrap(), 0x42);
+	assert_eq!(cpu.registers.x, 0x42);
+}
+
+#[test]
+fn test_transfer_instructions() {
+	// LDA #$00, TAX, TAY, LDX #$80, TXA, TXS, LDY #$01, TYA, TSX
+	let mut cpu = test_cpu(&[0xA9, 0x00, 0xAA, 0xA8, 0xA2, 0x80, 0x8A, 0x9A, 0xA0, 0x01, 0x98,
+	                         0xBA]);
+	cpu.registers.x = 0xFF;
+	cpu.registers.y = 0xFF;
+	run_instructions(&mut cpu, 3);
+	assert_eq!(cpu.registers.x, 0x00);
+	assert_eq!(cpu.registers.y, 0x00);
+	assert!(cpu.get_status(Flag::Zero));
+
+	run_instructions(&mut cpu, 2);
+	assert_eq!(cpu.registers.a, 0x80);
+	assert!(cpu.get_status(Flag::Negative));
+
+	// TXS doesn't touch flags
+	cpu.set_status(Flag::Zero, true);
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.registers.s, 0x80);
+	assert!(cpu.get_status(Flag::Zero));
+
+	run_instructions(&mut cpu, 2);
+	assert_eq!(cpu.registers.a, 0x01);
+	assert!(!cpu.get_status(Flag::Negative));
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.registers.x, 0x80);
+	assert!(cpu.get_status(Flag::Negative));
+}
+
+#[test]
+fn test_stack_instructions() {
+	// LDA #$7F, PHA, LDA #$00, PLA, PHP, PLP
+	let mut cpu = test_cpu(&[0xA9, 0x7F, 0x48, 0xA9, 0x00, 0x68, 0x08, 0x28]);
+	run_instructions(&mut cpu, 2);
+	assert_eq!(cpu.registers.s, 0xFC);
+	assert_eq!(cpu.load(0x01FD).unwrap(), 0x7F);
+
+	run_instructions(&mut cpu, 2);
+	assert_eq!(cpu.registers.a, 0x7F);
+	assert_eq!(cpu.registers.s, 0xFD);
+	assert!(!cpu.get_status(Flag::Zero));
+
+	// PHP pushes B and the unused bit, PLP discards B
+	cpu.registers.status = 0xC3;
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.load(0x01FD).unwrap(), 0xF3);
+	cpu.registers.status = 0x00;
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.registers.status, 0xE3);
+}
+
+#[test]
+fn test_adc() {
+	// CLC, LDA #$50, ADC #$50, ADC #$60, SEC, ADC #$00
+	let mut cpu = test_cpu(&[0x18, 0xA9, 0x50, 0x69, 0x50, 0x69, 0x60, 0x38, 0x69, 0x00]);
+	run_instructions(&mut cpu, 3);
+	assert_eq!(cpu.registers.a, 0xA0);
+	assert!(cpu.get_status(Flag::Overflow));
+	assert!(cpu.get_status(Flag::Negative));
+	assert!(!cpu.get_status(Flag::Carry));
+
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.registers.a, 0x00);
+	assert!(cpu.get_status(Flag::Carry));
+	assert!(cpu.get_status(Flag::Zero));
+	assert!(!cpu.get_status(Flag::Overflow));
+
+	run_instructions(&mut cpu, 2);
+	assert_eq!(cpu.registers.a, 0x01);
+	assert!(!cpu.get_status(Flag::Carry));
+}
+
+#[test]
+fn test_sbc() {
+	// SEC, LDA #$50, SBC #$F0, SBC #$B0, CLC, SBC #$00
+	let mut cpu = test_cpu(&[0x38, 0xA9, 0x50, 0xE9, 0xF0, 0xE9, 0xB0, 0x18, 0xE9, 0x00]);
+	run_instructions(&mut cpu, 3);
+	assert_eq!(cpu.registers.a, 0x60);
+	assert!(!cpu.get_status(Flag::Carry));
+	assert!(!cpu.get_status(Flag::Overflow));
+
+	// Borrow from the previous subtraction is applied
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.registers.a, 0xAF);
+	assert!(cpu.get_status(Flag::Overflow));
+	assert!(cpu.get_status(Flag::Negative));
+
+	run_instructions(&mut cpu, 2);
+	assert_eq!(cpu.registers.a, 0xAE);
+	assert!(cpu.get_status(Flag::Carry));
+}
+
+#[test]
+fn test_decimal_mode() {
+	// SED, CLC, LDA #$58, ADC #$46, SEC, LDA #$12, SBC #$21
+	let program = [0xF8, 0x18, 0xA9, 0x58, 0x69, 0x46, 0x38, 0xA9, 0x12, 0xE9, 0x21];
+	let mut cpu = test_cpu_variant(&program, CpuVariant::Nmos6502);
+	run_instructions(&mut cpu, 4);
+	assert_eq!(cpu.registers.a, 0x04);
+	assert!(cpu.get_status(Flag::Carry));
+	run_instructions(&mut cpu, 3);
+	assert_eq!(cpu.registers.a, 0x91);
+	assert!(!cpu.get_status(Flag::Carry));
+
+	// The 65C02 sets Z and N from the BCD result, and takes an extra cycle
+	let mut cpu = test_cpu_variant(&program, CpuVariant::Cmos65C02);
+	run_instructions(&mut cpu, 3);
+	let record = cpu.step().unwrap();
+	assert_eq!(cpu.registers.a, 0x04);
+	assert!(!cpu.get_status(Flag::Zero));
+	assert_eq!(record.cycles, 3);
+	run_instructions(&mut cpu, 3);
+	assert_eq!(cpu.registers.a, 0x91);
+	assert!(cpu.get_status(Flag::Negative));
+
+	// The 2A03 ignores the decimal flag
+	let mut cpu = test_cpu(&program);
+	run_instructions(&mut cpu, 4);
+	assert_eq!(cpu.registers.a, 0x9E);
+	assert!(cpu.get_status(Flag::Decimal));
+}
+
+#[test]
+fn test_logical_instructions() {
+	// LDA #$F0, AND #$3C, ORA #$03, EOR #$FF, BIT $10
+	let mut cpu = test_cpu(&[0xA9, 0xF0, 0x29, 0x3C, 0x09, 0x03, 0x49, 0xFF, 0x24, 0x10]);
+	cpu.store(0x10, 0xC0).unwrap();
+	run_instructions(&mut cpu, 2);
+	assert_eq!(cpu.registers.a, 0x30);
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.registers.a, 0x33);
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.registers.a, 0xCC);
+	assert!(cpu.get_status(Flag::Negative));
+
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.registers.a, 0xCC);
+	assert!(!cpu.get_status(Flag::Zero));
+	assert!(cpu.get_status(Flag::Negative));
+	assert!(cpu.get_status(Flag::Overflow));
+}
+
+#[test]
+fn test_shift_instructions() {
+	// LDA #$81, ASL A, LSR A, LSR A, ASL $10, LSR $11
+	let mut cpu = test_cpu(&[0xA9, 0x81, 0x0A, 0x4A, 0x4A, 0x06, 0x10, 0x46, 0x11]);
+	cpu.store(0x10, 0x40).unwrap();
+	cpu.store(0x11, 0x01).unwrap();
+	run_instructions(&mut cpu, 2);
+	assert_eq!(cpu.registers.a, 0x02);
+	assert!(cpu.get_status(Flag::Carry));
+
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.registers.a, 0x01);
+	assert!(!cpu.get_status(Flag::Carry));
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.registers.a, 0x00);
+	assert!(cpu.get_status(Flag::Carry));
+	assert!(cpu.get_status(Flag::Zero));
+
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.load(0x10).unwrap(), 0x80);
+	assert!(cpu.get_status(Flag::Negative));
+	assert!(!cpu.get_status(Flag::Carry));
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.load(0x11).unwrap(), 0x00);
+	assert!(cpu.get_status(Flag::Carry));
+	assert!(cpu.get_status(Flag::Zero));
+}
+
+#[test]
+fn test_rotate_instructions() {
+	// SEC, LDA #$80, ROL A, ROL A, ROR A, ROR A, ROL $10, ROR $11
+	let mut cpu = test_cpu(&[0x38, 0xA9, 0x80, 0x2A, 0x2A, 0x6A, 0x6A, 0x26, 0x10, 0x66, 0x11]);
+	cpu.store(0x10, 0x80).unwrap();
+	cpu.store(0x11, 0x01).unwrap();
+	run_instructions(&mut cpu, 3);
+	assert_eq!(cpu.registers.a, 0x01);
+	assert!(cpu.get_status(Flag::Carry));
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.registers.a, 0x03);
+	assert!(!cpu.get_status(Flag::Carry));
+
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.registers.a, 0x01);
+	assert!(cpu.get_status(Flag::Carry));
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.registers.a, 0x80);
+	assert!(cpu.get_status(Flag::Carry));
+	assert!(cpu.get_status(Flag::Negative));
+
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.load(0x10).unwrap(), 0x01);
+	assert!(cpu.get_status(Flag::Carry));
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.load(0x11).unwrap(), 0x80);
+	assert!(cpu.get_status(Flag::Carry));
+}
+
+#[test]
+fn test_increment_decrement_instructions() {
+	// INC $10, DEC $11, INX, INY, DEX, DEY, DEY
+	let mut cpu = test_cpu(&[0xE6, 0x10, 0xC6, 0x11, 0xE8, 0xC8, 0xCA, 0x88, 0x88]);
+	cpu.store(0x10, 0xFF).unwrap();
+	cpu.store(0x11, 0x00).unwrap();
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.load(0x10).unwrap(), 0x00);
+	assert!(cpu.get_status(Flag::Zero));
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.load(0x11).unwrap(), 0xFF);
+	assert!(cpu.get_status(Flag::Negative));
+
+	run_instructions(&mut cpu, 2);
+	assert_eq!(cpu.registers.x, 0x01);
+	assert_eq!(cpu.registers.y, 0x01);
+	run_instructions(&mut cpu, 2);
+	assert_eq!(cpu.registers.x, 0x00);
+	assert_eq!(cpu.registers.y, 0x00);
+	assert!(cpu.get_status(Flag::Zero));
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.registers.y, 0xFF);
+	assert!(cpu.get_status(Flag::Negative));
+}
+
+#[test]
+fn test_compare_instructions() {
+	// LDA #$40, CMP #$40, CMP #$41, LDX #$10, CPX #$01, LDY #$00, CPY #$01
+	let mut cpu = test_cpu(&[0xA9, 0x40, 0xC9, 0x40, 0xC9, 0x41, 0xA2, 0x10, 0xE0, 0x01, 0xA0,
+	                         0x00, 0xC0, 0x01]);
+	run_instructions(&mut cpu, 2);
+	assert!(cpu.get_status(Flag::Zero));
+	assert!(cpu.get_status(Flag::Carry));
+	run_instructions(&mut cpu, 1);
+	assert!(!cpu.get_status(Flag::Zero));
+	assert!(!cpu.get_status(Flag::Carry));
+	assert!(cpu.get_status(Flag::Negative));
+
+	run_instructions(&mut cpu, 2);
+	assert!(cpu.get_status(Flag::Carry));
+	assert!(!cpu.get_status(Flag::Negative));
+	run_instructions(&mut cpu, 2);
+	assert!(!cpu.get_status(Flag::Carry));
+	assert!(cpu.get_status(Flag::Negative));
+}
+
+#[test]
+fn test_branch_instructions() {
+	// LDX #$03, DEX, BNE -3, BEQ +2, NOP, NOP, BMI +0
+	let mut cpu = test_cpu(&[0xA2, 0x03, 0xCA, 0xD0, 0xFD, 0xF0, 0x02, 0xEA, 0xEA, 0x30, 0x00]);
+	run_instructions(&mut cpu, 7);
+	assert_eq!(cpu.registers.x, 0x00);
+	assert_eq!(cpu.registers.pc, 0x8005);
+
+	// Taken branch skips both NOPs
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.registers.pc, 0x8009);
+
+	// Branches that aren't taken still consume their operand
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.registers.pc, 0x800B);
+}
+
+#[test]
+fn test_flag_branches() {
+	// SEC, BCC +1, BCS +1, BRK, CLV, BVS +1, BVC +1, BRK, LDA #$01, BPL +1, BRK
+	let mut cpu = test_cpu(&[0x38, 0x90, 0x01, 0xB0, 0x01, 0x00, 0xB8, 0x70, 0x01, 0x50, 0x01,
+	                         0x00, 0xA9, 0x01, 0x10, 0x01, 0x00]);
+	run_instructions(&mut cpu, 3);
+	assert_eq!(cpu.registers.pc, 0x8006);
+	run_instructions(&mut cpu, 3);
+	assert_eq!(cpu.registers.pc, 0x800C);
+	run_instructions(&mut cpu, 2);
+	assert_eq!(cpu.registers.pc, 0x8011);
+}
+
+#[test]
+fn test_flag_instructions() {
+	// SEC, SED, SEI, CLC, CLD, CLI, CLV
+	let mut cpu = test_cpu(&[0x38, 0xF8, 0x78, 0x18, 0xD8, 0x58, 0xB8]);
+	cpu.registers.status = 0x00;
+	run_instructions(&mut cpu, 3);
+	assert!(cpu.get_status(Flag::Carry));
+	assert!(cpu.get_status(Flag::Decimal));
+	assert!(cpu.get_status(Flag::Irq));
+
+	cpu.set_status(Flag::Overflow, true);
+	run_instructions(&mut cpu, 4);
+	assert_eq!(cpu.registers.status, 0x00);
+}
+
+#[test]
+fn test_jump_instructions() {
+	// JMP $8005, NOP, NOP, JMP ($0210)
+	let mut cpu = test_cpu(&[0x4C, 0x05, 0x80, 0xEA, 0xEA, 0x6C, 0x10, 0x02]);
+	cpu.storew(0x0210, 0x1234).unwrap();
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.registers.pc, 0x8005);
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.registers.pc, 0x1234);
+}
+
+#[test]
+fn test_jump_indirect_page_wrap() {
+	// JMP ($02FF) reads the high byte from 0x0200, not 0x0300
+	let mut cpu = test_cpu(&[0x6C, 0xFF, 0x02]);
+	cpu.store(0x02FF, 0x34).unwrap();
+	cpu.store(0x0200, 0x12).unwrap();
+	cpu.store(0x0300, 0x56).unwrap();
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.registers.pc, 0x1234);
+}
+
+#[test]
+fn test_jump_indirect_65c02() {
+	// JMP ($02FF) reads the high byte from 0x0300 on the 65C02
+	let mut cpu = test_cpu_variant(&[0x6C, 0xFF, 0x02], CpuVariant::Cmos65C02);
+	cpu.store(0x02FF, 0x34).unwrap();
+	cpu.store(0x0200, 0x12).unwrap();
+	cpu.store(0x0300, 0x56).unwrap();
+	let record = cpu.step().unwrap();
+	assert_eq!(cpu.registers.pc, 0x5634);
+	assert_eq!(record.cycles, 6);
+}
+
+#[test]
+fn test_trace_jump_indirect() {
+	// The trace shows the target each variant actually jumps to
+	for &(variant, target) in [(CpuVariant::Ricoh2A03, "1234"), (CpuVariant::Cmos65C02, "5634")].iter() {
+		let mut cpu = test_cpu_variant(&[0x6C, 0xFF, 0x02], variant);
+		cpu.store(0x02FF, 0x34).unwrap();
+		cpu.store(0x0200, 0x12).unwrap();
+		cpu.store(0x0300, 0x56).unwrap();
+		assert!(format_line(&cpu).starts_with(&format!("8000  6C FF 02  JMP ($02FF) = {}", target)));
+	}
+}
+
+#[test]
+fn test_subroutine_instructions() {
+	// JSR $8004, BRK, LDA #$01, RTS
+	let mut cpu = test_cpu(&[0x20, 0x04, 0x80, 0x00, 0xA9, 0x01, 0x60]);
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.registers.pc, 0x8004);
+	assert_eq!(cpu.registers.s, 0xFB);
+	assert_eq!(cpu.load(0x01FD).unwrap(), 0x80);
+	assert_eq!(cpu.load(0x01FC).unwrap(), 0x02);
+
+	run_instructions(&mut cpu, 2);
+	assert_eq!(cpu.registers.a, 0x01);
+	assert_eq!(cpu.registers.pc, 0x8003);
+	assert_eq!(cpu.registers.s, 0xFD);
+}
+
+#[test]
+fn test_brk_and_rti() {
+	// BRK, (padding), NOP
+	let mut cpu = test_cpu(&[0x00, 0xFF, 0xEA]);
+	cpu.registers.status = 0x21;
+	// IRQ handler at 0x9000: RTI
+	cpu.cartridge.borrow_mut().cpu_write(0x9000, 0x40).unwrap();
+
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.registers.pc, 0x9000);
+	assert!(cpu.get_status(Flag::Irq));
+	assert_eq!(cpu.load(0x01FD).unwrap(), 0x80);
+	assert_eq!(cpu.load(0x01FC).unwrap(), 0x02);
+	assert_eq!(cpu.load(0x01FB).unwrap(), 0x31);
+
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.registers.pc, 0x8002);
+	assert_eq!(cpu.registers.status, 0x21);
+	assert_eq!(cpu.registers.s, 0xFD);
+}
+
+#[test]
+fn test_base_cycles() {
+	// LDA #$01, STA $10, INC $0200, JSR $800D, NOP, NOP, RTS
+	let mut cpu = test_cpu(&[0xA9, 0x01, 0x85, 0x10, 0xEE, 0x00, 0x02, 0x20, 0x0D, 0x80, 0xEA,
+	                         0xEA, 0xEA, 0x60]);
+	let expected = [2, 3, 6, 6, 6];
+	for &cycles in expected.iter() {
+		assert_eq!(cpu.step().unwrap().cycles, cycles);
+	}
+	assert_eq!(cpu.cycles(), RESET_CYCLES + 23);
+}
+
+#[test]
+fn test_page_cross_cycles() {
+	// LDA $02F0,X, LDA $02F0,X, STA $02F0,X, LDA ($10),Y, INC $02F0,X
+	let mut cpu = test_cpu(&[0xBD, 0xF0, 0x02, 0xBD, 0xF0, 0x02, 0x9D, 0xF0, 0x02, 0xB1, 0x10,
+	                         0xFE, 0xF0, 0x02]);
+	cpu.storew(0x10, 0x02FF).unwrap();
+
+	cpu.registers.x = 0x0F;
+	assert_eq!(cpu.step().unwrap().cycles, 4);
+
+	// Crossing into page 3 costs an extra cycle for reads only
+	cpu.registers.x = 0x10;
+	assert_eq!(cpu.step().unwrap().cycles, 5);
+	assert_eq!(cpu.step().unwrap().cycles, 5);
+
+	cpu.registers.y = 0x01;
+	assert_eq!(cpu.step().unwrap().cycles, 6);
+	assert_eq!(cpu.step().unwrap().cycles, 7);
+}
+
+#[test]
+fn test_branch_cycles() {
+	// CLC, BCS +0, BCC +0, BCC +0x7F (to 0x8086)
+	let mut cpu = test_cpu(&[0x18, 0xB0, 0x00, 0x90, 0x00, 0x90, 0x7F]);
+	run_instructions(&mut cpu, 1);
+
+	assert_eq!(cpu.step().unwrap().cycles, 2);
+	assert_eq!(cpu.step().unwrap().cycles, 3);
+	assert_eq!(cpu.step().unwrap().cycles, 3);
+
+	// Branch back from 0x8102 into the previous page
+	cpu.registers.pc = 0x8100;
+	cpu.cartridge.borrow_mut().cpu_write(0x8100, 0x90).unwrap();
+	cpu.cartridge.borrow_mut().cpu_write(0x8101, 0xF0).unwrap();
+	assert_eq!(cpu.step().unwrap().cycles, 4);
+	assert_eq!(cpu.registers.pc, 0x80F2);
+}
+
+#[test]
+fn test_step_record() {
+	// LDX #$01, STA $0200,X, JMP $8000
+	let mut cpu = test_cpu(&[0xA2, 0x01, 0x9D, 0x00, 0x02, 0x4C, 0x00, 0x80]);
+
+	let record = cpu.step().unwrap();
+	assert_eq!(record.pc, 0x8000);
+	assert_eq!(record.opcode, 0xA2);
+	assert_eq!(record.operands(), &[0x01]);
+	assert_eq!(record.address, None);
+	assert_eq!(record.cycles, 2);
+	assert_eq!(record.before.x, 0x00);
+	assert_eq!(record.after.x, 0x01);
+	assert_eq!(record.after.pc, 0x8002);
+
+	let record = cpu.step().unwrap();
+	assert_eq!(record.opcode, 0x9D);
+	assert_eq!(record.operands(), &[0x00, 0x02]);
+	assert_eq!(record.address, Some(0x0201));
+	assert_eq!(record.cycles, 5);
+
+	let record = cpu.step().unwrap();
+	assert_eq!(record.length, 3);
+	assert_eq!(record.address, Some(0x8000));
+	assert_eq!(record.after, cpu.registers());
+	assert_eq!(cpu.registers().pc, 0x8000);
+}
+
+#[test]
+fn test_run_for_cycles() {
+	// NOP, JMP $8000
+	let mut cpu = test_cpu(&[0xEA, 0x4C, 0x00, 0x80]);
+	assert_eq!(cpu.run_for_cycles(10).unwrap(), 10);
+	// Instructions aren't split, so this overshoots by one cycle
+	assert_eq!(cpu.run_for_cycles(4).unwrap(), 5);
+	assert_eq!(cpu.cycles(), RESET_CYCLES + 15);
+}
+
+#[test]
+fn test_run_until() {
+	// INX, BNE -3, NOP
+	let mut cpu = test_cpu(&[0xE8, 0xD0, 0xFD, 0xEA]);
+	let record = cpu.run_until(|record| record.opcode == 0xEA).unwrap();
+	assert_eq!(record.pc, 0x8003);
+	assert_eq!(record.before.x, 0x00);
+}
+
+#[test]
+fn test_65c02_instructions() {
+	// LDA #$0F, STZ $10, TSB $10, LDA #$03, TRB $10, INC A, PHA, PLX, LDA ($20), BRA +1, NOP, BIT #$00
+	let mut cpu = test_cpu_variant(&[
+		0xA9, 0x0F, 0x64, 0x10, 0x04, 0x10, 0xA9, 0x03, 0x14, 0x10, 0x1A, 0x48, 0xFA, 0xB2, 0x20,
+		0x80, 0x01, 0xEA, 0x89, 0x00
+	], CpuVariant::Cmos65C02);
+	cpu.store(0x10, 0xFF).unwrap();
+	cpu.storew(0x20, 0x0300).unwrap();
+	cpu.store(0x0300, 0x80).unwrap();
+
+	run_instructions(&mut cpu, 2);
+	assert_eq!(cpu.load(0x10).unwrap(), 0x00);
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.load(0x10).unwrap(), 0x0F);
+	assert!(cpu.get_status(Flag::Zero));
+	run_instructions(&mut cpu, 2);
+	assert_eq!(cpu.load(0x10).unwrap(), 0x0C);
+	assert!(!cpu.get_status(Flag::Zero));
+
+	run_instructions(&mut cpu, 3);
+	assert_eq!((cpu.registers.a, cpu.registers.x), (0x04, 0x04));
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cpu.registers.a, 0x80);
+
+	let record = cpu.step().unwrap();
+	assert_eq!(cpu.registers.pc, 0x8012);
+	assert_eq!(record.cycles, 3);
+
+	// BIT #imm leaves N alone
+	run_instructions(&mut cpu, 1);
+	assert!(cpu.get_status(Flag::Zero));
+	assert!(cpu.get_status(Flag::Negative));
+}
+
+#[test]
+fn test_65c02_nops() {
+	// Opcodes that were unofficial on the NMOS 6502 are NOPs of different lengths
+	let mut cpu = test_cpu_variant(&[0x03, 0x02, 0xFF, 0x5C, 0x00, 0x00, 0xAB], CpuVariant::Cmos65C02);
+	let cycles: Vec<(u16, u32)> = (0..4).map(|_| {
+		let record = cpu.step().unwrap();
+		(record.pc, record.cycles)
+	}).collect();
+	assert_eq!(cycles, vec![(0x8000, 1), (0x8001, 2), (0x8003, 8), (0x8006, 1)]);
+	assert_eq!(cpu.registers.a, 0x00);
+}
+
+#[test]
+fn test_illegal_opcode() {
+	// NOP, XAA #$FF
+	let mut cpu = test_cpu(&[0xEA, 0x8B, 0xFF]);
+	cpu.set_unstable_policy(UnstablePolicy::Reject);
+	cpu.step().unwrap();
+	match cpu.step() {
+		Err(EmuError::IllegalOpcode { opcode: 0x8B, address: 0x8001 }) => (),
+		other => panic!("Expected an illegal opcode, got {:?}", other)
+	}
+}
+
+#[test]
+fn test_kil() {
+	// NOP, KIL
+	let mut cpu = test_cpu(&[0xEA, 0x02]);
+	cpu.step().unwrap();
+	for _ in 0..2 {
+		match cpu.step() {
+			Err(EmuError::Halted { opcode: 0x02, address: 0x8001 }) => (),
+			other => panic!("Expected a halt, got {:?}", other)
+		}
+	}
+	assert_eq!(cpu.registers.pc, 0x8001);
+}
+
+#[test]
+fn test_unofficial_load_store() {
+	// LDA #$F0, LDX #$3C, SAX $10, LAX $10, NOP $10FF,X
+	let mut cpu = test_cpu(&[0xA9, 0xF0, 0xA2, 0x3C, 0x87, 0x10, 0xA7, 0x10, 0x1C, 0xFF, 0x10]);
+	run_instructions(&mut cpu, 3);
+	assert_eq!(cpu.load(0x10).unwrap(), 0x30);
+	cpu.registers.a = 0;
+	let record = cpu.step().unwrap();
+	assert_eq!((cpu.registers.a, cpu.registers.x), (0x30, 0x30));
+	assert_eq!(record.cycles, 3);
+	// NOP abs,X pays for crossing a page
+	cpu.registers.x = 0x01;
+	let record = cpu.step().unwrap();
+	assert_eq!(record.cycles, 5);
+}
+
+#[test]
+fn test_unofficial_read_modify_write() {
+	// DCP $10, ISC $11, SLO $12, RLA $13, SRE $14, RRA $15
+	let mut cpu = test_cpu(&[0xC7, 0x10, 0xE7, 0x11, 0x07, 0x12, 0x27, 0x13, 0x47, 0x14, 0x67, 0x15]);
+	for (i, &value) in [0x41, 0x0F, 0x81, 0xC3, 0x03, 0x02].iter().enumerate() {
+		cpu.store(0x10 + i as u16, value).unwrap();
+	}
+	cpu.registers.a = 0x40;
+
+	let record = cpu.step().unwrap();
+	assert_eq!(cpu.load(0x10).unwrap(), 0x40);
+	assert!(cpu.get_status(Flag::Zero) && cpu.get_status(Flag::Carry));
+	assert_eq!(record.cycles, 5);
+
+	// 0x40 - 0x10 - 0
+	cpu.step().unwrap();
+	assert_eq!(cpu.load(0x11).unwrap(), 0x10);
+	assert_eq!(cpu.registers.a, 0x30);
+	assert!(cpu.get_status(Flag::Carry));
+
+	cpu.step().unwrap();
+	assert_eq!(cpu.load(0x12).unwrap(), 0x02);
+	assert_eq!(cpu.registers.a, 0x32);
+	assert!(cpu.get_status(Flag::Carry));
+
+	cpu.step().unwrap();
+	assert_eq!(cpu.load(0x13).unwrap(), 0x87);
+	assert_eq!(cpu.registers.a, 0x02);
+	assert!(cpu.get_status(Flag::Carry));
+
+	cpu.step().unwrap();
+	assert_eq!(cpu.load(0x14).unwrap(), 0x01);
+	assert_eq!(cpu.registers.a, 0x03);
+	assert!(cpu.get_status(Flag::Carry));
+
+	// 0x03 + 0x81 + 0
+	cpu.step().unwrap();
+	assert_eq!(cpu.load(0x15).unwrap(), 0x81);
+	assert_eq!(cpu.registers.a, 0x84);
+	assert!(!cpu.get_status(Flag::Carry));
+}
+
+#[test]
+fn test_unofficial_immediate() {
+	// ANC #$80, ALR #$03, ARR #$C0, AXS #$01, SBC #$01
+	let mut cpu = test_cpu(&[0x0B, 0x80, 0x4B, 0x03, 0x6B, 0xC0, 0xCB, 0x01, 0xEB, 0x01]);
+	cpu.registers.a = 0xFF;
+	cpu.step().unwrap();
+	assert_eq!(cpu.registers.a, 0x80);
+	assert!(cpu.get_status(Flag::Carry) && cpu.get_status(Flag::Negative));
+
+	cpu.registers.a = 0x03;
+	cpu.step().unwrap();
+	assert_eq!(cpu.registers.a, 0x01);
+	assert!(cpu.get_status(Flag::Carry));
+
+	// (0xFF & 0xC0) >> 1 | C << 7
+	cpu.registers.a = 0xFF;
+	cpu.step().unwrap();
+	assert_eq!(cpu.registers.a, 0xE0);
+	assert!(cpu.get_status(Flag::Carry));
+	assert!(!cpu.get_status(Flag::Overflow));
+
+	cpu.registers.a = 0x0F;
+	cpu.registers.x = 0x00;
+	cpu.step().unwrap();
+	assert_eq!(cpu.registers.x, 0xFF);
+	assert!(!cpu.get_status(Flag::Carry));
+
+	cpu.registers.a = 0x10;
+	cpu.set_status(Flag::Carry, true);
+	cpu.step().unwrap();
+	assert_eq!(cpu.registers.a, 0x0F);
+}
+
+#[test]
+fn test_unstable() {
+	// SHX $02FF,Y, SHY $0200,X, LXA #$0F
+	let mut cpu = test_cpu(&[0x9E, 0xFF, 0x02, 0x9C, 0x00, 0x02, 0xAB, 0x0F]);
+	cpu.registers.x = 0xF1;
+	cpu.registers.y = 0x01;
+	// The page crossed, so the high byte of the address is replaced by X & 0x03
+	cpu.step().unwrap();
+	assert_eq!(cpu.load(0x0300).unwrap(), 0x00);
+	assert_eq!(cpu.load(0x0100).unwrap(), 0x01);
+
+	cpu.registers.x = 0x10;
+	cpu.step().unwrap();
+	assert_eq!(cpu.load(0x0210).unwrap(), 0x01);
+
+	cpu.registers.a = 0x00;
+	cpu.step().unwrap();
+	assert_eq!((cpu.registers.a, cpu.registers.x), (0x0E, 0x0E));
+}
+
+#[test]
+fn test_unmapped_register() {
+	// STA $2000
+	let mut cpu = test_cpu(&[0x8D, 0x00, 0x20]);
+	match cpu.run() {
+		EmuError::UnmappedRegister { address: 0x2000, access: Access::Write } => (),
+		other => panic!("Expected an unmapped register, got {:?}", other)
+	}
+	// Reads of I/O registers through peek don't fault
+	assert_eq!(cpu.peek(0x2000), 0xFF);
+}
+
+#[test]
+fn test_nmi() {
+	// NOP, NOP
+	let mut cpu = test_cpu(&[0xEA, 0xEA]);
+	// Handler at 0x9100: NOP, NOP
+	cpu.cartridge.borrow_mut().cpu_write(0x9100, 0xEA).unwrap();
+	cpu.cartridge.borrow_mut().cpu_write(0x9101, 0xEA).unwrap();
+	cpu.registers.status = 0x21;
+	cpu.set_nmi_line(true);
+
+	// NMI is serviced before the first instruction, even with interrupts disabled
+	cpu.registers.status |= Flag::Irq as u8;
+	let record = cpu.step().unwrap();
+	assert_eq!(record.interrupt, Some(Interrupt::Nmi));
+	assert_eq!(record.pc, 0x9100);
+	assert_eq!(record.cycles, 7 + 2);
+	assert_eq!(cpu.load(0x01FD).unwrap(), 0x80);
+	assert_eq!(cpu.load(0x01FC).unwrap(), 0x00);
+	// Break bit is clear in the pushed status
+	assert_eq!(cpu.load(0x01FB).unwrap(), 0x25);
+
+	// Holding the line doesn't trigger another NMI
+	let record = cpu.step().unwrap();
+	assert_eq!(record.interrupt, None);
+
+	// Only a new rising edge does
+	cpu.set_nmi_line(false);
+	cpu.set_nmi_line(true);
+	let record = cpu.step().unwrap();
+	assert_eq!(record.interrupt, Some(Interrupt::Nmi));
+}
+
+#[test]
+fn test_irq_level() {
+	// CLI, NOP, NOP
+	let mut cpu = test_cpu(&[0x58, 0xEA, 0xEA]);
+	// Handler at 0x9000: RTI
+	cpu.cartridge.borrow_mut().cpu_write(0x9000, 0x40).unwrap();
+	cpu.set_irq_line(IrqSource::External, true);
+
+	// Interrupts are disabled at power up
+	let record = cpu.step().unwrap();
+	assert_eq!(record.interrupt, None);
+	// CLI takes effect one instruction late
+	let record = cpu.step().unwrap();
+	assert_eq!(record.interrupt, None);
+	assert_eq!(record.pc, 0x8001);
+
+	let record = cpu.step().unwrap();
+	assert_eq!(record.interrupt, Some(Interrupt::Irq));
+	assert_eq!(record.pc, 0x9000);
+	assert_eq!(cpu.load(0x01FB).unwrap() & Flag::Break as u8, 0);
+
+	// RTI restores the I flag immediately, so a held line fires again right away
+	let record = cpu.step().unwrap();
+	assert_eq!(record.interrupt, Some(Interrupt::Irq));
+
+	// Releasing the line stops it
+	cpu.set_irq_line(IrqSource::External, false);
+	let record = cpu.step().unwrap();
+	assert_eq!(record.interrupt, None);
+	assert_eq!(record.pc, 0x8002);
+}
+
+#[test]
+fn test_irq_sources() {
+	let mut cpu = test_cpu(&[0xEA, 0xEA]);
+	cpu.irq_inhibit = false;
+	cpu.registers.status = 0x20;
+	cpu.set_irq_line(IrqSource::Mapper, true);
+	cpu.set_irq_line(IrqSource::FrameCounter, true);
+	cpu.set_irq_line(IrqSource::Mapper, false);
+	assert_eq!(cpu.poll_interrupt(), Some(Interrupt::Irq));
+	cpu.set_irq_line(IrqSource::FrameCounter, false);
+	assert_eq!(cpu.poll_interrupt(), None);
+}
+
+#[test]
+fn test_apu_registers() {
+	// LDA #$01, STA $4015, LDA #$3F, STA $4000, LDA #$08, STA $4003, LDA $4015
+	let mut cpu = test_cpu(&[0xA9, 0x01, 0x8D, 0x15, 0x40, 0xA9, 0x3F, 0x8D, 0x00, 0x40, 0xA9, 0x08,
+		0x8D, 0x03, 0x40, 0xAD, 0x15, 0x40]);
+	run_instructions(&mut cpu, 7);
+	assert_eq!(cpu.registers.a, 0x01);
+
+	// The frame counter's IRQ reaches the CPU's IRQ line
+	cpu.stall(30000);
+	assert_eq!(cpu.irq_lines, IrqSource::FrameCounter as u8);
+	assert_eq!(cpu.load(0x4015).unwrap() & 0x40, 0x40);
+	cpu.stall(1);
+	assert_eq!(cpu.irq_lines, 0);
+}
+
+#[test]
+fn test_samples() {
+	let mut cpu = test_cpu(&[0xEA; 0x100]);
+	assert!(cpu.take_samples().is_empty());
+	cpu.set_resampler(Some(Resampler::new(1000.0, 10)));
+	run_instructions(&mut cpu, 100);
+	assert_eq!(cpu.take_samples().len(), 2);
+}
+
+#[test]
+fn test_read_modify_write_dummy_write() {
+	let mut data = vec![0; 0x10000];
+	data[0x8000..0x8006].copy_from_slice(&[0xEE, 0x00, 0x90, 0xEE, 0x00, 0x90]); // INC $9000, INC $9000
+	data[0x9000] = 0x41;
+	let cartridge = TestCartridge::new(data);
+	let mut cpu = CPU::new(cartridge.clone(), CpuVariant::Ricoh2A03);
+	cpu.power_up_with_pc_override(0x8000);
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cartridge.borrow().writes, vec![(0x9000, 0x41), (0x9000, 0x42)]);
+
+	// The 65C02 doesn't write the old value back
+	let mut cpu = CPU::new(cartridge.clone(), CpuVariant::Cmos65C02);
+	cpu.power_up_with_pc_override(0x8003);
+	run_instructions(&mut cpu, 1);
+	assert_eq!(cartridge.borrow().writes[2..], [(0x9000, 0x43)]);
+}
+
+#[test]
+fn test_mapper_irq() {
+	let mut data = vec![0; 0x10000];
+	data[0x8000..0x8003].copy_from_slice(&[0x58, 0xEA, 0xEA]); // CLI, NOP, NOP
+	data[0x9000] = 0x40; // RTI
+	data[IRQ_VECTOR as usize + 1] = 0x90;
+	let cartridge = TestCartridge::new(data);
+	let mut cpu = CPU::new(cartridge.clone(), CpuVariant::Ricoh2A03);
+	cpu.power_up_with_pc_override(0x8000);
+	run_instructions(&mut cpu, 2);
+
+	cartridge.borrow_mut().irq = true;
+	let record = cpu.step().unwrap();
+	assert_eq!(record.interrupt, Some(Interrupt::Irq));
+
+	cartridge.borrow_mut().irq = false;
+	let record = cpu.step().unwrap();
+	assert_eq!(record.interrupt, None);
+	assert_eq!(record.pc, 0x8002);
+}
+
+#[test]
+fn test_sei_delay() {
+	// SEI, NOP
+	let mut cpu = test_cpu(&[0x78, 0xEA]);
+	cpu.registers.status = 0x20;
+	cpu.irq_inhibit = false;
+	cpu.step().unwrap();
+	cpu.set_irq_line(IrqSource::External, true);
+
+	// The IRQ polled during SEI still fires, with I set in the pushed status
+	let record = cpu.step().unwrap();
+	assert_eq!(record.interrupt, Some(Interrupt::Irq));
+	assert_eq!(cpu.load(0x01FB).unwrap(), 0x24);
+}
+
+#[test]
+fn test_reset() {
+	let mut cpu = test_cpu(&[0xA9, 0x42, 0xEA]);
+	cpu.step().unwrap();
+	cpu.registers.status = 0x20;
+	cpu.reset().unwrap();
+	assert_eq!(cpu.registers.pc, 0x8000);
+	assert_eq!(cpu.registers.s, 0xFA);
+	assert_eq!(cpu.registers.a, 0x42);
+	assert!(cpu.get_status(Flag::Irq));
+	assert_eq!(cpu.cycles(), RESET_CYCLES + 2 + RESET_CYCLES);
+}
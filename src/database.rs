@@ -0,0 +1,243 @@
+use hash::{ crc32, sha1 };
+use mapper::Mirroring;
+use rom::{ Header, Timing };
+use std::fmt;
+
+/// Games known to have bad headers in common dumps, in the format of the NES 2.0 XML database
+static EMBEDDED: &str = include_str!("../data/nes20db.xml");
+
+/// Correct headers for known games, keyed by the CRC-32 and SHA-1 of their PRG and CHR ROM
+/// together. Follows the NES 2.0 XML database, where each game looks like:
+///     <game>
+///         <!-- Path\To\Title.nes -->
+///         <prgrom size="32768" crc32="..." sha1="..."/>
+///         <rom size="40960" crc32="..." sha1="..."/>
+///         <pcb mapper="0" submapper="0" mirroring="V" battery="0"/>
+///         <console type="0" region="0"/>
+///     </game>
+/// Only the whole-ROM hashes and the board and region are used.
+pub struct Database {
+	games: Vec<Game>
+}
+
+/// A game in the database
+#[derive(Clone, Debug, PartialEq)]
+pub struct Game {
+	pub title: String,
+	pub crc32: u32,
+	pub sha1: Option<[u8; 20]>,
+	pub mapper: Option<u16>,
+	pub submapper: Option<u8>,
+	pub mirroring: Option<Mirroring>,
+	pub battery: Option<bool>,
+	pub timing: Option<Timing>
+}
+
+/// A header field that the database changed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Correction {
+	Mapper { from: u16, to: u16 },
+	Submapper { from: u8, to: u8 },
+	Mirroring { from: Mirroring, to: Mirroring },
+	Battery { from: bool, to: bool },
+	Timing { from: Timing, to: Timing }
+}
+
+impl Database {
+	/// The database built into the emulator
+	pub fn embedded() -> Database {
+		Database::parse(EMBEDDED)
+	}
+
+	/// Read a database in the NES 2.0 XML format. Games without a whole-ROM CRC-32 can't be
+	/// looked up, so they're skipped.
+	pub fn parse(xml: &str) -> Database {
+		let games = xml.split("<game>").skip(1)
+			.filter_map(|game| Game::parse(game.split("</game>").next().unwrap_or(game)))
+			.collect();
+		Database {
+			games
+		}
+	}
+
+	/// Find the game with this PRG and CHR ROM
+	pub fn find(&self, prg: &[u8], chr: &[u8]) -> Option<&Game> {
+		let mut data = prg.to_vec();
+		data.extend_from_slice(chr);
+		let crc = crc32(&data);
+		let mut candidates = self.games.iter().filter(|game| game.crc32 == crc).peekable();
+		candidates.peek()?;
+		// Only hash with SHA-1 once the CRC has narrowed it down
+		let digest = sha1(&data);
+		candidates.find(|game| game.sha1.is_none_or(|sha1| sha1 == digest))
+	}
+}
+
+impl Game {
+	// Game from the contents of a <game> element
+	fn parse(xml: &str) -> Option<Game> {
+		let crc32 = u32::from_str_radix(attribute(xml, "rom", "crc32")?, 16).ok()?;
+		// The title is in a comment, as the path of the file it was dumped to
+		let title = xml.split("<!--").nth(1)
+			.and_then(|comment| comment.split("-->").next())
+			.map(|path| path.trim().rsplit(['\\', '/']).next().unwrap_or(path))
+			.map(|file| file.trim_end_matches(".nes").to_string())
+			.unwrap_or_default();
+		let mirroring = match attribute(xml, "pcb", "mirroring") {
+			Some("H") => Some(Mirroring::Horizontal),
+			Some("V") => Some(Mirroring::Vertical),
+			Some("4") => Some(Mirroring::FourScreen),
+			_ => None
+		};
+		let timing = match attribute(xml, "console", "region") {
+			Some("0") => Some(Timing::Ntsc),
+			Some("1") => Some(Timing::Pal),
+			Some("2") => Some(Timing::MultipleRegion),
+			Some("3") => Some(Timing::Dendy),
+			_ => None
+		};
+		Some(Game {
+			title,
+			crc32,
+			sha1: attribute(xml, "rom", "sha1").and_then(parse_sha1),
+			mapper: attribute(xml, "pcb", "mapper").and_then(|m| m.parse().ok()),
+			submapper: attribute(xml, "pcb", "submapper").and_then(|s| s.parse().ok()),
+			mirroring,
+			battery: attribute(xml, "pcb", "battery").map(|b| b == "1"),
+			timing
+		})
+	}
+
+	/// Overwrite the header's fields with the database's, returning the ones that changed
+	pub fn correct(&self, header: &mut Header) -> Vec<Correction> {
+		let mut corrections = Vec::new();
+		if let Some(mapper) = self.mapper.filter(|&m| m != header.mapper_number) {
+			corrections.push(Correction::Mapper { from: header.mapper_number, to: mapper });
+			header.mapper_number = mapper;
+		}
+		if let Some(submapper) = self.submapper.filter(|&s| s != header.submapper) {
+			corrections.push(Correction::Submapper { from: header.submapper, to: submapper });
+			header.submapper = submapper;
+		}
+		if let Some(mirroring) = self.mirroring.filter(|&m| m != header.mirroring()) {
+			corrections.push(Correction::Mirroring { from: header.mirroring(), to: mirroring });
+			header.flags6.four_screen_vram = mirroring == Mirroring::FourScreen;
+			header.flags6.horizontal_arrangement = mirroring == Mirroring::Vertical;
+		}
+		let battery = header.flags6.battery_backed_prg_ram;
+		if let Some(to) = self.battery.filter(|&b| b != battery) {
+			corrections.push(Correction::Battery { from: battery, to });
+			header.flags6.battery_backed_prg_ram = to;
+		}
+		if let Some(timing) = self.timing.filter(|&t| t != header.timing) {
+			corrections.push(Correction::Timing { from: header.timing, to: timing });
+			header.timing = timing;
+		}
+		corrections
+	}
+}
+
+impl fmt::Display for Correction {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Correction::Mapper { from, to } => write!(f, "mapper {} -> {}", from, to),
+			Correction::Submapper { from, to } => write!(f, "submapper {} -> {}", from, to),
+			Correction::Mirroring { from, to } => write!(f, "mirroring {:?} -> {:?}", from, to),
+			Correction::Battery { from, to } => write!(f, "battery {} -> {}", from, to),
+			Correction::Timing { from, to } => write!(f, "timing {:?} -> {:?}", from, to)
+		}
+	}
+}
+
+// Value of an attribute of the first element with the given name, e.g. attribute(xml, "pcb",
+// "mapper") for <pcb mapper="4"/>
+fn attribute<'a>(xml: &'a str, element: &str, name: &str) -> Option<&'a str> {
+	let start = xml.find(&format!("<{} ", element))?;
+	let tag = &xml[start..start + xml[start..].find('>')?];
+	let value = &tag[tag.find(&format!(" {}=\"", name))? + name.len() + 3..];
+	Some(&value[..value.find('"')?])
+}
+
+fn parse_sha1(hex: &str) -> Option<[u8; 20]> {
+	if hex.len() != 40 {
+		return None;
+	}
+	let mut digest = [0; 20];
+	for (i, byte) in digest.iter_mut().enumerate() {
+		*byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+	}
+	Some(digest)
+}
+
+#[cfg(test)]
+use mapper::test_header;
+
+// Database with a game whose ROM is 16 KB of PRG and 8 KB of CHR, all zero
+#[cfg(test)]
+fn test_database(sha1: &str) -> Database {
+	let data = vec![0; 0x6000];
+	Database::parse(&format!(r#"<?xml version="1.0" encoding="UTF-8"?>
+<nes20db>
+	<game>
+		<!-- Homebrew\Zeroes (World).nes -->
+		<prgrom size="16384" crc32="00000000"/>
+		<rom size="24576" crc32="{:08X}" sha1="{}"/>
+		<pcb mapper="4" submapper="1" mirroring="4" battery="1"/>
+		<console type="0" region="1"/>
+	</game>
+	<game>
+		<rom size="16" crc32="nonsense"/>
+	</game>
+</nes20db>"#, crc32(&data), sha1))
+}
+
+#[test]
+fn test_find() {
+	let digest: String = sha1(&[0; 0x6000]).iter().map(|b| format!("{:02X}", b)).collect();
+	let database = test_database(&digest);
+	assert_eq!(database.games.len(), 1);
+	let game = database.find(&[0; 0x4000], &[0; 0x2000]).unwrap();
+	assert_eq!(game.title, "Zeroes (World)");
+	assert_eq!(game.mapper, Some(4));
+	assert_eq!(game.mirroring, Some(Mirroring::FourScreen));
+	assert_eq!(game.timing, Some(Timing::Pal));
+	assert!(database.find(&[0; 0x4000], &[1; 0x2000]).is_none());
+
+	// A CRC collision is told apart by SHA-1
+	let database = test_database(&"0".repeat(40));
+	assert!(database.find(&[0; 0x4000], &[0; 0x2000]).is_none());
+}
+
+#[test]
+fn test_correct() {
+	let digest: String = sha1(&[0; 0x6000]).iter().map(|b| format!("{:02x}", b)).collect();
+	let database = test_database(&digest);
+	let game = database.find(&[0; 0x4000], &[0; 0x2000]).unwrap();
+	let mut header = test_header(1, 1, 0x40);
+	let corrections = game.correct(&mut header);
+	assert_eq!(corrections, vec![
+		Correction::Submapper { from: 0, to: 1 },
+		Correction::Mirroring { from: Mirroring::Horizontal, to: Mirroring::FourScreen },
+		Correction::Battery { from: false, to: true },
+		Correction::Timing { from: Timing::Ntsc, to: Timing::Pal }
+	]);
+	assert_eq!((header.mapper_number, header.submapper), (4, 1));
+	assert_eq!(header.mirroring(), Mirroring::FourScreen);
+	assert!(header.flags6.battery_backed_prg_ram);
+	assert!(game.correct(&mut header).is_empty());
+}
+
+#[test]
+fn test_embedded() {
+	let database = Database::embedded();
+	assert!(!database.games.is_empty());
+	// Super Mario Bros. dumped with a horizontal mirroring, PAL header
+	let game = database.games.iter().find(|game| game.crc32 == 0x3337EC46).unwrap();
+	assert_eq!(game.title, "Super Mario Bros. (World)");
+	let mut header = test_header(2, 1, 0);
+	header.timing = Timing::Pal;
+	assert_eq!(game.correct(&mut header), vec![
+		Correction::Mirroring { from: Mirroring::Horizontal, to: Mirroring::Vertical },
+		Correction::Timing { from: Timing::Pal, to: Timing::Ntsc }
+	]);
+}
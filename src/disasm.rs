@@ -0,0 +1,211 @@
+use cpu::{ CpuVariant, IRQ_VECTOR, NMI_VECTOR, RESET_VECTOR };
+use error::Result;
+use memory::Memory;
+use opcode::{ Mode, Opcode };
+use std::collections::BTreeMap;
+
+/// Names given to the interrupt vectors, in the order they're labelled
+const VECTORS: [(u16, &str); 3] = [
+	(RESET_VECTOR, "RESET"),
+	(NMI_VECTOR, "NMI"),
+	(IRQ_VECTOR, "IRQ")
+];
+
+/// A single decoded instruction
+#[derive(Clone, Debug)]
+pub struct Instruction {
+	pub address: u16,
+	pub opcode: &'static Opcode,
+	bytes: [u8; 3]
+}
+
+impl Instruction {
+	/// Opcode and operand bytes
+	pub fn bytes(&self) -> &[u8] {
+		&self.bytes[..self.opcode.length() as usize]
+	}
+
+	/// Operand as a little endian word, or a byte for 2 byte instructions
+	pub fn operand(&self) -> u16 {
+		match self.opcode.length() {
+			1 => 0,
+			2 => self.bytes[1] as u16,
+			_ => self.bytes[1] as u16 | (self.bytes[2] as u16) << 8
+		}
+	}
+
+	/// Address the instruction branches or jumps to, if it's known without running it
+	pub fn target(&self) -> Option<u16> {
+		match self.opcode.mode {
+			Mode::Relative => {
+				let next = self.address.wrapping_add(2);
+				Some(next.wrapping_add(self.bytes[1] as i8 as u16))
+			},
+			Mode::Absolute if self.opcode.mnemonic == "JMP" || self.opcode.mnemonic == "JSR" =>
+				Some(self.operand()),
+			_ => None
+		}
+	}
+}
+
+/// Decode the instruction at the given address
+pub fn decode(memory: &dyn Memory, address: u16, variant: CpuVariant) -> Result<Instruction> {
+	let opcode = &variant.opcodes()[memory.load(address)? as usize];
+	let mut bytes = [0; 3];
+	for (i, byte) in bytes.iter_mut().enumerate().take(opcode.length() as usize) {
+		*byte = memory.load(address.wrapping_add(i as u16))?;
+	}
+	Ok(Instruction { address, opcode, bytes })
+}
+
+/// Disassemble the instructions from start to end inclusive. Branch and jump targets inside the
+/// range get labels, as do the NMI, RESET and IRQ vectors. When the range includes the vectors
+/// they're shown as words rather than decoded as instructions.
+///
+/// Example:
+///     RESET:
+///     C000  78        SEI
+///     C001  D0 FD     BNE RESET
+pub fn disassemble(memory: &dyn Memory, start: u16, end: u16, variant: CpuVariant)
+	-> Result<String>
+{
+	let code_end = if start < NMI_VECTOR && end >= NMI_VECTOR { NMI_VECTOR - 1 } else { end };
+
+	let mut instructions = Vec::new();
+	let mut address = start as u32;
+	while address <= code_end as u32 {
+		let instruction = decode(memory, address as u16, variant)?;
+		address += instruction.opcode.length() as u32;
+		instructions.push(instruction);
+	}
+
+	// Only label addresses that start an instruction, so every label is printed
+	let starts: Vec<u16> = instructions.iter().map(|i| i.address).collect();
+	let mut labels = BTreeMap::new();
+	for &(vector, name) in VECTORS.iter() {
+		if let Ok(target) = memory.loadw(vector) {
+			if starts.binary_search(&target).is_ok() {
+				labels.entry(target).or_insert_with(|| name.to_string());
+			}
+		}
+	}
+	for target in instructions.iter().filter_map(|i| i.target()) {
+		if starts.binary_search(&target).is_ok() {
+			labels.entry(target).or_insert_with(|| format!("L{:04X}", target));
+		}
+	}
+
+	let mut output = String::new();
+	for instruction in instructions.iter() {
+		if let Some(label) = labels.get(&instruction.address) {
+			output.push_str(&format!("{}:\n", label));
+		}
+		output.push_str(&format_instruction(instruction, &labels));
+		output.push('\n');
+	}
+
+	if code_end != end {
+		let mut vectors = VECTORS.to_vec();
+		vectors.sort();
+		for &(vector, name) in vectors.iter() {
+			let target = memory.loadw(vector)?;
+			let value = labels.get(&target).cloned().unwrap_or_else(|| format!("${:04X}", target));
+			output.push_str(&format!("{:04X}  {:02X} {:02X}     .word {:<26}; {}\n",
+				vector, target as u8, target >> 8, value, name));
+		}
+	}
+	Ok(output)
+}
+
+/// Format a single instruction in the same columns as the tracer, using labels for targets that
+/// have one
+pub fn format_instruction(instruction: &Instruction, labels: &BTreeMap<u16, String>) -> String {
+	let bytes: Vec<String> = instruction.bytes().iter().map(|b| format!("{:02X}", b)).collect();
+	let operand = format_operand(instruction, labels);
+	let marker = if instruction.opcode.official { ' ' } else { '*' };
+	let line = format!("{:04X}  {:<8} {}{} {}", instruction.address, bytes.join(" "), marker,
+		instruction.opcode.mnemonic, operand);
+	line.trim_end().to_string()
+}
+
+fn format_operand(instruction: &Instruction, labels: &BTreeMap<u16, String>) -> String {
+	if let Some(label) = instruction.target().and_then(|target| labels.get(&target)) {
+		return label.clone();
+	}
+
+	let operand = instruction.operand();
+	match instruction.opcode.mode {
+		Mode::Implied => String::new(),
+		Mode::Accumulator => "A".to_string(),
+		Mode::Immediate => format!("#${:02X}", operand),
+		Mode::ZeroPage => format!("${:02X}", operand),
+		Mode::ZeroPageX => format!("${:02X},X", operand),
+		Mode::ZeroPageY => format!("${:02X},Y", operand),
+		Mode::Absolute => format!("${:04X}", operand),
+		Mode::AbsoluteX => format!("${:04X},X", operand),
+		Mode::AbsoluteY => format!("${:04X},Y", operand),
+		Mode::Indirect => format!("(${:04X})", operand),
+		Mode::IndirectX => format!("(${:02X},X)", operand),
+		Mode::IndirectY => format!("(${:02X}),Y", operand),
+		Mode::Relative => format!("${:04X}", instruction.target().unwrap_or(0)),
+		Mode::ZeroPageIndirect => format!("(${:02X})", operand),
+		Mode::AbsoluteIndexedIndirect => format!("(${:04X},X)", operand)
+	}
+}
+
+#[cfg(test)]
+use memory::ReadWriteMemory;
+
+#[cfg(test)]
+fn test_memory(address: u16, program: &[u8]) -> ReadWriteMemory {
+	let mut memory = ReadWriteMemory::new(0x10000);
+	for (i, &byte) in program.iter().enumerate() {
+		memory.store(address + i as u16, byte).unwrap();
+	}
+	memory
+}
+
+#[test]
+fn test_disassemble() {
+	// SEI, LDX #$FF, DEX, BNE -3, JSR $C00C, JMP $C000, *NOP $44, RTI
+	let mut memory = test_memory(0xC000, &[
+		0x78, 0xA2, 0xFF, 0xCA, 0xD0, 0xFD, 0x20, 0x0C, 0xC0, 0x4C, 0x00, 0xC0, 0x04, 0x44, 0x40
+	]);
+	memory.storew(RESET_VECTOR, 0xC000).unwrap();
+	memory.storew(NMI_VECTOR, 0xC00E).unwrap();
+	memory.storew(IRQ_VECTOR, 0xC00E).unwrap();
+
+	let text = disassemble(&memory, 0xC000, 0xC00E, CpuVariant::Ricoh2A03).unwrap();
+	assert_eq!(text, "\
+RESET:
+C000  78        SEI
+C001  A2 FF     LDX #$FF
+LC003:
+C003  CA        DEX
+C004  D0 FD     BNE LC003
+C006  20 0C C0  JSR LC00C
+C009  4C 00 C0  JMP RESET
+LC00C:
+C00C  04 44    *NOP $44
+NMI:
+C00E  40        RTI
+");
+}
+
+#[test]
+fn test_disassemble_vectors() {
+	// BRA -2 on the 65C02, followed by the vectors
+	let mut memory = test_memory(0xFFF8, &[0x80, 0xFE]);
+	memory.storew(NMI_VECTOR, 0xFFF8).unwrap();
+	memory.storew(RESET_VECTOR, 0xFFF8).unwrap();
+	memory.storew(IRQ_VECTOR, 0x1234).unwrap();
+
+	let text = disassemble(&memory, 0xFFF8, 0xFFFF, CpuVariant::Cmos65C02).unwrap();
+	assert_eq!(text, "\
+RESET:
+FFF8  80 FE     BRA RESET
+FFFA  F8 FF     .word RESET                     ; NMI
+FFFC  F8 FF     .word RESET                     ; RESET
+FFFE  34 12     .word $1234                     ; IRQ
+");
+}
@@ -0,0 +1,131 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+
+pub type Result<T> = result::Result<T, EmuError>;
+
+/// Direction of a bus access
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+	Read,
+	Write
+}
+
+/// Errors that stop emulation. None of these are fatal to the process; a frontend can report them
+/// and keep the session alive.
+#[derive(Debug)]
+pub enum EmuError {
+	/// The CPU fetched an opcode it doesn't implement
+	IllegalOpcode { opcode: u8, address: u16 },
+	/// The CPU executed a KIL opcode and stays halted until it's reset
+	Halted { opcode: u8, address: u16 },
+	/// Access to an address that nothing is mapped to, or a write to read only memory
+	BusFault { address: u16, access: Access },
+	/// Access to an I/O register that isn't emulated
+	UnmappedRegister { address: u16, access: Access },
+	/// The ROM file is malformed
+	Rom(RomError),
+	Io(io::Error)
+}
+
+/// Problems with the format of a ROM file
+#[derive(Debug, PartialEq)]
+pub enum RomError {
+	/// File doesn't start with the iNES identifier
+	InvalidIdentifier,
+	/// File ended before the end of the 16 byte header
+	TruncatedHeader,
+	/// File ended before the end of the trainer
+	TruncatedTrainer { expected: usize, found: usize },
+	/// Trainer on a board without PRG RAM at 0x7000-0x71FF to copy it to
+	UnmappedTrainer,
+	/// File ended before the amount of PRG ROM given in the header
+	TruncatedPrg { expected: usize, found: usize },
+	/// File ended before the amount of CHR ROM given in the header
+	TruncatedChr { expected: usize, found: usize },
+	/// UNIF or NSFe file ended in the middle of a chunk
+	TruncatedChunk { id: String },
+	/// NSFe file without a chunk it needs
+	MissingChunk { id: String },
+	/// UNIF file without a board name
+	MissingBoard,
+	/// UNIF board name that doesn't correspond to a supported mapper
+	UnsupportedBoard { name: String },
+	/// No mapper is registered for the header's mapper and submapper numbers
+	UnsupportedMapper { mapper: u16, submapper: u8 },
+	/// File doesn't start with the NSF or NSFe identifier
+	InvalidNsfIdentifier
+}
+
+impl fmt::Display for Access {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			Access::Read => write!(f, "read"),
+			Access::Write => write!(f, "write")
+		}
+	}
+}
+
+impl fmt::Display for EmuError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			EmuError::IllegalOpcode { opcode, address } =>
+				write!(f, "Illegal opcode {:#04X} at {:#06X}", opcode, address),
+			EmuError::Halted { opcode, address } =>
+				write!(f, "CPU halted by opcode {:#04X} at {:#06X}", opcode, address),
+			EmuError::BusFault { address, access } =>
+				write!(f, "Bus fault on {} of {:#06X}", access, address),
+			EmuError::UnmappedRegister { address, access } =>
+				write!(f, "Unmapped register {:#06X} on {}", address, access),
+			EmuError::Rom(ref e) => write!(f, "Invalid ROM: {}", e),
+			EmuError::Io(ref e) => write!(f, "I/O error: {}", e)
+		}
+	}
+}
+
+impl fmt::Display for RomError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			RomError::InvalidIdentifier => write!(f, "File is not in iNES or UNIF file format"),
+			RomError::TruncatedHeader => write!(f, "Header is incomplete"),
+			RomError::TruncatedTrainer { expected, found } =>
+				write!(f, "Trainer is incomplete, expected {} bytes but found {}", expected, found),
+			RomError::UnmappedTrainer => write!(f, "Board has no PRG RAM at 0x7000 for the trainer"),
+			RomError::TruncatedPrg { expected, found } =>
+				write!(f, "PRG ROM is incomplete, expected {} bytes but found {}", expected, found),
+			RomError::TruncatedChr { expected, found } =>
+				write!(f, "CHR ROM is incomplete, expected {} bytes but found {}", expected, found),
+			RomError::TruncatedChunk { ref id } => write!(f, "{} chunk is incomplete", id),
+			RomError::MissingChunk { ref id } => write!(f, "NSFe file has no {} chunk", id),
+			RomError::MissingBoard => write!(f, "UNIF file has no board name"),
+			RomError::UnsupportedBoard { ref name } => write!(f, "Board {} is not supported", name),
+			RomError::UnsupportedMapper { mapper, submapper: 0 } =>
+				write!(f, "Mapper {} is not supported", mapper),
+			RomError::UnsupportedMapper { mapper, submapper } =>
+				write!(f, "Mapper {} submapper {} is not supported", mapper, submapper),
+			RomError::InvalidNsfIdentifier => write!(f, "File is not in NSF or NSFe file format")
+		}
+	}
+}
+
+impl error::Error for EmuError {
+	fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+		match *self {
+			EmuError::Io(ref e) => Some(e),
+			_ => None
+		}
+	}
+}
+
+impl From<io::Error> for EmuError {
+	fn from(e: io::Error) -> EmuError {
+		EmuError::Io(e)
+	}
+}
+
+impl From<RomError> for EmuError {
+	fn from(e: RomError) -> EmuError {
+		EmuError::Rom(e)
+	}
+}
@@ -0,0 +1,83 @@
+/// CRC-32 as used by zip files and ROM databases, with the reflected polynomial 0xEDB88320
+pub fn crc32(data: &[u8]) -> u32 {
+	let mut crc = !0u32;
+	for &byte in data {
+		crc ^= byte as u32;
+		for _ in 0..8 {
+			let mask = (crc & 1).wrapping_neg();
+			crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+		}
+	}
+	!crc
+}
+
+/// SHA-1 digest
+///     https://tools.ietf.org/html/rfc3174
+pub fn sha1(data: &[u8]) -> [u8; 20] {
+	let mut h: [u32; 5] = [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476, 0xC3D2_E1F0];
+
+	// Pad to a whole number of 64 byte blocks, ending with the length in bits
+	let mut message = data.to_vec();
+	message.push(0x80);
+	while message.len() % 64 != 56 {
+		message.push(0);
+	}
+	message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+	for block in message.chunks(64) {
+		let mut w = [0u32; 80];
+		for (i, word) in block.chunks(4).enumerate() {
+			w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+		}
+		for i in 16..80 {
+			w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+		}
+
+		let [mut a, mut b, mut c, mut d, mut e] = h;
+		for (i, &word) in w.iter().enumerate() {
+			let (f, k) = match i {
+				0 ..= 19 => ((b & c) | (!b & d), 0x5A82_7999),
+				20 ..= 39 => (b ^ c ^ d, 0x6ED9_EBA1),
+				40 ..= 59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+				_ => (b ^ c ^ d, 0xCA62_C1D6)
+			};
+			let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e)
+				.wrapping_add(k).wrapping_add(word);
+			e = d;
+			d = c;
+			c = b.rotate_left(30);
+			b = a;
+			a = temp;
+		}
+		for (h, value) in h.iter_mut().zip([a, b, c, d, e].iter()) {
+			*h = h.wrapping_add(*value);
+		}
+	}
+
+	let mut digest = [0; 20];
+	for (bytes, word) in digest.chunks_mut(4).zip(h.iter()) {
+		bytes.copy_from_slice(&word.to_be_bytes());
+	}
+	digest
+}
+
+#[cfg(test)]
+fn hex(bytes: &[u8]) -> String {
+	bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[test]
+fn test_crc32() {
+	assert_eq!(crc32(b""), 0);
+	assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+	assert_eq!(crc32(b"The quick brown fox jumps over the lazy dog"), 0x414F_A339);
+}
+
+#[test]
+fn test_sha1() {
+	assert_eq!(hex(&sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+	assert_eq!(hex(&sha1(b"abc")), "a9993e364706816aba3e25717850c26c9cd0d89d");
+	// Two blocks once padded
+	assert_eq!(hex(&sha1(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")),
+		"84983e441c3bd26ebaae4aa1f95129e5e54670f1");
+}
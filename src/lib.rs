@@ -0,0 +1,15 @@
+pub mod apu;
+pub mod cpu;
+pub mod database;
+pub mod disasm;
+pub mod error;
+pub mod hash;
+pub mod mapper;
+pub mod memory;
+pub mod nsf;
+pub mod opcode;
+pub mod rom;
+pub mod save;
+pub mod trace;
+pub mod unif;
+pub mod wav;
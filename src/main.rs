@@ -1,26 +1,224 @@
-mod cpu;
-mod mapper;
-mod memory;
-mod rom;
-
-use cpu::CPU;
-use rom::Rom;
-use mapper::{ NRomPRG };
+extern crate jane;
+
+use jane::cpu::{ CpuVariant, CPU };
+use jane::disasm;
+use jane::nsf::{ Nsf, Player };
+use jane::rom::Rom;
+use jane::mapper;
+use jane::save::{ self, SaveFile };
+use jane::trace::Tracer;
+use jane::wav;
 use std::env;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::process;
+use std::str::FromStr;
+
+/// Sample rate of rendered audio
+const SAMPLE_RATE: u32 = 44100;
 
 fn main() {
     // TODO: Impement real command line parsing, possibly with getopts or something similars
-    let rom_file = env::args().nth(1).unwrap();
+    let mut args = env::args().skip(1).peekable();
+    // jane nsf <file> renders a song from an NSF music file instead of running a ROM
+    if args.peek().is_some_and(|arg| arg == "nsf") {
+        args.next();
+        play_nsf(args);
+        return;
+    }
+    // jane disasm <rom> prints the disassembly of PRG ROM instead of running it
+    let disassemble = args.peek().is_some_and(|arg| arg == "disasm");
+    if disassemble {
+        args.next();
+    }
+    let rom_file = args.next().unwrap();
+    // Optional: --trace <file> writes a nestest.log compatible trace
+    // Optional: --no-database trusts the ROM's header even for games in the database
+    // Optional: --nestest starts at 0xC000, nestest's automated mode, instead of the reset vector
+    let mut trace_file = None;
+    let mut use_database = true;
+    let mut nestest = false;
+    while let Some(flag) = args.next() {
+        match flag.as_str() {
+            "--trace" => trace_file = args.next(),
+            "--no-database" => use_database = false,
+            "--nestest" => nestest = true,
+            _ => {
+                eprintln!("Unknown option: {}", flag);
+                process::exit(1);
+            }
+        }
+    }
+
+    let rom = if use_database { Rom::open(&rom_file) } else { Rom::open_uncorrected(&rom_file) };
+    let mut rom = match rom {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("Failed to open ROM: {}", e);
+            process::exit(1);
+        }
+    };
+
+	for warning in rom.header.warnings.iter() {
+		eprintln!("Warning: {}", warning);
+	}
+	if let Some(ref title) = rom.title {
+		println!("{}", title);
+	}
+	for correction in rom.corrections.iter() {
+		eprintln!("Corrected header from the database: {}", correction);
+	}
 
-    let rom = Rom::open(rom_file).unwrap();
-	println!("{:#?}", rom.header);
+	// 16KB of PRG ROM is mirrored into both halves, so only disassemble it once
+	let disasm_start = if rom.prg.len() <= 0x4000 { 0xC000 } else { 0x8000 };
+	let header = rom.header.clone();
+	let trainer = rom.trainer.take();
+	let cartridge = match mapper::create(rom) {
+		Ok(cartridge) => cartridge,
+		Err(e) => {
+			eprintln!("Failed to load cartridge: {}", e);
+			process::exit(1);
+		}
+	};
+	let mut cpu = CPU::new(cartridge.clone(), CpuVariant::Ricoh2A03);
 
-	let prg_rom = Box::new(NRomPRG::new(rom.header.clone(), rom.prg));
+	if disassemble {
+		match disasm::disassemble(&cpu, disasm_start, 0xFFFF, CpuVariant::Ricoh2A03) {
+			Ok(text) => print!("{}", text),
+			Err(e) => {
+				eprintln!("Failed to disassemble: {}", e);
+				process::exit(1);
+			}
+		}
+		return;
+	}
 
-	let mut cpu = CPU::new(prg_rom);
+	println!("{:#?}", header);
+
+	// Battery backed PRG RAM picks up where the last session left off
+	let mut save_file = if header.flags6.battery_backed_prg_ram {
+		let mut save_file = SaveFile::for_rom(&rom_file);
+		if let Err(e) = save_file.load(&mut *cartridge.borrow_mut()) {
+			eprintln!("Failed to load {}: {}", save_file.path().display(), e);
+			process::exit(1);
+		}
+		Some(save_file)
+	} else {
+		None
+	};
+	// The trainer goes in after the save, which would otherwise overwrite its code
+	if let Some(trainer) = trainer {
+		if let Err(e) = mapper::load_trainer(&mut *cartridge.borrow_mut(), &trainer) {
+			eprintln!("Failed to load trainer: {}", e);
+			process::exit(1);
+		}
+	}
+
+	if let Some(trace_file) = trace_file {
+		let output = match File::create(&trace_file) {
+			Ok(file) => BufWriter::new(file),
+			Err(e) => {
+				eprintln!("Failed to create {}: {}", trace_file, e);
+				process::exit(1);
+			}
+		};
+		cpu.set_tracer(Some(Tracer::new(Box::new(output))));
+	}
 	println!("Before power up: {}", cpu);
-	cpu.power_up_with_pc_override(0xC000);
+	if nestest {
+		cpu.power_up_with_pc_override(0xC000);
+	} else if let Err(e) = cpu.power_up() {
+		eprintln!("Failed to power up: {}", e);
+		process::exit(1);
+	}
 	println!("After power up: {}", cpu);
-	cpu.run();
+	let error = match save_file {
+		Some(ref mut save_file) => loop {
+			if let Err(e) = cpu.run_for_cycles(save::FLUSH_INTERVAL) {
+				break e;
+			}
+			flush(save_file, &cartridge);
+		},
+		None => cpu.run()
+	};
+	eprintln!("CPU stopped: {}", error);
+	if let Some(ref mut save_file) = save_file {
+		flush(save_file, &cartridge);
+	}
 	println!("After run: {}", cpu);
 }
+
+fn flush(save_file: &mut SaveFile, cartridge: &mapper::Cartridge) {
+	if let Err(e) = save_file.flush(&*cartridge.borrow()) {
+		eprintln!("Failed to write {}: {}", save_file.path().display(), e);
+	}
+}
+
+// jane nsf <file> [--track <n>] [--seconds <n>] [--wav <file>]
+// Renders a song, from 1 and the NSF's starting song by default, to a WAV file named after the
+// NSF by default
+fn play_nsf<I: Iterator<Item = String>>(mut args: I) {
+	let nsf_file = args.next().unwrap();
+	let (mut track, mut seconds, mut wav_file) = (None, 60.0, None);
+	while let Some(flag) = args.next() {
+		match flag.as_str() {
+			"--track" => track = Some(number(&flag, args.next())),
+			"--seconds" => seconds = number(&flag, args.next()),
+			"--wav" => wav_file = args.next(),
+			_ => {
+				eprintln!("Unknown option: {}", flag);
+				process::exit(1);
+			}
+		}
+	}
+
+	let nsf = match Nsf::open(&nsf_file) {
+		Ok(nsf) => nsf,
+		Err(e) => {
+			eprintln!("Failed to open NSF: {}", e);
+			process::exit(1);
+		}
+	};
+	let track = track.unwrap_or(nsf.starting_song);
+	if track == 0 || track > nsf.songs {
+		eprintln!("Track {} is not between 1 and {}", track, nsf.songs);
+		process::exit(1);
+	}
+	let credits: Vec<&str> = [&nsf.title, &nsf.artist, &nsf.copyright].iter()
+		.map(|text| text.as_str())
+		.filter(|text| !text.is_empty())
+		.collect();
+	println!("{}", credits.join(" - "));
+	println!("Track {} of {}", track, nsf.songs);
+
+	let mut player = Player::new(&nsf, SAMPLE_RATE);
+	let samples = match player.start(track).and_then(|_| player.render(seconds)) {
+		Ok(samples) => samples,
+		Err(e) => {
+			eprintln!("CPU stopped: {}", e);
+			process::exit(1);
+		}
+	};
+
+	let wav_file = wav_file.unwrap_or_else(|| {
+		Path::new(&nsf_file).with_extension("wav").to_string_lossy().into_owned()
+	});
+	let result = File::create(&wav_file)
+		.and_then(|file| wav::write(&mut BufWriter::new(file), SAMPLE_RATE, &samples));
+	if let Err(e) = result {
+		eprintln!("Failed to write {}: {}", wav_file, e);
+		process::exit(1);
+	}
+}
+
+// Value of a numeric option, or exit when it's missing or not a number
+fn number<T: FromStr>(flag: &str, value: Option<String>) -> T {
+	match value.as_ref().and_then(|value| value.parse().ok()) {
+		Some(value) => value,
+		None => {
+			eprintln!("{} needs a number", flag);
+			process::exit(1);
+		}
+	}
+}
@@ -0,0 +1,292 @@
+use error::{ Access, EmuError, Result };
+use mapper::{ chr_memory, Mapper, Mirroring };
+use memory::*;
+use rom::*;
+
+// Boards built from discrete logic chips. They have a single register anywhere in 0x8000-0xFFFF,
+// and most don't disable the PRG ROM while it's written, so the ROM drives the data bus at the same
+// time as the CPU. The result is the AND of the two values. NES 2.0 submapper 2 selects this bus
+// conflict behavior for UxROM, CNROM and AxROM, while 0 and 1 leave it off. GxROM always has it.
+//     http://wiki.nesdev.com/w/index.php/Bus_conflict
+
+/// Submapper for boards with AND-type bus conflicts
+const SUBMAPPER_BUS_CONFLICTS: u8 = 2;
+
+// Value the register sees after a bus conflict with the PRG ROM
+fn conflicted(prg: &BankedMemory, bus_conflicts: bool, address: u16, value: u8) -> Result<u8> {
+	if bus_conflicts {
+		Ok(value & prg.load(address - 0x8000)?)
+	} else {
+		Ok(value)
+	}
+}
+
+fn read_prg(prg: &BankedMemory, address: u16) -> Result<u8> {
+	match address {
+		0x8000 ..= 0xFFFF => prg.load(address - 0x8000),
+		_ => Err(EmuError::BusFault { address, access: Access::Read })
+	}
+}
+
+fn read_chr(chr: &BankedMemory, address: u16) -> Result<u8> {
+	match address {
+		0x0000 ..= 0x1FFF => chr.load(address),
+		_ => Err(EmuError::BusFault { address, access: Access::Read })
+	}
+}
+
+fn write_chr(chr: &mut BankedMemory, address: u16, value: u8) -> Result<()> {
+	match address {
+		0x0000 ..= 0x1FFF => chr.store(address, value),
+		_ => Err(EmuError::BusFault { address, access: Access::Write })
+	}
+}
+
+/// UxROM (0x2) Mapper: 16 KB switchable PRG ROM bank at 0x8000 and the last bank fixed at 0xC000
+///     http://wiki.nesdev.com/w/index.php/UxROM
+pub struct UxRom {
+	prg: BankedMemory,
+	chr: BankedMemory,
+	mirroring: Mirroring,
+	bus_conflicts: bool
+}
+
+impl UxRom {
+	pub fn new(header: Header, prg: Vec<u8>, chr: Vec<u8>) -> UxRom {
+		let mut prg = BankedMemory::rom(prg, 0x4000, 2);
+		prg.switch_from_end(1, 1);
+		UxRom {
+			prg,
+			chr: chr_memory(chr, 0x2000, 1),
+			mirroring: header.mirroring(),
+			bus_conflicts: header.submapper == SUBMAPPER_BUS_CONFLICTS
+		}
+	}
+}
+
+impl Mapper for UxRom {
+	fn cpu_read(&self, address: u16) -> Result<u8> { read_prg(&self.prg, address) }
+
+	fn cpu_write(&mut self, address: u16, value: u8) -> Result<()> {
+		match address {
+			0x8000 ..= 0xFFFF => {
+				let bank = conflicted(&self.prg, self.bus_conflicts, address, value)?;
+				self.prg.switch(0, bank as usize);
+				Ok(())
+			},
+			_ => Err(EmuError::BusFault { address, access: Access::Write })
+		}
+	}
+
+	fn ppu_read(&mut self, address: u16) -> Result<u8> { read_chr(&self.chr, address) }
+
+	fn ppu_write(&mut self, address: u16, value: u8) -> Result<()> {
+		write_chr(&mut self.chr, address, value)
+	}
+
+	fn mirroring(&self) -> Mirroring {
+		self.mirroring
+	}
+}
+
+/// CNROM (0x3) Mapper: 16 or 32 KB of fixed PRG ROM and a switchable 8 KB CHR ROM bank
+///     http://wiki.nesdev.com/w/index.php/CNROM
+pub struct CnRom {
+	prg: BankedMemory,
+	chr: BankedMemory,
+	mirroring: Mirroring,
+	bus_conflicts: bool
+}
+
+impl CnRom {
+	pub fn new(header: Header, prg: Vec<u8>, chr: Vec<u8>) -> CnRom {
+		CnRom {
+			// 16 KB of PRG ROM is mirrored at 0xC000
+			prg: BankedMemory::rom(prg, 0x4000, 2),
+			chr: chr_memory(chr, 0x2000, 1),
+			mirroring: header.mirroring(),
+			bus_conflicts: header.submapper == SUBMAPPER_BUS_CONFLICTS
+		}
+	}
+}
+
+impl Mapper for CnRom {
+	fn cpu_read(&self, address: u16) -> Result<u8> { read_prg(&self.prg, address) }
+
+	fn cpu_write(&mut self, address: u16, value: u8) -> Result<()> {
+		match address {
+			0x8000 ..= 0xFFFF => {
+				let bank = conflicted(&self.prg, self.bus_conflicts, address, value)?;
+				self.chr.switch(0, bank as usize);
+				Ok(())
+			},
+			_ => Err(EmuError::BusFault { address, access: Access::Write })
+		}
+	}
+
+	fn ppu_read(&mut self, address: u16) -> Result<u8> { read_chr(&self.chr, address) }
+
+	fn ppu_write(&mut self, address: u16, value: u8) -> Result<()> {
+		write_chr(&mut self.chr, address, value)
+	}
+
+	fn mirroring(&self) -> Mirroring {
+		self.mirroring
+	}
+}
+
+/// AxROM (0x7) Mapper: 32 KB switchable PRG ROM bank and single screen mirroring selected by
+/// bit 4 of the register
+///     http://wiki.nesdev.com/w/index.php/AxROM
+pub struct AxRom {
+	prg: BankedMemory,
+	chr: BankedMemory,
+	mirroring: Mirroring,
+	bus_conflicts: bool
+}
+
+impl AxRom {
+	pub fn new(header: Header, prg: Vec<u8>, chr: Vec<u8>) -> AxRom {
+		AxRom {
+			prg: BankedMemory::rom(prg, 0x8000, 1),
+			chr: chr_memory(chr, 0x2000, 1),
+			mirroring: Mirroring::SingleScreenLower,
+			bus_conflicts: header.submapper == SUBMAPPER_BUS_CONFLICTS
+		}
+	}
+}
+
+impl Mapper for AxRom {
+	fn cpu_read(&self, address: u16) -> Result<u8> { read_prg(&self.prg, address) }
+
+	fn cpu_write(&mut self, address: u16, value: u8) -> Result<()> {
+		match address {
+			0x8000 ..= 0xFFFF => {
+				let value = conflicted(&self.prg, self.bus_conflicts, address, value)?;
+				self.prg.switch(0, (value & 0x07) as usize);
+				self.mirroring = if value & 0x10 == 0 {
+					Mirroring::SingleScreenLower
+				} else {
+					Mirroring::SingleScreenUpper
+				};
+				Ok(())
+			},
+			_ => Err(EmuError::BusFault { address, access: Access::Write })
+		}
+	}
+
+	fn ppu_read(&mut self, address: u16) -> Result<u8> { read_chr(&self.chr, address) }
+
+	fn ppu_write(&mut self, address: u16, value: u8) -> Result<()> {
+		write_chr(&mut self.chr, address, value)
+	}
+
+	fn mirroring(&self) -> Mirroring {
+		self.mirroring
+	}
+}
+
+/// GxROM (0x42) Mapper: 32 KB switchable PRG ROM bank in bits 4-5 of the register and 8 KB
+/// switchable CHR ROM bank in bits 0-1
+///     http://wiki.nesdev.com/w/index.php/GxROM
+pub struct GxRom {
+	prg: BankedMemory,
+	chr: BankedMemory,
+	mirroring: Mirroring
+}
+
+impl GxRom {
+	pub fn new(header: Header, prg: Vec<u8>, chr: Vec<u8>) -> GxRom {
+		GxRom {
+			prg: BankedMemory::rom(prg, 0x8000, 1),
+			chr: chr_memory(chr, 0x2000, 1),
+			mirroring: header.mirroring()
+		}
+	}
+}
+
+impl Mapper for GxRom {
+	fn cpu_read(&self, address: u16) -> Result<u8> { read_prg(&self.prg, address) }
+
+	fn cpu_write(&mut self, address: u16, value: u8) -> Result<()> {
+		match address {
+			0x8000 ..= 0xFFFF => {
+				let value = conflicted(&self.prg, true, address, value)?;
+				self.prg.switch(0, ((value >> 4) & 0x03) as usize);
+				self.chr.switch(0, (value & 0x03) as usize);
+				Ok(())
+			},
+			_ => Err(EmuError::BusFault { address, access: Access::Write })
+		}
+	}
+
+	fn ppu_read(&mut self, address: u16) -> Result<u8> { read_chr(&self.chr, address) }
+
+	fn ppu_write(&mut self, address: u16, value: u8) -> Result<()> {
+		write_chr(&mut self.chr, address, value)
+	}
+
+	fn mirroring(&self) -> Mirroring {
+		self.mirroring
+	}
+}
+
+#[cfg(test)]
+use mapper::{ numbered_banks, test_header };
+
+// Banks are filled with 0xFF around their bank numbers, so that writes there don't conflict
+#[test]
+fn test_uxrom() {
+	let mut uxrom = UxRom::new(test_header(8, 0, 0), numbered_banks(8, 0x4000, 0xFF), Vec::new());
+	assert_eq!(uxrom.cpu_read(0x8000).unwrap(), 0);
+	assert_eq!(uxrom.cpu_read(0xC000).unwrap(), 7);
+	uxrom.cpu_write(0x8001, 3).unwrap();
+	assert_eq!(uxrom.cpu_read(0x8000).unwrap(), 3);
+	assert_eq!(uxrom.cpu_read(0xC000).unwrap(), 7);
+
+	// CHR RAM
+	uxrom.ppu_write(0x1234, 0x56).unwrap();
+	assert_eq!(uxrom.ppu_read(0x1234).unwrap(), 0x56);
+}
+
+#[test]
+fn test_bus_conflicts() {
+	let mut header = test_header(8, 0, 0);
+	header.submapper = SUBMAPPER_BUS_CONFLICTS;
+	let mut uxrom = UxRom::new(header, numbered_banks(8, 0x4000, 0xFF), Vec::new());
+	// The ROM at 0xC000 holds 7, so writing 6 there selects 6 & 7
+	uxrom.cpu_write(0xC000, 0x06).unwrap();
+	assert_eq!(uxrom.cpu_read(0x8000).unwrap(), 6);
+	// The ROM at 0x8000 holds 6, so writing 5 there selects 5 & 6
+	uxrom.cpu_write(0x8000, 0x05).unwrap();
+	assert_eq!(uxrom.cpu_read(0x8000).unwrap(), 4);
+}
+
+#[test]
+fn test_cnrom() {
+	let (prg, chr) = (numbered_banks(1, 0x4000, 0xFF), numbered_banks(4, 0x2000, 0xFF));
+	let mut cnrom = CnRom::new(test_header(1, 4, 1), prg, chr);
+	assert_eq!(cnrom.cpu_read(0xC000).unwrap(), 0);
+	cnrom.cpu_write(0x8001, 2).unwrap();
+	assert_eq!(cnrom.ppu_read(0x0000).unwrap(), 2);
+	assert!(cnrom.ppu_write(0x0000, 0).is_err());
+	assert_eq!(cnrom.mirroring(), Mirroring::Vertical);
+}
+
+#[test]
+fn test_axrom() {
+	let mut axrom = AxRom::new(test_header(8, 0, 0), numbered_banks(4, 0x8000, 0xFF), Vec::new());
+	assert_eq!(axrom.mirroring(), Mirroring::SingleScreenLower);
+	axrom.cpu_write(0x8001, 0x13).unwrap();
+	assert_eq!(axrom.cpu_read(0x8000).unwrap(), 3);
+	assert_eq!(axrom.mirroring(), Mirroring::SingleScreenUpper);
+}
+
+#[test]
+fn test_gxrom() {
+	let (prg, chr) = (numbered_banks(4, 0x8000, 0xFF), numbered_banks(4, 0x2000, 0xFF));
+	let mut gxrom = GxRom::new(test_header(8, 4, 0), prg, chr);
+	gxrom.cpu_write(0x8001, 0x21).unwrap();
+	assert_eq!(gxrom.cpu_read(0x8000).unwrap(), 2);
+	assert_eq!(gxrom.ppu_read(0x0000).unwrap(), 1);
+}
@@ -0,0 +1,255 @@
+use error::{ Access, EmuError, Result };
+use mapper::sunsoft5b_audio::Sunsoft5bAudio;
+use mapper::{ chr_memory, Mapper, Mirroring };
+use memory::*;
+use rom::*;
+
+/// Size of the PRG ROM and RAM windows
+const PRG_BANK_SIZE: usize = 0x2000;
+
+/// Size of the CHR windows
+const CHR_BANK_SIZE: usize = 0x0400;
+
+/// Sunsoft FME-7 (0x45) Mapper, and the Sunsoft 5A and 5B which add audio
+///     http://wiki.nesdev.com/w/index.php/Sunsoft_FME-7
+///
+/// Registers are written by selecting a command at 0x8000-0x9FFF then writing its parameter to
+/// 0xA000-0xBFFF:
+///     0x0 -> 0x7: CHR banks
+///     0x8:        PRG bank at 0x6000, with bit 6 selecting RAM and bit 7 enabling it
+///     0x9 -> 0xB: PRG ROM banks at 0x8000, 0xA000 and 0xC000
+///     0xC:        Mirroring
+///     0xD:        IRQ control, bit 0 enabling IRQ and bit 7 the counter. Writing acknowledges.
+///     0xE, 0xF:   IRQ counter low and high bytes
+/// The 5B's audio is at 0xC000-0xDFFF (register select) and 0xE000-0xFFFF (data).
+///
+/// The IRQ counter decrements on every CPU cycle while enabled, and asserts IRQ when it wraps
+/// from 0 to 0xFFFF.
+pub struct Fme7 {
+	prg_rom: BankedMemory,
+	prg_ram: BankedMemory,
+	chr: BankedMemory,
+	command: u8,
+	prg_6000: u8,
+	mirroring: Mirroring,
+	irq_enabled: bool,
+	counter_enabled: bool,
+	counter: u16,
+	irq_pending: bool,
+	audio: Sunsoft5bAudio
+}
+
+impl Fme7 {
+	pub fn new(header: Header, prg: Vec<u8>, chr: Vec<u8>) -> Fme7 {
+		let mut prg_rom = BankedMemory::rom(prg, PRG_BANK_SIZE, 5);
+		prg_rom.switch_from_end(4, 1);
+		Fme7 {
+			prg_rom,
+			// Boards with more than one bank of PRG RAM switch between them with the same register
+			prg_ram: BankedMemory::ram(header.prg_ram_total(), PRG_BANK_SIZE, 1),
+			chr: chr_memory(chr, CHR_BANK_SIZE, 8),
+			command: 0,
+			prg_6000: 0,
+			mirroring: header.mirroring(),
+			irq_enabled: false,
+			counter_enabled: false,
+			counter: 0,
+			irq_pending: false,
+			audio: Sunsoft5bAudio::new()
+		}
+	}
+
+	fn prg_ram_selected(&self) -> bool {
+		self.prg_6000 & 0x40 != 0
+	}
+
+	fn prg_ram_enabled(&self) -> bool {
+		self.prg_6000 & 0xC0 == 0xC0
+	}
+
+	fn write_parameter(&mut self, value: u8) {
+		match self.command {
+			0x0 ..= 0x7 => self.chr.switch(self.command as usize, value as usize),
+			0x8 => {
+				self.prg_6000 = value;
+				let bank = (value & 0x3F) as usize;
+				self.prg_rom.switch(0, bank);
+				self.prg_ram.switch(0, bank);
+			},
+			0x9 ..= 0xB => self.prg_rom.switch((self.command - 0x8) as usize, (value & 0x3F) as usize),
+			0xC => {
+				self.mirroring = match value & 0b11 {
+					0 => Mirroring::Vertical,
+					1 => Mirroring::Horizontal,
+					2 => Mirroring::SingleScreenLower,
+					_ => Mirroring::SingleScreenUpper
+				};
+			},
+			0xD => {
+				self.irq_enabled = value & 0x01 != 0;
+				self.counter_enabled = value & 0x80 != 0;
+				self.irq_pending = false;
+			},
+			0xE => self.counter = (self.counter & 0xFF00) | value as u16,
+			_ => self.counter = (self.counter & 0x00FF) | (value as u16) << 8
+		}
+	}
+}
+
+// FME-7 Memory Map
+// CPU:
+// 0x6000 -> 0x7FFF: 8 KB switchable PRG ROM or RAM bank
+// 0x8000 -> 0xDFFF: Three 8 KB switchable PRG ROM banks
+// 0xE000 -> 0xFFFF: 8 KB PRG ROM bank fixed to the last bank
+// PPU:
+// 0x0000 -> 0x1FFF: Eight 1 KB switchable CHR banks
+impl Mapper for Fme7 {
+	fn cpu_read(&self, address: u16) -> Result<u8> {
+		match address {
+			0x6000 ..= 0x7FFF if !self.prg_ram_selected() => self.prg_rom.load(address - 0x6000),
+			0x6000 ..= 0x7FFF if self.prg_ram_enabled() => self.prg_ram.load(address - 0x6000),
+			0x8000 ..= 0xFFFF => self.prg_rom.load(address - 0x6000),
+			_ => Err(EmuError::BusFault { address, access: Access::Read })
+		}
+	}
+
+	fn cpu_write(&mut self, address: u16, value: u8) -> Result<()> {
+		match address {
+			0x6000 ..= 0x7FFF if self.prg_ram_enabled() => {
+				return self.prg_ram.store(address - 0x6000, value);
+			},
+			0x8000 ..= 0x9FFF => self.command = value & 0x0F,
+			0xA000 ..= 0xBFFF => self.write_parameter(value),
+			0xC000 ..= 0xDFFF => self.audio.select(value),
+			0xE000 ..= 0xFFFF => self.audio.write(value),
+			_ => return Err(EmuError::BusFault { address, access: Access::Write })
+		}
+		Ok(())
+	}
+
+	fn ppu_read(&mut self, address: u16) -> Result<u8> {
+		match address {
+			0x0000 ..= 0x1FFF => self.chr.load(address),
+			_ => Err(EmuError::BusFault { address, access: Access::Read })
+		}
+	}
+
+	fn ppu_write(&mut self, address: u16, value: u8) -> Result<()> {
+		match address {
+			0x0000 ..= 0x1FFF => self.chr.store(address, value),
+			_ => Err(EmuError::BusFault { address, access: Access::Write })
+		}
+	}
+
+	fn mirroring(&self) -> Mirroring {
+		self.mirroring
+	}
+
+	fn irq(&self) -> bool {
+		self.irq_pending
+	}
+
+	fn clock(&mut self, cpu_cycles: u32) {
+		for _ in 0..cpu_cycles {
+			if self.counter_enabled {
+				self.counter = self.counter.wrapping_sub(1);
+				if self.counter == 0xFFFF && self.irq_enabled {
+					self.irq_pending = true;
+				}
+			}
+			self.audio.clock();
+		}
+	}
+
+	fn audio(&self) -> f32 {
+		self.audio.output()
+	}
+
+	fn prg_ram(&self) -> Option<&[u8]> {
+		Some(self.prg_ram.data())
+	}
+
+	fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+		Some(self.prg_ram.data_mut())
+	}
+}
+
+#[cfg(test)]
+use mapper::{ numbered_banks, prg_banks, test_header };
+
+// 256 KB of PRG ROM and CHR ROM where each bank starts with its bank number
+#[cfg(test)]
+fn test_fme7() -> Fme7 {
+	let mut header = test_header(16, 32, 0);
+	header.prg_ram_size = 2 * PRG_BANK_SIZE;
+	let prg = numbered_banks(32, PRG_BANK_SIZE, 0);
+	let chr = numbered_banks(256, CHR_BANK_SIZE, 0);
+	Fme7::new(header, prg, chr)
+}
+
+#[cfg(test)]
+fn write_command(fme7: &mut Fme7, command: u8, value: u8) {
+	fme7.cpu_write(0x8000, command).unwrap();
+	fme7.cpu_write(0xA000, value).unwrap();
+}
+
+#[test]
+fn test_banking() {
+	let mut fme7 = test_fme7();
+	for (command, bank) in [(0x9, 3), (0xA, 4), (0xB, 5), (0x5, 0x42)].iter() {
+		write_command(&mut fme7, *command, *bank);
+	}
+	assert_eq!(prg_banks(&fme7), vec![3, 4, 5, 31]);
+	assert_eq!(fme7.ppu_read(0x1400).unwrap(), 0x42);
+	write_command(&mut fme7, 0xC, 3);
+	assert_eq!(fme7.mirroring(), Mirroring::SingleScreenUpper);
+}
+
+#[test]
+fn test_prg_6000() {
+	let mut fme7 = test_fme7();
+	// ROM
+	write_command(&mut fme7, 0x8, 0x07);
+	assert_eq!(fme7.cpu_read(0x6000).unwrap(), 7);
+	assert!(fme7.cpu_write(0x6000, 0x12).is_err());
+
+	// RAM, disabled then enabled
+	write_command(&mut fme7, 0x8, 0x40);
+	assert!(fme7.cpu_read(0x6000).is_err());
+	write_command(&mut fme7, 0x8, 0xC0);
+	fme7.cpu_write(0x6000, 0x12).unwrap();
+	write_command(&mut fme7, 0x8, 0xC1);
+	fme7.cpu_write(0x6000, 0x34).unwrap();
+	write_command(&mut fme7, 0x8, 0xC0);
+	assert_eq!(fme7.cpu_read(0x6000).unwrap(), 0x12);
+}
+
+#[test]
+fn test_irq() {
+	let mut fme7 = test_fme7();
+	write_command(&mut fme7, 0xE, 0x02);
+	write_command(&mut fme7, 0xF, 0x00);
+	write_command(&mut fme7, 0xD, 0x81);
+	fme7.clock(2);
+	assert!(!fme7.irq());
+	fme7.clock(1);
+	assert!(fme7.irq());
+
+	// Writing the control register acknowledges it, and the counter keeps going from 0xFFFF
+	write_command(&mut fme7, 0xD, 0x81);
+	assert!(!fme7.irq());
+	fme7.clock(0xFFFF);
+	assert!(!fme7.irq());
+	fme7.clock(1);
+	assert!(fme7.irq());
+}
+
+#[test]
+fn test_audio() {
+	let mut fme7 = test_fme7();
+	fme7.cpu_write(0xC000, 0x07).unwrap();
+	fme7.cpu_write(0xE000, 0x3F).unwrap();
+	fme7.cpu_write(0xC000, 0x08).unwrap();
+	fme7.cpu_write(0xE000, 0x0F).unwrap();
+	assert!(fme7.audio() > 0.0);
+}
@@ -0,0 +1,293 @@
+use error::{ Access, EmuError, Result };
+use mapper::{ chr_memory, Mapper, Mirroring };
+use memory::*;
+use rom::*;
+
+/// Size of the PRG ROM windows at 0x8000 and 0xC000
+const PRG_BANK_SIZE: usize = 0x4000;
+
+/// Size of the CHR windows at 0x0000 and 0x1000
+const CHR_BANK_SIZE: usize = 0x1000;
+
+/// Value of the shift register when it's empty. The marker bit reaches bit 0 after four writes,
+/// so the fifth write completes the register.
+const SHIFT_EMPTY: u8 = 0x10;
+
+/// MMC1 (0x1) Mapper, used by the SxROM boards
+///     http://wiki.nesdev.com/w/index.php/MMC1
+///
+/// Registers are loaded one bit at a time through a serial port at 0x8000-0xFFFF; the address of
+/// the fifth write selects the register:
+///     0x8000 -> 0x9FFF: Control (mirroring, PRG and CHR banking modes)
+///     0xA000 -> 0xBFFF: CHR bank 0
+///     0xC000 -> 0xDFFF: CHR bank 1
+///     0xE000 -> 0xFFFF: PRG bank and PRG RAM enable
+pub struct Mmc1 {
+	prg: BankedMemory,
+	chr: BankedMemory,
+	prg_ram: BankedMemory,
+	shift: u8,
+	control: u8,
+	chr_bank_0: u8,
+	chr_bank_1: u8,
+	prg_bank: u8,
+	wrote_this_instruction: bool // Set by a write to the serial port until the instruction ends
+}
+
+impl Mmc1 {
+	pub fn new(header: Header, prg: Vec<u8>, chr: Vec<u8>) -> Mmc1 {
+		let mut mmc1 = Mmc1 {
+			prg: BankedMemory::rom(prg, PRG_BANK_SIZE, 2),
+			chr: chr_memory(chr, CHR_BANK_SIZE, 2),
+			prg_ram: BankedMemory::ram(header.prg_ram_total(), 0x2000, 1),
+			shift: SHIFT_EMPTY,
+			// PRG mode 3 at power up, so the reset vector is always in the last bank
+			control: 0x0C,
+			chr_bank_0: 0,
+			chr_bank_1: 0,
+			prg_bank: 0,
+			wrote_this_instruction: false
+		};
+		mmc1.update_banks();
+		mmc1
+	}
+
+	fn prg_ram_enabled(&self) -> bool {
+		self.prg_bank & 0x10 == 0
+	}
+
+	// Write a bit to the shift register, or reset it when bit 7 is set
+	fn write_serial(&mut self, address: u16, value: u8) {
+		if value & 0x80 != 0 {
+			self.shift = SHIFT_EMPTY;
+			self.control |= 0x0C;
+		} else {
+			let complete = self.shift & 1 == 1;
+			self.shift = (self.shift >> 1) | (value & 1) << 4;
+			if complete {
+				let register = self.shift;
+				match address {
+					0x8000 ..= 0x9FFF => self.control = register,
+					0xA000 ..= 0xBFFF => self.chr_bank_0 = register,
+					0xC000 ..= 0xDFFF => self.chr_bank_1 = register,
+					_ => self.prg_bank = register
+				}
+				self.shift = SHIFT_EMPTY;
+			}
+		}
+		self.update_banks();
+	}
+
+	fn update_banks(&mut self) {
+		// Boards with 512 KB of PRG ROM (SUROM) use bit 4 of the CHR bank to select which 256 KB
+		// the PRG banks come from
+		let outer = if self.prg.bank_count() > 16 { (self.chr_bank_0 & 0x10) as usize } else { 0 };
+		let bank = (self.prg_bank & 0x0F) as usize | outer;
+		match (self.control >> 2) & 0b11 {
+			// 32 KB at 0x8000, ignoring the low bit of the bank number
+			0 | 1 => {
+				self.prg.switch(0, bank & !1);
+				self.prg.switch(1, bank | 1);
+			},
+			// First bank fixed at 0x8000, 16 KB switched at 0xC000
+			2 => {
+				self.prg.switch(0, outer);
+				self.prg.switch(1, bank);
+			},
+			// 16 KB switched at 0x8000, last bank fixed at 0xC000
+			_ => {
+				self.prg.switch(0, bank);
+				self.prg.switch(1, outer | 0x0F);
+			}
+		}
+
+		// Boards with 16 KB (SOROM) or 32 KB (SXROM) of PRG RAM select its 8 KB bank with bits 3
+		// and 2 of the CHR bank
+		let ram_bank = match self.prg_ram.bank_count() {
+			1 => 0,
+			2 => (self.chr_bank_0 >> 3) & 1,
+			_ => (self.chr_bank_0 >> 2) & 0b11
+		};
+		self.prg_ram.switch(0, ram_bank as usize);
+
+		if self.control & 0x10 == 0 {
+			// 8 KB, ignoring the low bit of the bank number
+			let bank = (self.chr_bank_0 & 0x1E) as usize;
+			self.chr.switch(0, bank);
+			self.chr.switch(1, bank | 1);
+		} else {
+			self.chr.switch(0, self.chr_bank_0 as usize);
+			self.chr.switch(1, self.chr_bank_1 as usize);
+		}
+	}
+}
+
+// MMC1 Memory Map
+// CPU:
+// 0x6000 -> 0x7FFF: 8 KB PRG RAM bank, when enabled
+// 0x8000 -> 0xBFFF: 16 KB PRG ROM bank, switchable or fixed to the first bank
+// 0xC000 -> 0xFFFF: 16 KB PRG ROM bank, switchable or fixed to the last bank
+// PPU:
+// 0x0000 -> 0x0FFF: 4 KB switchable CHR bank
+// 0x1000 -> 0x1FFF: 4 KB switchable CHR bank
+impl Mapper for Mmc1 {
+	fn cpu_read(&self, address: u16) -> Result<u8> {
+		match address {
+			0x6000 ..= 0x7FFF if self.prg_ram_enabled() => self.prg_ram.load(address - 0x6000),
+			0x8000 ..= 0xFFFF => self.prg.load(address - 0x8000),
+			_ => Err(EmuError::BusFault { address, access: Access::Read })
+		}
+	}
+
+	fn cpu_write(&mut self, address: u16, value: u8) -> Result<()> {
+		match address {
+			0x6000 ..= 0x7FFF if self.prg_ram_enabled() => self.prg_ram.store(address - 0x6000, value),
+			0x8000 ..= 0xFFFF => {
+				// Writes on consecutive cycles, like the dummy write of a read-modify-write
+				// instruction, only count once
+				if !self.wrote_this_instruction {
+					self.wrote_this_instruction = true;
+					self.write_serial(address, value);
+				}
+				Ok(())
+			},
+			_ => Err(EmuError::BusFault { address, access: Access::Write })
+		}
+	}
+
+	fn ppu_read(&mut self, address: u16) -> Result<u8> {
+		match address {
+			0x0000 ..= 0x1FFF => self.chr.load(address),
+			_ => Err(EmuError::BusFault { address, access: Access::Read })
+		}
+	}
+
+	fn ppu_write(&mut self, address: u16, value: u8) -> Result<()> {
+		match address {
+			0x0000 ..= 0x1FFF => self.chr.store(address, value),
+			_ => Err(EmuError::BusFault { address, access: Access::Write })
+		}
+	}
+
+	fn mirroring(&self) -> Mirroring {
+		match self.control & 0b11 {
+			0 => Mirroring::SingleScreenLower,
+			1 => Mirroring::SingleScreenUpper,
+			2 => Mirroring::Vertical,
+			_ => Mirroring::Horizontal
+		}
+	}
+
+	fn clock(&mut self, _cpu_cycles: u32) {
+		self.wrote_this_instruction = false;
+	}
+
+	fn prg_ram(&self) -> Option<&[u8]> {
+		Some(self.prg_ram.data())
+	}
+
+	fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+		Some(self.prg_ram.data_mut())
+	}
+}
+
+#[cfg(test)]
+use mapper::{ numbered_banks, test_header };
+
+// Load a register through the serial port, one instruction per write
+#[cfg(test)]
+fn write_register(mmc1: &mut Mmc1, address: u16, value: u8) {
+	for i in 0..5 {
+		mmc1.cpu_write(address, value >> i).unwrap();
+		mmc1.clock(4);
+	}
+}
+
+// 256 KB of PRG ROM where each 16 KB bank starts with its bank number
+#[cfg(test)]
+fn test_mmc1() -> Mmc1 {
+	let prg = numbered_banks(16, PRG_BANK_SIZE, 0);
+	Mmc1::new(test_header(16, 0, 0), prg, Vec::new())
+}
+
+#[test]
+fn test_prg_banking() {
+	let mut mmc1 = test_mmc1();
+	// Last bank fixed at power up
+	assert_eq!(mmc1.cpu_read(0xC000).unwrap(), 15);
+
+	write_register(&mut mmc1, 0xE000, 5);
+	assert_eq!(mmc1.cpu_read(0x8000).unwrap(), 5);
+	assert_eq!(mmc1.cpu_read(0xC000).unwrap(), 15);
+
+	// Fix the first bank
+	write_register(&mut mmc1, 0x8000, 0b01000);
+	assert_eq!(mmc1.cpu_read(0x8000).unwrap(), 0);
+	assert_eq!(mmc1.cpu_read(0xC000).unwrap(), 5);
+
+	// 32 KB mode ignores the low bit
+	write_register(&mut mmc1, 0x8000, 0b00000);
+	assert_eq!(mmc1.cpu_read(0x8000).unwrap(), 4);
+	assert_eq!(mmc1.cpu_read(0xC000).unwrap(), 5);
+	assert_eq!(mmc1.mirroring(), Mirroring::SingleScreenLower);
+
+	// Reset returns to fixing the last bank
+	mmc1.cpu_write(0x8000, 0x80).unwrap();
+	assert_eq!(mmc1.cpu_read(0xC000).unwrap(), 15);
+}
+
+#[test]
+fn test_chr_banking() {
+	let chr = numbered_banks(4, CHR_BANK_SIZE, 0);
+	let mut mmc1 = Mmc1::new(test_header(2, 2, 0), vec![0; 2 * PRG_BANK_SIZE], chr);
+
+	// 8 KB mode
+	write_register(&mut mmc1, 0xA000, 3);
+	assert_eq!(mmc1.ppu_read(0x0000).unwrap(), 2);
+	assert_eq!(mmc1.ppu_read(0x1000).unwrap(), 3);
+
+	// 4 KB mode, with vertical mirroring
+	write_register(&mut mmc1, 0x8000, 0b11110);
+	write_register(&mut mmc1, 0xC000, 1);
+	assert_eq!(mmc1.ppu_read(0x0000).unwrap(), 3);
+	assert_eq!(mmc1.ppu_read(0x1000).unwrap(), 1);
+	assert_eq!(mmc1.mirroring(), Mirroring::Vertical);
+}
+
+#[test]
+fn test_prg_ram_enable() {
+	let mut mmc1 = test_mmc1();
+	mmc1.cpu_write(0x6000, 0x12).unwrap();
+	assert_eq!(mmc1.cpu_read(0x6000).unwrap(), 0x12);
+	write_register(&mut mmc1, 0xE000, 0x10);
+	assert!(mmc1.cpu_read(0x6000).is_err());
+	assert!(mmc1.cpu_write(0x6000, 0x34).is_err());
+}
+
+#[test]
+fn test_prg_ram_banking() {
+	// SXROM, with 32 KB of PRG RAM
+	let mut header = test_header(16, 0, 0);
+	header.prg_ram_size = 0x8000;
+	let mut mmc1 = Mmc1::new(header, numbered_banks(16, PRG_BANK_SIZE, 0), Vec::new());
+	assert_eq!(mmc1.prg_ram().unwrap().len(), 0x8000);
+	mmc1.cpu_write(0x6000, 0x12).unwrap();
+	write_register(&mut mmc1, 0xA000, 0b01100);
+	mmc1.cpu_write(0x6000, 0x34).unwrap();
+	assert_eq!(mmc1.prg_ram().unwrap()[0x0000], 0x12);
+	assert_eq!(mmc1.prg_ram().unwrap()[0x6000], 0x34);
+	write_register(&mut mmc1, 0xA000, 0);
+	assert_eq!(mmc1.cpu_read(0x6000).unwrap(), 0x12);
+}
+
+#[test]
+fn test_consecutive_writes() {
+	let mut mmc1 = test_mmc1();
+	// A read-modify-write writes twice in one instruction; only the first write counts
+	for _ in 0..5 {
+		mmc1.cpu_write(0xE000, 0x01).unwrap();
+		mmc1.cpu_write(0xE000, 0x00).unwrap();
+		mmc1.clock(6);
+	}
+	assert_eq!(mmc1.cpu_read(0x8000).unwrap(), 15);
+}
@@ -0,0 +1,235 @@
+use error::{ Access, EmuError, Result };
+use mapper::{ chr_memory, Mapper, Mirroring };
+use memory::*;
+use rom::*;
+
+/// Size of the CHR windows at 0x0000 and 0x1000
+const CHR_BANK_SIZE: usize = 0x1000;
+
+/// Which of the two latch mappers the board uses. They share the CHR latches and differ in PRG
+/// banking.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LatchChip {
+	/// MMC2 (0x9), used by Punch-Out!!: 8 KB switchable PRG ROM bank and the last three fixed
+	Mmc2,
+	/// MMC4 (0xA), used by the Fire Emblem games: 16 KB switchable PRG ROM bank and the last one
+	/// fixed, with 8 KB of PRG RAM
+	Mmc4
+}
+
+/// MMC2 (0x9) and MMC4 (0xA) Mappers, used by the PxROM and FxROM boards
+///     http://wiki.nesdev.com/w/index.php/MMC2
+///     http://wiki.nesdev.com/w/index.php/MMC4
+///
+/// Each CHR window has two bank registers, and a latch that picks between them. The latches are
+/// set by the PPU fetching the pattern of tile 0xFD or 0xFE, so a game can switch banks partway
+/// down the screen by placing those tiles. The new bank takes effect after the fetch that set it.
+///     0xA000 -> 0xAFFF: PRG ROM bank
+///     0xB000 -> 0xBFFF: CHR bank at 0x0000 when latch 0 is 0xFD
+///     0xC000 -> 0xCFFF: CHR bank at 0x0000 when latch 0 is 0xFE
+///     0xD000 -> 0xDFFF: CHR bank at 0x1000 when latch 1 is 0xFD
+///     0xE000 -> 0xEFFF: CHR bank at 0x1000 when latch 1 is 0xFE
+///     0xF000 -> 0xFFFF: Mirroring
+pub struct Mmc2 {
+	chip: LatchChip,
+	prg: BankedMemory,
+	chr: BankedMemory,
+	prg_ram: BankedMemory,
+	chr_banks: [[u8; 2]; 2], // FD and FE banks for each window
+	latches: [u8; 2],        // 0xFD or 0xFE for each window
+	mirroring: Mirroring
+}
+
+impl Mmc2 {
+	pub fn new(header: Header, prg: Vec<u8>, chr: Vec<u8>, chip: LatchChip) -> Mmc2 {
+		let prg = match chip {
+			LatchChip::Mmc2 => {
+				let mut prg = BankedMemory::rom(prg, 0x2000, 4);
+				for window in 1..4 {
+					prg.switch_from_end(window, 4 - window);
+				}
+				prg
+			},
+			LatchChip::Mmc4 => {
+				let mut prg = BankedMemory::rom(prg, 0x4000, 2);
+				prg.switch_from_end(1, 1);
+				prg
+			}
+		};
+		let mut mmc2 = Mmc2 {
+			chip,
+			prg,
+			chr: chr_memory(chr, CHR_BANK_SIZE, 2),
+			prg_ram: BankedMemory::ram(header.prg_ram_total(), 0x2000, 1),
+			chr_banks: [[0; 2]; 2],
+			latches: [0xFE; 2],
+			mirroring: Mirroring::Vertical
+		};
+		mmc2.update_chr_banks();
+		mmc2
+	}
+
+	fn has_prg_ram(&self) -> bool {
+		self.chip == LatchChip::Mmc4
+	}
+
+	fn update_chr_banks(&mut self) {
+		for window in 0..2 {
+			let latch = (self.latches[window] - 0xFD) as usize;
+			self.chr.switch(window, self.chr_banks[window][latch] as usize);
+		}
+	}
+
+	// Set a latch if the PPU fetched the pattern of tile 0xFD or 0xFE. The MMC2 only watches the
+	// first fetch of the tile for latch 0, while the MMC4 and latch 1 watch all eight.
+	fn watch_fetch(&mut self, address: u16) {
+		let window = (address >> 12) as usize & 1;
+		let tile = (address >> 4) as u8;
+		if address & 0x0008 == 0 || (tile != 0xFD && tile != 0xFE) {
+			return;
+		}
+		if window == 0 && self.chip == LatchChip::Mmc2 && address & 0x0007 != 0 {
+			return;
+		}
+		self.latches[window] = tile;
+		self.update_chr_banks();
+	}
+}
+
+// MMC2 Memory Map
+// CPU:
+// 0x6000 -> 0x7FFF: 8 KB PRG RAM (MMC4 only)
+// 0x8000 -> 0x9FFF: 8 KB switchable PRG ROM bank (MMC2)
+// 0xA000 -> 0xFFFF: Last three 8 KB PRG ROM banks, fixed (MMC2)
+// 0x8000 -> 0xBFFF: 16 KB switchable PRG ROM bank (MMC4)
+// 0xC000 -> 0xFFFF: Last 16 KB PRG ROM bank, fixed (MMC4)
+// PPU:
+// 0x0000 -> 0x0FFF: 4 KB CHR bank selected by latch 0
+// 0x1000 -> 0x1FFF: 4 KB CHR bank selected by latch 1
+impl Mapper for Mmc2 {
+	fn cpu_read(&self, address: u16) -> Result<u8> {
+		match address {
+			0x6000 ..= 0x7FFF if self.has_prg_ram() => self.prg_ram.load(address - 0x6000),
+			0x8000 ..= 0xFFFF => self.prg.load(address - 0x8000),
+			_ => Err(EmuError::BusFault { address, access: Access::Read })
+		}
+	}
+
+	fn cpu_write(&mut self, address: u16, value: u8) -> Result<()> {
+		match address {
+			0x6000 ..= 0x7FFF if self.has_prg_ram() => self.prg_ram.store(address - 0x6000, value),
+			0xA000 ..= 0xAFFF => {
+				self.prg.switch(0, (value & 0x0F) as usize);
+				Ok(())
+			},
+			0xB000 ..= 0xEFFF => {
+				let register = ((address - 0xB000) >> 12) as usize;
+				self.chr_banks[register / 2][register % 2] = value & 0x1F;
+				self.update_chr_banks();
+				Ok(())
+			},
+			0xF000 ..= 0xFFFF => {
+				self.mirroring = if value & 1 == 0 { Mirroring::Vertical } else { Mirroring::Horizontal };
+				Ok(())
+			},
+			// Writes to 0x8000-0x9FFF don't reach a register
+			0x8000 ..= 0x9FFF => Ok(()),
+			_ => Err(EmuError::BusFault { address, access: Access::Write })
+		}
+	}
+
+	fn ppu_read(&mut self, address: u16) -> Result<u8> {
+		match address {
+			0x0000 ..= 0x1FFF => {
+				let value = self.chr.load(address)?;
+				self.watch_fetch(address);
+				Ok(value)
+			},
+			_ => Err(EmuError::BusFault { address, access: Access::Read })
+		}
+	}
+
+	fn ppu_write(&mut self, address: u16, value: u8) -> Result<()> {
+		match address {
+			0x0000 ..= 0x1FFF => self.chr.store(address, value),
+			_ => Err(EmuError::BusFault { address, access: Access::Write })
+		}
+	}
+
+	fn mirroring(&self) -> Mirroring {
+		self.mirroring
+	}
+
+	fn prg_ram(&self) -> Option<&[u8]> {
+		if self.has_prg_ram() { Some(self.prg_ram.data()) } else { None }
+	}
+
+	fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+		if self.has_prg_ram() { Some(self.prg_ram.data_mut()) } else { None }
+	}
+}
+
+#[cfg(test)]
+use mapper::{ numbered_banks, prg_banks, test_header };
+
+// 128 KB of PRG ROM in 8 KB banks and 16 banks of CHR ROM, each starting with its bank number
+#[cfg(test)]
+fn test_mmc2(chip: LatchChip) -> Mmc2 {
+	let prg = numbered_banks(16, 0x2000, 0);
+	let chr = numbered_banks(16, CHR_BANK_SIZE, 0);
+	Mmc2::new(test_header(8, 8, 0), prg, chr, chip)
+}
+
+#[test]
+fn test_prg_banking() {
+	let mut mmc2 = test_mmc2(LatchChip::Mmc2);
+	mmc2.cpu_write(0xA000, 5).unwrap();
+	assert_eq!(prg_banks(&mmc2), vec![5, 13, 14, 15]);
+	assert!(mmc2.cpu_read(0x6000).is_err());
+
+	let mut mmc4 = test_mmc2(LatchChip::Mmc4);
+	mmc4.cpu_write(0xA000, 2).unwrap();
+	assert_eq!(mmc4.cpu_read(0x8000).unwrap(), 4);
+	assert_eq!(mmc4.cpu_read(0xC000).unwrap(), 14);
+	mmc4.cpu_write(0x6000, 0x12).unwrap();
+	assert_eq!(mmc4.cpu_read(0x6000).unwrap(), 0x12);
+}
+
+#[test]
+fn test_chr_latches() {
+	let mut mmc2 = test_mmc2(LatchChip::Mmc2);
+	for (i, address) in [0xB000, 0xC000, 0xD000, 0xE000].iter().enumerate() {
+		mmc2.cpu_write(*address, i as u8 + 4).unwrap();
+	}
+	// Both latches start on 0xFE
+	assert_eq!(mmc2.ppu_read(0x0000).unwrap(), 5);
+	assert_eq!(mmc2.ppu_read(0x1000).unwrap(), 7);
+
+	// The fetch that sets the latch still sees the old bank
+	mmc2.ppu_read(0x0FD8).unwrap();
+	assert_eq!(mmc2.ppu_read(0x0000).unwrap(), 4);
+	mmc2.ppu_read(0x1FDB).unwrap();
+	assert_eq!(mmc2.ppu_read(0x1000).unwrap(), 6);
+
+	// Latch 0 on the MMC2 only watches the first fetch of the tile
+	mmc2.ppu_read(0x0FE9).unwrap();
+	assert_eq!(mmc2.ppu_read(0x0000).unwrap(), 4);
+	mmc2.ppu_read(0x0FE8).unwrap();
+	assert_eq!(mmc2.ppu_read(0x0000).unwrap(), 5);
+
+	// The low plane of the tile doesn't set the latch
+	mmc2.ppu_read(0x1FD0).unwrap();
+	assert_eq!(mmc2.ppu_read(0x1000).unwrap(), 6);
+
+	let mut mmc4 = test_mmc2(LatchChip::Mmc4);
+	mmc4.cpu_write(0xB000, 3).unwrap();
+	mmc4.ppu_read(0x0FDF).unwrap();
+	assert_eq!(mmc4.ppu_read(0x0000).unwrap(), 3);
+}
+
+#[test]
+fn test_mirroring() {
+	let mut mmc2 = test_mmc2(LatchChip::Mmc2);
+	mmc2.cpu_write(0xF000, 1).unwrap();
+	assert_eq!(mmc2.mirroring(), Mirroring::Horizontal);
+}
@@ -0,0 +1,430 @@
+use error::{ Access, EmuError, Result };
+use mapper::{ chr_memory, Mapper, Mirroring };
+use memory::*;
+use rom::*;
+
+/// Size of the PRG ROM windows
+const PRG_BANK_SIZE: usize = 0x2000;
+
+/// Size of the CHR windows. R0 and R1 select pairs of banks.
+const CHR_BANK_SIZE: usize = 0x0400;
+
+/// PRG RAM inside the MMC6, mirrored through 0x7000-0x7FFF
+const MMC6_RAM_SIZE: usize = 0x0400;
+
+/// Revision of the MMC3, which changes when the scanline counter asserts IRQ, or the MMC6
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mmc3Revision {
+	/// NEC MMC3A (NES 2.0 submapper 4). Only asserts IRQ when the counter is decremented to 0, or
+	/// reloaded after a write to 0xC001, so a latch of 0 gives a single IRQ.
+	A,
+	/// Sharp MMC3B and MMC3C. Asserts IRQ whenever the counter is 0 after clocking, so a latch of
+	/// 0 gives an IRQ on every scanline.
+	B,
+	/// MMC6 (NES 2.0 submapper 1), used by the HKROM board. Counts like the MMC3B, but has 1 KB of
+	/// PRG RAM of its own, enabled by bit 5 of bank select and protected a half at a time by bits
+	/// 4-7 of 0xA001.
+	Mmc6
+}
+
+/// MMC3 (0x4) Mapper, used by the TxROM boards
+///     http://wiki.nesdev.com/w/index.php/MMC3
+///
+/// Registers are selected by the address range and whether the address is even or odd:
+///     0x8000 -> 0x9FFF: Bank select (even), bank data (odd)
+///     0xA000 -> 0xBFFF: Mirroring (even), PRG RAM protect (odd)
+///     0xC000 -> 0xDFFF: IRQ latch (even), IRQ reload (odd)
+///     0xE000 -> 0xFFFF: IRQ disable (even), IRQ enable (odd)
+///
+/// The scanline counter is clocked by rising edges of PPU A12. With the usual setup of
+/// background tiles at 0x0000 and sprites at 0x1000, that happens once per scanline when the PPU
+/// starts fetching sprite patterns. MMC3 keeps the default nametable_read(), so it ignores the
+/// nametable fetches the PPU makes with A12 low between sprite fetches. That stands in for the
+/// real chip's filter, which ignores short low pulses of A12.
+pub struct Mmc3 {
+	revision: Mmc3Revision,
+	prg: BankedMemory,
+	chr: BankedMemory,
+	prg_ram: BankedMemory,
+	bank_select: u8,
+	registers: [u8; 8],     // R0-R7, written through bank data
+	mirroring: Mirroring,
+	four_screen: bool,
+	prg_ram_enabled: bool,
+	prg_ram_writable: bool,
+	mmc6_protect: u8,       // Read and write enables for 0x7200 (bits 3, 2) and 0x7000 (bits 1, 0)
+	irq_latch: u8,
+	irq_counter: u8,
+	irq_reload: bool,       // Set by 0xC001 until the counter is next clocked
+	irq_enabled: bool,
+	irq_pending: bool,
+	a12: bool               // Level of PPU A12 on the last PPU access
+}
+
+impl Mmc3 {
+	pub fn new(header: Header, prg: Vec<u8>, chr: Vec<u8>, revision: Mmc3Revision) -> Mmc3 {
+		let mirroring = header.mirroring();
+		let mmc6 = revision == Mmc3Revision::Mmc6;
+		let mut mmc3 = Mmc3 {
+			revision,
+			prg: BankedMemory::rom(prg, PRG_BANK_SIZE, 4),
+			chr: chr_memory(chr, CHR_BANK_SIZE, 8),
+			prg_ram: if mmc6 {
+				BankedMemory::ram(MMC6_RAM_SIZE, MMC6_RAM_SIZE, 1)
+			} else {
+				BankedMemory::ram(header.prg_ram_total(), 0x2000, 1)
+			},
+			bank_select: 0,
+			registers: [0, 2, 4, 5, 6, 7, 0, 1],
+			mirroring,
+			four_screen: mirroring == Mirroring::FourScreen,
+			prg_ram_enabled: !mmc6,
+			prg_ram_writable: true,
+			mmc6_protect: 0,
+			irq_latch: 0,
+			irq_counter: 0,
+			irq_reload: false,
+			irq_enabled: false,
+			irq_pending: false,
+			a12: false
+		};
+		mmc3.update_banks();
+		mmc3
+	}
+
+	fn update_banks(&mut self) {
+		let r = self.registers;
+
+		// PRG mode 1 swaps the windows at 0x8000 and 0xC000
+		let (r6_window, fixed_window) = if self.bank_select & 0x40 == 0 { (0, 2) } else { (2, 0) };
+		self.prg.switch(r6_window, (r[6] & 0x3F) as usize);
+		self.prg.switch(1, (r[7] & 0x3F) as usize);
+		self.prg.switch_from_end(fixed_window, 2);
+		self.prg.switch_from_end(3, 1);
+
+		// CHR inversion swaps the 2 KB banks at 0x0000 with the 1 KB banks at 0x1000
+		let (two_kb, one_kb) = if self.bank_select & 0x80 == 0 { (0, 4) } else { (4, 0) };
+		self.chr.switch(two_kb, (r[0] & 0xFE) as usize);
+		self.chr.switch(two_kb + 1, (r[0] | 0x01) as usize);
+		self.chr.switch(two_kb + 2, (r[1] & 0xFE) as usize);
+		self.chr.switch(two_kb + 3, (r[1] | 0x01) as usize);
+		for i in 0..4 {
+			self.chr.switch(one_kb + i, r[2 + i] as usize);
+		}
+	}
+
+	fn write_register(&mut self, address: u16, value: u8) {
+		match (address & 0xE000, address & 1) {
+			(0x8000, 0) => {
+				self.bank_select = value;
+				if self.revision == Mmc3Revision::Mmc6 {
+					self.prg_ram_enabled = value & 0x20 != 0;
+				}
+				self.update_banks();
+			},
+			(0x8000, _) => {
+				self.registers[(self.bank_select & 0x07) as usize] = value;
+				self.update_banks();
+			},
+			(0xA000, 0) => {
+				// Boards with four screen VRAM have the mirroring hardwired
+				if !self.four_screen {
+					self.mirroring = if value & 1 == 0 { Mirroring::Vertical } else { Mirroring::Horizontal };
+				}
+			},
+			// The MMC6 ignores writes to its protect bits while its RAM is disabled
+			(0xA000, _) if self.revision == Mmc3Revision::Mmc6 => {
+				if self.prg_ram_enabled {
+					self.mmc6_protect = value >> 4;
+				}
+			},
+			(0xA000, _) => {
+				self.prg_ram_enabled = value & 0x80 != 0;
+				self.prg_ram_writable = value & 0x40 == 0;
+			},
+			(0xC000, 0) => self.irq_latch = value,
+			(0xC000, _) => {
+				self.irq_counter = 0;
+				self.irq_reload = true;
+			},
+			(0xE000, 0) => {
+				self.irq_enabled = false;
+				self.irq_pending = false;
+			},
+			_ => self.irq_enabled = true
+		}
+	}
+
+	// Clock the scanline counter on a rising edge of PPU A12
+	fn watch_a12(&mut self, address: u16) {
+		let a12 = address & 0x1000 != 0;
+		if a12 && !self.a12 {
+			self.clock_counter();
+		}
+		self.a12 = a12;
+	}
+
+	fn clock_counter(&mut self) {
+		let was_zero = self.irq_counter == 0;
+		let reloaded = self.irq_reload;
+		if was_zero || reloaded {
+			self.irq_counter = self.irq_latch;
+		} else {
+			self.irq_counter -= 1;
+		}
+		self.irq_reload = false;
+
+		let fires = match self.revision {
+			Mmc3Revision::A => !was_zero || reloaded,
+			Mmc3Revision::B | Mmc3Revision::Mmc6 => true
+		};
+		if fires && self.irq_counter == 0 && self.irq_enabled {
+			self.irq_pending = true;
+		}
+	}
+
+	// A half of the MMC6's RAM that can't be read reads as 0, unless neither half can
+	fn load_mmc6_ram(&self, address: u16) -> Result<u8> {
+		match address {
+			0x7000 ..= 0x7FFF if self.prg_ram_enabled && self.mmc6_protect & 0b1010 != 0 => {
+				if self.mmc6_protect >> mmc6_shift(address) & 0b10 != 0 {
+					self.prg_ram.load(address & 0x03FF)
+				} else {
+					Ok(0)
+				}
+			},
+			_ => Err(EmuError::BusFault { address, access: Access::Read })
+		}
+	}
+
+	// Writes to a half of the MMC6's RAM need both its read and write enables
+	fn store_mmc6_ram(&mut self, address: u16, value: u8) -> Result<()> {
+		match address {
+			0x7000 ..= 0x7FFF if self.prg_ram_enabled => {
+				if self.mmc6_protect >> mmc6_shift(address) & 0b11 == 0b11 {
+					self.prg_ram.store(address & 0x03FF, value)?;
+				}
+				Ok(())
+			},
+			_ => Err(EmuError::BusFault { address, access: Access::Write })
+		}
+	}
+}
+
+// Shift of the MMC6 protect bits for the half of its RAM the address is in
+fn mmc6_shift(address: u16) -> u8 {
+	if address & 0x0200 != 0 { 2 } else { 0 }
+}
+
+// MMC3 Memory Map
+// CPU:
+// 0x6000 -> 0x7FFF: 8 KB PRG RAM, when enabled, or on the MMC6 1 KB mirrored from 0x7000
+// 0x8000 -> 0x9FFF: 8 KB switchable PRG ROM bank (R6), or fixed to the second last bank
+// 0xA000 -> 0xBFFF: 8 KB switchable PRG ROM bank (R7)
+// 0xC000 -> 0xDFFF: 8 KB PRG ROM bank fixed to the second last bank, or switchable (R6)
+// 0xE000 -> 0xFFFF: 8 KB PRG ROM bank fixed to the last bank
+// PPU:
+// 0x0000 -> 0x0FFF: Two 2 KB switchable CHR banks (R0, R1), or four 1 KB banks when inverted
+// 0x1000 -> 0x1FFF: Four 1 KB switchable CHR banks (R2-R5), or two 2 KB banks when inverted
+impl Mapper for Mmc3 {
+	fn cpu_read(&self, address: u16) -> Result<u8> {
+		match address {
+			0x6000 ..= 0x7FFF if self.revision == Mmc3Revision::Mmc6 => self.load_mmc6_ram(address),
+			0x6000 ..= 0x7FFF if self.prg_ram_enabled => self.prg_ram.load(address - 0x6000),
+			0x8000 ..= 0xFFFF => self.prg.load(address - 0x8000),
+			_ => Err(EmuError::BusFault { address, access: Access::Read })
+		}
+	}
+
+	fn cpu_write(&mut self, address: u16, value: u8) -> Result<()> {
+		match address {
+			0x6000 ..= 0x7FFF if self.revision == Mmc3Revision::Mmc6 => {
+				self.store_mmc6_ram(address, value)
+			},
+			0x6000 ..= 0x7FFF if self.prg_ram_enabled => {
+				// Writes to protected PRG RAM are ignored
+				if self.prg_ram_writable {
+					self.prg_ram.store(address - 0x6000, value)?;
+				}
+				Ok(())
+			},
+			0x8000 ..= 0xFFFF => {
+				self.write_register(address, value);
+				Ok(())
+			},
+			_ => Err(EmuError::BusFault { address, access: Access::Write })
+		}
+	}
+
+	fn ppu_read(&mut self, address: u16) -> Result<u8> {
+		self.watch_a12(address);
+		match address {
+			0x0000 ..= 0x1FFF => self.chr.load(address),
+			_ => Err(EmuError::BusFault { address, access: Access::Read })
+		}
+	}
+
+	fn ppu_write(&mut self, address: u16, value: u8) -> Result<()> {
+		self.watch_a12(address);
+		match address {
+			0x0000 ..= 0x1FFF => self.chr.store(address, value),
+			_ => Err(EmuError::BusFault { address, access: Access::Write })
+		}
+	}
+
+	fn mirroring(&self) -> Mirroring {
+		self.mirroring
+	}
+
+	fn irq(&self) -> bool {
+		self.irq_pending
+	}
+
+	fn prg_ram(&self) -> Option<&[u8]> {
+		Some(self.prg_ram.data())
+	}
+
+	fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+		Some(self.prg_ram.data_mut())
+	}
+}
+
+#[cfg(test)]
+use mapper::{ numbered_banks, prg_banks, test_header };
+
+// 8 banks of PRG ROM and CHR ROM where each bank starts with its bank number
+#[cfg(test)]
+fn test_mmc3(revision: Mmc3Revision) -> Mmc3 {
+	let prg = numbered_banks(8, PRG_BANK_SIZE, 0);
+	let chr = numbered_banks(8, CHR_BANK_SIZE, 0);
+	Mmc3::new(test_header(4, 1, 0), prg, chr, revision)
+}
+
+// Fetch a background tile then a sprite tile, like the PPU does on each rendered scanline
+#[cfg(test)]
+fn scanline(mmc3: &mut Mmc3) {
+	mmc3.ppu_read(0x0010).unwrap();
+	mmc3.ppu_read(0x1010).unwrap();
+	mmc3.ppu_read(0x1018).unwrap();
+}
+
+#[test]
+fn test_prg_banking() {
+	let mut mmc3 = test_mmc3(Mmc3Revision::B);
+	mmc3.cpu_write(0x8000, 6).unwrap();
+	mmc3.cpu_write(0x8001, 3).unwrap();
+	mmc3.cpu_write(0x8000, 7).unwrap();
+	mmc3.cpu_write(0x8001, 4).unwrap();
+	assert_eq!(prg_banks(&mmc3), vec![3, 4, 6, 7]);
+
+	// PRG mode 1 fixes the second last bank at 0x8000
+	mmc3.cpu_write(0x8000, 0x40).unwrap();
+	assert_eq!(prg_banks(&mmc3), vec![6, 4, 3, 7]);
+}
+
+#[test]
+fn test_chr_banking() {
+	let mut mmc3 = test_mmc3(Mmc3Revision::B);
+	for (register, bank) in [(0, 3), (1, 6), (2, 1), (5, 7)].iter() {
+		mmc3.cpu_write(0x8000, *register).unwrap();
+		mmc3.cpu_write(0x8001, *bank).unwrap();
+	}
+	let banks = |mmc3: &mut Mmc3| -> Vec<u8> {
+		(0..8).map(|i| mmc3.ppu_read(i * 0x400).unwrap()).collect()
+	};
+	assert_eq!(banks(&mut mmc3), vec![2, 3, 6, 7, 1, 5, 6, 7]);
+
+	// Inverted
+	mmc3.cpu_write(0x8000, 0x80).unwrap();
+	assert_eq!(banks(&mut mmc3), vec![1, 5, 6, 7, 2, 3, 6, 7]);
+}
+
+#[test]
+fn test_mirroring_and_prg_ram() {
+	let mut mmc3 = test_mmc3(Mmc3Revision::B);
+	mmc3.cpu_write(0xA000, 1).unwrap();
+	assert_eq!(mmc3.mirroring(), Mirroring::Horizontal);
+	mmc3.cpu_write(0xA000, 0).unwrap();
+	assert_eq!(mmc3.mirroring(), Mirroring::Vertical);
+
+	mmc3.cpu_write(0x6000, 0x12).unwrap();
+	mmc3.cpu_write(0xA001, 0xC0).unwrap();
+	mmc3.cpu_write(0x6000, 0x34).unwrap();
+	assert_eq!(mmc3.cpu_read(0x6000).unwrap(), 0x12);
+	mmc3.cpu_write(0xA001, 0x00).unwrap();
+	assert!(mmc3.cpu_read(0x6000).is_err());
+}
+
+#[test]
+fn test_scanline_irq() {
+	let mut mmc3 = test_mmc3(Mmc3Revision::B);
+	mmc3.cpu_write(0xC000, 2).unwrap();
+	mmc3.cpu_write(0xC001, 0).unwrap();
+	mmc3.cpu_write(0xE001, 0).unwrap();
+
+	// Reload to 2, then 1, then 0
+	scanline(&mut mmc3);
+	scanline(&mut mmc3);
+	assert!(!mmc3.irq());
+	scanline(&mut mmc3);
+	assert!(mmc3.irq());
+
+	// Acknowledge by disabling, then enable again; the counter reloads to 2
+	mmc3.cpu_write(0xE000, 0).unwrap();
+	mmc3.cpu_write(0xE001, 0).unwrap();
+	assert!(!mmc3.irq());
+	scanline(&mut mmc3);
+	assert!(!mmc3.irq());
+}
+
+#[test]
+fn test_revisions() {
+	// With a latch of 0, revision B asserts IRQ on every scanline
+	let mut mmc3 = test_mmc3(Mmc3Revision::B);
+	mmc3.cpu_write(0xE001, 0).unwrap();
+	scanline(&mut mmc3);
+	assert!(mmc3.irq());
+	mmc3.cpu_write(0xE000, 0).unwrap();
+	mmc3.cpu_write(0xE001, 0).unwrap();
+	scanline(&mut mmc3);
+	assert!(mmc3.irq());
+
+	// Revision A only asserts it after a reload
+	let mut mmc3 = test_mmc3(Mmc3Revision::A);
+	mmc3.cpu_write(0xE001, 0).unwrap();
+	scanline(&mut mmc3);
+	assert!(!mmc3.irq());
+	mmc3.cpu_write(0xC001, 0).unwrap();
+	scanline(&mut mmc3);
+	assert!(mmc3.irq());
+	mmc3.cpu_write(0xE000, 0).unwrap();
+	mmc3.cpu_write(0xE001, 0).unwrap();
+	scanline(&mut mmc3);
+	assert!(!mmc3.irq());
+}
+
+#[test]
+fn test_mmc6_prg_ram() {
+	let mut mmc6 = test_mmc3(Mmc3Revision::Mmc6);
+	assert_eq!(mmc6.prg_ram().unwrap().len(), MMC6_RAM_SIZE);
+	// Disabled at power up, when the protect bits can't be written either
+	assert!(mmc6.cpu_read(0x7000).is_err());
+	mmc6.cpu_write(0xA001, 0xF0).unwrap();
+	mmc6.cpu_write(0x8000, 0x20).unwrap();
+	assert!(mmc6.cpu_read(0x7000).is_err());
+
+	// Only 0x7000-0x71FF readable and writable
+	mmc6.cpu_write(0xA001, 0x30).unwrap();
+	mmc6.cpu_write(0x7000, 0x12).unwrap();
+	mmc6.cpu_write(0x7200, 0x34).unwrap();
+	assert_eq!(mmc6.cpu_read(0x7400).unwrap(), 0x12);
+	assert_eq!(mmc6.cpu_read(0x7200).unwrap(), 0x00);
+	assert_eq!(mmc6.prg_ram().unwrap()[0x200], 0x00);
+	assert!(mmc6.cpu_read(0x6000).is_err());
+
+	// 0x7200-0x73FF read only
+	mmc6.prg_ram_mut().unwrap()[0x200] = 0x56;
+	mmc6.cpu_write(0xA001, 0xB0).unwrap();
+	mmc6.cpu_write(0x7200, 0x78).unwrap();
+	assert_eq!(mmc6.cpu_read(0x7200).unwrap(), 0x56);
+}
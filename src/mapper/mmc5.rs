@@ -0,0 +1,738 @@
+use error::{ Access, EmuError, Result };
+use mapper::{ Mapper, Mirroring };
+use memory::*;
+use rom::*;
+use std::cell::Cell;
+
+/// Size of the PRG ROM and RAM windows. Larger banks are shown through several of them.
+const PRG_BANK_SIZE: usize = 0x2000;
+
+/// Size of the smallest CHR bank
+const CHR_BANK_SIZE: usize = 0x0400;
+
+/// Reads the PPU makes in a scanline before it starts on the sprite patterns: 32 tiles of a
+/// nametable, attribute and two pattern fetches. The sprites take 8 fetches of four reads.
+const SPRITE_FETCHES: std::ops::Range<u16> = 128..160;
+
+/// MMC5 (0x5) Mapper, used by the ExROM boards
+///     http://wiki.nesdev.com/w/index.php/MMC5
+///
+/// Registers:
+///     0x5000 -> 0x5015: Audio
+///     0x5100, 0x5101:   PRG and CHR banking modes
+///     0x5102, 0x5103:   PRG RAM write protect, unlocked by writing 2 and 1
+///     0x5104:           ExRAM mode
+///     0x5105:           Source of each nametable: VRAM page 0 or 1, ExRAM or fill mode
+///     0x5106, 0x5107:   Fill mode tile and attribute
+///     0x5113 -> 0x5117: PRG banks, with bit 7 selecting ROM in the CPU's 0x8000-0xDFFF
+///     0x5120 -> 0x5127: CHR banks for sprites, or everything with 8x8 sprites
+///     0x5128 -> 0x512B: CHR banks for the background with 8x16 sprites
+///     0x5130:           Upper bits of CHR banks
+///     0x5200 -> 0x5202: Vertical split mode, scroll and CHR bank
+///     0x5203, 0x5204:   Scanline IRQ compare value, and IRQ enable or status
+///     0x5205, 0x5206:   8x8 bit unsigned multiplier
+///     0x5C00 -> 0x5FFF: 1 KB ExRAM
+///
+/// The MMC5 has no PPU A12 or scanline input. It tells what the PPU is doing by watching its
+/// reads: three reads in a row from the same nametable address only happen at the start of a
+/// scanline, counting reads from there tells sprite fetches from background fetches, and a CPU
+/// instruction without any reads means rendering has stopped. It also watches the CPU's writes
+/// to PPUCTRL for the sprite size.
+///
+/// The audio registers are accepted but the audio isn't emulated.
+pub struct Mmc5 {
+	prg_rom: BankedMemory,
+	prg_ram: BankedMemory, // Windows at 0x6000, 0x8000, 0xA000, 0xC000 and 0xE000
+	prg_in_ram: [bool; 4], // For each window at 0x8000-0xFFFF
+	prg_mode: u8,
+	prg_banks: [u8; 5],    // 0x5113-0x5117
+	prg_ram_protect: [u8; 2],
+	chr: Vec<u8>,
+	chr_writable: bool,
+	chr_mode: u8,
+	chr_registers: [u16; 12],
+	chr_upper: u8,
+	chr_set_b_written: bool, // Last CHR bank written was for the background
+	sprite_banks: [usize; 8],
+	background_banks: [usize; 8],
+	exram: [u8; 0x400],
+	exram_mode: u8,
+	nametables: u8,
+	fill_tile: u8,
+	fill_attribute: u8,
+	split_control: u8,
+	split_scroll: u8,
+	split_bank: u8,
+	irq_compare: u8,
+	irq_enabled: bool,
+	irq_pending: Cell<bool>, // Cleared by reading 0x5204
+	multiplicand: u8,
+	multiplier: u8,
+	large_sprites: bool,
+	in_frame: bool,
+	scanline: u16,
+	last_address: u16,     // Last PPU read
+	repeated_reads: u8,    // Reads of last_address after the first
+	fetch: u16,            // Reads since the scanline started
+	read_since_clock: bool,
+	ext_attribute: u8,     // ExRAM byte for the tile being fetched in extended attribute mode
+	split_tile: Option<u8> // Tile being fetched from the split, if it's in the split
+}
+
+impl Mmc5 {
+	pub fn new(header: Header, prg: Vec<u8>, chr: Vec<u8>) -> Mmc5 {
+		let chr_writable = chr.is_empty();
+		let mut mmc5 = Mmc5 {
+			prg_rom: BankedMemory::rom(prg, PRG_BANK_SIZE, 4),
+			// The mapper can address 64 KB of RAM. Boards have less, and banks past the end wrap around.
+			prg_ram: BankedMemory::ram(header.prg_ram_total(), PRG_BANK_SIZE, 5),
+			prg_in_ram: [false; 4],
+			// Every bank register points at the last ROM bank at power up
+			prg_mode: 3,
+			prg_banks: [0, 0xFF, 0xFF, 0xFF, 0xFF],
+			prg_ram_protect: [0; 2],
+			chr: if chr_writable { vec![0; 0x2000] } else { chr },
+			chr_writable,
+			chr_mode: 0,
+			chr_registers: [0; 12],
+			chr_upper: 0,
+			chr_set_b_written: false,
+			sprite_banks: [0; 8],
+			background_banks: [0; 8],
+			exram: [0; 0x400],
+			exram_mode: 0,
+			nametables: 0,
+			fill_tile: 0,
+			fill_attribute: 0,
+			split_control: 0,
+			split_scroll: 0,
+			split_bank: 0,
+			irq_compare: 0,
+			irq_enabled: false,
+			irq_pending: Cell::new(false),
+			multiplicand: 0xFF,
+			multiplier: 0xFF,
+			large_sprites: false,
+			in_frame: false,
+			scanline: 0,
+			last_address: 0,
+			repeated_reads: 0,
+			fetch: 0,
+			read_since_clock: false,
+			ext_attribute: 0,
+			split_tile: None
+		};
+		mmc5.update_prg_banks();
+		mmc5.update_chr_banks();
+		mmc5
+	}
+
+	fn prg_ram_writable(&self) -> bool {
+		self.prg_ram_protect == [0b10, 0b01]
+	}
+
+	fn update_prg_banks(&mut self) {
+		let b = self.prg_banks;
+		// Register and bank number for each window at 0x8000-0xFFFF, where register 4 is 0x5117
+		let windows: [(usize, u8); 4] = match self.prg_mode {
+			0 => {
+				let bank = b[4] & 0x7C;
+				[(4, bank), (4, bank | 1), (4, bank | 2), (4, bank | 3)]
+			},
+			1 => [(2, b[2] & 0x7E), (2, b[2] | 1), (4, b[4] & 0x7E), (4, b[4] | 1)],
+			2 => [(2, b[2] & 0x7E), (2, b[2] | 1), (3, b[3]), (4, b[4])],
+			_ => [(1, b[1]), (2, b[2]), (3, b[3]), (4, b[4])]
+		};
+		self.prg_ram.switch(0, (b[0] & 0x07) as usize);
+		for (window, &(register, bank)) in windows.iter().enumerate() {
+			// 0x5117 always selects ROM
+			let rom = register == 4 || b[register] & 0x80 != 0;
+			self.prg_in_ram[window] = !rom;
+			if rom {
+				self.prg_rom.switch(window, (bank & 0x7F) as usize);
+			} else {
+				self.prg_ram.switch(window + 1, (bank & 0x07) as usize);
+			}
+		}
+	}
+
+	// Update the 1 KB banks of each CHR set. Registers hold bank numbers in units of the mode's
+	// bank size, and only the last register of each group is used in the larger sizes.
+	fn update_chr_banks(&mut self) {
+		let r = self.chr_registers;
+		for i in 0..8 {
+			let (sprite, background) = match self.chr_mode {
+				0 => (r[7] as usize * 8 + i, r[11] as usize * 8 + i),
+				1 => (r[i / 4 * 4 + 3] as usize * 4 + i % 4, r[11] as usize * 4 + i % 4),
+				2 => {
+					let background = r[(i / 2) % 2 * 2 + 9] as usize * 2 + i % 2;
+					(r[i / 2 * 2 + 1] as usize * 2 + i % 2, background)
+				},
+				_ => (r[i] as usize, r[8 + i % 4] as usize)
+			};
+			self.sprite_banks[i] = sprite;
+			self.background_banks[i] = background;
+		}
+	}
+
+	fn chr_offset(&self, bank: usize, address: u16) -> usize {
+		(bank * CHR_BANK_SIZE + (address as usize % CHR_BANK_SIZE)) % self.chr.len()
+	}
+
+	// Banks for CPU access through PPUDATA, when the PPU isn't rendering
+	fn idle_banks(&self) -> &[usize; 8] {
+		if self.large_sprites && self.chr_set_b_written {
+			&self.background_banks
+		} else {
+			&self.sprite_banks
+		}
+	}
+
+	// Count a PPU read, and start a scanline on the third read in a row of a nametable address
+	fn count_read(&mut self, address: u16) {
+		self.read_since_clock = true;
+		if address >= 0x2000 && address == self.last_address {
+			self.repeated_reads = self.repeated_reads.saturating_add(1);
+		} else {
+			self.repeated_reads = 0;
+		}
+		self.last_address = address;
+		self.fetch = self.fetch.saturating_add(1);
+		if self.repeated_reads == 2 {
+			self.start_scanline();
+		}
+	}
+
+	fn start_scanline(&mut self) {
+		self.fetch = 0;
+		if self.in_frame {
+			self.scanline += 1;
+			if self.scanline == self.irq_compare as u16 {
+				self.irq_pending.set(true);
+			}
+		} else {
+			self.in_frame = true;
+			self.scanline = 0;
+			self.irq_pending.set(false);
+		}
+	}
+
+	// Background Column - Screen column of the tile being fetched, if it's a background fetch.
+	// The first 32 tiles of a scanline start two columns in, and the last two are the first of
+	// the next scanline.
+	fn background_column(&self) -> Option<u16> {
+		if !self.in_frame || SPRITE_FETCHES.contains(&self.fetch) {
+			return None;
+		}
+		let tile = self.fetch / 4;
+		match tile {
+			0 ..= 31 => Some(tile + 2),
+			40 ..= 41 => Some(tile - 40),
+			_ => None
+		}
+	}
+
+	fn in_split(&self, column: u16) -> bool {
+		let tiles = (self.split_control & 0x1F) as u16;
+		let right = self.split_control & 0x40 != 0;
+		self.split_control & 0x80 != 0 && self.exram_mode <= 1 && (column >= tiles) == right
+	}
+
+	// Split Y - Scroll position of the split for the tile being fetched
+	fn split_y(&self) -> u16 {
+		let line = if self.fetch / 4 >= 40 { self.scanline + 1 } else { self.scanline };
+		(self.split_scroll as u16 + line) % 240
+	}
+
+	fn read_split(&mut self, column: u16, attribute: bool) -> u8 {
+		let y = self.split_y();
+		let column = column % 32;
+		if attribute {
+			let byte = self.exram[0x3C0 + (y as usize / 32) * 8 + column as usize / 4];
+			let shift = ((y / 16) & 1) * 4 + ((column / 2) & 1) * 2;
+			((byte >> shift) & 0b11) * 0x55
+		} else {
+			let tile = self.exram[(y as usize / 8) * 32 + column as usize];
+			self.split_tile = Some(tile);
+			tile
+		}
+	}
+
+	// Pattern Offset - Offset into CHR of a pattern table read, which depends on whether it's a
+	// sprite or background fetch and on the split and extended attributes
+	fn pattern_offset(&self, address: u16) -> usize {
+		let window = address as usize / CHR_BANK_SIZE;
+		match self.background_column() {
+			Some(_) if self.split_tile.is_some() => {
+				let tile = self.split_tile.unwrap_or(0) as u16;
+				let offset = tile * 16 + (address & 0x08) + (self.split_y() & 0x07);
+				self.chr_offset(self.split_bank as usize * 4 + offset as usize / CHR_BANK_SIZE, offset)
+			},
+			Some(_) if self.exram_mode == 1 => {
+				let bank = (self.ext_attribute & 0x3F) as usize | (self.chr_upper as usize) << 6;
+				let offset = address & 0x0FFF;
+				self.chr_offset(bank * 4 + offset as usize / CHR_BANK_SIZE, offset)
+			},
+			Some(_) if self.large_sprites => self.chr_offset(self.background_banks[window], address),
+			Some(_) => self.chr_offset(self.sprite_banks[window], address),
+			None if self.in_frame => self.chr_offset(self.sprite_banks[window], address),
+			None => self.chr_offset(self.idle_banks()[window], address)
+		}
+	}
+
+	fn write_register(&mut self, address: u16, value: u8) -> Result<()> {
+		match address {
+			// Audio isn't emulated
+			0x5000 ..= 0x5015 => (),
+			0x5100 => {
+				self.prg_mode = value & 0b11;
+				self.update_prg_banks();
+			},
+			0x5101 => {
+				self.chr_mode = value & 0b11;
+				self.update_chr_banks();
+			},
+			0x5102 ..= 0x5103 => self.prg_ram_protect[(address - 0x5102) as usize] = value & 0b11,
+			0x5104 => self.exram_mode = value & 0b11,
+			0x5105 => self.nametables = value,
+			0x5106 => self.fill_tile = value,
+			0x5107 => self.fill_attribute = value & 0b11,
+			0x5113 ..= 0x5117 => {
+				self.prg_banks[(address - 0x5113) as usize] = value;
+				self.update_prg_banks();
+			},
+			0x5120 ..= 0x512B => {
+				let bank = value as u16 | (self.chr_upper as u16) << 8;
+				self.chr_registers[(address - 0x5120) as usize] = bank;
+				self.chr_set_b_written = address >= 0x5128;
+				self.update_chr_banks();
+			},
+			0x5130 => self.chr_upper = value & 0b11,
+			0x5200 => self.split_control = value,
+			0x5201 => self.split_scroll = value,
+			0x5202 => self.split_bank = value,
+			0x5203 => self.irq_compare = value,
+			0x5204 => self.irq_enabled = value & 0x80 != 0,
+			0x5205 => self.multiplicand = value,
+			0x5206 => self.multiplier = value,
+			0x5C00 ..= 0x5FFF => {
+				let offset = (address - 0x5C00) as usize;
+				match self.exram_mode {
+					// Writes while the PPU isn't rendering store 0
+					0 | 1 => self.exram[offset] = if self.in_frame { value } else { 0 },
+					2 => self.exram[offset] = value,
+					_ => ()
+				}
+			},
+			_ => return Err(EmuError::BusFault { address, access: Access::Write })
+		}
+		Ok(())
+	}
+}
+
+// MMC5 Memory Map
+// CPU:
+// 0x5000 -> 0x5BFF: Registers
+// 0x5C00 -> 0x5FFF: 1 KB ExRAM, in ExRAM modes 2 and 3
+// 0x6000 -> 0x7FFF: 8 KB switchable PRG RAM bank
+// 0x8000 -> 0xFFFF: One 32 KB, two 16 KB, one 16 KB and two 8 KB, or four 8 KB switchable PRG
+//                   banks, the last of which is always ROM
+// PPU:
+// 0x0000 -> 0x1FFF: Eight 1 KB, four 2 KB, two 4 KB or one 8 KB switchable CHR banks, from one
+//                   of two sets
+// 0x2000 -> 0x2FFF: Four nametables, each from VRAM, ExRAM or fill mode
+impl Mapper for Mmc5 {
+	fn cpu_read(&self, address: u16) -> Result<u8> {
+		match address {
+			0x5204 => {
+				let status = (self.irq_pending.get() as u8) << 7 | (self.in_frame as u8) << 6;
+				self.irq_pending.set(false);
+				Ok(status)
+			},
+			0x5205 => Ok((self.multiplicand as u16 * self.multiplier as u16) as u8),
+			0x5206 => Ok(((self.multiplicand as u16 * self.multiplier as u16) >> 8) as u8),
+			0x5C00 ..= 0x5FFF if self.exram_mode >= 2 => Ok(self.exram[(address - 0x5C00) as usize]),
+			0x6000 ..= 0x7FFF => self.prg_ram.load(address - 0x6000),
+			0x8000 ..= 0xFFFF => {
+				if self.prg_in_ram[(address - 0x8000) as usize / PRG_BANK_SIZE] {
+					self.prg_ram.load(address - 0x6000)
+				} else {
+					self.prg_rom.load(address - 0x8000)
+				}
+			},
+			_ => Err(EmuError::BusFault { address, access: Access::Read })
+		}
+	}
+
+	fn cpu_write(&mut self, address: u16, value: u8) -> Result<()> {
+		let ram = match address {
+			0x6000 ..= 0x7FFF => true,
+			0x8000 ..= 0xFFFF => self.prg_in_ram[(address - 0x8000) as usize / PRG_BANK_SIZE],
+			_ => return self.write_register(address, value)
+		};
+		if !ram {
+			Err(EmuError::BusFault { address, access: Access::Write })
+		} else if self.prg_ram_writable() {
+			self.prg_ram.store(address - 0x6000, value)
+		} else {
+			// Writes to protected PRG RAM are ignored
+			Ok(())
+		}
+	}
+
+	fn ppu_read(&mut self, address: u16) -> Result<u8> {
+		self.count_read(address);
+		match address {
+			0x0000 ..= 0x1FFF => Ok(self.chr[self.pattern_offset(address)]),
+			_ => Err(EmuError::BusFault { address, access: Access::Read })
+		}
+	}
+
+	fn ppu_write(&mut self, address: u16, value: u8) -> Result<()> {
+		match address {
+			0x0000 ..= 0x1FFF if self.chr_writable => {
+				let bank = self.idle_banks()[address as usize / CHR_BANK_SIZE];
+				let offset = self.chr_offset(bank, address);
+				self.chr[offset] = value;
+				Ok(())
+			},
+			_ => Err(EmuError::BusFault { address, access: Access::Write })
+		}
+	}
+
+	/// Closest standard arrangement of the nametables. Other arrangements, and ones using ExRAM
+	/// or fill mode, are reported as four screen since the mapper supplies the nametables.
+	fn mirroring(&self) -> Mirroring {
+		match self.nametables {
+			0x00 => Mirroring::SingleScreenLower,
+			0x55 => Mirroring::SingleScreenUpper,
+			0x44 => Mirroring::Vertical,
+			0x50 => Mirroring::Horizontal,
+			_ => Mirroring::FourScreen
+		}
+	}
+
+	fn nametable_read(&mut self, address: u16, vram: &[u8]) -> u8 {
+		self.count_read(address);
+		let offset = (address & 0x3FF) as usize;
+		let attribute = offset >= 0x3C0;
+
+		if let Some(column) = self.background_column() {
+			if !attribute {
+				self.split_tile = None;
+			}
+			if self.in_split(column) {
+				return self.read_split(column, attribute);
+			}
+			if self.exram_mode == 1 {
+				if attribute {
+					return (self.ext_attribute >> 6) * 0x55;
+				}
+				self.ext_attribute = self.exram[offset];
+			}
+		}
+
+		let table = (address >> 10) & 0b11;
+		match (self.nametables >> (table * 2)) & 0b11 {
+			0 => vram[offset % vram.len()],
+			1 => vram[(0x400 + offset) % vram.len()],
+			2 if self.exram_mode <= 1 => self.exram[offset],
+			2 => 0,
+			_ if attribute => self.fill_attribute * 0x55,
+			_ => self.fill_tile
+		}
+	}
+
+	fn nametable_write(&mut self, address: u16, value: u8, vram: &mut [u8]) {
+		let offset = (address & 0x3FF) as usize;
+		let table = (address >> 10) & 0b11;
+		match (self.nametables >> (table * 2)) & 0b11 {
+			0 => vram[offset % vram.len()] = value,
+			1 => vram[(0x400 + offset) % vram.len()] = value,
+			2 if self.exram_mode <= 1 => self.exram[offset] = value,
+			_ => ()
+		}
+	}
+
+	fn snoop_write(&mut self, address: u16, value: u8) {
+		match address {
+			0x2000 ..= 0x3FFF if address & 0x07 == 0 => self.large_sprites = value & 0x20 != 0,
+			0x2000 ..= 0x3FFF if address & 0x07 == 1 && value & 0x18 == 0 => self.in_frame = false,
+			_ => ()
+		}
+	}
+
+	fn irq(&self) -> bool {
+		self.irq_enabled && self.irq_pending.get()
+	}
+
+	fn clock(&mut self, _cpu_cycles: u32) {
+		if !self.read_since_clock {
+			self.in_frame = false;
+		}
+		self.read_since_clock = false;
+	}
+
+	fn prg_ram(&self) -> Option<&[u8]> {
+		Some(self.prg_ram.data())
+	}
+
+	fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+		Some(self.prg_ram.data_mut())
+	}
+}
+
+#[cfg(test)]
+use mapper::{ numbered_banks, prg_banks, test_header };
+
+// 128 KB of PRG ROM in 8 KB banks and 256 KB of CHR ROM in 1 KB banks, each bank starting with
+// its bank number, and 32 KB of PRG RAM
+#[cfg(test)]
+fn test_mmc5() -> Mmc5 {
+	let prg = numbered_banks(16, PRG_BANK_SIZE, 0);
+	let chr = numbered_banks(256, CHR_BANK_SIZE, 0);
+	let mut header = test_header(8, 32, 0);
+	header.prg_ram_size = 4 * PRG_BANK_SIZE;
+	Mmc5::new(header, prg, chr)
+}
+
+// Fetch a background tile like the PPU: nametable, attribute, then both planes of the pattern.
+// Returns the tile, attribute and low plane.
+#[cfg(test)]
+fn fetch_tile(mmc5: &mut Mmc5, vram: &[u8], nametable: u16) -> (u8, u8, u8) {
+	let tile = mmc5.nametable_read(nametable, vram);
+	let attribute = mmc5.nametable_read(0x23C0 | (nametable & 0x0C00), vram);
+	let low = mmc5.ppu_read(tile as u16 * 16).unwrap();
+	mmc5.ppu_read(tile as u16 * 16 + 8).unwrap();
+	(tile, attribute, low)
+}
+
+// Fetch a sprite like the PPU: two garbage nametable reads, then both planes of the pattern.
+// Returns the low plane.
+#[cfg(test)]
+fn fetch_sprite(mmc5: &mut Mmc5, vram: &[u8], pattern: u16) -> u8 {
+	mmc5.nametable_read(0x2000, vram);
+	mmc5.nametable_read(0x2000, vram);
+	let low = mmc5.ppu_read(pattern).unwrap();
+	mmc5.ppu_read(pattern + 8).unwrap();
+	low
+}
+
+// Make the reads of the pre-render line and the given number of visible scanlines, with every
+// tile fetched from 0x2000 and every sprite from 0x1000, then the CPU running through vertical
+// blank. The pre-render line fetches from 0x2400 so it doesn't follow on from the last frame.
+// Returns the background and sprite fetches of each visible scanline.
+#[cfg(test)]
+fn render(mmc5: &mut Mmc5, vram: &[u8], lines: usize) -> Vec<Vec<(u8, u8, u8)>> {
+	let mut scanlines = Vec::new();
+	for line in 0..=lines {
+		let mut fetches = Vec::new();
+		for _ in 0..32 {
+			fetches.push(fetch_tile(mmc5, vram, if line == 0 { 0x2400 } else { 0x2000 }));
+		}
+		for _ in 0..8 {
+			fetches.push((0, 0, fetch_sprite(mmc5, vram, 0x1000)));
+		}
+		for _ in 0..2 {
+			fetches.push(fetch_tile(mmc5, vram, 0x2000));
+		}
+		mmc5.nametable_read(0x2000, vram);
+		mmc5.nametable_read(0x2000, vram);
+		mmc5.clock(1);
+		scanlines.push(fetches);
+	}
+	mmc5.clock(1);
+	mmc5.clock(1);
+	scanlines.remove(0);
+	scanlines
+}
+
+#[test]
+fn test_prg_banking() {
+	let mut mmc5 = test_mmc5();
+	assert_eq!(prg_banks(&mmc5), vec![15, 15, 15, 15]);
+
+	mmc5.cpu_write(0x5100, 0).unwrap();
+	mmc5.cpu_write(0x5117, 0x05).unwrap();
+	assert_eq!(prg_banks(&mmc5), vec![4, 5, 6, 7]);
+
+	mmc5.cpu_write(0x5100, 2).unwrap();
+	mmc5.cpu_write(0x5115, 0x83).unwrap();
+	mmc5.cpu_write(0x5116, 0x89).unwrap();
+	mmc5.cpu_write(0x5117, 0x0A).unwrap();
+	assert_eq!(prg_banks(&mmc5), vec![2, 3, 9, 10]);
+
+	mmc5.cpu_write(0x5100, 3).unwrap();
+	mmc5.cpu_write(0x5114, 0x81).unwrap();
+	assert_eq!(prg_banks(&mmc5), vec![1, 3, 9, 10]);
+}
+
+#[test]
+fn test_prg_ram() {
+	let mut mmc5 = test_mmc5();
+	// Protected until 0x5102 and 0x5103 are unlocked
+	mmc5.cpu_write(0x6000, 0x12).unwrap();
+	assert_eq!(mmc5.cpu_read(0x6000).unwrap(), 0x00);
+	mmc5.cpu_write(0x5102, 0x02).unwrap();
+	mmc5.cpu_write(0x5103, 0x01).unwrap();
+	mmc5.cpu_write(0x5113, 0x03).unwrap();
+	mmc5.cpu_write(0x6000, 0x12).unwrap();
+
+	// The same RAM bank seen at 0xA000
+	mmc5.cpu_write(0x5115, 0x03).unwrap();
+	assert_eq!(mmc5.cpu_read(0xA000).unwrap(), 0x12);
+	mmc5.cpu_write(0xA001, 0x34).unwrap();
+	assert_eq!(mmc5.cpu_read(0x6001).unwrap(), 0x34);
+	assert!(mmc5.cpu_write(0xE000, 0x56).is_err());
+}
+
+#[test]
+fn test_chr_sets() {
+	let mut mmc5 = test_mmc5();
+	let vram = [0; 0x800];
+	mmc5.cpu_write(0x5101, 3).unwrap();
+	for i in 0..12 {
+		mmc5.cpu_write(0x5120 + i, 0x10 + i as u8).unwrap();
+	}
+
+	// With 8x8 sprites, the sprite set is used for everything
+	let lines = render(&mut mmc5, &vram, 1);
+	assert_eq!(lines[0][0].2, 0x10);
+	assert_eq!(lines[0][32].2, 0x14);
+
+	// With 8x16 sprites the background has its own set, mirrored at 0x1000
+	mmc5.snoop_write(0x2000, 0x20);
+	let lines = render(&mut mmc5, &vram, 1);
+	assert_eq!(lines[0][0].2, 0x18);
+	assert_eq!(lines[0][32].2, 0x14);
+	assert_eq!(lines[0][40].2, 0x18);
+	assert_eq!(mmc5.ppu_read(0x1000).unwrap(), 0x18);
+
+	// Outside rendering, the set written last is used
+	mmc5.cpu_write(0x5120, 0x20).unwrap();
+	assert_eq!(mmc5.ppu_read(0x0000).unwrap(), 0x20);
+
+	// Upper bits
+	mmc5.cpu_write(0x5130, 0x01).unwrap();
+	mmc5.cpu_write(0x5101, 0).unwrap();
+	mmc5.cpu_write(0x5127, 0x01).unwrap();
+	assert_eq!(mmc5.ppu_read(0x0400).unwrap() as usize, (0x101 * 8 + 1) % 256);
+}
+
+#[test]
+fn test_nametables() {
+	let mut mmc5 = test_mmc5();
+	let mut vram = [0; 0x800];
+	// VRAM page 1, ExRAM, fill mode, VRAM page 0
+	mmc5.cpu_write(0x5105, 0b00_11_10_01).unwrap();
+	mmc5.cpu_write(0x5104, 2).unwrap();
+	mmc5.cpu_write(0x5C05, 0x77).unwrap();
+	mmc5.cpu_write(0x5104, 0).unwrap();
+	mmc5.cpu_write(0x5106, 0x42).unwrap();
+	mmc5.cpu_write(0x5107, 0x02).unwrap();
+	mmc5.nametable_write(0x2005, 0x11, &mut vram);
+	mmc5.nametable_write(0x2C05, 0x22, &mut vram);
+
+	assert_eq!(vram[0x405], 0x11);
+	assert_eq!(vram[0x005], 0x22);
+	assert_eq!(mmc5.nametable_read(0x2405, &vram), 0x77);
+	assert_eq!(mmc5.nametable_read(0x2805, &vram), 0x42);
+	assert_eq!(mmc5.nametable_read(0x2BC0, &vram), 0xAA);
+	assert_eq!(mmc5.mirroring(), Mirroring::FourScreen);
+}
+
+#[test]
+fn test_exram_access() {
+	let mut mmc5 = test_mmc5();
+	// Not readable in modes 0 and 1, and writes outside rendering store 0
+	mmc5.cpu_write(0x5C00, 0x12).unwrap();
+	assert!(mmc5.cpu_read(0x5C00).is_err());
+	mmc5.cpu_write(0x5104, 2).unwrap();
+	assert_eq!(mmc5.cpu_read(0x5C00).unwrap(), 0x00);
+	mmc5.cpu_write(0x5C00, 0x12).unwrap();
+	assert_eq!(mmc5.cpu_read(0x5C00).unwrap(), 0x12);
+	// Read only in mode 3
+	mmc5.cpu_write(0x5104, 3).unwrap();
+	mmc5.cpu_write(0x5C00, 0x34).unwrap();
+	assert_eq!(mmc5.cpu_read(0x5C00).unwrap(), 0x12);
+}
+
+#[test]
+fn test_extended_attributes() {
+	let mut mmc5 = test_mmc5();
+	let vram = [0; 0x800];
+	mmc5.cpu_write(0x5104, 2).unwrap();
+	// Every tile uses 4 KB bank 5 and palette 2
+	for offset in 0..0x3C0 {
+		mmc5.cpu_write(0x5C00 + offset, 0x85).unwrap();
+	}
+	mmc5.cpu_write(0x5104, 1).unwrap();
+	let lines = render(&mut mmc5, &vram, 1);
+	assert_eq!(lines[0][0], (0, 0xAA, 20));
+	// Sprites aren't affected
+	assert_eq!(lines[0][32].2, 4);
+}
+
+#[test]
+fn test_split() {
+	let mut mmc5 = test_mmc5();
+	let vram = [0; 0x800];
+	mmc5.cpu_write(0x5104, 2).unwrap();
+	mmc5.cpu_write(0x5C00 + 32 * 3 + 2, 0x40).unwrap();
+	mmc5.cpu_write(0x5FC0, 0xFF).unwrap();
+	mmc5.cpu_write(0x5104, 1).unwrap();
+
+	// Split the left four columns, scrolled to the fourth tile row, from 4 KB bank 2
+	mmc5.cpu_write(0x5200, 0x84).unwrap();
+	mmc5.cpu_write(0x5201, 24).unwrap();
+	mmc5.cpu_write(0x5202, 2).unwrap();
+	let lines = render(&mut mmc5, &vram, 1);
+	// Column 2, from the split, is tile 0x40 of 4 KB bank 2, which starts 1 KB bank 9
+	assert_eq!(lines[0][0], (0x40, 0xFF, 9));
+	// Column 4 is outside the split
+	assert_eq!(lines[0][2], (0, 0, 0));
+	// Columns 0 and 1 of the next scanline come from the split too
+	assert_eq!(lines[0][40].1, 0xFF);
+}
+
+#[test]
+fn test_scanline_irq() {
+	let mut mmc5 = test_mmc5();
+	let vram = [0; 0x800];
+	mmc5.cpu_write(0x5203, 3).unwrap();
+	mmc5.cpu_write(0x5204, 0x80).unwrap();
+
+	// Scanlines 0 to 2
+	render(&mut mmc5, &vram, 3);
+	assert!(!mmc5.irq());
+	assert_eq!(mmc5.cpu_read(0x5204).unwrap(), 0x00);
+
+	render(&mut mmc5, &vram, 4);
+	assert!(mmc5.irq());
+	// Reading the status acknowledges it
+	assert_eq!(mmc5.cpu_read(0x5204).unwrap(), 0x80);
+	assert!(!mmc5.irq());
+}
+
+#[test]
+fn test_in_frame() {
+	let mut mmc5 = test_mmc5();
+	let vram = [0; 0x800];
+	for _ in 0..3 {
+		mmc5.nametable_read(0x2000, &vram);
+	}
+	mmc5.clock(1);
+	assert_eq!(mmc5.cpu_read(0x5204).unwrap(), 0x40);
+	// Turning rendering off leaves the frame
+	mmc5.snoop_write(0x2001, 0x00);
+	assert_eq!(mmc5.cpu_read(0x5204).unwrap(), 0x00);
+}
+
+#[test]
+fn test_multiplier() {
+	let mut mmc5 = test_mmc5();
+	mmc5.cpu_write(0x5205, 200).unwrap();
+	mmc5.cpu_write(0x5206, 150).unwrap();
+	assert_eq!(mmc5.cpu_read(0x5205).unwrap(), (30000 & 0xFF) as u8);
+	assert_eq!(mmc5.cpu_read(0x5206).unwrap(), (30000 >> 8) as u8);
+}
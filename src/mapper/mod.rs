@@ -0,0 +1,372 @@
+use error::{ RomError, Result };
+#[cfg(test)]
+use error::EmuError;
+use memory::BankedMemory;
+use rom::*;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+mod discrete;
+mod fme7;
+mod mmc1;
+mod mmc2;
+mod mmc3;
+mod mmc5;
+mod namco163;
+mod nrom;
+mod nsf;
+mod sunsoft5b_audio;
+mod vrc;
+mod vrc6_audio;
+
+pub use self::discrete::{ AxRom, CnRom, GxRom, UxRom };
+pub use self::fme7::Fme7;
+pub use self::mmc1::Mmc1;
+pub use self::mmc2::{ LatchChip, Mmc2 };
+pub use self::mmc3::{ Mmc3, Mmc3Revision };
+pub use self::mmc5::Mmc5;
+pub use self::namco163::Namco163;
+pub use self::nrom::NRom;
+pub use self::nsf::NsfMapper;
+pub use self::sunsoft5b_audio::Sunsoft5bAudio;
+pub use self::vrc::{ Vrc, VrcChip };
+pub use self::vrc6_audio::Vrc6Audio;
+
+/// Nametable mirroring, as seen by the PPU
+///     http://wiki.nesdev.com/w/index.php/Mirroring#Nametable_Mirroring
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mirroring {
+	/// 0x2000 and 0x2400 share a nametable, as do 0x2800 and 0x2C00
+	Horizontal,
+	/// 0x2000 and 0x2800 share a nametable, as do 0x2400 and 0x2C00
+	Vertical,
+	/// All four nametables show the first page of VRAM
+	SingleScreenLower,
+	/// All four nametables show the second page of VRAM
+	SingleScreenUpper,
+	/// The cartridge provides VRAM for all four nametables
+	FourScreen
+}
+
+/// A cartridge board. The CPU and the PPU each have their own bus to the cartridge, and both go
+/// through the same mapper so that bank switching on one side is seen by the other.
+///
+/// CPU addresses are in 0x4020-0xFFFF. PPU addresses are in the pattern tables, 0x0000-0x1FFF,
+/// while nametables live in the PPU's own VRAM and go through nametable_read() and
+/// nametable_write() so that boards can rearrange or replace them.
+pub trait Mapper {
+	fn cpu_read(&self, address: u16) -> Result<u8>;
+	fn cpu_write(&mut self, address: u16, value: u8) -> Result<()>;
+
+	/// PPU reads take &mut self since some mappers watch the PPU's address bus, e.g. to latch
+	/// banks or count scanlines. The PPU must make every pattern table fetch through here, in the
+	/// order it does them, including the fetches for sprites and for tiles that aren't shown.
+	fn ppu_read(&mut self, address: u16) -> Result<u8>;
+	fn ppu_write(&mut self, address: u16, value: u8) -> Result<()>;
+
+	/// Current nametable arrangement
+	fn mirroring(&self) -> Mirroring;
+
+	/// Read a nametable or attribute byte at 0x2000-0x2FFF. vram is the PPU's nametable RAM, 2 KB
+	/// or 4 KB for four screen boards. Like ppu_read(), the PPU must make every nametable and
+	/// attribute fetch through here, in order. The default arranges VRAM by mirroring().
+	fn nametable_read(&mut self, address: u16, vram: &[u8]) -> u8 {
+		vram[nametable_offset(self.mirroring(), address) % vram.len()]
+	}
+
+	fn nametable_write(&mut self, address: u16, value: u8, vram: &mut [u8]) {
+		let offset = nametable_offset(self.mirroring(), address) % vram.len();
+		vram[offset] = value;
+	}
+
+	/// Called for CPU writes to the PPU and APU registers at 0x2000-0x401F, for boards that watch
+	/// them
+	fn snoop_write(&mut self, _address: u16, _value: u8) { }
+
+	/// Level of the cartridge's IRQ output, true when asserted
+	fn irq(&self) -> bool {
+		false
+	}
+
+	/// Called after every CPU instruction with the number of cycles it took, including any
+	/// interrupt sequence before it
+	fn clock(&mut self, _cpu_cycles: u32) { }
+
+	/// Current level of the cartridge's expansion audio, on the same scale as the APU's mixed
+	/// output where the APU alone ranges from 0.0 to 1.0
+	fn audio(&self) -> f32 {
+		0.0
+	}
+
+	/// PRG RAM at 0x6000-0x7FFF, all banks of it, so that battery backed RAM can be kept in a save
+	/// file. None for boards without PRG RAM.
+	fn prg_ram(&self) -> Option<&[u8]> {
+		None
+	}
+
+	fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+		None
+	}
+}
+
+/// A mapper shared by the CPU and the PPU
+pub type Cartridge = Rc<RefCell<dyn Mapper>>;
+
+/// Builds a mapper from a ROM
+type Constructor = fn(Rom) -> Result<Cartridge>;
+
+/// A supported board, keyed on the iNES mapper number and, for boards that need it, the NES 2.0
+/// submapper
+struct Registration {
+	mapper: u16,
+	/// None matches any submapper
+	submapper: Option<u8>,
+	name: &'static str,
+	new: Constructor
+}
+
+/// Supported mappers. Add new ones here; entries with a submapper must come before the entry for
+/// the same mapper without one.
+static MAPPERS: &[Registration] = &[
+	Registration {
+		mapper: 0, submapper: None, name: "NROM",
+		new: |rom| share(NRom::new(rom.header, rom.prg, rom.chr))
+	},
+	Registration {
+		mapper: 1, submapper: None, name: "MMC1",
+		new: |rom| share(Mmc1::new(rom.header, rom.prg, rom.chr))
+	},
+	Registration {
+		mapper: 2, submapper: None, name: "UxROM",
+		new: |rom| share(UxRom::new(rom.header, rom.prg, rom.chr))
+	},
+	Registration {
+		mapper: 3, submapper: None, name: "CNROM",
+		new: |rom| share(CnRom::new(rom.header, rom.prg, rom.chr))
+	},
+	Registration {
+		mapper: 4, submapper: Some(4), name: "MMC3A",
+		new: |rom| share(Mmc3::new(rom.header, rom.prg, rom.chr, Mmc3Revision::A))
+	},
+	Registration {
+		mapper: 4, submapper: Some(1), name: "MMC6",
+		new: |rom| share(Mmc3::new(rom.header, rom.prg, rom.chr, Mmc3Revision::Mmc6))
+	},
+	Registration {
+		mapper: 4, submapper: None, name: "MMC3",
+		new: |rom| share(Mmc3::new(rom.header, rom.prg, rom.chr, Mmc3Revision::B))
+	},
+	Registration {
+		mapper: 5, submapper: None, name: "MMC5",
+		new: |rom| share(Mmc5::new(rom.header, rom.prg, rom.chr))
+	},
+	Registration {
+		mapper: 7, submapper: None, name: "AxROM",
+		new: |rom| share(AxRom::new(rom.header, rom.prg, rom.chr))
+	},
+	Registration {
+		mapper: 9, submapper: None, name: "MMC2",
+		new: |rom| share(Mmc2::new(rom.header, rom.prg, rom.chr, LatchChip::Mmc2))
+	},
+	Registration {
+		mapper: 10, submapper: None, name: "MMC4",
+		new: |rom| share(Mmc2::new(rom.header, rom.prg, rom.chr, LatchChip::Mmc4))
+	},
+	Registration {
+		mapper: 19, submapper: None, name: "Namco 163",
+		new: |rom| share(Namco163::new(rom.header, rom.prg, rom.chr))
+	},
+	Registration {
+		mapper: 21, submapper: None, name: "VRC4",
+		new: |rom| share(Vrc::new(rom.header, rom.prg, rom.chr))
+	},
+	Registration {
+		mapper: 22, submapper: None, name: "VRC2",
+		new: |rom| share(Vrc::new(rom.header, rom.prg, rom.chr))
+	},
+	Registration {
+		mapper: 23, submapper: None, name: "VRC2/VRC4",
+		new: |rom| share(Vrc::new(rom.header, rom.prg, rom.chr))
+	},
+	Registration {
+		mapper: 24, submapper: None, name: "VRC6",
+		new: |rom| share(Vrc::new(rom.header, rom.prg, rom.chr))
+	},
+	Registration {
+		mapper: 25, submapper: None, name: "VRC2/VRC4",
+		new: |rom| share(Vrc::new(rom.header, rom.prg, rom.chr))
+	},
+	Registration {
+		mapper: 26, submapper: None, name: "VRC6",
+		new: |rom| share(Vrc::new(rom.header, rom.prg, rom.chr))
+	},
+	Registration {
+		mapper: 66, submapper: None, name: "GxROM",
+		new: |rom| share(GxRom::new(rom.header, rom.prg, rom.chr))
+	},
+	Registration {
+		mapper: 69, submapper: None, name: "FME-7",
+		new: |rom| share(Fme7::new(rom.header, rom.prg, rom.chr))
+	},
+	Registration {
+		mapper: 85, submapper: None, name: "VRC7",
+		new: |rom| share(Vrc::new(rom.header, rom.prg, rom.chr))
+	},
+];
+
+/// Offset into the PPU's nametable RAM of a nametable address for the given arrangement
+pub fn nametable_offset(mirroring: Mirroring, address: u16) -> usize {
+	let table = (address as usize >> 10) & 0b11;
+	let page = match mirroring {
+		Mirroring::Horizontal => table >> 1,
+		Mirroring::Vertical => table & 1,
+		Mirroring::SingleScreenLower => 0,
+		Mirroring::SingleScreenUpper => 1,
+		Mirroring::FourScreen => table
+	};
+	page * 0x400 + (address as usize & 0x3FF)
+}
+
+/// CHR ROM split into banks, or 8 KB of CHR RAM for boards without CHR ROM
+fn chr_memory(chr: Vec<u8>, bank_size: usize, windows: usize) -> BankedMemory {
+	if chr.is_empty() {
+		BankedMemory::ram(CHR_ROM_UNIT_SIZE, bank_size, windows)
+	} else {
+		BankedMemory::rom(chr, bank_size, windows)
+	}
+}
+
+fn share<M: Mapper + 'static>(mapper: M) -> Result<Cartridge> {
+	Ok(Rc::new(RefCell::new(mapper)))
+}
+
+fn find(mapper: u16, submapper: u8) -> Option<&'static Registration> {
+	MAPPERS.iter().find(|r| r.mapper == mapper && r.submapper.is_none_or(|s| s == submapper))
+}
+
+/// Name of the board the header asks for, if it's supported
+pub fn name(header: &Header) -> Option<&'static str> {
+	find(header.mapper_number, header.submapper).map(|r| r.name)
+}
+
+/// Build the mapper the ROM's header asks for, in its power up state. The trainer is left for
+/// load_trainer(), so that it can go in after any saved PRG RAM.
+pub fn create(rom: Rom) -> Result<Cartridge> {
+	let (mapper, submapper) = (rom.header.mapper_number, rom.header.submapper);
+	match find(mapper, submapper) {
+		Some(registration) => (registration.new)(rom),
+		None => Err(RomError::UnsupportedMapper { mapper, submapper }.into())
+	}
+}
+
+/// Copy a trainer to 0x7000 in PRG RAM, where the first bank of RAM is mapped at power up. Boards
+/// without that much PRG RAM have nowhere to put it, and the game would run without its code.
+pub fn load_trainer(mapper: &mut dyn Mapper, trainer: &[u8]) -> Result<()> {
+	let start = TRAINER_ADDRESS as usize - 0x6000;
+	let end = start + trainer.len();
+	match mapper.prg_ram_mut().and_then(|ram| ram.get_mut(start..end)) {
+		Some(ram) => {
+			ram.copy_from_slice(trainer);
+			Ok(())
+		},
+		None => Err(RomError::UnmappedTrainer.into())
+	}
+}
+
+#[cfg(test)]
+fn test_rom(header: Header, prg: Vec<u8>, chr: Vec<u8>) -> Rom {
+	Rom {
+		header,
+		trainer: None,
+		prg,
+		chr,
+		playchoice_inst_rom: None,
+		playchoice_prom: None,
+		title: None,
+		corrections: Vec::new()
+	}
+}
+
+#[cfg(test)]
+pub fn test_header(prg_rom_size: u8, chr_rom_size: u8, flags6: u8) -> Header {
+	let mut data = [0; 16];
+	data[0..4].copy_from_slice(&IDENTIFIER);
+	data[4] = prg_rom_size;
+	data[5] = chr_rom_size;
+	data[6] = flags6;
+	Header::new(&data).unwrap()
+}
+
+/// Memory where each bank of the given size starts with its bank number and the rest is filled
+/// with the given byte
+#[cfg(test)]
+pub fn numbered_banks(banks: usize, bank_size: usize, fill: u8) -> Vec<u8> {
+	let mut data = vec![fill; banks * bank_size];
+	for bank in 0..banks {
+		data[bank * bank_size] = bank as u8;
+	}
+	data
+}
+
+/// First byte of each 8 KB window of PRG at 0x8000, 0xA000, 0xC000 and 0xE000, which for banks
+/// from numbered_banks() is the bank number shown there
+#[cfg(test)]
+pub fn prg_banks(mapper: &dyn Mapper) -> Vec<u8> {
+	[0x8000, 0xA000, 0xC000, 0xE000].iter().map(|&a| mapper.cpu_read(a).unwrap()).collect()
+}
+
+#[test]
+fn test_create() {
+	let header = test_header(1, 1, 0);
+	assert_eq!(name(&header), Some("NROM"));
+	let cartridge = create(test_rom(header, vec![0; PRG_ROM_UNIT_SIZE], vec![0; CHR_ROM_UNIT_SIZE]));
+	assert_eq!(cartridge.unwrap().borrow().mirroring(), Mirroring::Horizontal);
+}
+
+#[test]
+fn test_unsupported_mapper() {
+	let mut header = test_header(1, 1, 0);
+	header.mapper_number = 200;
+	match create(test_rom(header, vec![0; PRG_ROM_UNIT_SIZE], vec![0; CHR_ROM_UNIT_SIZE])) {
+		Err(EmuError::Rom(RomError::UnsupportedMapper { mapper: 200, submapper: 0 })) => (),
+		Err(e) => panic!("Expected an unsupported mapper, got {:?}", e),
+		Ok(_) => panic!("Expected an unsupported mapper")
+	}
+}
+
+#[test]
+fn test_trainer() {
+	let rom = test_rom(test_header(1, 1, 0b110), vec![0; PRG_ROM_UNIT_SIZE], Vec::new());
+	let cartridge = create(rom).unwrap();
+	load_trainer(&mut *cartridge.borrow_mut(), &[0xEA; TRAINER_SIZE]).unwrap();
+	let cartridge = cartridge.borrow();
+	assert_eq!(cartridge.cpu_read(0x6FFF).unwrap(), 0x00);
+	assert_eq!(cartridge.cpu_read(0x7000).unwrap(), 0xEA);
+	assert_eq!(cartridge.cpu_read(0x71FF).unwrap(), 0xEA);
+	assert_eq!(cartridge.cpu_read(0x7200).unwrap(), 0x00);
+
+	// UxROM has no PRG RAM, and an NES 2.0 header can say there's none
+	let mut header = test_header(1, 0, 0b110);
+	header.mapper_number = 2;
+	let cartridge = create(test_rom(header, vec![0; PRG_ROM_UNIT_SIZE], Vec::new())).unwrap();
+	let mut header = test_header(1, 1, 0b110);
+	header.prg_nvram_size = 0;
+	let nrom = create(test_rom(header, vec![0; PRG_ROM_UNIT_SIZE], Vec::new())).unwrap();
+	for cartridge in [cartridge, nrom].iter() {
+		match load_trainer(&mut *cartridge.borrow_mut(), &[0xEA; TRAINER_SIZE]) {
+			Err(EmuError::Rom(RomError::UnmappedTrainer)) => (),
+			other => panic!("Expected an unmapped trainer, got {:?}", other)
+		}
+	}
+}
+
+#[test]
+fn test_nametable_offset() {
+	let offsets = |mirroring| -> Vec<usize> {
+		[0x2000, 0x2401, 0x2802, 0x2C03].iter().map(|&a| nametable_offset(mirroring, a)).collect()
+	};
+	assert_eq!(offsets(Mirroring::Horizontal), vec![0x000, 0x001, 0x402, 0x403]);
+	assert_eq!(offsets(Mirroring::Vertical), vec![0x000, 0x401, 0x002, 0x403]);
+	assert_eq!(offsets(Mirroring::SingleScreenUpper), vec![0x400, 0x401, 0x402, 0x403]);
+	assert_eq!(offsets(Mirroring::FourScreen), vec![0x000, 0x401, 0x802, 0xC03]);
+}
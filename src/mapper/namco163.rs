@@ -0,0 +1,405 @@
+use error::{ Access, EmuError, Result };
+use mapper::{ Mapper, Mirroring };
+use memory::*;
+use rom::*;
+use std::cell::Cell;
+
+/// Size of the PRG ROM windows
+const PRG_BANK_SIZE: usize = 0x2000;
+
+/// Size of the CHR banks, for both the pattern tables and the nametables
+const CHR_BANK_SIZE: usize = 0x0400;
+
+/// CHR banks from here up select a page of the PPU's VRAM instead of CHR ROM
+const VRAM_BANKS: u8 = 0xE0;
+
+/// Start of the channel registers in internal RAM. Channel 7's are at the end, and it's always
+/// enabled.
+const CHANNEL_REGISTERS: usize = 0x40;
+
+/// CPU cycles it takes to update one audio channel
+const CHANNEL_CYCLES: u8 = 15;
+
+/// Output of one step of a sample at volume 1. A channel at full volume and amplitude is a little
+/// louder than an APU pulse channel at full volume.
+const NAMCO_163_LEVEL: f32 = 0.0015;
+
+/// Namco 163 (0x13) Mapper
+///     http://wiki.nesdev.com/w/index.php/INES_Mapper_019
+///     http://wiki.nesdev.com/w/index.php/Namco_163_audio
+///
+/// Registers:
+///     0x4800 -> 0x4FFF: Internal RAM data port
+///     0x5000 -> 0x57FF: IRQ counter low byte
+///     0x5800 -> 0x5FFF: IRQ counter high bits and enable
+///     0x8000 -> 0xBFFF: 1 KB CHR banks for the pattern tables, a register every 0x800
+///     0xC000 -> 0xDFFF: 1 KB CHR banks for the nametables, a register every 0x800
+///     0xE000 -> 0xE7FF: PRG ROM bank at 0x8000, and audio disable
+///     0xE800 -> 0xEFFF: PRG ROM bank at 0xA000
+///     0xF000 -> 0xF7FF: PRG ROM bank at 0xC000
+///     0xF800 -> 0xFFFF: Internal RAM address and auto increment, and PRG RAM write protect
+///
+/// The 128 bytes of internal RAM hold both the audio channel registers and their waveforms. The
+/// audio updates one channel every 15 CPU cycles, so more channels sound lower in quality.
+///
+/// Nametables can be CHR ROM banks or pages of the PPU's VRAM. Pattern tables can also be
+/// mapped to VRAM on the chip, but no game relies on it and it isn't emulated; they always come
+/// from CHR ROM.
+pub struct Namco163 {
+	prg: BankedMemory,
+	prg_ram: BankedMemory,
+	write_protect: u8,
+	chr: Vec<u8>,
+	chr_banks: [u8; 12],         // Pattern tables then nametables
+	ram: [u8; 0x80],
+	ram_address: Cell<u8>,       // Auto increment in bit 7, moved on by reads of the data port
+	counter: u16,
+	irq_enabled: bool,
+	irq_pending: bool,
+	audio_disabled: bool,
+	audio_cycles: u8,
+	audio_channel: usize,
+	outputs: [i16; 8]
+}
+
+impl Namco163 {
+	pub fn new(header: Header, prg: Vec<u8>, chr: Vec<u8>) -> Namco163 {
+		let mut prg = BankedMemory::rom(prg, PRG_BANK_SIZE, 4);
+		prg.switch_from_end(3, 1);
+		let mut chr_banks = [0; 12];
+		chr_banks[8..].copy_from_slice(&match header.mirroring() {
+			Mirroring::Vertical => [0xE0, 0xE1, 0xE0, 0xE1],
+			_ => [0xE0, 0xE0, 0xE1, 0xE1]
+		});
+		Namco163 {
+			prg,
+			prg_ram: BankedMemory::ram(header.prg_ram_total(), 0x2000, 1),
+			write_protect: 0,
+			chr,
+			chr_banks,
+			ram: [0; 0x80],
+			ram_address: Cell::new(0),
+			counter: 0,
+			irq_enabled: false,
+			irq_pending: false,
+			audio_disabled: false,
+			audio_cycles: 0,
+			audio_channel: 7,
+			outputs: [0; 8]
+		}
+	}
+
+	fn chr_load(&self, bank: u8, address: u16) -> u8 {
+		if self.chr.is_empty() {
+			return 0;
+		}
+		let offset = bank as usize * CHR_BANK_SIZE + (address as usize % CHR_BANK_SIZE);
+		self.chr[offset % self.chr.len()]
+	}
+
+	// Move on the internal RAM address after an access, if auto increment is on
+	fn increment_ram_address(&self) {
+		let address = self.ram_address.get();
+		if address & 0x80 != 0 {
+			self.ram_address.set(0x80 | (address.wrapping_add(1) & 0x7F));
+		}
+	}
+
+	fn prg_ram_writable(&self, address: u16) -> bool {
+		let region = (address - 0x6000) / 0x800;
+		self.write_protect & 0xF0 == 0x40 && self.write_protect & (1 << region) == 0
+	}
+
+	fn active_channels(&self) -> usize {
+		((self.ram[0x7F] >> 4) & 0x07) as usize + 1
+	}
+
+	// Advance a channel's phase through its waveform, and work out its output
+	fn update_channel(&mut self, channel: usize) {
+		let base = CHANNEL_REGISTERS + channel * 8;
+		let r = &self.ram[base..base + 8];
+		let frequency = r[0] as u32 | (r[2] as u32) << 8 | ((r[4] & 0x03) as u32) << 16;
+		let phase = r[1] as u32 | (r[3] as u32) << 8 | (r[5] as u32) << 16;
+		let length = 256 - (r[4] & 0xFC) as u32;
+		let (offset, volume) = (r[6] as u32, (r[7] & 0x0F) as i16);
+
+		let phase = (phase + frequency) % (length << 16);
+		let index = (((phase >> 16) + offset) & 0xFF) as usize;
+		let sample = (self.ram[index / 2] >> ((index & 1) * 4)) & 0x0F;
+		self.outputs[channel] = (sample as i16 - 8) * volume;
+
+		self.ram[base + 1] = phase as u8;
+		self.ram[base + 3] = (phase >> 8) as u8;
+		self.ram[base + 5] = (phase >> 16) as u8;
+	}
+
+	fn clock_audio(&mut self) {
+		self.audio_cycles += 1;
+		if self.audio_cycles < CHANNEL_CYCLES {
+			return;
+		}
+		self.audio_cycles = 0;
+		let channel = self.audio_channel;
+		self.update_channel(channel);
+		// Channels are updated from 7 down to the last active one
+		self.audio_channel = if channel <= 8 - self.active_channels() { 7 } else { channel - 1 };
+	}
+}
+
+// Namco 163 Memory Map
+// CPU:
+// 0x4800 -> 0x5FFF: Registers
+// 0x6000 -> 0x7FFF: 8 KB PRG RAM
+// 0x8000 -> 0xDFFF: Three 8 KB switchable PRG ROM banks
+// 0xE000 -> 0xFFFF: 8 KB PRG ROM bank fixed to the last bank
+// PPU:
+// 0x0000 -> 0x1FFF: Eight 1 KB switchable CHR ROM banks
+// 0x2000 -> 0x2FFF: Four 1 KB switchable nametables, from CHR ROM or VRAM
+impl Mapper for Namco163 {
+	fn cpu_read(&self, address: u16) -> Result<u8> {
+		match address {
+			0x4800 ..= 0x4FFF => {
+				let value = self.ram[(self.ram_address.get() & 0x7F) as usize];
+				self.increment_ram_address();
+				Ok(value)
+			},
+			0x5000 ..= 0x57FF => Ok(self.counter as u8),
+			0x5800 ..= 0x5FFF => Ok((self.counter >> 8) as u8 | (self.irq_enabled as u8) << 7),
+			0x6000 ..= 0x7FFF => self.prg_ram.load(address - 0x6000),
+			0x8000 ..= 0xFFFF => self.prg.load(address - 0x8000),
+			_ => Err(EmuError::BusFault { address, access: Access::Read })
+		}
+	}
+
+	fn cpu_write(&mut self, address: u16, value: u8) -> Result<()> {
+		match address {
+			0x4800 ..= 0x4FFF => {
+				self.ram[(self.ram_address.get() & 0x7F) as usize] = value;
+				self.increment_ram_address();
+			},
+			0x5000 ..= 0x57FF => {
+				self.counter = (self.counter & 0x7F00) | value as u16;
+				self.irq_pending = false;
+			},
+			0x5800 ..= 0x5FFF => {
+				self.counter = (self.counter & 0x00FF) | ((value & 0x7F) as u16) << 8;
+				self.irq_enabled = value & 0x80 != 0;
+				self.irq_pending = false;
+			},
+			0x6000 ..= 0x7FFF => {
+				// Writes to protected PRG RAM are ignored
+				if self.prg_ram_writable(address) {
+					self.prg_ram.store(address - 0x6000, value)?;
+				}
+			},
+			0x8000 ..= 0xDFFF => self.chr_banks[(address - 0x8000) as usize / 0x800] = value,
+			0xE000 ..= 0xE7FF => {
+				self.prg.switch(0, (value & 0x3F) as usize);
+				self.audio_disabled = value & 0x40 != 0;
+			},
+			0xE800 ..= 0xEFFF => self.prg.switch(1, (value & 0x3F) as usize),
+			0xF000 ..= 0xF7FF => self.prg.switch(2, (value & 0x3F) as usize),
+			0xF800 ..= 0xFFFF => {
+				self.ram_address.set(value);
+				self.write_protect = value;
+			},
+			_ => return Err(EmuError::BusFault { address, access: Access::Write })
+		}
+		Ok(())
+	}
+
+	fn ppu_read(&mut self, address: u16) -> Result<u8> {
+		match address {
+			0x0000 ..= 0x1FFF => {
+				let bank = self.chr_banks[address as usize / CHR_BANK_SIZE];
+				Ok(self.chr_load(bank, address))
+			},
+			_ => Err(EmuError::BusFault { address, access: Access::Read })
+		}
+	}
+
+	fn ppu_write(&mut self, address: u16, _value: u8) -> Result<()> {
+		Err(EmuError::BusFault { address, access: Access::Write })
+	}
+
+	/// Arrangement of the nametables when they're all in VRAM. Other arrangements, and ones using
+	/// CHR ROM, are reported as four screen since the mapper supplies the nametables.
+	fn mirroring(&self) -> Mirroring {
+		let banks = &self.chr_banks[8..];
+		if banks.iter().any(|&bank| bank < VRAM_BANKS) {
+			return Mirroring::FourScreen;
+		}
+		match banks.iter().map(|&bank| bank & 1).collect::<Vec<u8>>()[..] {
+			[0, 0, 0, 0] => Mirroring::SingleScreenLower,
+			[1, 1, 1, 1] => Mirroring::SingleScreenUpper,
+			[0, 1, 0, 1] => Mirroring::Vertical,
+			[0, 0, 1, 1] => Mirroring::Horizontal,
+			_ => Mirroring::FourScreen
+		}
+	}
+
+	fn nametable_read(&mut self, address: u16, vram: &[u8]) -> u8 {
+		let bank = self.chr_banks[8 + ((address as usize >> 10) & 0b11)];
+		let offset = address as usize & 0x3FF;
+		if bank >= VRAM_BANKS {
+			vram[((bank as usize & 1) * 0x400 + offset) % vram.len()]
+		} else {
+			self.chr_load(bank, address)
+		}
+	}
+
+	fn nametable_write(&mut self, address: u16, value: u8, vram: &mut [u8]) {
+		let bank = self.chr_banks[8 + ((address as usize >> 10) & 0b11)];
+		let offset = address as usize & 0x3FF;
+		// Writes to nametables in CHR ROM are ignored
+		if bank >= VRAM_BANKS {
+			vram[((bank as usize & 1) * 0x400 + offset) % vram.len()] = value;
+		}
+	}
+
+	fn irq(&self) -> bool {
+		self.irq_pending
+	}
+
+	fn clock(&mut self, cpu_cycles: u32) {
+		for _ in 0..cpu_cycles {
+			if self.irq_enabled && self.counter < 0x7FFF {
+				self.counter += 1;
+				if self.counter == 0x7FFF {
+					self.irq_pending = true;
+				}
+			}
+			if !self.audio_disabled {
+				self.clock_audio();
+			}
+		}
+	}
+
+	/// The chip plays one channel at a time, which averages out to the mean of the channels
+	fn audio(&self) -> f32 {
+		if self.audio_disabled {
+			return 0.0;
+		}
+		let active = self.active_channels();
+		let sum: i16 = self.outputs[8 - active..].iter().sum();
+		sum as f32 * NAMCO_163_LEVEL / active as f32
+	}
+
+	fn prg_ram(&self) -> Option<&[u8]> {
+		Some(self.prg_ram.data())
+	}
+
+	fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+		Some(self.prg_ram.data_mut())
+	}
+}
+
+#[cfg(test)]
+use mapper::{ numbered_banks, prg_banks, test_header };
+
+// 128 KB of PRG ROM in 8 KB banks and 64 KB of CHR ROM in 1 KB banks, each bank starting with its
+// bank number
+#[cfg(test)]
+fn test_namco163() -> Namco163 {
+	let prg = numbered_banks(16, PRG_BANK_SIZE, 0);
+	let chr = numbered_banks(64, CHR_BANK_SIZE, 0);
+	Namco163::new(test_header(8, 8, 0), prg, chr)
+}
+
+#[cfg(test)]
+fn write_ram(namco163: &mut Namco163, address: u8, values: &[u8]) {
+	namco163.cpu_write(0xF800, 0x80 | address).unwrap();
+	for &value in values {
+		namco163.cpu_write(0x4800, value).unwrap();
+	}
+}
+
+#[test]
+fn test_banking() {
+	let mut namco163 = test_namco163();
+	namco163.cpu_write(0xE000, 3).unwrap();
+	namco163.cpu_write(0xE800, 4).unwrap();
+	namco163.cpu_write(0xF000, 5).unwrap();
+	assert_eq!(prg_banks(&namco163), vec![3, 4, 5, 15]);
+	namco163.cpu_write(0xB800, 0x21).unwrap();
+	assert_eq!(namco163.ppu_read(0x1C00).unwrap(), 0x21);
+}
+
+#[test]
+fn test_nametables() {
+	let mut namco163 = test_namco163();
+	let mut vram = [0; 0x800];
+	assert_eq!(namco163.mirroring(), Mirroring::Horizontal);
+	namco163.cpu_write(0xC800, 0xE1).unwrap();
+	namco163.cpu_write(0xD000, 0xE0).unwrap();
+	assert_eq!(namco163.mirroring(), Mirroring::Vertical);
+	namco163.nametable_write(0x2405, 0x12, &mut vram);
+	assert_eq!(vram[0x405], 0x12);
+
+	// A nametable from CHR ROM
+	namco163.cpu_write(0xD800, 0x09).unwrap();
+	assert_eq!(namco163.nametable_read(0x2C00, &vram), 0x09);
+	namco163.nametable_write(0x2C00, 0x34, &mut vram);
+	assert_eq!(namco163.nametable_read(0x2C00, &vram), 0x09);
+	assert_eq!(namco163.mirroring(), Mirroring::FourScreen);
+}
+
+#[test]
+fn test_internal_ram() {
+	let mut namco163 = test_namco163();
+	write_ram(&mut namco163, 0x7E, &[0x11, 0x22, 0x33]);
+	// Auto increment wraps around
+	namco163.cpu_write(0xF800, 0x80).unwrap();
+	assert_eq!(namco163.cpu_read(0x4800).unwrap(), 0x33);
+	assert_eq!(namco163.cpu_read(0x4800).unwrap(), 0x00);
+	namco163.cpu_write(0xF800, 0x7E).unwrap();
+	assert_eq!(namco163.cpu_read(0x4800).unwrap(), 0x11);
+	assert_eq!(namco163.cpu_read(0x4800).unwrap(), 0x11);
+}
+
+#[test]
+fn test_prg_ram_protect() {
+	let mut namco163 = test_namco163();
+	namco163.cpu_write(0x6000, 0x12).unwrap();
+	assert_eq!(namco163.cpu_read(0x6000).unwrap(), 0x00);
+	// Enable writes, except to 0x6800-0x6FFF
+	namco163.cpu_write(0xF800, 0x42).unwrap();
+	namco163.cpu_write(0x6000, 0x12).unwrap();
+	namco163.cpu_write(0x6800, 0x34).unwrap();
+	assert_eq!(namco163.cpu_read(0x6000).unwrap(), 0x12);
+	assert_eq!(namco163.cpu_read(0x6800).unwrap(), 0x00);
+}
+
+#[test]
+fn test_irq() {
+	let mut namco163 = test_namco163();
+	namco163.cpu_write(0x5000, 0xFD).unwrap();
+	namco163.cpu_write(0x5800, 0xFF).unwrap();
+	namco163.clock(1);
+	assert!(!namco163.irq());
+	namco163.clock(1);
+	assert!(namco163.irq());
+	assert_eq!(namco163.cpu_read(0x5800).unwrap(), 0xFF);
+
+	// The counter stops at 0x7FFF, and writing it acknowledges the IRQ
+	namco163.clock(10);
+	assert_eq!(namco163.cpu_read(0x5000).unwrap(), 0xFF);
+	namco163.cpu_write(0x5000, 0x00).unwrap();
+	assert!(!namco163.irq());
+}
+
+#[test]
+fn test_audio() {
+	let mut namco163 = test_namco163();
+	// A 4 sample waveform at the start of RAM, played one sample per update by channel 7 alone
+	write_ram(&mut namco163, 0x00, &[0x9F, 0x30]);
+	write_ram(&mut namco163, 0x78, &[0x00, 0x00, 0x00, 0x00, 0xFD, 0x00, 0x00, 0x0F]);
+
+	let mut levels = Vec::new();
+	for _ in 0..4 {
+		namco163.clock(CHANNEL_CYCLES as u32);
+		levels.push((namco163.audio() / NAMCO_163_LEVEL).round() as i16);
+	}
+	// The phase starts at 0 and moves on before each sample is played
+	assert_eq!(levels, vec![15, -8 * 15, -5 * 15, 7 * 15]);
+}
@@ -0,0 +1,127 @@
+use error::{ Access, EmuError, Result };
+use mapper::{ Mapper, Mirroring };
+use memory::*;
+use rom::*;
+
+/// NROM (0x0) Mapper: up to 32 KB of PRG ROM, 8 KB of CHR ROM or RAM and no bank switching
+///     http://wiki.nesdev.com/w/index.php/NROM
+pub struct NRom {
+	is_mirroring_prg: bool,
+	prg: ReadOnlyMemory,
+	prg_ram: BankedMemory,
+	chr: Box<dyn Memory>,
+	mirroring: Mirroring
+}
+
+impl NRom {
+	pub fn new(header: Header, prg: Vec<u8>, chr: Vec<u8>) -> NRom {
+		// Boards without CHR ROM have 8 KB of CHR RAM instead
+		let chr: Box<dyn Memory> = if chr.is_empty() {
+			Box::new(ReadWriteMemory::new(CHR_ROM_UNIT_SIZE))
+		} else {
+			Box::new(ReadOnlyMemory::new(chr))
+		};
+		NRom {
+			is_mirroring_prg: header.prg_rom_size == PRG_ROM_UNIT_SIZE,
+			prg: ReadOnlyMemory::new(prg),
+			// Only Family BASIC has PRG RAM, but it costs nothing to give it to every board
+			prg_ram: BankedMemory::ram(header.prg_ram_total(), 0x2000, 1),
+			chr,
+			mirroring: header.mirroring()
+		}
+	}
+}
+
+// NROM Memory Map
+// CPU:
+// 0x6000 -> 0x7FFF: PRG RAM
+// 0x8000 -> 0xBFFF: First 16 KB of ROM.
+// 0xC000 -> 0xFFFF: Last 16 KB of ROM (or mirror of first 16 KB)
+// PPU:
+// 0x0000 -> 0x1FFF: 8 KB of CHR ROM or RAM
+impl Mapper for NRom {
+	fn cpu_read(&self, address: u16) -> Result<u8> {
+		match address {
+			0x6000 ..= 0x7FFF => self.prg_ram.load(address - 0x6000),
+			0x8000 ..= 0xFFFF => {
+				if self.is_mirroring_prg && address > 0xBFFF {
+					self.prg.load(address - 0xC000)
+				} else {
+					self.prg.load(address - 0x8000)
+				}
+			},
+			_ => Err(EmuError::BusFault { address, access: Access::Read })
+		}
+	}
+
+	fn cpu_write(&mut self, address: u16, value: u8) -> Result<()> {
+		match address {
+			0x6000 ..= 0x7FFF => self.prg_ram.store(address - 0x6000, value),
+			_ => Err(EmuError::BusFault { address, access: Access::Write })
+		}
+	}
+
+	fn ppu_read(&mut self, address: u16) -> Result<u8> {
+		match address {
+			0x0000 ..= 0x1FFF => self.chr.load(address),
+			_ => Err(EmuError::BusFault { address, access: Access::Read })
+		}
+	}
+
+	fn ppu_write(&mut self, address: u16, value: u8) -> Result<()> {
+		match address {
+			0x0000 ..= 0x1FFF => self.chr.store(address, value),
+			_ => Err(EmuError::BusFault { address, access: Access::Write })
+		}
+	}
+
+	fn mirroring(&self) -> Mirroring {
+		self.mirroring
+	}
+
+	fn prg_ram(&self) -> Option<&[u8]> {
+		Some(self.prg_ram.data())
+	}
+
+	fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+		Some(self.prg_ram.data_mut())
+	}
+}
+
+#[cfg(test)]
+use mapper::test_header;
+
+#[test]
+fn test_nrom_prg_mirroring() {
+	let mut prg = vec![0; PRG_ROM_UNIT_SIZE];
+	prg[0] = 0x12;
+	let nrom = NRom::new(test_header(1, 1, 0), prg, vec![0; CHR_ROM_UNIT_SIZE]);
+	assert_eq!(nrom.cpu_read(0x8000).unwrap(), 0x12);
+	assert_eq!(nrom.cpu_read(0xC000).unwrap(), 0x12);
+	assert_eq!(nrom.mirroring(), Mirroring::Horizontal);
+}
+
+#[test]
+fn test_nrom_chr() {
+	let mut chr = vec![0; CHR_ROM_UNIT_SIZE];
+	chr[0x1FFF] = 0x34;
+	let mut nrom = NRom::new(test_header(2, 1, 1), vec![0; 2 * PRG_ROM_UNIT_SIZE], chr);
+	assert_eq!(nrom.ppu_read(0x1FFF).unwrap(), 0x34);
+	assert!(nrom.ppu_write(0x1FFF, 0x56).is_err());
+	assert_eq!(nrom.mirroring(), Mirroring::Vertical);
+
+	// CHR RAM when the header has no CHR ROM
+	let mut nrom = NRom::new(test_header(2, 0, 0), vec![0; 2 * PRG_ROM_UNIT_SIZE], Vec::new());
+	nrom.ppu_write(0x1FFF, 0x56).unwrap();
+	assert_eq!(nrom.ppu_read(0x1FFF).unwrap(), 0x56);
+}
+
+#[test]
+fn test_nrom_prg_ram() {
+	let mut nrom = NRom::new(test_header(1, 1, 0b10), vec![0; PRG_ROM_UNIT_SIZE], Vec::new());
+	nrom.cpu_write(0x6000, 0x12).unwrap();
+	nrom.cpu_write(0x7FFF, 0x34).unwrap();
+	assert_eq!(nrom.cpu_read(0x6000).unwrap(), 0x12);
+	assert_eq!(nrom.prg_ram().unwrap()[0x1FFF], 0x34);
+	assert!(nrom.cpu_write(0x8000, 0x56).is_err());
+}
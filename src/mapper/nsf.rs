@@ -0,0 +1,187 @@
+use error::{ Access, EmuError, Result };
+use mapper::{ Mapper, Mirroring };
+use mapper::sunsoft5b_audio::Sunsoft5bAudio;
+use mapper::vrc6_audio::Vrc6Audio;
+use memory::*;
+use nsf::Nsf;
+
+const PRG_BANK_SIZE: usize = 0x1000;
+
+/// The board an NSF player puts an NSF's music code on: 32 KB of ROM in 8 windows of 4 KB, each
+/// switched by its own register, and 8 KB of RAM
+///     http://wiki.nesdev.com/w/index.php/NSF#Bankswitching
+///
+/// Of the expansion audio chips, only the VRC6 and the Sunsoft 5B are emulated. Writes to the
+/// registers of the others, and to ROM, are ignored, so their parts of a tune are silent.
+pub struct NsfMapper {
+	prg: BankedMemory,
+	prg_ram: BankedMemory,
+	vrc6_audio: Option<Vrc6Audio>,
+	sunsoft5b_audio: Option<Sunsoft5bAudio>
+}
+
+impl NsfMapper {
+	pub fn new(nsf: &Nsf) -> NsfMapper {
+		// Bankswitched data starts as far into its first bank as the load address is into a 4 KB
+		// page. Otherwise it goes where it's loaded, and the banks are fixed.
+		let (padding, banks) = match nsf.banks {
+			Some(banks) => ((nsf.load_address & 0x0FFF) as usize, banks),
+			None => (nsf.load_address.saturating_sub(0x8000) as usize, [0, 1, 2, 3, 4, 5, 6, 7])
+		};
+		let mut data = vec![0; padding];
+		data.extend_from_slice(&nsf.data);
+		let size = data.len().div_ceil(PRG_BANK_SIZE).max(1) * PRG_BANK_SIZE;
+		data.resize(size, 0);
+
+		let mut prg = BankedMemory::rom(data, PRG_BANK_SIZE, 8);
+		for (window, &bank) in banks.iter().enumerate() {
+			prg.switch(window, bank as usize);
+		}
+		NsfMapper {
+			prg,
+			prg_ram: BankedMemory::ram(0x2000, 0x2000, 1),
+			vrc6_audio: nsf.expansion_audio.vrc6.then(Vrc6Audio::new),
+			sunsoft5b_audio: nsf.expansion_audio.sunsoft5b.then(Sunsoft5bAudio::new)
+		}
+	}
+}
+
+// NSF Memory Map
+// CPU:
+// 0x5FF8 -> 0x5FFF: 4 KB bank shown at 0x8000, 0x9000 ... 0xF000 (write only)
+// 0x6000 -> 0x7FFF: RAM
+// 0x8000 -> 0xFFFF: ROM in 8 switchable 4 KB banks
+// VRC6 audio registers at 0x9000 -> 0x9003, 0xA000 -> 0xA002 and 0xB000 -> 0xB002, and Sunsoft
+// 5B audio register select and data at 0xC000 and 0xE000, when the NSF uses them
+// PPU:
+// Nothing
+impl Mapper for NsfMapper {
+	fn cpu_read(&self, address: u16) -> Result<u8> {
+		match address {
+			0x6000 ..= 0x7FFF => self.prg_ram.load(address - 0x6000),
+			0x8000 ..= 0xFFFF => self.prg.load(address - 0x8000),
+			_ => Err(EmuError::BusFault { address, access: Access::Read })
+		}
+	}
+
+	fn cpu_write(&mut self, address: u16, value: u8) -> Result<()> {
+		match address {
+			0x5FF8 ..= 0x5FFF => self.prg.switch((address - 0x5FF8) as usize, value as usize),
+			0x6000 ..= 0x7FFF => return self.prg_ram.store(address - 0x6000, value),
+			0x9000 ..= 0x9003 | 0xA000 ..= 0xA002 | 0xB000 ..= 0xB002 => {
+				if let Some(ref mut audio) = self.vrc6_audio {
+					audio.write(address, value);
+				}
+			},
+			0xC000 => {
+				if let Some(ref mut audio) = self.sunsoft5b_audio {
+					audio.select(value);
+				}
+			},
+			0xE000 => {
+				if let Some(ref mut audio) = self.sunsoft5b_audio {
+					audio.write(value);
+				}
+			},
+			_ => ()
+		}
+		Ok(())
+	}
+
+	fn ppu_read(&mut self, address: u16) -> Result<u8> {
+		Err(EmuError::BusFault { address, access: Access::Read })
+	}
+
+	fn ppu_write(&mut self, address: u16, _value: u8) -> Result<()> {
+		Err(EmuError::BusFault { address, access: Access::Write })
+	}
+
+	fn mirroring(&self) -> Mirroring {
+		Mirroring::Horizontal
+	}
+
+	fn clock(&mut self, cpu_cycles: u32) {
+		for _ in 0..cpu_cycles {
+			if let Some(ref mut audio) = self.vrc6_audio {
+				audio.clock();
+			}
+			if let Some(ref mut audio) = self.sunsoft5b_audio {
+				audio.clock();
+			}
+		}
+	}
+
+	fn audio(&self) -> f32 {
+		self.vrc6_audio.as_ref().map_or(0.0, Vrc6Audio::output)
+			+ self.sunsoft5b_audio.as_ref().map_or(0.0, Sunsoft5bAudio::output)
+	}
+
+	fn prg_ram(&self) -> Option<&[u8]> {
+		Some(self.prg_ram.data())
+	}
+
+	fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+		Some(self.prg_ram.data_mut())
+	}
+}
+
+#[cfg(test)]
+use nsf::test_nsf;
+
+#[test]
+fn test_fixed_banks() {
+	// Loaded at 0x8400 without bankswitching
+	let mut nsf = test_nsf(vec![0x11; 0x1000]);
+	nsf.load_address = 0x8400;
+	let mapper = NsfMapper::new(&nsf);
+	assert_eq!(mapper.cpu_read(0x83FF).unwrap(), 0x00);
+	assert_eq!(mapper.cpu_read(0x8400).unwrap(), 0x11);
+	assert_eq!(mapper.cpu_read(0x93FF).unwrap(), 0x11);
+	assert_eq!(mapper.cpu_read(0x9400).unwrap(), 0x00);
+}
+
+#[test]
+fn test_bankswitching() {
+	// Bank n starts with n, loaded 0x200 bytes into the first bank
+	let mut data = vec![0; 0x1000 * 4 - 0x200];
+	for bank in 1..4 {
+		data[bank * 0x1000 - 0x200] = bank as u8;
+	}
+	data[0] = 0xAA;
+	let mut nsf = test_nsf(data);
+	nsf.load_address = 0x8200;
+	nsf.banks = Some([0, 1, 2, 3, 3, 2, 1, 0]);
+	let mut mapper = NsfMapper::new(&nsf);
+	assert_eq!(mapper.cpu_read(0x8200).unwrap(), 0xAA);
+	assert_eq!(mapper.cpu_read(0xC000).unwrap(), 3);
+	assert_eq!(mapper.cpu_read(0xE000).unwrap(), 1);
+	assert_eq!(mapper.cpu_read(0xF200).unwrap(), 0xAA);
+
+	mapper.cpu_write(0x5FFF, 2).unwrap();
+	assert_eq!(mapper.cpu_read(0xF000).unwrap(), 2);
+	// Writes to ROM are ignored
+	mapper.cpu_write(0xF000, 0x55).unwrap();
+	assert_eq!(mapper.cpu_read(0xF000).unwrap(), 2);
+
+	mapper.cpu_write(0x6000, 0x42).unwrap();
+	assert_eq!(mapper.cpu_read(0x6000).unwrap(), 0x42);
+}
+
+#[test]
+fn test_expansion_audio() {
+	let mut nsf = test_nsf(vec![0; 0x1000]);
+	nsf.expansion_audio.vrc6 = true;
+	let mut mapper = NsfMapper::new(&nsf);
+	// Pulse 1 at full volume, ignoring its duty
+	mapper.cpu_write(0x9000, 0x8F).unwrap();
+	mapper.cpu_write(0x9002, 0x80).unwrap();
+	mapper.clock(10);
+	assert!(mapper.audio() > 0.0);
+
+	// Without the chip the same writes do nothing
+	let mut mapper = NsfMapper::new(&test_nsf(vec![0; 0x1000]));
+	mapper.cpu_write(0x9000, 0x8F).unwrap();
+	mapper.cpu_write(0x9002, 0x80).unwrap();
+	mapper.clock(10);
+	assert_eq!(mapper.audio(), 0.0);
+}
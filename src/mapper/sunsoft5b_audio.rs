@@ -0,0 +1,229 @@
+/// Output of one channel at full volume, about as loud as an APU pulse channel at full volume
+const SUNSOFT_5B_LEVEL: f32 = 0.15;
+
+/// CPU cycles per step of the tone, noise and envelope generators
+const DIVIDER: u8 = 16;
+
+/// Sunsoft 5B expansion audio, a YM2149F: three square wave channels with a shared noise
+/// generator and envelope
+///     http://wiki.nesdev.com/w/index.php/Sunsoft_5B_audio
+///
+/// Registers, selected by writing the register number to 0xC000 then the value to 0xE000:
+///     0x00 -> 0x05: Tone period of each channel, low and high bits
+///     0x06:         Noise period
+///     0x07:         Tone and noise disable for each channel
+///     0x08 -> 0x0A: Volume of each channel, or bit 4 to use the envelope
+///     0x0B -> 0x0D: Envelope period, low and high bits, and shape
+pub struct Sunsoft5bAudio {
+	select: u8,
+	registers: [u8; 16],
+	divider: u8,
+	tones: [Generator; 3],
+	noise: Generator,
+	lfsr: u32,       // 17 bit noise shift register
+	envelope: Generator,
+	envelope_step: u8,
+	envelope_attack: bool,
+	envelope_holding: bool
+}
+
+/// Counts steps up to a period, then toggles its output
+#[derive(Default)]
+struct Generator {
+	counter: u16,
+	output: bool
+}
+
+impl Generator {
+	fn clock(&mut self, period: u16) -> bool {
+		self.counter += 1;
+		if self.counter >= period.max(1) {
+			self.counter = 0;
+			self.output = !self.output;
+			true
+		} else {
+			false
+		}
+	}
+}
+
+impl Sunsoft5bAudio {
+	pub fn new() -> Sunsoft5bAudio {
+		Sunsoft5bAudio {
+			select: 0,
+			registers: [0; 16],
+			divider: 0,
+			tones: [Generator::default(), Generator::default(), Generator::default()],
+			noise: Generator::default(),
+			lfsr: 1,
+			envelope: Generator::default(),
+			envelope_step: 0,
+			envelope_attack: false,
+			envelope_holding: false
+		}
+	}
+
+	pub fn select(&mut self, value: u8) {
+		self.select = value;
+	}
+
+	pub fn write(&mut self, value: u8) {
+		// The high bits of the register number disable writes
+		if self.select & 0xF0 != 0 {
+			return;
+		}
+		self.registers[self.select as usize] = value;
+		if self.select == 0x0D {
+			self.envelope_step = 0;
+			self.envelope_attack = value & 0x04 != 0;
+			self.envelope_holding = false;
+			self.envelope.counter = 0;
+		}
+	}
+
+	fn tone_period(&self, channel: usize) -> u16 {
+		self.registers[channel * 2] as u16 | ((self.registers[channel * 2 + 1] & 0x0F) as u16) << 8
+	}
+
+	fn envelope_level(&self) -> u8 {
+		if self.envelope_attack { self.envelope_step } else { 31 - self.envelope_step }
+	}
+
+	fn step_envelope(&mut self) {
+		if self.envelope_holding {
+			return;
+		}
+		self.envelope_step += 1;
+		if self.envelope_step < 32 {
+			return;
+		}
+		let shape = self.registers[0x0D];
+		let (continuing, alternate, hold) = (shape & 0x08 != 0, shape & 0x02 != 0, shape & 0x01 != 0);
+		if !continuing || hold {
+			// Hold at the end of the ramp, or at zero for shapes that don't continue
+			self.envelope_holding = true;
+			self.envelope_step = 31;
+			if !continuing {
+				self.envelope_attack = false;
+			} else if alternate {
+				self.envelope_attack = !self.envelope_attack;
+			}
+		} else {
+			self.envelope_step = 0;
+			if alternate {
+				self.envelope_attack = !self.envelope_attack;
+			}
+		}
+	}
+
+	/// Advance the generators by one CPU cycle
+	pub fn clock(&mut self) {
+		self.divider += 1;
+		if self.divider < DIVIDER {
+			return;
+		}
+		self.divider = 0;
+
+		for channel in 0..3 {
+			let period = self.tone_period(channel);
+			self.tones[channel].clock(period);
+		}
+		if self.noise.clock((self.registers[0x06] & 0x1F) as u16) {
+			let bit = (self.lfsr ^ (self.lfsr >> 3)) & 1;
+			self.lfsr = (self.lfsr >> 1) | bit << 16;
+		}
+		let envelope_period = self.registers[0x0B] as u16 | (self.registers[0x0C] as u16) << 8;
+		if self.envelope.clock(envelope_period) {
+			self.step_envelope();
+		}
+	}
+
+	pub fn output(&self) -> f32 {
+		let mixer = self.registers[0x07];
+		let noise = self.lfsr & 1 != 0;
+		let mut output = 0.0;
+		for channel in 0..3 {
+			let tone_off = mixer & (1 << channel) != 0;
+			let noise_off = mixer & (8 << channel) != 0;
+			if (self.tones[channel].output || tone_off) && (noise || noise_off) {
+				let volume = self.registers[0x08 + channel];
+				// Volumes are in 3 dB steps, and the envelope in 1.5 dB steps
+				let level = if volume & 0x10 != 0 {
+					self.envelope_level()
+				} else if volume & 0x0F == 0 {
+					0
+				} else {
+					(volume & 0x0F) * 2 + 1
+				};
+				if level != 0 {
+					output += SUNSOFT_5B_LEVEL * 2f32.powf((level as f32 - 31.0) / 4.0);
+				}
+			}
+		}
+		output
+	}
+}
+
+impl Default for Sunsoft5bAudio {
+	fn default() -> Sunsoft5bAudio {
+		Sunsoft5bAudio::new()
+	}
+}
+
+#[cfg(test)]
+fn write_register(audio: &mut Sunsoft5bAudio, register: u8, value: u8) {
+	audio.select(register);
+	audio.write(value);
+}
+
+#[test]
+fn test_tone() {
+	let mut audio = Sunsoft5bAudio::new();
+	// Channel A only, at full volume with a period of 2
+	write_register(&mut audio, 0x07, 0b111_110);
+	write_register(&mut audio, 0x08, 0x0F);
+	write_register(&mut audio, 0x00, 0x02);
+	let mut levels = Vec::new();
+	for _ in 0..4 {
+		for _ in 0..2 * DIVIDER {
+			audio.clock();
+		}
+		levels.push(audio.output());
+	}
+	assert_eq!(levels, vec![SUNSOFT_5B_LEVEL, 0.0, SUNSOFT_5B_LEVEL, 0.0]);
+
+	// Each step of volume is 3 dB
+	write_register(&mut audio, 0x08, 0x0D);
+	for _ in 0..2 * DIVIDER {
+		audio.clock();
+	}
+	assert!((audio.output() / SUNSOFT_5B_LEVEL - 0.5).abs() < 0.01);
+}
+
+#[test]
+fn test_envelope() {
+	let mut audio = Sunsoft5bAudio::new();
+	// Channel A always on and following the envelope, which ramps up once then holds
+	write_register(&mut audio, 0x07, 0b111_111);
+	write_register(&mut audio, 0x08, 0x10);
+	write_register(&mut audio, 0x0B, 0x01);
+	write_register(&mut audio, 0x0D, 0x0D);
+	assert_eq!(audio.envelope_level(), 0);
+	for _ in 0..16 * DIVIDER as u32 {
+		audio.clock();
+	}
+	assert_eq!(audio.envelope_level(), 16);
+	for _ in 0..64 * DIVIDER as u32 {
+		audio.clock();
+	}
+	assert_eq!(audio.envelope_level(), 31);
+	assert_eq!(audio.output(), SUNSOFT_5B_LEVEL);
+
+	// Ramping down without continuing ends at 0
+	write_register(&mut audio, 0x0D, 0x00);
+	for _ in 0..64 * DIVIDER as u32 {
+		audio.clock();
+	}
+	assert_eq!(audio.envelope_level(), 0);
+	assert_eq!(audio.output(), 0.0);
+}
@@ -0,0 +1,565 @@
+use error::{ Access, EmuError, Result };
+use mapper::vrc6_audio::Vrc6Audio;
+use mapper::{ chr_memory, Mapper, Mirroring };
+use memory::*;
+use rom::*;
+
+/// Size of the PRG ROM windows. The VRC6's 16 KB bank is shown through two of them.
+const PRG_BANK_SIZE: usize = 0x2000;
+
+/// Size of the CHR windows
+const CHR_BANK_SIZE: usize = 0x0400;
+
+/// CPU cycles per scanline times 3, the step of the IRQ prescaler
+const PRESCALER_PERIOD: i16 = 341;
+
+/// Konami VRC chip on the board
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VrcChip {
+	Vrc2,
+	Vrc4,
+	Vrc6,
+	Vrc7
+}
+
+/// How a board connects the CPU address lines to the chip's two register select inputs. Each
+/// mask holds the address lines that drive one input. When a header doesn't say which board it
+/// is, the lines of every board using that mapper number are combined, which works because games
+/// only write to addresses that are valid for their own board.
+#[derive(Clone, Copy, Debug)]
+struct Variant {
+	chip: VrcChip,
+	a0: u16,
+	a1: u16,
+	/// VRC2a ignores the low bit of its CHR bank numbers
+	chr_shift: bool
+}
+
+impl Variant {
+	fn new(chip: VrcChip, a0: u16, a1: u16) -> Variant {
+		Variant { chip, a0, a1, chr_shift: false }
+	}
+
+	// Variant - The board for an iNES mapper number and NES 2.0 submapper
+	//     http://wiki.nesdev.com/w/index.php/VRC2_and_VRC4
+	fn from_header(mapper: u16, submapper: u8) -> Variant {
+		use self::VrcChip::*;
+		match (mapper, submapper) {
+			(21, 1) => Variant::new(Vrc4, 0x02, 0x04),  // VRC4a
+			(21, 2) => Variant::new(Vrc4, 0x40, 0x80),  // VRC4c
+			(21, _) => Variant::new(Vrc4, 0x42, 0x84),
+			(22, _) => Variant { chr_shift: true, ..Variant::new(Vrc2, 0x02, 0x01) }, // VRC2a
+			(23, 1) => Variant::new(Vrc4, 0x01, 0x02),  // VRC4f
+			(23, 2) => Variant::new(Vrc4, 0x04, 0x08),  // VRC4e
+			(23, 3) => Variant::new(Vrc2, 0x01, 0x02),  // VRC2b
+			(23, _) => Variant::new(Vrc4, 0x05, 0x0A),
+			(24, _) => Variant::new(Vrc6, 0x01, 0x02),  // VRC6a
+			(25, 1) => Variant::new(Vrc4, 0x02, 0x01),  // VRC4b
+			(25, 2) => Variant::new(Vrc4, 0x08, 0x04),  // VRC4d
+			(25, 3) => Variant::new(Vrc2, 0x02, 0x01),  // VRC2c
+			(25, _) => Variant::new(Vrc4, 0x0A, 0x05),
+			(26, _) => Variant::new(Vrc6, 0x02, 0x01),  // VRC6b
+			(85, 1) => Variant::new(Vrc7, 0x08, 0x00),  // VRC7b
+			(85, 2) => Variant::new(Vrc7, 0x10, 0x00),  // VRC7a
+			_ => Variant::new(Vrc7, 0x18, 0x00)
+		}
+	}
+}
+
+/// IRQ counter of the VRC4, VRC6 and VRC7. It counts up from the latch and asserts IRQ when it
+/// overflows, either on every CPU cycle or once per scanline using a prescaler of 113⅔ cycles.
+///     http://wiki.nesdev.com/w/index.php/VRC_IRQ
+struct VrcIrq {
+	latch: u8,
+	counter: u8,
+	prescaler: i16,
+	enabled: bool,
+	enable_after_ack: bool,
+	cycle_mode: bool,
+	pending: bool
+}
+
+impl VrcIrq {
+	fn new() -> VrcIrq {
+		VrcIrq {
+			latch: 0,
+			counter: 0,
+			prescaler: PRESCALER_PERIOD,
+			enabled: false,
+			enable_after_ack: false,
+			cycle_mode: false,
+			pending: false
+		}
+	}
+
+	fn write_control(&mut self, value: u8) {
+		self.enable_after_ack = value & 0x01 != 0;
+		self.enabled = value & 0x02 != 0;
+		self.cycle_mode = value & 0x04 != 0;
+		self.pending = false;
+		if self.enabled {
+			self.counter = self.latch;
+			self.prescaler = PRESCALER_PERIOD;
+		}
+	}
+
+	fn acknowledge(&mut self) {
+		self.pending = false;
+		self.enabled = self.enable_after_ack;
+	}
+
+	fn clock(&mut self) {
+		if !self.enabled {
+			return;
+		}
+		if self.cycle_mode {
+			self.clock_counter();
+		} else {
+			self.prescaler -= 3;
+			if self.prescaler <= 0 {
+				self.prescaler += PRESCALER_PERIOD;
+				self.clock_counter();
+			}
+		}
+	}
+
+	fn clock_counter(&mut self) {
+		if self.counter == 0xFF {
+			self.counter = self.latch;
+			self.pending = true;
+		} else {
+			self.counter += 1;
+		}
+	}
+}
+
+/// Konami VRC2 (0x16), VRC4 (0x15, 0x17, 0x19), VRC6 (0x18, 0x1A) and VRC7 (0x55) Mappers
+///     http://wiki.nesdev.com/w/index.php/VRC2_and_VRC4
+///     http://wiki.nesdev.com/w/index.php/VRC6
+///     http://wiki.nesdev.com/w/index.php/VRC7
+///
+/// Each register is in one 4 KB page of 0x8000-0xFFFF, and the board's wiring of two address
+/// lines selects between up to four registers in the page. Registers below are given after that
+/// wiring has been undone, so 0x9001 is the second register at 0x9000 whatever lines select it.
+///
+/// VRC2 and VRC4:
+///     0x8000:           PRG ROM bank at 0x8000, or 0xC000 when swapped
+///     0x9000:           Mirroring
+///     0x9002:           PRG swap mode (VRC4)
+///     0xA000:           PRG ROM bank at 0xA000
+///     0xB000 -> 0xE003: CHR banks, low and high bits of two banks per page
+///     0xF000 -> 0xF003: IRQ latch low and high bits, control, acknowledge (VRC4)
+/// VRC6:
+///     0x8000:           16 KB PRG ROM bank at 0x8000
+///     0x9000 -> 0xB002: Audio
+///     0xB003:           Mirroring and PRG RAM enable
+///     0xC000:           8 KB PRG ROM bank at 0xC000
+///     0xD000 -> 0xE003: CHR banks
+///     0xF000 -> 0xF002: IRQ latch, control, acknowledge
+/// VRC7, where 0x9010 and 0x9030 are always decoded from A4 and A5:
+///     0x8000 -> 0x9000: PRG ROM banks at 0x8000, 0xA000 and 0xC000
+///     0x9010, 0x9030:   Audio register select and data
+///     0xA000 -> 0xD001: CHR banks
+///     0xE000:           Mirroring and PRG RAM enable
+///     0xE001 -> 0xF001: IRQ latch, control, acknowledge
+///
+/// The VRC6 only emulates its first PPU banking mode, which is the one every game uses. The VRC7's
+/// FM synthesizer isn't emulated, so its registers are kept but it's silent.
+pub struct Vrc {
+	variant: Variant,
+	prg: BankedMemory,
+	chr: BankedMemory,
+	prg_ram: BankedMemory,
+	prg_banks: [u8; 3],
+	prg_swap: bool,
+	chr_banks: [u16; 8],
+	mirroring: Mirroring,
+	prg_ram_enabled: bool,
+	microwire: u8,        // VRC2 latch at 0x6000, for boards without PRG RAM
+	irq: VrcIrq,
+	vrc6_audio: Vrc6Audio,
+	vrc7_select: u8,
+	vrc7_registers: [u8; 0x40]
+}
+
+impl Vrc {
+	pub fn new(header: Header, prg: Vec<u8>, chr: Vec<u8>) -> Vrc {
+		let variant = Variant::from_header(header.mapper_number, header.submapper);
+		let mut vrc = Vrc {
+			variant,
+			prg: BankedMemory::rom(prg, PRG_BANK_SIZE, 4),
+			chr: chr_memory(chr, CHR_BANK_SIZE, 8),
+			prg_ram: BankedMemory::ram(header.prg_ram_total(), 0x2000, 1),
+			prg_banks: [0, 1, 2],
+			prg_swap: false,
+			chr_banks: [0; 8],
+			mirroring: header.mirroring(),
+			// Only the VRC6 and VRC7 have an enable bit. VRC2 boards have either PRG RAM or the
+			// microwire latch; an iNES header only tells them apart by the battery.
+			prg_ram_enabled: match variant.chip {
+				VrcChip::Vrc2 => header.flags6.battery_backed_prg_ram
+					|| (header.flags7.ines_2 && header.prg_ram_total() > 0),
+				chip => chip == VrcChip::Vrc4
+			},
+			microwire: 0,
+			irq: VrcIrq::new(),
+			vrc6_audio: Vrc6Audio::new(),
+			vrc7_select: 0,
+			vrc7_registers: [0; 0x40]
+		};
+		vrc.update_banks();
+		vrc
+	}
+
+	pub fn chip(&self) -> VrcChip {
+		self.variant.chip
+	}
+
+	fn has_prg_ram(&self) -> bool {
+		self.variant.chip != VrcChip::Vrc2 || self.prg_ram_enabled
+	}
+
+	// Register - Page of the address plus the register selected by the board's wiring
+	fn register(&self, address: u16) -> u16 {
+		let a0 = (address & self.variant.a0 != 0) as u16;
+		let a1 = (address & self.variant.a1 != 0) as u16;
+		(address & 0xF000) | a1 << 1 | a0
+	}
+
+	fn update_banks(&mut self) {
+		let banks = self.prg_banks;
+		match self.variant.chip {
+			VrcChip::Vrc2 | VrcChip::Vrc4 => {
+				let (bank_window, fixed_window) = if self.prg_swap { (2, 0) } else { (0, 2) };
+				self.prg.switch(bank_window, (banks[0] & 0x1F) as usize);
+				self.prg.switch(1, (banks[1] & 0x1F) as usize);
+				self.prg.switch_from_end(fixed_window, 2);
+			},
+			VrcChip::Vrc6 => {
+				let bank = (banks[0] & 0x0F) as usize * 2;
+				self.prg.switch(0, bank);
+				self.prg.switch(1, bank + 1);
+				self.prg.switch(2, (banks[1] & 0x1F) as usize);
+			},
+			VrcChip::Vrc7 => {
+				for (window, &bank) in banks.iter().enumerate() {
+					self.prg.switch(window, (bank & 0x3F) as usize);
+				}
+			}
+		}
+		self.prg.switch_from_end(3, 1);
+
+		for (window, &bank) in self.chr_banks.iter().enumerate() {
+			let bank = if self.variant.chr_shift { bank >> 1 } else { bank };
+			self.chr.switch(window, bank as usize);
+		}
+	}
+
+	// Mirroring - From the low two bits of the VRC4, VRC6 and VRC7 mirroring registers
+	fn mirroring_from(value: u8) -> Mirroring {
+		match value & 0x03 {
+			0 => Mirroring::Vertical,
+			1 => Mirroring::Horizontal,
+			2 => Mirroring::SingleScreenLower,
+			_ => Mirroring::SingleScreenUpper
+		}
+	}
+
+	fn write_vrc2_vrc4(&mut self, register: u16, value: u8) {
+		let vrc4 = self.variant.chip == VrcChip::Vrc4;
+		match register {
+			0x8000 ..= 0x8003 => self.prg_banks[0] = value,
+			0x9000 ..= 0x9001 if vrc4 => self.mirroring = Vrc::mirroring_from(value),
+			0x9000 ..= 0x9003 if !vrc4 => {
+				self.mirroring = if value & 1 == 0 { Mirroring::Vertical } else { Mirroring::Horizontal };
+			},
+			0x9002 ..= 0x9003 => self.prg_swap = value & 0x02 != 0,
+			0xA000 ..= 0xA003 => self.prg_banks[1] = value,
+			0xB000 ..= 0xEFFF => {
+				// Low four bits of the bank in the even register and high bits in the odd one
+				let index = ((register - 0xB000) >> 12) as usize * 2 + (register as usize & 0x02) / 2;
+				let bank = self.chr_banks[index];
+				self.chr_banks[index] = if register & 1 == 0 {
+					(bank & 0x1F0) | (value & 0x0F) as u16
+				} else {
+					let high = if vrc4 { value & 0x1F } else { value & 0x0F };
+					(bank & 0x00F) | (high as u16) << 4
+				};
+			},
+			0xF000 if vrc4 => self.irq.latch = (self.irq.latch & 0xF0) | (value & 0x0F),
+			0xF001 if vrc4 => self.irq.latch = (self.irq.latch & 0x0F) | (value & 0x0F) << 4,
+			0xF002 if vrc4 => self.irq.write_control(value),
+			0xF003 if vrc4 => self.irq.acknowledge(),
+			_ => ()
+		}
+		self.update_banks();
+	}
+
+	fn write_vrc6(&mut self, register: u16, value: u8) {
+		match register {
+			0x8000 ..= 0x8003 => self.prg_banks[0] = value,
+			0xB003 => {
+				self.mirroring = Vrc::mirroring_from(value >> 2);
+				self.prg_ram_enabled = value & 0x80 != 0;
+			},
+			0x9000 ..= 0xB002 => self.vrc6_audio.write(register, value),
+			0xC000 ..= 0xC003 => self.prg_banks[1] = value,
+			0xD000 ..= 0xE003 => {
+				let index = ((register - 0xD000) >> 12) as usize * 4 + (register as usize & 0x03);
+				self.chr_banks[index] = value as u16;
+			},
+			0xF000 => self.irq.latch = value,
+			0xF001 => self.irq.write_control(value),
+			0xF002 => self.irq.acknowledge(),
+			_ => ()
+		}
+		self.update_banks();
+	}
+
+	fn write_vrc7(&mut self, address: u16, register: u16, value: u8) {
+		match register {
+			0x9000 ..= 0x9FFF if address & 0x0010 != 0 => {
+				if address & 0x0020 == 0 {
+					self.vrc7_select = value & 0x3F;
+				} else {
+					self.vrc7_registers[self.vrc7_select as usize] = value;
+				}
+			},
+			0x8000 | 0x8001 => self.prg_banks[(register & 1) as usize] = value,
+			0x9000 => self.prg_banks[2] = value,
+			0xA000 ..= 0xDFFF => {
+				let index = ((register - 0xA000) >> 12) as usize * 2 + (register as usize & 1);
+				self.chr_banks[index] = value as u16;
+			},
+			0xE000 => {
+				self.mirroring = Vrc::mirroring_from(value);
+				self.prg_ram_enabled = value & 0x80 != 0;
+			},
+			0xE001 => self.irq.latch = value,
+			0xF000 => self.irq.write_control(value),
+			0xF001 => self.irq.acknowledge(),
+			_ => ()
+		}
+		self.update_banks();
+	}
+}
+
+// VRC Memory Map
+// CPU:
+// 0x6000 -> 0x7FFF: 8 KB PRG RAM, or the VRC2 microwire latch at 0x6000-0x6FFF on boards without it
+// 0x8000 -> 0xDFFF: Switchable PRG ROM banks, with 0x8000 or 0xC000 fixed to the second last bank
+//                   on the VRC2 and VRC4
+// 0xE000 -> 0xFFFF: 8 KB PRG ROM bank fixed to the last bank
+// PPU:
+// 0x0000 -> 0x1FFF: Eight 1 KB switchable CHR banks
+impl Mapper for Vrc {
+	fn cpu_read(&self, address: u16) -> Result<u8> {
+		match address {
+			// Only bit 0 is driven; the rest is open bus, usually the high byte of the address
+			0x6000 ..= 0x6FFF if !self.has_prg_ram() =>
+				Ok((address >> 8) as u8 & 0xFE | self.microwire),
+			0x6000 ..= 0x7FFF if self.prg_ram_enabled => self.prg_ram.load(address - 0x6000),
+			0x8000 ..= 0xFFFF => self.prg.load(address - 0x8000),
+			_ => Err(EmuError::BusFault { address, access: Access::Read })
+		}
+	}
+
+	fn cpu_write(&mut self, address: u16, value: u8) -> Result<()> {
+		match address {
+			0x6000 ..= 0x6FFF if !self.has_prg_ram() => {
+				self.microwire = value & 0x01;
+				Ok(())
+			},
+			0x6000 ..= 0x7FFF if self.prg_ram_enabled => self.prg_ram.store(address - 0x6000, value),
+			0x8000 ..= 0xFFFF => {
+				let register = self.register(address);
+				match self.variant.chip {
+					VrcChip::Vrc2 | VrcChip::Vrc4 => self.write_vrc2_vrc4(register, value),
+					VrcChip::Vrc6 => self.write_vrc6(register, value),
+					VrcChip::Vrc7 => self.write_vrc7(address, register, value)
+				}
+				Ok(())
+			},
+			_ => Err(EmuError::BusFault { address, access: Access::Write })
+		}
+	}
+
+	fn ppu_read(&mut self, address: u16) -> Result<u8> {
+		match address {
+			0x0000 ..= 0x1FFF => self.chr.load(address),
+			_ => Err(EmuError::BusFault { address, access: Access::Read })
+		}
+	}
+
+	fn ppu_write(&mut self, address: u16, value: u8) -> Result<()> {
+		match address {
+			0x0000 ..= 0x1FFF => self.chr.store(address, value),
+			_ => Err(EmuError::BusFault { address, access: Access::Write })
+		}
+	}
+
+	fn mirroring(&self) -> Mirroring {
+		self.mirroring
+	}
+
+	fn irq(&self) -> bool {
+		self.irq.pending
+	}
+
+	fn clock(&mut self, cpu_cycles: u32) {
+		for _ in 0..cpu_cycles {
+			self.irq.clock();
+			if self.variant.chip == VrcChip::Vrc6 {
+				self.vrc6_audio.clock();
+			}
+		}
+	}
+
+	fn audio(&self) -> f32 {
+		match self.variant.chip {
+			VrcChip::Vrc6 => self.vrc6_audio.output(),
+			_ => 0.0
+		}
+	}
+
+	fn prg_ram(&self) -> Option<&[u8]> {
+		if self.has_prg_ram() { Some(self.prg_ram.data()) } else { None }
+	}
+
+	fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+		if self.has_prg_ram() { Some(self.prg_ram.data_mut()) } else { None }
+	}
+}
+
+#[cfg(test)]
+use mapper::{ numbered_banks, prg_banks, test_header };
+
+// 256 KB of PRG ROM and CHR ROM where each bank starts with its bank number
+#[cfg(test)]
+fn test_vrc(mapper: u16, submapper: u8) -> Vrc {
+	let mut header = test_header(16, 32, 0);
+	header.mapper_number = mapper;
+	header.submapper = submapper;
+	let prg = numbered_banks(32, PRG_BANK_SIZE, 0);
+	let chr = numbered_banks(256, CHR_BANK_SIZE, 0);
+	Vrc::new(header, prg, chr)
+}
+
+#[test]
+fn test_vrc4_wiring() {
+	// VRC4a selects registers with A1 and A2, VRC4c with A6 and A7
+	for &(submapper, register_3) in [(1, 0x0006), (2, 0x00C0), (0, 0x0006), (0, 0x00C0)].iter() {
+		let mut vrc = test_vrc(21, submapper);
+		assert_eq!(vrc.chip(), VrcChip::Vrc4);
+		vrc.cpu_write(0x9000 | register_3, 0x02).unwrap();
+		vrc.cpu_write(0x8000, 3).unwrap();
+		vrc.cpu_write(0xA000, 4).unwrap();
+		assert_eq!(prg_banks(&vrc), vec![30, 4, 3, 31]);
+	}
+}
+
+#[test]
+fn test_vrc4_chr_and_mirroring() {
+	let mut vrc = test_vrc(25, 1);
+	// VRC4b swaps A0 and A1, so 0xC002 holds the high bits of the third bank
+	vrc.cpu_write(0xC000, 0x05).unwrap();
+	vrc.cpu_write(0xC002, 0x0C).unwrap();
+	assert_eq!(vrc.ppu_read(0x0800).unwrap(), 0xC5);
+	vrc.cpu_write(0x9000, 3).unwrap();
+	assert_eq!(vrc.mirroring(), Mirroring::SingleScreenUpper);
+
+	// VRC2a ignores the low bit of the bank number
+	let mut vrc = test_vrc(22, 0);
+	vrc.cpu_write(0xB000, 0x07).unwrap();
+	assert_eq!(vrc.ppu_read(0x0000).unwrap(), 3);
+}
+
+#[test]
+fn test_vrc2_microwire() {
+	let mut vrc = test_vrc(23, 3);
+	assert_eq!(vrc.chip(), VrcChip::Vrc2);
+	vrc.cpu_write(0x6000, 0xFF).unwrap();
+	assert_eq!(vrc.cpu_read(0x6000).unwrap() & 1, 1);
+	vrc.cpu_write(0x6000, 0xFE).unwrap();
+	assert_eq!(vrc.cpu_read(0x6000).unwrap() & 1, 0);
+	assert!(vrc.cpu_read(0x7000).is_err());
+	assert!(vrc.prg_ram().is_none());
+}
+
+#[test]
+fn test_vrc2_prg_ram() {
+	// VRC2c with a battery, like Ganbare Goemon Gaiden
+	let mut header = test_header(16, 32, 0b10);
+	header.mapper_number = 25;
+	header.submapper = 3;
+	let mut vrc = Vrc::new(header, numbered_banks(32, PRG_BANK_SIZE, 0), Vec::new());
+	assert_eq!(vrc.chip(), VrcChip::Vrc2);
+	vrc.cpu_write(0x6000, 0x12).unwrap();
+	vrc.cpu_write(0x7FFF, 0x34).unwrap();
+	assert_eq!(vrc.cpu_read(0x6000).unwrap(), 0x12);
+	assert_eq!(vrc.prg_ram().unwrap()[0x1FFF], 0x34);
+}
+
+#[test]
+fn test_vrc6_banks() {
+	let mut vrc = test_vrc(26, 0);
+	vrc.cpu_write(0x8000, 2).unwrap();
+	vrc.cpu_write(0xC000, 9).unwrap();
+	assert_eq!(prg_banks(&vrc), vec![4, 5, 9, 31]);
+	// VRC6b swaps A0 and A1
+	vrc.cpu_write(0xE001, 0x42).unwrap();
+	assert_eq!(vrc.ppu_read(0x1800).unwrap(), 0x42);
+	vrc.cpu_write(0xB003, 0x84).unwrap();
+	assert_eq!(vrc.mirroring(), Mirroring::Horizontal);
+	vrc.cpu_write(0x6000, 0x12).unwrap();
+	assert_eq!(vrc.cpu_read(0x6000).unwrap(), 0x12);
+}
+
+#[test]
+fn test_vrc7_banks() {
+	let mut vrc = test_vrc(85, 2);
+	vrc.cpu_write(0x8000, 1).unwrap();
+	vrc.cpu_write(0x8010, 2).unwrap();
+	vrc.cpu_write(0x9000, 3).unwrap();
+	assert_eq!(prg_banks(&vrc), vec![1, 2, 3, 31]);
+	vrc.cpu_write(0xD010, 0x77).unwrap();
+	assert_eq!(vrc.ppu_read(0x1C00).unwrap(), 0x77);
+	// Audio writes don't change the PRG bank at 0xC000
+	vrc.cpu_write(0x9010, 0x10).unwrap();
+	vrc.cpu_write(0x9030, 0x20).unwrap();
+	assert_eq!(prg_banks(&vrc), vec![1, 2, 3, 31]);
+}
+
+#[test]
+fn test_irq_cycle_mode() {
+	let mut vrc = test_vrc(24, 0);
+	vrc.cpu_write(0xF000, 0xFC).unwrap();
+	vrc.cpu_write(0xF001, 0x07).unwrap();
+	vrc.clock(3);
+	assert!(!vrc.irq());
+	vrc.clock(1);
+	assert!(vrc.irq());
+
+	// Acknowledging copies the enable after acknowledge bit, and the counter reloads
+	vrc.cpu_write(0xF002, 0).unwrap();
+	assert!(!vrc.irq());
+	vrc.clock(4);
+	assert!(vrc.irq());
+}
+
+#[test]
+fn test_irq_scanline_mode() {
+	let mut vrc = test_vrc(23, 1);
+	vrc.cpu_write(0xF000, 0x0E).unwrap();
+	vrc.cpu_write(0xF001, 0x0F).unwrap();
+	vrc.cpu_write(0xF002, 0x02).unwrap();
+	// Two scanlines of 113⅔ cycles
+	vrc.clock(227);
+	assert!(!vrc.irq());
+	vrc.clock(1);
+	assert!(vrc.irq());
+	vrc.cpu_write(0xF003, 0).unwrap();
+	assert!(!vrc.irq());
+	vrc.clock(1000);
+	assert!(!vrc.irq());
+}
@@ -0,0 +1,199 @@
+/// Output level of one step of a VRC6 channel, so that a pulse at full volume is about as loud as
+/// an APU pulse channel at full volume
+const VRC6_LEVEL: f32 = 0.01;
+
+/// VRC6 expansion audio: two pulse channels and a sawtooth, all clocked by the CPU
+///     http://wiki.nesdev.com/w/index.php/VRC6_audio
+///
+/// Registers, after the board's address wiring has been undone:
+///     0x9000 -> 0x9002: Pulse 1 (duty and volume, period low, enable and period high)
+///     0x9003:           Halt and frequency scaling for all channels
+///     0xA000 -> 0xA002: Pulse 2
+///     0xB000 -> 0xB002: Sawtooth (accumulator rate, period low, enable and period high)
+pub struct Vrc6Audio {
+	pulses: [Pulse; 2],
+	saw: Saw,
+	halt: bool,
+	shift: u8     // Right shift of every channel's period, from 0x9003
+}
+
+#[derive(Default)]
+struct Pulse {
+	volume: u8,
+	duty: u8,
+	ignore_duty: bool,
+	enabled: bool,
+	period: u16,
+	timer: u16,
+	step: u8      // Counts down from 15; the output is high while it's at or below the duty
+}
+
+#[derive(Default)]
+struct Saw {
+	rate: u8,
+	enabled: bool,
+	period: u16,
+	timer: u16,
+	step: u8,     // Rate is added on every second step, and the accumulator resets after 14
+	accumulator: u8
+}
+
+impl Pulse {
+	fn write(&mut self, register: u16, value: u8) {
+		match register {
+			0 => {
+				self.ignore_duty = value & 0x80 != 0;
+				self.duty = (value >> 4) & 0x07;
+				self.volume = value & 0x0F;
+			},
+			1 => self.period = (self.period & 0x0F00) | value as u16,
+			_ => {
+				self.period = (self.period & 0x00FF) | ((value & 0x0F) as u16) << 8;
+				self.enabled = value & 0x80 != 0;
+				if !self.enabled {
+					self.step = 15;
+				}
+			}
+		}
+	}
+
+	fn clock(&mut self, shift: u8) {
+		if !self.enabled {
+			return;
+		}
+		if self.timer == 0 {
+			self.timer = self.period >> shift;
+			self.step = self.step.wrapping_sub(1) & 0x0F;
+		} else {
+			self.timer -= 1;
+		}
+	}
+
+	fn output(&self) -> u8 {
+		if self.enabled && (self.ignore_duty || self.step <= self.duty) { self.volume } else { 0 }
+	}
+}
+
+impl Saw {
+	fn write(&mut self, register: u16, value: u8) {
+		match register {
+			0 => self.rate = value & 0x3F,
+			1 => self.period = (self.period & 0x0F00) | value as u16,
+			_ => {
+				self.period = (self.period & 0x00FF) | ((value & 0x0F) as u16) << 8;
+				self.enabled = value & 0x80 != 0;
+				if !self.enabled {
+					self.step = 0;
+					self.accumulator = 0;
+				}
+			}
+		}
+	}
+
+	fn clock(&mut self, shift: u8) {
+		if !self.enabled {
+			return;
+		}
+		if self.timer == 0 {
+			self.timer = self.period >> shift;
+			self.step += 1;
+			if self.step == 14 {
+				self.step = 0;
+				self.accumulator = 0;
+			} else if self.step & 1 == 0 {
+				self.accumulator = self.accumulator.wrapping_add(self.rate);
+			}
+		} else {
+			self.timer -= 1;
+		}
+	}
+
+	fn output(&self) -> u8 {
+		self.accumulator >> 3
+	}
+}
+
+impl Vrc6Audio {
+	pub fn new() -> Vrc6Audio {
+		Vrc6Audio {
+			pulses: [Pulse::default(), Pulse::default()],
+			saw: Saw::default(),
+			halt: false,
+			shift: 0
+		}
+	}
+
+	pub fn write(&mut self, address: u16, value: u8) {
+		let register = address & 0x0003;
+		match address & 0xF000 {
+			0x9000 if register == 3 => {
+				self.halt = value & 0x01 != 0;
+				self.shift = if value & 0x04 != 0 { 8 } else if value & 0x02 != 0 { 4 } else { 0 };
+			},
+			0x9000 => self.pulses[0].write(register, value),
+			0xA000 => self.pulses[1].write(register, value),
+			_ => self.saw.write(register, value)
+		}
+	}
+
+	/// Advance every channel by one CPU cycle
+	pub fn clock(&mut self) {
+		if self.halt {
+			return;
+		}
+		for pulse in self.pulses.iter_mut() {
+			pulse.clock(self.shift);
+		}
+		self.saw.clock(self.shift);
+	}
+
+	pub fn output(&self) -> f32 {
+		let sum = self.pulses[0].output() + self.pulses[1].output() + self.saw.output();
+		sum as f32 * VRC6_LEVEL
+	}
+}
+
+impl Default for Vrc6Audio {
+	fn default() -> Vrc6Audio {
+		Vrc6Audio::new()
+	}
+}
+
+#[test]
+fn test_pulse() {
+	let mut audio = Vrc6Audio::new();
+	// Duty 1 of 16 at volume 15 with a period of 1, so the output is high for 4 of 32 cycles
+	audio.write(0x9000, 0x1F);
+	audio.write(0x9001, 0x01);
+	audio.write(0x9002, 0x80);
+	let mut high = 0;
+	for _ in 0..32 {
+		audio.clock();
+		if audio.output() > 0.0 {
+			high += 1;
+		}
+	}
+	assert_eq!(high, 4);
+
+	// Ignoring the duty gives a constant level
+	audio.write(0x9000, 0x8F);
+	audio.clock();
+	assert_eq!(audio.output(), 15.0 * VRC6_LEVEL);
+
+	// Disabling silences it
+	audio.write(0x9002, 0x00);
+	assert_eq!(audio.output(), 0.0);
+}
+
+#[test]
+fn test_saw() {
+	let mut audio = Vrc6Audio::new();
+	audio.write(0xB000, 0x08);
+	audio.write(0xB002, 0x80);
+	let mut levels = Vec::new();
+	for _ in 0..14 {
+		audio.clock();
+		levels.push((audio.output() / VRC6_LEVEL).round() as u8);
+	}
+	assert_eq!(levels, vec![0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 0]);
+}
@@ -1,37 +1,41 @@
+use error::{ Access, EmuError, Result };
 
 pub trait Memory {
-	fn load(&self, address: u16) -> u8;
-	fn store(&mut self, address: u16, value: u8);
+	fn load(&self, address: u16) -> Result<u8>;
+	fn store(&mut self, address: u16, value: u8) -> Result<()>;
 
-	fn loadw(&self, address: u16) -> u16 {
-		self.load(address) as u16 | (self.load(address + 1) as u16) << 8
+	fn loadw(&self, address: u16) -> Result<u16> {
+		Ok(self.load(address)? as u16 | (self.load(address.wrapping_add(1))? as u16) << 8)
 	}
 
-	fn storew(&mut self, address: u16, value: u16) {
-		self.store(address, value as u8);
-		self.store(address + 1, (value >> 8) as u8);
+	fn storew(&mut self, address: u16, value: u16) -> Result<()> {
+		self.store(address, value as u8)?;
+		self.store(address.wrapping_add(1), (value >> 8) as u8)
 	}
 }
 
 pub struct ReadOnlyMemory {
-	data: Box<Vec<u8>>
+	data: Vec<u8>
 }
 
 impl ReadOnlyMemory {
-	pub fn new(data: Box<Vec<u8>>) -> ReadOnlyMemory {
+	pub fn new(data: Vec<u8>) -> ReadOnlyMemory {
 		ReadOnlyMemory {
-			data: data
+			data
 		}
 	}
 }
 
 impl Memory for ReadOnlyMemory {
-	fn load(&self, address: u16) -> u8 {
-		self.data[address as usize]
+	fn load(&self, address: u16) -> Result<u8> {
+		match self.data.get(address as usize) {
+			Some(&value) => Ok(value),
+			None => Err(EmuError::BusFault { address, access: Access::Read })
+		}
 	}
 
-	fn store(&mut self, address: u16, value: u8) {
-		panic!("Can't write to read only memory!")
+	fn store(&mut self, address: u16, _value: u8) -> Result<()> {
+		Err(EmuError::BusFault { address, access: Access::Write })
 	}
 }
 
@@ -42,29 +46,170 @@ pub struct ReadWriteMemory {
 impl ReadWriteMemory {
 	pub fn new(capacity: usize) -> ReadWriteMemory {
 		ReadWriteMemory {
-			data: Vec::with_capacity(capacity)
+			data: vec![0; capacity]
 		}
 	}
+
+	pub fn data(&self) -> &[u8] {
+		&self.data
+	}
+
+	pub fn data_mut(&mut self) -> &mut [u8] {
+		&mut self.data
+	}
 }
 
 impl Memory for ReadWriteMemory {
-	fn load(&self, address: u16) -> u8 {
-		self.data[address as usize]
+	fn load(&self, address: u16) -> Result<u8> {
+		match self.data.get(address as usize) {
+			Some(&value) => Ok(value),
+			None => Err(EmuError::BusFault { address, access: Access::Read })
+		}
+	}
+
+	fn store(&mut self, address: u16, value: u8) -> Result<()> {
+		match self.data.get_mut(address as usize) {
+			Some(data) => {
+				*data = value;
+				Ok(())
+			},
+			None => Err(EmuError::BusFault { address, access: Access::Write })
+		}
+	}
+}
+
+/// ROM or RAM seen through a number of equally sized windows, each of which can show any bank of
+/// the data. Addresses are relative to the start of the first window.
+pub struct BankedMemory {
+	data: Vec<u8>,
+	bank_size: usize,
+	banks: Vec<usize>, // Bank shown in each window
+	writable: bool
+}
+
+impl BankedMemory {
+	pub fn rom(data: Vec<u8>, bank_size: usize, windows: usize) -> BankedMemory {
+		BankedMemory {
+			data,
+			bank_size,
+			banks: (0..windows).collect(),
+			writable: false
+		}
+	}
+
+	pub fn ram(capacity: usize, bank_size: usize, windows: usize) -> BankedMemory {
+		BankedMemory {
+			writable: true,
+			..BankedMemory::rom(vec![0; capacity], bank_size, windows)
+		}
+	}
+
+	/// Number of whole banks in the data, at least 1
+	pub fn bank_count(&self) -> usize {
+		(self.data.len() / self.bank_size).max(1)
+	}
+
+	/// All of the data, regardless of which banks are shown
+	pub fn data(&self) -> &[u8] {
+		&self.data
+	}
+
+	pub fn data_mut(&mut self) -> &mut [u8] {
+		&mut self.data
+	}
+
+	/// Show a bank in a window. Bank numbers past the end of the data wrap around, like the
+	/// unconnected high address lines of a board with less memory than the mapper supports.
+	pub fn switch(&mut self, window: usize, bank: usize) {
+		self.banks[window] = bank % self.bank_count();
+	}
+
+	/// Show a bank counted from the end of the data, where 1 is the last bank
+	pub fn switch_from_end(&mut self, window: usize, bank: usize) {
+		let count = self.bank_count();
+		self.banks[window] = (count - bank % count) % count;
+	}
+
+	fn offset(&self, address: u16) -> Option<usize> {
+		let window = address as usize / self.bank_size;
+		let bank = *self.banks.get(window)?;
+		if self.data.is_empty() {
+			None
+		} else {
+			Some((bank * self.bank_size + address as usize % self.bank_size) % self.data.len())
+		}
+	}
+}
+
+impl Memory for BankedMemory {
+	fn load(&self, address: u16) -> Result<u8> {
+		match self.offset(address) {
+			Some(offset) => Ok(self.data[offset]),
+			None => Err(EmuError::BusFault { address, access: Access::Read })
+		}
 	}
 
-	fn store(&mut self, address: u16, value: u8) {
-		self.data[address as usize] = value;
+	fn store(&mut self, address: u16, value: u8) -> Result<()> {
+		match self.offset(address) {
+			Some(offset) if self.writable => {
+				self.data[offset] = value;
+				Ok(())
+			},
+			_ => Err(EmuError::BusFault { address, access: Access::Write })
+		}
 	}
 }
 
 #[test]
 fn test_load() {
-	let mem = ReadOnlyMemory::new(Box::new(vec![0x1, 0x2, 0x3]));
-	assert!(mem.load(0x1) == 0x2);
+	let mem = ReadOnlyMemory::new(vec![0x1, 0x2, 0x3]);
+	assert!(mem.load(0x1).unwrap() == 0x2);
 }
 
 #[test]
 fn test_loadw() {
-	let mem = ReadOnlyMemory::new(Box::new(vec![0x01, 0x02]));
-	assert!(mem.loadw(0x0) == 0x0201);
+	let mem = ReadOnlyMemory::new(vec![0x01, 0x02]);
+	assert!(mem.loadw(0x0).unwrap() == 0x0201);
+}
+
+#[test]
+fn test_out_of_range() {
+	let mut mem = ReadWriteMemory::new(0x10);
+	assert!(mem.store(0x10, 0x1).is_err());
+	assert!(mem.load(0x10).is_err());
+}
+
+#[test]
+fn test_read_only_store() {
+	let mut mem = ReadOnlyMemory::new(vec![0x01, 0x02]);
+	match mem.store(0x1, 0x3) {
+		Err(EmuError::BusFault { address: 0x1, access: Access::Write }) => (),
+		other => panic!("Expected a bus fault, got {:?}", other)
+	}
+	assert!(mem.load(0x1).unwrap() == 0x02);
+}
+
+#[test]
+fn test_banked_memory() {
+	let data: Vec<u8> = (0..8).collect();
+	let mut mem = BankedMemory::rom(data, 2, 2);
+	assert_eq!(mem.bank_count(), 4);
+	assert_eq!(mem.load(0x2).unwrap(), 2);
+	mem.switch(0, 3);
+	mem.switch_from_end(1, 1);
+	assert_eq!(mem.load(0x1).unwrap(), 7);
+	assert_eq!(mem.load(0x2).unwrap(), 6);
+	// Banks wrap around the end of the data
+	mem.switch(1, 5);
+	assert_eq!(mem.load(0x3).unwrap(), 3);
+	assert!(mem.load(0x4).is_err());
+	assert!(mem.store(0x0, 0x1).is_err());
+
+	let mut mem = BankedMemory::ram(4, 2, 1);
+	mem.switch(0, 1);
+	mem.store(0x1, 0x5).unwrap();
+	mem.switch(0, 0);
+	assert_eq!(mem.load(0x1).unwrap(), 0);
+	mem.switch(0, 1);
+	assert_eq!(mem.load(0x1).unwrap(), 5);
 }
@@ -0,0 +1,533 @@
+use apu::{ Resampler, NTSC_CPU_RATE, PAL_CPU_RATE };
+use cpu::{ CpuVariant, Registers, CPU };
+use error::{ RomError, Result };
+use mapper::NsfMapper;
+use memory::Memory;
+use rom::Timing;
+use std::cell::RefCell;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+use std::rc::Rc;
+#[cfg(test)]
+use error::EmuError;
+
+/// Identifiers at the start of NSF and NSFe files
+pub const IDENTIFIER: [u8; 5] = *b"NESM\x1A";
+pub const NSFE_IDENTIFIER: [u8; 4] = *b"NSFE";
+
+/// Size of the NSF header; the music data follows it
+const HEADER_SIZE: usize = 0x80;
+
+/// Microseconds between calls to PLAY when the file doesn't say, 60 Hz and 50 Hz
+const DEFAULT_NTSC_SPEED: u16 = 16639;
+const DEFAULT_PAL_SPEED: u16 = 19997;
+
+/// Address that the player's calls to INIT and PLAY return to. It's never executed, and nothing
+/// is mapped there, so a routine can't get there any other way.
+const RETURN_ADDRESS: u16 = 0x4100;
+
+/// Cutoff of the filter that takes the DC offset out of the rendered audio, as the console's own
+/// output does
+const HIGH_PASS_CUTOFF: f64 = 90.0;
+
+/// Expansion audio chips an NSF uses
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ExpansionAudio {
+	pub vrc6: bool,
+	pub vrc7: bool,
+	pub fds: bool,
+	pub mmc5: bool,
+	pub namco163: bool,
+	pub sunsoft5b: bool
+}
+
+impl ExpansionAudio {
+	fn from_bits(bits: u8) -> ExpansionAudio {
+		ExpansionAudio {
+			vrc6: bits & 0x01 != 0,
+			vrc7: bits & 0x02 != 0,
+			fds: bits & 0x04 != 0,
+			mmc5: bits & 0x08 != 0,
+			namco163: bits & 0x10 != 0,
+			sunsoft5b: bits & 0x20 != 0
+		}
+	}
+}
+
+/// NSF music file: the music code and data of a game, called by a player
+///     http://wiki.nesdev.com/w/index.php/NSF
+///
+/// NSF Header (128 Bytes)
+/// Format:
+///   0x00-0x04: Identifier
+///        0x05: Version
+///        0x06: Number of songs
+///        0x07: Starting song, from 1
+///   0x08-0x0D: Load, INIT and PLAY addresses
+///   0x0E-0x6D: Title, artist and copyright, 32 bytes each, null terminated
+///   0x6E-0x6F: Microseconds between calls to PLAY on NTSC
+///   0x70-0x77: Initial banks, or all zero when the NSF doesn't bankswitch
+///   0x78-0x79: Microseconds between calls to PLAY on PAL
+///        0x7A: Region, bit 0 for PAL and bit 1 for either
+///        0x7B: Expansion audio chips
+///   0x7C-0x7F: NSF2 flags and data length, not used
+pub struct Nsf {
+	pub songs: u8,
+	/// Song to play first, counting from 1
+	pub starting_song: u8,
+	pub load_address: u16,
+	pub init_address: u16,
+	pub play_address: u16,
+	pub title: String,
+	pub artist: String,
+	pub copyright: String,
+	pub ntsc_speed: u16,
+	pub pal_speed: u16,
+	pub banks: Option<[u8; 8]>,
+	pub timing: Timing,
+	pub expansion_audio: ExpansionAudio,
+	pub data: Vec<u8>
+}
+
+impl Nsf {
+	/// Open an NSF or NSFe file
+	pub fn open<P: AsRef<Path>>(path: P) -> Result<Nsf> {
+		let mut data = Vec::<u8>::new();
+		File::open(path)?.read_to_end(&mut data)?;
+		Nsf::parse(&data)
+	}
+
+	/// Read an NSF or NSFe file from memory
+	pub fn parse(data: &[u8]) -> Result<Nsf> {
+		if data.starts_with(&NSFE_IDENTIFIER) {
+			return Nsf::parse_nsfe(data);
+		}
+		if data.len() < HEADER_SIZE {
+			return Err(RomError::TruncatedHeader.into());
+		}
+		if data[0..5] != IDENTIFIER {
+			return Err(RomError::InvalidNsfIdentifier.into());
+		}
+
+		let word = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+		let mut banks = [0; 8];
+		banks.copy_from_slice(&data[0x70..0x78]);
+		Ok(Nsf {
+			songs: data[0x06],
+			starting_song: data[0x07],
+			load_address: word(0x08),
+			init_address: word(0x0A),
+			play_address: word(0x0C),
+			title: text(&data[0x0E..0x2E]),
+			artist: text(&data[0x2E..0x4E]),
+			copyright: text(&data[0x4E..0x6E]),
+			ntsc_speed: word(0x6E),
+			pal_speed: word(0x78),
+			banks: Some(banks).filter(|banks| banks.iter().any(|&bank| bank != 0)),
+			timing: timing(data[0x7A]),
+			expansion_audio: ExpansionAudio::from_bits(data[0x7B]),
+			data: data[HEADER_SIZE..].to_vec()
+		})
+	}
+
+	// An NSFe file is the identifier followed by chunks, each a 32 bit little endian length, a 4
+	// byte ID and then the data:
+	//     INFO: Load, INIT and PLAY addresses, region, expansion audio, songs, starting song from 0
+	//     DATA: Music code and data
+	//     BANK: Initial banks
+	//     RATE: Microseconds between calls to PLAY on NTSC and PAL
+	//     auth: Title, artist, copyright and ripper, null terminated
+	//     NEND: End of the file
+	// Other chunks are ignored.
+	//     http://wiki.nesdev.com/w/index.php/NSFe
+	fn parse_nsfe(data: &[u8]) -> Result<Nsf> {
+		let mut nsf = Nsf {
+			songs: 1,
+			starting_song: 1,
+			load_address: 0,
+			init_address: 0,
+			play_address: 0,
+			title: String::new(),
+			artist: String::new(),
+			copyright: String::new(),
+			ntsc_speed: DEFAULT_NTSC_SPEED,
+			pal_speed: DEFAULT_PAL_SPEED,
+			banks: None,
+			timing: Timing::Ntsc,
+			expansion_audio: ExpansionAudio::default(),
+			data: Vec::new()
+		};
+		let (mut has_info, mut has_data) = (false, false);
+		let mut data = &data[NSFE_IDENTIFIER.len()..];
+		while !data.is_empty() {
+			if data.len() < 8 {
+				let id = chunk_name(data.get(4..).unwrap_or(&[]));
+				return Err(RomError::TruncatedChunk { id }.into());
+			}
+			let length = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+			let id = &data[4..8];
+			let chunk = match data[8..].get(..length as usize) {
+				Some(chunk) => chunk,
+				None => return Err(RomError::TruncatedChunk { id: chunk_name(id) }.into())
+			};
+			data = &data[8 + chunk.len()..];
+
+			match id {
+				b"INFO" => {
+					if chunk.len() < 8 {
+						return Err(RomError::TruncatedChunk { id: chunk_name(id) }.into());
+					}
+					let word = |offset: usize| {
+						u16::from_le_bytes([chunk[offset], chunk[offset + 1]])
+					};
+					nsf.load_address = word(0);
+					nsf.init_address = word(2);
+					nsf.play_address = word(4);
+					nsf.timing = timing(chunk[6]);
+					nsf.expansion_audio = ExpansionAudio::from_bits(chunk[7]);
+					nsf.songs = chunk.get(8).cloned().unwrap_or(1);
+					nsf.starting_song = chunk.get(9).cloned().unwrap_or(0) + 1;
+					has_info = true;
+				},
+				b"DATA" => {
+					nsf.data = chunk.to_vec();
+					has_data = true;
+				},
+				b"BANK" => {
+					// Missing banks are 0
+					let mut banks = [0; 8];
+					let length = chunk.len().min(8);
+					banks[..length].copy_from_slice(&chunk[..length]);
+					nsf.banks = Some(banks);
+				},
+				b"RATE" => {
+					if chunk.len() >= 2 {
+						nsf.ntsc_speed = u16::from_le_bytes([chunk[0], chunk[1]]);
+					}
+					if chunk.len() >= 4 {
+						nsf.pal_speed = u16::from_le_bytes([chunk[2], chunk[3]]);
+					}
+				},
+				b"auth" => {
+					let mut strings = chunk.split(|&b| b == 0).map(text);
+					nsf.title = strings.next().unwrap_or_default();
+					nsf.artist = strings.next().unwrap_or_default();
+					nsf.copyright = strings.next().unwrap_or_default();
+				},
+				b"NEND" => break,
+				_ => ()
+			}
+		}
+
+		if !has_info {
+			return Err(RomError::MissingChunk { id: "INFO".to_string() }.into());
+		}
+		if !has_data {
+			return Err(RomError::MissingChunk { id: "DATA".to_string() }.into());
+		}
+		Ok(nsf)
+	}
+}
+
+// Text up to its null terminator
+fn text(data: &[u8]) -> String {
+	let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+	String::from_utf8_lossy(&data[..end]).into_owned()
+}
+
+fn chunk_name(id: &[u8]) -> String {
+	String::from_utf8_lossy(&id[..id.len().min(4)]).into_owned()
+}
+
+fn timing(region: u8) -> Timing {
+	if region & 0x02 != 0 {
+		Timing::MultipleRegion
+	} else if region & 0x01 != 0 {
+		Timing::Pal
+	} else {
+		Timing::Ntsc
+	}
+}
+
+/// Plays the songs of an NSF on an emulated CPU and APU, the way an NSF player program does:
+///     http://wiki.nesdev.com/w/index.php/NSF#Initializing_a_tune
+///
+/// Starting a song calls INIT with the song number in A and the region in X. After INIT returns,
+/// PLAY is called once per frame at the rate in the header. A PLAY that's still running when the
+/// next frame comes is left to finish rather than called again. Tunes for either region are
+/// played as NTSC.
+pub struct Player {
+	cpu: CPU,
+	init_address: u16,
+	play_address: u16,
+	banks: Option<[u8; 8]>,
+	pal: bool,
+	cycles_per_frame: f64,
+	next_frame: f64,            // CPU cycle the next frame starts on
+	running: bool,              // INIT or PLAY hasn't returned yet
+	sample_rate: u32,
+	filter: Option<(f32, f32)>  // Last input and output of the high pass filter
+}
+
+impl Player {
+	pub fn new(nsf: &Nsf, sample_rate: u32) -> Player {
+		let pal = nsf.timing == Timing::Pal;
+		let (cpu_rate, speed, default_speed) = if pal {
+			(PAL_CPU_RATE, nsf.pal_speed, DEFAULT_PAL_SPEED)
+		} else {
+			(NTSC_CPU_RATE, nsf.ntsc_speed, DEFAULT_NTSC_SPEED)
+		};
+		let speed = if speed == 0 { default_speed } else { speed };
+
+		let mut cpu = CPU::new(Rc::new(RefCell::new(NsfMapper::new(nsf))), CpuVariant::Ricoh2A03);
+		cpu.power_up_with_pc_override(RETURN_ADDRESS);
+		cpu.set_resampler(Some(Resampler::new(cpu_rate, sample_rate)));
+		Player {
+			cpu,
+			init_address: nsf.init_address,
+			play_address: nsf.play_address,
+			banks: nsf.banks,
+			pal,
+			cycles_per_frame: speed as f64 * cpu_rate / 1_000_000.0,
+			next_frame: 0.0,
+			running: false,
+			sample_rate,
+			filter: None
+		}
+	}
+
+	/// Start a song, counting from 1
+	pub fn start(&mut self, song: u8) -> Result<()> {
+		for address in (0x0000..0x0800).chain(0x6000..0x8000) {
+			self.cpu.store(address, 0)?;
+		}
+		for address in 0x4000..0x4014 {
+			self.cpu.store(address, 0)?;
+		}
+		self.cpu.store(0x4015, 0x00)?;
+		self.cpu.store(0x4015, 0x0F)?;
+		self.cpu.store(0x4017, 0x40)?;
+		if let Some(banks) = self.banks {
+			for (i, &bank) in banks.iter().enumerate() {
+				self.cpu.store(0x5FF8 + i as u16, bank)?;
+			}
+		}
+
+		self.next_frame = self.cpu.cycles() as f64;
+		self.call(self.init_address, song.wrapping_sub(1), self.pal as u8)
+	}
+
+	// Set the CPU up to run a routine, with a return address on the stack that leads back to
+	// RETURN_ADDRESS
+	fn call(&mut self, address: u16, a: u8, x: u8) -> Result<()> {
+		let return_address = RETURN_ADDRESS - 1;
+		self.cpu.store(0x01FF, (return_address >> 8) as u8)?;
+		self.cpu.store(0x01FE, return_address as u8)?;
+		self.cpu.set_registers(Registers {
+			a,
+			x,
+			y: 0,
+			s: 0xFD,
+			pc: address,
+			status: 0x24
+		});
+		self.running = true;
+		Ok(())
+	}
+
+	/// Run the song for one frame, calling PLAY if the last routine has returned
+	pub fn run_frame(&mut self) -> Result<()> {
+		if !self.running {
+			self.call(self.play_address, 0, 0)?;
+		}
+		self.next_frame += self.cycles_per_frame;
+		while self.running && (self.cpu.cycles() as f64) < self.next_frame {
+			if self.cpu.step()?.after.pc == RETURN_ADDRESS {
+				self.running = false;
+			}
+		}
+		let remaining = self.next_frame - self.cpu.cycles() as f64;
+		if remaining >= 1.0 {
+			self.cpu.stall(remaining as u32);
+		}
+		Ok(())
+	}
+
+	/// Play the song for a number of seconds, returning samples from -1.0 to 1.0
+	pub fn render(&mut self, seconds: f64) -> Result<Vec<f32>> {
+		let count = (seconds * self.sample_rate as f64) as usize;
+		let mut samples = Vec::with_capacity(count);
+		while samples.len() < count {
+			self.run_frame()?;
+			samples.extend(self.cpu.take_samples());
+		}
+		samples.truncate(count);
+
+		let rc = 1.0 / (2.0 * PI * HIGH_PASS_CUTOFF);
+		let alpha = (rc / (rc + 1.0 / self.sample_rate as f64)) as f32;
+		// Start the filter from the first level rather than from silence, to avoid a pop
+		if let (None, Some(&first)) = (self.filter, samples.first()) {
+			self.filter = Some((first, 0.0));
+		}
+		for sample in samples.iter_mut() {
+			let (last_input, last_output) = self.filter.unwrap_or_default();
+			let output = alpha * (last_output + *sample - last_input);
+			self.filter = Some((*sample, output));
+			*sample = output.clamp(-1.0, 1.0);
+		}
+		Ok(samples)
+	}
+}
+
+/// NSF without bankswitching, loaded at 0x8000
+#[cfg(test)]
+pub fn test_nsf(data: Vec<u8>) -> Nsf {
+	Nsf {
+		songs: 1,
+		starting_song: 1,
+		load_address: 0x8000,
+		init_address: 0x8000,
+		play_address: 0x8000,
+		title: String::new(),
+		artist: String::new(),
+		copyright: String::new(),
+		ntsc_speed: DEFAULT_NTSC_SPEED,
+		pal_speed: DEFAULT_PAL_SPEED,
+		banks: None,
+		timing: Timing::Ntsc,
+		expansion_audio: ExpansionAudio::default(),
+		data
+	}
+}
+
+#[cfg(test)]
+fn test_file(data: &[u8]) -> Vec<u8> {
+	let mut file = vec![0; HEADER_SIZE];
+	file[0..5].copy_from_slice(&IDENTIFIER);
+	file[0x05] = 1;
+	file[0x06] = 5;
+	file[0x07] = 2;
+	file[0x08..0x0E].copy_from_slice(&[0x00, 0x80, 0x03, 0x80, 0x10, 0x80]);
+	file[0x0E..0x13].copy_from_slice(b"Title");
+	file[0x2E..0x34].copy_from_slice(b"Artist");
+	file[0x6E..0x70].copy_from_slice(&DEFAULT_NTSC_SPEED.to_le_bytes());
+	file[0x7B] = 0x21;
+	file.extend_from_slice(data);
+	file
+}
+
+#[test]
+fn test_parse() {
+	let nsf = Nsf::parse(&test_file(&[1, 2, 3])).unwrap();
+	assert_eq!((nsf.songs, nsf.starting_song), (5, 2));
+	assert_eq!((nsf.load_address, nsf.init_address, nsf.play_address),
+		(0x8000, 0x8003, 0x8010));
+	assert_eq!((nsf.title.as_str(), nsf.artist.as_str(), nsf.copyright.as_str()),
+		("Title", "Artist", ""));
+	assert_eq!(nsf.ntsc_speed, DEFAULT_NTSC_SPEED);
+	assert_eq!(nsf.banks, None);
+	assert_eq!(nsf.timing, Timing::Ntsc);
+	assert!(nsf.expansion_audio.vrc6 && nsf.expansion_audio.sunsoft5b && !nsf.expansion_audio.fds);
+	assert_eq!(nsf.data, vec![1, 2, 3]);
+
+	let mut file = test_file(&[]);
+	file[0x77] = 1;
+	file[0x7A] = 0x01;
+	let nsf = Nsf::parse(&file).unwrap();
+	assert_eq!(nsf.banks, Some([0, 0, 0, 0, 0, 0, 0, 1]));
+	assert_eq!(nsf.timing, Timing::Pal);
+
+	match Nsf::parse(&file[..0x40]) {
+		Err(EmuError::Rom(RomError::TruncatedHeader)) => (),
+		other => panic!("Expected a truncated header, got {:?}", other.err())
+	}
+	file[0] = b'X';
+	match Nsf::parse(&file) {
+		Err(EmuError::Rom(RomError::InvalidNsfIdentifier)) => (),
+		other => panic!("Expected an invalid identifier, got {:?}", other.err())
+	}
+}
+
+#[cfg(test)]
+fn test_nsfe_file(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+	let mut data = NSFE_IDENTIFIER.to_vec();
+	for &(id, chunk) in chunks {
+		data.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+		data.extend_from_slice(id);
+		data.extend_from_slice(chunk);
+	}
+	data
+}
+
+#[test]
+fn test_parse_nsfe() {
+	let data = test_nsfe_file(&[
+		(b"INFO", &[0x00, 0x80, 0x03, 0x80, 0x10, 0x80, 0x02, 0x01, 0x04, 0x01]),
+		(b"BANK", &[0, 1, 2]),
+		(b"RATE", &[0x1A, 0x41]),
+		(b"auth", b"Title\0Artist\0Copyright\0Ripper\0"),
+		(b"DATA", &[1, 2, 3]),
+		(b"NEND", &[]),
+		(b"JUNK", &[])
+	]);
+	let nsf = Nsf::parse(&data).unwrap();
+	assert_eq!((nsf.songs, nsf.starting_song), (4, 2));
+	assert_eq!((nsf.load_address, nsf.init_address, nsf.play_address),
+		(0x8000, 0x8003, 0x8010));
+	assert_eq!(nsf.timing, Timing::MultipleRegion);
+	assert!(nsf.expansion_audio.vrc6);
+	assert_eq!(nsf.banks, Some([0, 1, 2, 0, 0, 0, 0, 0]));
+	assert_eq!((nsf.ntsc_speed, nsf.pal_speed), (0x411A, DEFAULT_PAL_SPEED));
+	assert_eq!((nsf.title.as_str(), nsf.artist.as_str(), nsf.copyright.as_str()),
+		("Title", "Artist", "Copyright"));
+	assert_eq!(nsf.data, vec![1, 2, 3]);
+
+	match Nsf::parse(&test_nsfe_file(&[(b"DATA", &[0])])) {
+		Err(EmuError::Rom(RomError::MissingChunk { ref id })) if id == "INFO" => (),
+		other => panic!("Expected a missing chunk, got {:?}", other.err())
+	}
+	let mut data = test_nsfe_file(&[(b"INFO", &[0; 10])]);
+	data.truncate(data.len() - 1);
+	match Nsf::parse(&data) {
+		Err(EmuError::Rom(RomError::TruncatedChunk { ref id })) if id == "INFO" => (),
+		other => panic!("Expected a truncated chunk, got {:?}", other.err())
+	}
+}
+
+#[test]
+fn test_player() {
+	let program = [
+		// INIT: store the song number, then start pulse 1 at constant volume
+		0x85, 0x00,       // STA $00
+		0x86, 0x01,       // STX $01
+		0xA9, 0xBF,       // LDA #$BF
+		0x8D, 0x00, 0x40, // STA $4000
+		0xA9, 0x80,       // LDA #$80
+		0x8D, 0x02, 0x40, // STA $4002
+		0xA9, 0x08,       // LDA #$08
+		0x8D, 0x03, 0x40, // STA $4003
+		0x60,             // RTS
+		// PLAY: count the calls
+		0xE6, 0x02,       // INC $02
+		0x60              // RTS
+	];
+	let mut nsf = test_nsf(program.to_vec());
+	nsf.play_address = 0x8014;
+	let mut player = Player::new(&nsf, 44100);
+	player.start(3).unwrap();
+	// PLAY isn't called in the frame INIT runs in
+	for _ in 0..10 {
+		player.run_frame().unwrap();
+	}
+	assert_eq!((player.cpu.load(0x00).unwrap(), player.cpu.load(0x01).unwrap()), (2, 0));
+	assert_eq!(player.cpu.load(0x02).unwrap(), 9);
+	let samples = player.render(0.5).unwrap();
+	assert_eq!(samples.len(), 22050);
+	assert!(samples.iter().any(|&s| s > 0.05) && samples.iter().any(|&s| s < -0.05));
+
+	// Starting again clears RAM
+	player.start(1).unwrap();
+	assert_eq!(player.cpu.load(0x02).unwrap(), 0);
+}
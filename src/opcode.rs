@@ -0,0 +1,590 @@
+/// Addressing modes of the 6502
+///     http://wiki.nesdev.com/w/index.php/CPU_addressing_modes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+	Implied,
+	Accumulator,
+	Immediate,
+	ZeroPage,
+	ZeroPageX,
+	ZeroPageY,
+	Absolute,
+	AbsoluteX,
+	AbsoluteY,
+	Indirect,
+	IndirectX,
+	IndirectY,
+	Relative,
+	/// ($zp) on the 65C02
+	ZeroPageIndirect,
+	/// ($abs,X) on the 65C02, only used by JMP
+	AbsoluteIndexedIndirect
+}
+
+impl Mode {
+	/// Length in bytes of an instruction using this mode, including the opcode
+	pub fn length(&self) -> u8 {
+		match *self {
+			Mode::Implied | Mode::Accumulator => 1,
+			Mode::Immediate | Mode::ZeroPage | Mode::ZeroPageX | Mode::ZeroPageY |
+			Mode::IndirectX | Mode::IndirectY | Mode::Relative | Mode::ZeroPageIndirect => 2,
+			Mode::Absolute | Mode::AbsoluteX | Mode::AbsoluteY | Mode::Indirect |
+			Mode::AbsoluteIndexedIndirect => 3
+		}
+	}
+}
+
+/// Metadata for a single opcode, shared by the CPU, the tracer and the disassembler
+#[derive(Clone, Copy, Debug)]
+pub struct Opcode {
+	pub mnemonic: &'static str,
+	pub mode: Mode,
+	/// Base cycle count. Page crossing, branch and decimal mode penalties are added while
+	/// executing.
+	pub cycles: u8,
+	/// False for undocumented opcodes
+	pub official: bool
+}
+
+impl Opcode {
+	/// Instruction length in bytes, including the opcode
+	pub fn length(&self) -> u8 {
+		self.mode.length()
+	}
+}
+
+const fn op(mnemonic: &'static str, mode: Mode, cycles: u8) -> Opcode {
+	Opcode { mnemonic, mode, cycles, official: true }
+}
+
+const fn unofficial(mnemonic: &'static str, mode: Mode, cycles: u8) -> Opcode {
+	Opcode { mnemonic, mode, cycles, official: false }
+}
+
+use self::Mode::*;
+
+/// Opcode metadata for the NMOS 6502 and 2A03, indexed by opcode. Unofficial mnemonics follow
+/// nestest.log where it has them, so ISC is spelled ISB.
+///     http://wiki.nesdev.com/w/index.php/6502_cycle_times
+///     http://wiki.nesdev.com/w/index.php/CPU_unofficial_opcodes
+pub static OPCODES: [Opcode; 256] = [
+	op("BRK", Implied, 7), // 00
+	op("ORA", IndirectX, 6), // 01
+	unofficial("KIL", Implied, 2), // 02
+	unofficial("SLO", IndirectX, 8), // 03
+	unofficial("NOP", ZeroPage, 3), // 04
+	op("ORA", ZeroPage, 3), // 05
+	op("ASL", ZeroPage, 5), // 06
+	unofficial("SLO", ZeroPage, 5), // 07
+	op("PHP", Implied, 3), // 08
+	op("ORA", Immediate, 2), // 09
+	op("ASL", Accumulator, 2), // 0A
+	unofficial("ANC", Immediate, 2), // 0B
+	unofficial("NOP", Absolute, 4), // 0C
+	op("ORA", Absolute, 4), // 0D
+	op("ASL", Absolute, 6), // 0E
+	unofficial("SLO", Absolute, 6), // 0F
+	op("BPL", Relative, 2), // 10
+	op("ORA", IndirectY, 5), // 11
+	unofficial("KIL", Implied, 2), // 12
+	unofficial("SLO", IndirectY, 8), // 13
+	unofficial("NOP", ZeroPageX, 4), // 14
+	op("ORA", ZeroPageX, 4), // 15
+	op("ASL", ZeroPageX, 6), // 16
+	unofficial("SLO", ZeroPageX, 6), // 17
+	op("CLC", Implied, 2), // 18
+	op("ORA", AbsoluteY, 4), // 19
+	unofficial("NOP", Implied, 2), // 1A
+	unofficial("SLO", AbsoluteY, 7), // 1B
+	unofficial("NOP", AbsoluteX, 4), // 1C
+	op("ORA", AbsoluteX, 4), // 1D
+	op("ASL", AbsoluteX, 7), // 1E
+	unofficial("SLO", AbsoluteX, 7), // 1F
+	op("JSR", Absolute, 6), // 20
+	op("AND", IndirectX, 6), // 21
+	unofficial("KIL", Implied, 2), // 22
+	unofficial("RLA", IndirectX, 8), // 23
+	op("BIT", ZeroPage, 3), // 24
+	op("AND", ZeroPage, 3), // 25
+	op("ROL", ZeroPage, 5), // 26
+	unofficial("RLA", ZeroPage, 5), // 27
+	op("PLP", Implied, 4), // 28
+	op("AND", Immediate, 2), // 29
+	op("ROL", Accumulator, 2), // 2A
+	unofficial("ANC", Immediate, 2), // 2B
+	op("BIT", Absolute, 4), // 2C
+	op("AND", Absolute, 4), // 2D
+	op("ROL", Absolute, 6), // 2E
+	unofficial("RLA", Absolute, 6), // 2F
+	op("BMI", Relative, 2), // 30
+	op("AND", IndirectY, 5), // 31
+	unofficial("KIL", Implied, 2), // 32
+	unofficial("RLA", IndirectY, 8), // 33
+	unofficial("NOP", ZeroPageX, 4), // 34
+	op("AND", ZeroPageX, 4), // 35
+	op("ROL", ZeroPageX, 6), // 36
+	unofficial("RLA", ZeroPageX, 6), // 37
+	op("SEC", Implied, 2), // 38
+	op("AND", AbsoluteY, 4), // 39
+	unofficial("NOP", Implied, 2), // 3A
+	unofficial("RLA", AbsoluteY, 7), // 3B
+	unofficial("NOP", AbsoluteX, 4), // 3C
+	op("AND", AbsoluteX, 4), // 3D
+	op("ROL", AbsoluteX, 7), // 3E
+	unofficial("RLA", AbsoluteX, 7), // 3F
+	op("RTI", Implied, 6), // 40
+	op("EOR", IndirectX, 6), // 41
+	unofficial("KIL", Implied, 2), // 42
+	unofficial("SRE", IndirectX, 8), // 43
+	unofficial("NOP", ZeroPage, 3), // 44
+	op("EOR", ZeroPage, 3), // 45
+	op("LSR", ZeroPage, 5), // 46
+	unofficial("SRE", ZeroPage, 5), // 47
+	op("PHA", Implied, 3), // 48
+	op("EOR", Immediate, 2), // 49
+	op("LSR", Accumulator, 2), // 4A
+	unofficial("ALR", Immediate, 2), // 4B
+	op("JMP", Absolute, 3), // 4C
+	op("EOR", Absolute, 4), // 4D
+	op("LSR", Absolute, 6), // 4E
+	unofficial("SRE", Absolute, 6), // 4F
+	op("BVC", Relative, 2), // 50
+	op("EOR", IndirectY, 5), // 51
+	unofficial("KIL", Implied, 2), // 52
+	unofficial("SRE", IndirectY, 8), // 53
+	unofficial("NOP", ZeroPageX, 4), // 54
+	op("EOR", ZeroPageX, 4), // 55
+	op("LSR", ZeroPageX, 6), // 56
+	unofficial("SRE", ZeroPageX, 6), // 57
+	op("CLI", Implied, 2), // 58
+	op("EOR", AbsoluteY, 4), // 59
+	unofficial("NOP", Implied, 2), // 5A
+	unofficial("SRE", AbsoluteY, 7), // 5B
+	unofficial("NOP", AbsoluteX, 4), // 5C
+	op("EOR", AbsoluteX, 4), // 5D
+	op("LSR", AbsoluteX, 7), // 5E
+	unofficial("SRE", AbsoluteX, 7), // 5F
+	op("RTS", Implied, 6), // 60
+	op("ADC", IndirectX, 6), // 61
+	unofficial("KIL", Implied, 2), // 62
+	unofficial("RRA", IndirectX, 8), // 63
+	unofficial("NOP", ZeroPage, 3), // 64
+	op("ADC", ZeroPage, 3), // 65
+	op("ROR", ZeroPage, 5), // 66
+	unofficial("RRA", ZeroPage, 5), // 67
+	op("PLA", Implied, 4), // 68
+	op("ADC", Immediate, 2), // 69
+	op("ROR", Accumulator, 2), // 6A
+	unofficial("ARR", Immediate, 2), // 6B
+	op("JMP", Indirect, 5), // 6C
+	op("ADC", Absolute, 4), // 6D
+	op("ROR", Absolute, 6), // 6E
+	unofficial("RRA", Absolute, 6), // 6F
+	op("BVS", Relative, 2), // 70
+	op("ADC", IndirectY, 5), // 71
+	unofficial("KIL", Implied, 2), // 72
+	unofficial("RRA", IndirectY, 8), // 73
+	unofficial("NOP", ZeroPageX, 4), // 74
+	op("ADC", ZeroPageX, 4), // 75
+	op("ROR", ZeroPageX, 6), // 76
+	unofficial("RRA", ZeroPageX, 6), // 77
+	op("SEI", Implied, 2), // 78
+	op("ADC", AbsoluteY, 4), // 79
+	unofficial("NOP", Implied, 2), // 7A
+	unofficial("RRA", AbsoluteY, 7), // 7B
+	unofficial("NOP", AbsoluteX, 4), // 7C
+	op("ADC", AbsoluteX, 4), // 7D
+	op("ROR", AbsoluteX, 7), // 7E
+	unofficial("RRA", AbsoluteX, 7), // 7F
+	unofficial("NOP", Immediate, 2), // 80
+	op("STA", IndirectX, 6), // 81
+	unofficial("NOP", Immediate, 2), // 82
+	unofficial("SAX", IndirectX, 6), // 83
+	op("STY", ZeroPage, 3), // 84
+	op("STA", ZeroPage, 3), // 85
+	op("STX", ZeroPage, 3), // 86
+	unofficial("SAX", ZeroPage, 3), // 87
+	op("DEY", Implied, 2), // 88
+	unofficial("NOP", Immediate, 2), // 89
+	op("TXA", Implied, 2), // 8A
+	unofficial("XAA", Immediate, 2), // 8B
+	op("STY", Absolute, 4), // 8C
+	op("STA", Absolute, 4), // 8D
+	op("STX", Absolute, 4), // 8E
+	unofficial("SAX", Absolute, 4), // 8F
+	op("BCC", Relative, 2), // 90
+	op("STA", IndirectY, 6), // 91
+	unofficial("KIL", Implied, 2), // 92
+	unofficial("SHA", IndirectY, 6), // 93
+	op("STY", ZeroPageX, 4), // 94
+	op("STA", ZeroPageX, 4), // 95
+	op("STX", ZeroPageY, 4), // 96
+	unofficial("SAX", ZeroPageY, 4), // 97
+	op("TYA", Implied, 2), // 98
+	op("STA", AbsoluteY, 5), // 99
+	op("TXS", Implied, 2), // 9A
+	unofficial("TAS", AbsoluteY, 5), // 9B
+	unofficial("SHY", AbsoluteX, 5), // 9C
+	op("STA", AbsoluteX, 5), // 9D
+	unofficial("SHX", AbsoluteY, 5), // 9E
+	unofficial("SHA", AbsoluteY, 5), // 9F
+	op("LDY", Immediate, 2), // A0
+	op("LDA", IndirectX, 6), // A1
+	op("LDX", Immediate, 2), // A2
+	unofficial("LAX", IndirectX, 6), // A3
+	op("LDY", ZeroPage, 3), // A4
+	op("LDA", ZeroPage, 3), // A5
+	op("LDX", ZeroPage, 3), // A6
+	unofficial("LAX", ZeroPage, 3), // A7
+	op("TAY", Implied, 2), // A8
+	op("LDA", Immediate, 2), // A9
+	op("TAX", Implied, 2), // AA
+	unofficial("LXA", Immediate, 2), // AB
+	op("LDY", Absolute, 4), // AC
+	op("LDA", Absolute, 4), // AD
+	op("LDX", Absolute, 4), // AE
+	unofficial("LAX", Absolute, 4), // AF
+	op("BCS", Relative, 2), // B0
+	op("LDA", IndirectY, 5), // B1
+	unofficial("KIL", Implied, 2), // B2
+	unofficial("LAX", IndirectY, 5), // B3
+	op("LDY", ZeroPageX, 4), // B4
+	op("LDA", ZeroPageX, 4), // B5
+	op("LDX", ZeroPageY, 4), // B6
+	unofficial("LAX", ZeroPageY, 4), // B7
+	op("CLV", Implied, 2), // B8
+	op("LDA", AbsoluteY, 4), // B9
+	op("TSX", Implied, 2), // BA
+	unofficial("LAS", AbsoluteY, 4), // BB
+	op("LDY", AbsoluteX, 4), // BC
+	op("LDA", AbsoluteX, 4), // BD
+	op("LDX", AbsoluteY, 4), // BE
+	unofficial("LAX", AbsoluteY, 4), // BF
+	op("CPY", Immediate, 2), // C0
+	op("CMP", IndirectX, 6), // C1
+	unofficial("NOP", Immediate, 2), // C2
+	unofficial("DCP", IndirectX, 8), // C3
+	op("CPY", ZeroPage, 3), // C4
+	op("CMP", ZeroPage, 3), // C5
+	op("DEC", ZeroPage, 5), // C6
+	unofficial("DCP", ZeroPage, 5), // C7
+	op("INY", Implied, 2), // C8
+	op("CMP", Immediate, 2), // C9
+	op("DEX", Implied, 2), // CA
+	unofficial("AXS", Immediate, 2), // CB
+	op("CPY", Absolute, 4), // CC
+	op("CMP", Absolute, 4), // CD
+	op("DEC", Absolute, 6), // CE
+	unofficial("DCP", Absolute, 6), // CF
+	op("BNE", Relative, 2), // D0
+	op("CMP", IndirectY, 5), // D1
+	unofficial("KIL", Implied, 2), // D2
+	unofficial("DCP", IndirectY, 8), // D3
+	unofficial("NOP", ZeroPageX, 4), // D4
+	op("CMP", ZeroPageX, 4), // D5
+	op("DEC", ZeroPageX, 6), // D6
+	unofficial("DCP", ZeroPageX, 6), // D7
+	op("CLD", Implied, 2), // D8
+	op("CMP", AbsoluteY, 4), // D9
+	unofficial("NOP", Implied, 2), // DA
+	unofficial("DCP", AbsoluteY, 7), // DB
+	unofficial("NOP", AbsoluteX, 4), // DC
+	op("CMP", AbsoluteX, 4), // DD
+	op("DEC", AbsoluteX, 7), // DE
+	unofficial("DCP", AbsoluteX, 7), // DF
+	op("CPX", Immediate, 2), // E0
+	op("SBC", IndirectX, 6), // E1
+	unofficial("NOP", Immediate, 2), // E2
+	unofficial("ISB", IndirectX, 8), // E3
+	op("CPX", ZeroPage, 3), // E4
+	op("SBC", ZeroPage, 3), // E5
+	op("INC", ZeroPage, 5), // E6
+	unofficial("ISB", ZeroPage, 5), // E7
+	op("INX", Implied, 2), // E8
+	op("SBC", Immediate, 2), // E9
+	op("NOP", Implied, 2), // EA
+	unofficial("SBC", Immediate, 2), // EB
+	op("CPX", Absolute, 4), // EC
+	op("SBC", Absolute, 4), // ED
+	op("INC", Absolute, 6), // EE
+	unofficial("ISB", Absolute, 6), // EF
+	op("BEQ", Relative, 2), // F0
+	op("SBC", IndirectY, 5), // F1
+	unofficial("KIL", Implied, 2), // F2
+	unofficial("ISB", IndirectY, 8), // F3
+	unofficial("NOP", ZeroPageX, 4), // F4
+	op("SBC", ZeroPageX, 4), // F5
+	op("INC", ZeroPageX, 6), // F6
+	unofficial("ISB", ZeroPageX, 6), // F7
+	op("SED", Implied, 2), // F8
+	op("SBC", AbsoluteY, 4), // F9
+	unofficial("NOP", Implied, 2), // FA
+	unofficial("ISB", AbsoluteY, 7), // FB
+	unofficial("NOP", AbsoluteX, 4), // FC
+	op("SBC", AbsoluteX, 4), // FD
+	op("INC", AbsoluteX, 7), // FE
+	unofficial("ISB", AbsoluteX, 7), // FF
+];
+
+/// Opcode metadata for the 65C02. Opcodes that were undocumented on the NMOS 6502 are either new
+/// instructions or NOPs of various lengths. The Rockwell and WDC bit instructions (RMB, SMB, BBR,
+/// BBS) and WAI/STP aren't included, so those opcodes are single byte NOPs.
+///     http://www.6502.org/tutorials/65c02opcodes.html
+pub static CMOS_OPCODES: [Opcode; 256] = [
+	op("BRK", Implied, 7), // 00
+	op("ORA", IndirectX, 6), // 01
+	unofficial("NOP", Immediate, 2), // 02
+	unofficial("NOP", Implied, 1), // 03
+	op("TSB", ZeroPage, 5), // 04
+	op("ORA", ZeroPage, 3), // 05
+	op("ASL", ZeroPage, 5), // 06
+	unofficial("NOP", Implied, 1), // 07
+	op("PHP", Implied, 3), // 08
+	op("ORA", Immediate, 2), // 09
+	op("ASL", Accumulator, 2), // 0A
+	unofficial("NOP", Implied, 1), // 0B
+	op("TSB", Absolute, 6), // 0C
+	op("ORA", Absolute, 4), // 0D
+	op("ASL", Absolute, 6), // 0E
+	unofficial("NOP", Implied, 1), // 0F
+	op("BPL", Relative, 2), // 10
+	op("ORA", IndirectY, 5), // 11
+	op("ORA", ZeroPageIndirect, 5), // 12
+	unofficial("NOP", Implied, 1), // 13
+	op("TRB", ZeroPage, 5), // 14
+	op("ORA", ZeroPageX, 4), // 15
+	op("ASL", ZeroPageX, 6), // 16
+	unofficial("NOP", Implied, 1), // 17
+	op("CLC", Implied, 2), // 18
+	op("ORA", AbsoluteY, 4), // 19
+	op("INC", Accumulator, 2), // 1A
+	unofficial("NOP", Implied, 1), // 1B
+	op("TRB", Absolute, 6), // 1C
+	op("ORA", AbsoluteX, 4), // 1D
+	op("ASL", AbsoluteX, 6), // 1E
+	unofficial("NOP", Implied, 1), // 1F
+	op("JSR", Absolute, 6), // 20
+	op("AND", IndirectX, 6), // 21
+	unofficial("NOP", Immediate, 2), // 22
+	unofficial("NOP", Implied, 1), // 23
+	op("BIT", ZeroPage, 3), // 24
+	op("AND", ZeroPage, 3), // 25
+	op("ROL", ZeroPage, 5), // 26
+	unofficial("NOP", Implied, 1), // 27
+	op("PLP", Implied, 4), // 28
+	op("AND", Immediate, 2), // 29
+	op("ROL", Accumulator, 2), // 2A
+	unofficial("NOP", Implied, 1), // 2B
+	op("BIT", Absolute, 4), // 2C
+	op("AND", Absolute, 4), // 2D
+	op("ROL", Absolute, 6), // 2E
+	unofficial("NOP", Implied, 1), // 2F
+	op("BMI", Relative, 2), // 30
+	op("AND", IndirectY, 5), // 31
+	op("AND", ZeroPageIndirect, 5), // 32
+	unofficial("NOP", Implied, 1), // 33
+	op("BIT", ZeroPageX, 4), // 34
+	op("AND", ZeroPageX, 4), // 35
+	op("ROL", ZeroPageX, 6), // 36
+	unofficial("NOP", Implied, 1), // 37
+	op("SEC", Implied, 2), // 38
+	op("AND", AbsoluteY, 4), // 39
+	op("DEC", Accumulator, 2), // 3A
+	unofficial("NOP", Implied, 1), // 3B
+	op("BIT", AbsoluteX, 4), // 3C
+	op("AND", AbsoluteX, 4), // 3D
+	op("ROL", AbsoluteX, 6), // 3E
+	unofficial("NOP", Implied, 1), // 3F
+	op("RTI", Implied, 6), // 40
+	op("EOR", IndirectX, 6), // 41
+	unofficial("NOP", Immediate, 2), // 42
+	unofficial("NOP", Implied, 1), // 43
+	unofficial("NOP", ZeroPage, 3), // 44
+	op("EOR", ZeroPage, 3), // 45
+	op("LSR", ZeroPage, 5), // 46
+	unofficial("NOP", Implied, 1), // 47
+	op("PHA", Implied, 3), // 48
+	op("EOR", Immediate, 2), // 49
+	op("LSR", Accumulator, 2), // 4A
+	unofficial("NOP", Implied, 1), // 4B
+	op("JMP", Absolute, 3), // 4C
+	op("EOR", Absolute, 4), // 4D
+	op("LSR", Absolute, 6), // 4E
+	unofficial("NOP", Implied, 1), // 4F
+	op("BVC", Relative, 2), // 50
+	op("EOR", IndirectY, 5), // 51
+	op("EOR", ZeroPageIndirect, 5), // 52
+	unofficial("NOP", Implied, 1), // 53
+	unofficial("NOP", ZeroPageX, 4), // 54
+	op("EOR", ZeroPageX, 4), // 55
+	op("LSR", ZeroPageX, 6), // 56
+	unofficial("NOP", Implied, 1), // 57
+	op("CLI", Implied, 2), // 58
+	op("EOR", AbsoluteY, 4), // 59
+	op("PHY", Implied, 3), // 5A
+	unofficial("NOP", Implied, 1), // 5B
+	unofficial("NOP", Absolute, 8), // 5C
+	op("EOR", AbsoluteX, 4), // 5D
+	op("LSR", AbsoluteX, 6), // 5E
+	unofficial("NOP", Implied, 1), // 5F
+	op("RTS", Implied, 6), // 60
+	op("ADC", IndirectX, 6), // 61
+	unofficial("NOP", Immediate, 2), // 62
+	unofficial("NOP", Implied, 1), // 63
+	op("STZ", ZeroPage, 3), // 64
+	op("ADC", ZeroPage, 3), // 65
+	op("ROR", ZeroPage, 5), // 66
+	unofficial("NOP", Implied, 1), // 67
+	op("PLA", Implied, 4), // 68
+	op("ADC", Immediate, 2), // 69
+	op("ROR", Accumulator, 2), // 6A
+	unofficial("NOP", Implied, 1), // 6B
+	op("JMP", Indirect, 6), // 6C
+	op("ADC", Absolute, 4), // 6D
+	op("ROR", Absolute, 6), // 6E
+	unofficial("NOP", Implied, 1), // 6F
+	op("BVS", Relative, 2), // 70
+	op("ADC", IndirectY, 5), // 71
+	op("ADC", ZeroPageIndirect, 5), // 72
+	unofficial("NOP", Implied, 1), // 73
+	op("STZ", ZeroPageX, 4), // 74
+	op("ADC", ZeroPageX, 4), // 75
+	op("ROR", ZeroPageX, 6), // 76
+	unofficial("NOP", Implied, 1), // 77
+	op("SEI", Implied, 2), // 78
+	op("ADC", AbsoluteY, 4), // 79
+	op("PLY", Implied, 4), // 7A
+	unofficial("NOP", Implied, 1), // 7B
+	op("JMP", AbsoluteIndexedIndirect, 6), // 7C
+	op("ADC", AbsoluteX, 4), // 7D
+	op("ROR", AbsoluteX, 6), // 7E
+	unofficial("NOP", Implied, 1), // 7F
+	op("BRA", Relative, 2), // 80
+	op("STA", IndirectX, 6), // 81
+	unofficial("NOP", Immediate, 2), // 82
+	unofficial("NOP", Implied, 1), // 83
+	op("STY", ZeroPage, 3), // 84
+	op("STA", ZeroPage, 3), // 85
+	op("STX", ZeroPage, 3), // 86
+	unofficial("NOP", Implied, 1), // 87
+	op("DEY", Implied, 2), // 88
+	op("BIT", Immediate, 2), // 89
+	op("TXA", Implied, 2), // 8A
+	unofficial("NOP", Implied, 1), // 8B
+	op("STY", Absolute, 4), // 8C
+	op("STA", Absolute, 4), // 8D
+	op("STX", Absolute, 4), // 8E
+	unofficial("NOP", Implied, 1), // 8F
+	op("BCC", Relative, 2), // 90
+	op("STA", IndirectY, 6), // 91
+	op("STA", ZeroPageIndirect, 5), // 92
+	unofficial("NOP", Implied, 1), // 93
+	op("STY", ZeroPageX, 4), // 94
+	op("STA", ZeroPageX, 4), // 95
+	op("STX", ZeroPageY, 4), // 96
+	unofficial("NOP", Implied, 1), // 97
+	op("TYA", Implied, 2), // 98
+	op("STA", AbsoluteY, 5), // 99
+	op("TXS", Implied, 2), // 9A
+	unofficial("NOP", Implied, 1), // 9B
+	op("STZ", Absolute, 4), // 9C
+	op("STA", AbsoluteX, 5), // 9D
+	op("STZ", AbsoluteX, 5), // 9E
+	unofficial("NOP", Implied, 1), // 9F
+	op("LDY", Immediate, 2), // A0
+	op("LDA", IndirectX, 6), // A1
+	op("LDX", Immediate, 2), // A2
+	unofficial("NOP", Implied, 1), // A3
+	op("LDY", ZeroPage, 3), // A4
+	op("LDA", ZeroPage, 3), // A5
+	op("LDX", ZeroPage, 3), // A6
+	unofficial("NOP", Implied, 1), // A7
+	op("TAY", Implied, 2), // A8
+	op("LDA", Immediate, 2), // A9
+	op("TAX", Implied, 2), // AA
+	unofficial("NOP", Implied, 1), // AB
+	op("LDY", Absolute, 4), // AC
+	op("LDA", Absolute, 4), // AD
+	op("LDX", Absolute, 4), // AE
+	unofficial("NOP", Implied, 1), // AF
+	op("BCS", Relative, 2), // B0
+	op("LDA", IndirectY, 5), // B1
+	op("LDA", ZeroPageIndirect, 5), // B2
+	unofficial("NOP", Implied, 1), // B3
+	op("LDY", ZeroPageX, 4), // B4
+	op("LDA", ZeroPageX, 4), // B5
+	op("LDX", ZeroPageY, 4), // B6
+	unofficial("NOP", Implied, 1), // B7
+	op("CLV", Implied, 2), // B8
+	op("LDA", AbsoluteY, 4), // B9
+	op("TSX", Implied, 2), // BA
+	unofficial("NOP", Implied, 1), // BB
+	op("LDY", AbsoluteX, 4), // BC
+	op("LDA", AbsoluteX, 4), // BD
+	op("LDX", AbsoluteY, 4), // BE
+	unofficial("NOP", Implied, 1), // BF
+	op("CPY", Immediate, 2), // C0
+	op("CMP", IndirectX, 6), // C1
+	unofficial("NOP", Immediate, 2), // C2
+	unofficial("NOP", Implied, 1), // C3
+	op("CPY", ZeroPage, 3), // C4
+	op("CMP", ZeroPage, 3), // C5
+	op("DEC", ZeroPage, 5), // C6
+	unofficial("NOP", Implied, 1), // C7
+	op("INY", Implied, 2), // C8
+	op("CMP", Immediate, 2), // C9
+	op("DEX", Implied, 2), // CA
+	unofficial("NOP", Implied, 1), // CB
+	op("CPY", Absolute, 4), // CC
+	op("CMP", Absolute, 4), // CD
+	op("DEC", Absolute, 6), // CE
+	unofficial("NOP", Implied, 1), // CF
+	op("BNE", Relative, 2), // D0
+	op("CMP", IndirectY, 5), // D1
+	op("CMP", ZeroPageIndirect, 5), // D2
+	unofficial("NOP", Implied, 1), // D3
+	unofficial("NOP", ZeroPageX, 4), // D4
+	op("CMP", ZeroPageX, 4), // D5
+	op("DEC", ZeroPageX, 6), // D6
+	unofficial("NOP", Implied, 1), // D7
+	op("CLD", Implied, 2), // D8
+	op("CMP", AbsoluteY, 4), // D9
+	op("PHX", Implied, 3), // DA
+	unofficial("NOP", Implied, 1), // DB
+	unofficial("NOP", Absolute, 4), // DC
+	op("CMP", AbsoluteX, 4), // DD
+	op("DEC", AbsoluteX, 7), // DE
+	unofficial("NOP", Implied, 1), // DF
+	op("CPX", Immediate, 2), // E0
+	op("SBC", IndirectX, 6), // E1
+	unofficial("NOP", Immediate, 2), // E2
+	unofficial("NOP", Implied, 1), // E3
+	op("CPX", ZeroPage, 3), // E4
+	op("SBC", ZeroPage, 3), // E5
+	op("INC", ZeroPage, 5), // E6
+	unofficial("NOP", Implied, 1), // E7
+	op("INX", Implied, 2), // E8
+	op("SBC", Immediate, 2), // E9
+	op("NOP", Implied, 2), // EA
+	unofficial("NOP", Implied, 1), // EB
+	op("CPX", Absolute, 4), // EC
+	op("SBC", Absolute, 4), // ED
+	op("INC", Absolute, 6), // EE
+	unofficial("NOP", Implied, 1), // EF
+	op("BEQ", Relative, 2), // F0
+	op("SBC", IndirectY, 5), // F1
+	op("SBC", ZeroPageIndirect, 5), // F2
+	unofficial("NOP", Implied, 1), // F3
+	unofficial("NOP", ZeroPageX, 4), // F4
+	op("SBC", ZeroPageX, 4), // F5
+	op("INC", ZeroPageX, 6), // F6
+	unofficial("NOP", Implied, 1), // F7
+	op("SED", Implied, 2), // F8
+	op("SBC", AbsoluteY, 4), // F9
+	op("PLX", Implied, 4), // FA
+	unofficial("NOP", Implied, 1), // FB
+	unofficial("NOP", Absolute, 4), // FC
+	op("SBC", AbsoluteX, 4), // FD
+	op("INC", AbsoluteX, 7), // FE
+	unofficial("NOP", Implied, 1), // FF
+];
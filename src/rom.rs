@@ -1,8 +1,13 @@
+use std::fmt;
 use std::fs::File;
 use std::io::prelude::*;
 use std::path::Path;
-use std::io::{Error, ErrorKind};
-use std::io::Result;
+use database::{ Correction, Database };
+use error::{ RomError, Result };
+use mapper::Mirroring;
+use unif;
+#[cfg(test)]
+use error::EmuError;
 
 /// Identifier should always be the first 4 bytes of iNES header
 pub const IDENTIFIER: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A];
@@ -13,6 +18,9 @@ pub const PRG_ROM_UNIT_SIZE: usize = 16 * 1024;
 // CHR ROM Unit Size (8 KB)
 pub const CHR_ROM_UNIT_SIZE: usize = 8 * 1024;
 
+/// PRG RAM Unit Size (8 KB)
+pub const PRG_RAM_UNIT_SIZE: usize = 8 * 1024;
+
 /// iNES Header (16 Bytes)
 /// Format:
 ///   0-3: Identifier
@@ -24,33 +32,418 @@ pub const CHR_ROM_UNIT_SIZE: usize = 8 * 1024;
 ///     9: Flags
 ///    10: Flags
 /// 11-15: Zero filled
+///
+/// NES 2.0 Header, when flags 7 says so
+///     http://wiki.nesdev.com/w/index.php/NES_2.0
+/// Format:
+///   0-7: As iNES, with the console type in the low bits of 7
+///     8: Mapper number bits 8-11 (low nibble) and submapper (high nibble)
+///     9: PRG ROM size bits 8-11 (low nibble) and CHR ROM size bits 8-11 (high nibble)
+///    10: PRG RAM (low nibble) and PRG NVRAM (high nibble) size, as 64 << n bytes or 0 for none
+///    11: CHR RAM (low nibble) and CHR NVRAM (high nibble) size, as 64 << n bytes or 0 for none
+///    12: CPU/PPU timing
+///    13: Vs. System PPU (low nibble) and hardware (high nibble), or the extended console type
+///    14: Number of miscellaneous ROMs
+///    15: Default expansion device
+///
+/// Sizes are in bytes. iNES files don't have most of these fields, so they get the values that
+/// older emulators assumed.
 #[derive(Clone, Debug)]
 pub struct Header {
-	pub prg_rom_size: u8,
-	pub chr_rom_size: u8,
-	pub prg_ram_size: u8,
+	pub prg_rom_size: usize,
+	pub chr_rom_size: usize,
+	pub prg_ram_size: usize,
+	/// Battery backed PRG RAM
+	pub prg_nvram_size: usize,
+	pub chr_ram_size: usize,
+	pub chr_nvram_size: usize,
 	pub flags6: Flags6,
 	pub flags7: Flags7,
-	pub mapper_number: u8,
-	// TODO: Flags 9,10 (Ignoring for now; flags 9 is unused and flags 10 is unofficial)
+	pub mapper_number: u16,
+	/// NES 2.0 submapper, 0 for iNES files
+	pub submapper: u8,
+	pub timing: Timing,
+	pub console_type: ConsoleType,
+	/// PPU and board of a Vs. System game, for NES 2.0 files
+	pub vs_system: Option<VsSystem>,
+	/// ROMs stored after CHR ROM that aren't PlayChoice-10 data
+	pub misc_roms: u8,
+	pub expansion_device: ExpansionDevice,
+	/// Problems found in the header that it was loaded despite
+	pub warnings: Vec<HeaderWarning>
 }
 
 impl Header {
-	fn new(data: &[u8; 16]) -> Result<Header> {
+	/// Parse the 16 byte header at the start of an iNES file
+	pub fn new(data: &[u8; 16]) -> Result<Header> {
 		if data[0..4] != IDENTIFIER {
-			Err(Error::new(ErrorKind::Other, "File is not in iNES file format!"))
+			return Err(RomError::InvalidIdentifier.into());
+		}
+		let (data, warnings) = clean(data);
+		let flags6 = Flags6::new(&data[6]);
+		let flags7 = Flags7::new(&data[7]);
+		// Set mapper number by combining upper and lower bits from flags
+		let mapper_number = ((flags7.mapper_upper << 4) | flags6.mapper_lower) as u16;
+		let mut header = if flags7.ines_2 {
+			Header::nes_2(&data, flags6, flags7, mapper_number)
+		} else {
+			Header::ines(&data, flags6, flags7, mapper_number)
+		};
+		header.warnings = warnings;
+		Ok(header)
+	}
+
+	fn ines(data: &[u8; 16], flags6: Flags6, flags7: Flags7, mapper_number: u16) -> Header {
+		// PRG RAM size is in 8 KB units, where 0 means 8 KB since most files leave it unset
+		let prg_ram_size = (data[8] as usize).max(1) * PRG_RAM_UNIT_SIZE;
+		let (prg_ram_size, prg_nvram_size) = if flags6.battery_backed_prg_ram {
+			(0, prg_ram_size)
 		} else {
-			let mut header = Header {
-				prg_rom_size: data[4],
-				chr_rom_size: data[5],
-				prg_ram_size: data[8],
-				flags6: Flags6::new(&data[6]),
-				flags7: Flags7::new(&data[7]),
-				mapper_number: 0
-			};
-			// Set mapper number by combing upper and lower bits from flags
-			header.mapper_number = (header.flags7.mapper_upper << 4) & header.flags6.mapper_lower;
-			Ok(header)
+			(prg_ram_size, 0)
+		};
+		let console_type = if flags7.vs_unisystem {
+			ConsoleType::VsSystem
+		} else if flags7.playchoice_10 {
+			ConsoleType::PlayChoice10
+		} else {
+			ConsoleType::Nes
+		};
+		Header {
+			prg_rom_size: data[4] as usize * PRG_ROM_UNIT_SIZE,
+			chr_rom_size: data[5] as usize * CHR_ROM_UNIT_SIZE,
+			prg_ram_size,
+			prg_nvram_size,
+			// Boards without CHR ROM have 8 KB of CHR RAM instead
+			chr_ram_size: if data[5] == 0 { CHR_ROM_UNIT_SIZE } else { 0 },
+			chr_nvram_size: 0,
+			flags6,
+			flags7,
+			mapper_number,
+			submapper: 0,
+			timing: Timing::Ntsc,
+			console_type,
+			vs_system: None,
+			misc_roms: 0,
+			expansion_device: ExpansionDevice::Unspecified,
+			warnings: Vec::new()
+		}
+	}
+
+	fn nes_2(data: &[u8; 16], flags6: Flags6, flags7: Flags7, mapper_number: u16) -> Header {
+		let console_type = match data[7] & 0b11 {
+			0 => ConsoleType::Nes,
+			1 => ConsoleType::VsSystem,
+			2 => ConsoleType::PlayChoice10,
+			_ => ConsoleType::extended(data[13] & 0x0F)
+		};
+		let vs_system = if console_type == ConsoleType::VsSystem {
+			let (ppu, hardware) = (VsPpu::new(data[13] & 0x0F), VsHardware::new(data[13] >> 4));
+			Some(VsSystem { ppu, hardware })
+		} else {
+			None
+		};
+		Header {
+			prg_rom_size: nes_2_rom_size(data[4], data[9] & 0x0F, PRG_ROM_UNIT_SIZE),
+			chr_rom_size: nes_2_rom_size(data[5], data[9] >> 4, CHR_ROM_UNIT_SIZE),
+			prg_ram_size: nes_2_ram_size(data[10] & 0x0F),
+			prg_nvram_size: nes_2_ram_size(data[10] >> 4),
+			chr_ram_size: nes_2_ram_size(data[11] & 0x0F),
+			chr_nvram_size: nes_2_ram_size(data[11] >> 4),
+			flags6,
+			flags7,
+			mapper_number: mapper_number | ((data[8] & 0x0F) as u16) << 8,
+			submapper: data[8] >> 4,
+			timing: Timing::new(data[12] & 0b11),
+			console_type,
+			vs_system,
+			misc_roms: data[14] & 0b11,
+			expansion_device: ExpansionDevice::new(data[15] & 0x3F),
+			warnings: Vec::new()
+		}
+	}
+
+	/// Bytes of PRG RAM of either kind. Boards with both map them to the same window, volatile RAM
+	/// first.
+	pub fn prg_ram_total(&self) -> usize {
+		self.prg_ram_size + self.prg_nvram_size
+	}
+
+	/// Nametable mirroring soldered on the board. Mappers that switch mirroring start from this.
+	pub fn mirroring(&self) -> Mirroring {
+		if self.flags6.four_screen_vram {
+			Mirroring::FourScreen
+		} else if self.flags6.horizontal_arrangement {
+			Mirroring::Vertical
+		} else {
+			Mirroring::Horizontal
+		}
+	}
+}
+
+// Validation pass over the raw header. Tools from before NES 2.0 wrote their names into bytes
+// 7-15, most famously "DiskDude!", which would otherwise be read as flags, the PRG RAM size and the
+// upper nibble of the mapper number. Returns the header with the bytes that can't be trusted
+// zeroed, and what was found.
+//     http://wiki.nesdev.com/w/index.php/INES#Variant_comparison
+fn clean(data: &[u8; 16]) -> ([u8; 16], Vec<HeaderWarning>) {
+	let mut data = *data;
+	let mut warnings = Vec::new();
+	let format = data[7] & 0b1100;
+	if format == 0b0100 {
+		// Archaic iNES, from before flags 7 existed
+		if data[7..16].starts_with(b"DiskDude") {
+			warnings.push(HeaderWarning::DiskDude);
+		} else {
+			warnings.push(HeaderWarning::ArchaicFormat);
+		}
+		data[7..16].copy_from_slice(&[0; 9]);
+	} else if format != 0b1000 && (format == 0b1100 || data[12..16] != [0; 4]) {
+		warnings.push(HeaderWarning::DirtyHeader);
+		data[7] &= 0x0F;
+		data[8..16].copy_from_slice(&[0; 8]);
+	}
+	(data, warnings)
+}
+
+/// Problems with a header that don't stop it from being loaded
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HeaderWarning {
+	/// Bytes 7-15 hold "DiskDude!", and were ignored
+	DiskDude,
+	/// Flags 7 marks the header as archaic iNES, so bytes 7-15 were ignored
+	ArchaicFormat,
+	/// Bytes 12-15 of an iNES header aren't zero, so the upper nibble of the mapper number and
+	/// bytes 8-15 were ignored
+	DirtyHeader
+}
+
+impl fmt::Display for HeaderWarning {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			HeaderWarning::DiskDude =>
+				write!(f, "Header has \"DiskDude!\" in bytes 7-15, ignoring them"),
+			HeaderWarning::ArchaicFormat =>
+				write!(f, "Header is in the archaic iNES format, ignoring bytes 7-15"),
+			HeaderWarning::DirtyHeader =>
+				write!(f, "Header has garbage in bytes 12-15, ignoring the upper nibble of the \
+					mapper number and bytes 8-15")
+		}
+	}
+}
+
+// Size of PRG or CHR ROM. Sizes that don't fit in 12 bits of units use the high nibble of 0xF to
+// switch to an exponent and multiplier, EEEEEEMM giving 2^E * (MM * 2 + 1) bytes.
+fn nes_2_rom_size(lsb: u8, msb: u8, unit: usize) -> usize {
+	if msb == 0x0F {
+		let multiplier = (lsb & 0b11) as usize * 2 + 1;
+		// Sizes too large to address can't be in the file anyway
+		1usize.checked_shl((lsb >> 2) as u32)
+			.and_then(|size| size.checked_mul(multiplier))
+			.unwrap_or(usize::MAX)
+	} else {
+		((msb as usize) << 8 | lsb as usize) * unit
+	}
+}
+
+// Size of a RAM given as a shift count, where 0 means there is none
+fn nes_2_ram_size(shift: u8) -> usize {
+	if shift == 0 { 0 } else { 64 << shift }
+}
+
+/// CPU/PPU timing the game was made for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Timing {
+	/// RP2C02, North America and Japan
+	Ntsc,
+	/// RP2C07, Europe and Australia
+	Pal,
+	/// Works on either
+	MultipleRegion,
+	/// UA6538, the Dendy and other famiclones
+	Dendy
+}
+
+impl Timing {
+	fn new(value: u8) -> Timing {
+		match value {
+			0 => Timing::Ntsc,
+			1 => Timing::Pal,
+			2 => Timing::MultipleRegion,
+			_ => Timing::Dendy
+		}
+	}
+}
+
+/// Console the game runs on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConsoleType {
+	/// NES or Famicom
+	Nes,
+	/// Nintendo Vs. System arcade board
+	VsSystem,
+	/// Nintendo PlayChoice-10 arcade board
+	PlayChoice10,
+	/// Famiclone with a CPU that has decimal mode
+	DecimalModeFamiclone,
+	/// NES or Famicom with the EPSM expansion module, or a plug-through cartridge
+	Epsm,
+	/// V.R. Technology VT01 with a red/cyan STN palette
+	Vt01,
+	/// V.R. Technology VT02, VT03, VT09, VT32 and VT369
+	Vt02,
+	Vt03,
+	Vt09,
+	Vt32,
+	Vt369,
+	/// UMC UM6578
+	Um6578,
+	/// Famicom Network System
+	FamicomNetworkSystem,
+	/// Extended console types that are reserved
+	Reserved(u8)
+}
+
+impl ConsoleType {
+	fn extended(value: u8) -> ConsoleType {
+		match value {
+			0x0 => ConsoleType::Nes,
+			0x1 => ConsoleType::VsSystem,
+			0x2 => ConsoleType::PlayChoice10,
+			0x3 => ConsoleType::DecimalModeFamiclone,
+			0x4 => ConsoleType::Epsm,
+			0x5 => ConsoleType::Vt01,
+			0x6 => ConsoleType::Vt02,
+			0x7 => ConsoleType::Vt03,
+			0x8 => ConsoleType::Vt09,
+			0x9 => ConsoleType::Vt32,
+			0xA => ConsoleType::Vt369,
+			0xB => ConsoleType::Um6578,
+			0xC => ConsoleType::FamicomNetworkSystem,
+			_ => ConsoleType::Reserved(value)
+		}
+	}
+}
+
+/// Vs. System PPU and board
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VsSystem {
+	pub ppu: VsPpu,
+	pub hardware: VsHardware
+}
+
+/// PPUs used by Vs. System games, which differ in palette and register layout
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VsPpu {
+	Rp2c03b,
+	Rp2c03g,
+	Rp2c04_0001,
+	Rp2c04_0002,
+	Rp2c04_0003,
+	Rp2c04_0004,
+	Rc2c03b,
+	Rc2c03c,
+	Rc2c05_01,
+	Rc2c05_02,
+	Rc2c05_03,
+	Rc2c05_04,
+	Rc2c05_05,
+	Reserved(u8)
+}
+
+impl VsPpu {
+	fn new(value: u8) -> VsPpu {
+		match value {
+			0x0 => VsPpu::Rp2c03b,
+			0x1 => VsPpu::Rp2c03g,
+			0x2 => VsPpu::Rp2c04_0001,
+			0x3 => VsPpu::Rp2c04_0002,
+			0x4 => VsPpu::Rp2c04_0003,
+			0x5 => VsPpu::Rp2c04_0004,
+			0x6 => VsPpu::Rc2c03b,
+			0x7 => VsPpu::Rc2c03c,
+			0x8 => VsPpu::Rc2c05_01,
+			0x9 => VsPpu::Rc2c05_02,
+			0xA => VsPpu::Rc2c05_03,
+			0xB => VsPpu::Rc2c05_04,
+			0xC => VsPpu::Rc2c05_05,
+			_ => VsPpu::Reserved(value)
+		}
+	}
+}
+
+/// Vs. System boards, including the games with their own protection hardware
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VsHardware {
+	Unisystem,
+	UnisystemRbiBaseball,
+	UnisystemTkoBoxing,
+	UnisystemSuperXevious,
+	UnisystemIceClimberJapan,
+	DualSystem,
+	DualSystemRaidOnBungelingBay,
+	Reserved(u8)
+}
+
+impl VsHardware {
+	fn new(value: u8) -> VsHardware {
+		match value {
+			0x0 => VsHardware::Unisystem,
+			0x1 => VsHardware::UnisystemRbiBaseball,
+			0x2 => VsHardware::UnisystemTkoBoxing,
+			0x3 => VsHardware::UnisystemSuperXevious,
+			0x4 => VsHardware::UnisystemIceClimberJapan,
+			0x5 => VsHardware::DualSystem,
+			0x6 => VsHardware::DualSystemRaidOnBungelingBay,
+			_ => VsHardware::Reserved(value)
+		}
+	}
+}
+
+/// Input device the game expects to have plugged in
+///     http://wiki.nesdev.com/w/index.php/NES_2.0#Default_Expansion_Device
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExpansionDevice {
+	Unspecified,
+	StandardControllers,
+	FourScore,
+	FamicomFourPlayersAdapter,
+	/// Vs. System with its coin slot on 0x4016 or 0x4017
+	VsSystem4016,
+	VsSystem4017,
+	VsZapper,
+	Zapper,
+	TwoZappers,
+	BandaiHyperShot,
+	PowerPadSideA,
+	PowerPadSideB,
+	FamilyTrainerSideA,
+	FamilyTrainerSideB,
+	ArkanoidVausNes,
+	ArkanoidVausFamicom,
+	/// Devices without a variant, by their number
+	Other(u8)
+}
+
+impl ExpansionDevice {
+	fn new(value: u8) -> ExpansionDevice {
+		match value {
+			0x00 => ExpansionDevice::Unspecified,
+			0x01 => ExpansionDevice::StandardControllers,
+			0x02 => ExpansionDevice::FourScore,
+			0x03 => ExpansionDevice::FamicomFourPlayersAdapter,
+			0x04 => ExpansionDevice::VsSystem4016,
+			0x05 => ExpansionDevice::VsSystem4017,
+			0x07 => ExpansionDevice::VsZapper,
+			0x08 => ExpansionDevice::Zapper,
+			0x09 => ExpansionDevice::TwoZappers,
+			0x0A => ExpansionDevice::BandaiHyperShot,
+			0x0B => ExpansionDevice::PowerPadSideA,
+			0x0C => ExpansionDevice::PowerPadSideB,
+			0x0D => ExpansionDevice::FamilyTrainerSideA,
+			0x0E => ExpansionDevice::FamilyTrainerSideB,
+			0x0F => ExpansionDevice::ArkanoidVausNes,
+			0x10 => ExpansionDevice::ArkanoidVausFamicom,
+			_ => ExpansionDevice::Other(value)
 		}
 	}
 }
@@ -59,7 +452,7 @@ impl Header {
 ///   0: Vertical arrangement/horizontal mirroring (CIRAM A10 = PPU A11)
 ///      Horizontal arrangement/vertical mirroring (CIRAM A10 = PPU A10)
 ///   1: Cartridge contains battery-backed PRG RAM ($6000-7FFF)
-///   2: 512-byte trainer at $7000-$71FF (stored before PRG data
+///   2: 512-byte trainer at $7000-$71FF (stored before PRG data)
 ///   3: Four-screen VRAM
 /// 4-7: Lower part of mapper number
 ///
@@ -88,6 +481,7 @@ impl Flags6 {
 /// Flags 7 (1 Byte)
 ///   0: vs Unisystem
 ///   1: PlayChoice-10 (8KB of Hint Screen data stored after CHR data)
+///      NES 2.0 uses both bits as the console type
 /// 2-3: If equal to 2, flags 8-15 are in NES 2.0 format
 /// 4-7: Upper part of mapper number
 ///
@@ -105,54 +499,318 @@ impl Flags7 {
 		Flags7 {
 			vs_unisystem: data & 0b1 == 0b1,
 			playchoice_10: data & 0b10 == 0b10,
-			ines_2: (data >> 2) as u8 & 0b11 == 2,
+			ines_2: (data >> 2) & 0b11 == 2,
 			mapper_upper: data >> 4
 		}
 	}
 }
 
+/// Size of the trainer stored between the header and PRG ROM, and where it's loaded
+pub const TRAINER_SIZE: usize = 512;
+pub const TRAINER_ADDRESS: u16 = 0x7000;
+
+/// Size of the PlayChoice-10 hint screen data stored after CHR ROM
+pub const PLAYCHOICE_INST_ROM_SIZE: usize = 8 * 1024;
+
+/// Size of the PlayChoice-10 PROM after the hint screen data, 16 bytes of data then 16 bytes of
+/// CounterOut
+pub const PLAYCHOICE_PROM_SIZE: usize = 32;
+
 /// Header and PGR/CHR data
 #[derive(Debug)]
 pub struct Rom {
 	pub header: Header,
-	pub prg: Box<Vec<u8>>,
-	pub chr: Box<Vec<u8>>
+	/// Code that a copier loads into 0x7000-0x71FF before the game starts, usually to patch it
+	/// for a different mapper
+	pub trainer: Option<Vec<u8>>,
+	pub prg: Vec<u8>,
+	pub chr: Vec<u8>,
+	/// PlayChoice-10 hint screen data (INST-ROM), when the header has the flag and the file
+	/// actually has the data
+	pub playchoice_inst_rom: Option<Vec<u8>>,
+	/// PlayChoice-10 PROM, when it follows the hint screen data
+	pub playchoice_prom: Option<Vec<u8>>,
+	/// Title from the database, for known games
+	pub title: Option<String>,
+	/// Header fields that the database corrected
+	pub corrections: Vec<Correction>
 }
 
 impl Rom {
+	/// Open an iNES or UNIF file, correcting its header if the game is in the embedded database
 	pub fn open<P: AsRef<Path>>(path: P) -> Result<Rom> {
-		let mut file = try!(File::open(path));
+		let mut rom = Rom::open_uncorrected(path)?;
+		rom.correct_header(&Database::embedded());
+		Ok(rom)
+	}
+
+	/// Open an iNES or UNIF file, trusting its header
+	pub fn open_uncorrected<P: AsRef<Path>>(path: P) -> Result<Rom> {
+		let mut data = Vec::<u8>::new();
+		File::open(path)?.read_to_end(&mut data)?;
+		Rom::parse(&data)
+	}
+
+	/// Look the game up by its PRG and CHR ROM, taking its title and correcting the header
+	pub fn correct_header(&mut self, database: &Database) {
+		if let Some(game) = database.find(&self.prg, &self.chr) {
+			self.title = Some(game.title.clone()).filter(|title| !title.is_empty());
+			self.corrections = game.correct(&mut self.header);
+		}
+	}
+
+	/// Split the contents of an iNES or UNIF file into its parts, telling them apart by their
+	/// identifier
+	pub fn parse(data: &[u8]) -> Result<Rom> {
+		if data.starts_with(&unif::IDENTIFIER) {
+			return unif::parse(data);
+		}
 
 		// Load header data
-		let mut header_data: [u8; 16] = [0; 16];
-		match file.read_exact(&mut header_data) {
-			Err(_) => return Err(Error::new(ErrorKind::Other, "Failed to read header!")),
-			_ => ()
+		if data.len() < 16 {
+			return Err(RomError::TruncatedHeader.into());
 		}
-		let header = try!(Header::new(&header_data));
+		let (header_data, data) = data.split_at(16);
+		let mut header_bytes: [u8; 16] = [0; 16];
+		header_bytes.copy_from_slice(header_data);
+		let header = Header::new(&header_bytes)?;
 
-		// Load all file data after header
-		let mut data = Vec::<u8>::new();
-		try!(file.read_to_end(&mut data));
+		// Load the trainer, which comes before PRG data
+		let (trainer, data) = if header.flags6.trainer {
+			if TRAINER_SIZE > data.len() {
+				let found = data.len();
+				return Err(RomError::TruncatedTrainer { expected: TRAINER_SIZE, found }.into());
+			}
+			let (trainer, data) = data.split_at(TRAINER_SIZE);
+			(Some(trainer.to_vec()), data)
+		} else {
+			(None, data)
+		};
 
 		// Load PRG data
-		let prg_size = header.prg_rom_size as usize * PRG_ROM_UNIT_SIZE;
+		let prg_size = header.prg_rom_size;
 		if  prg_size > data.len() {
-			return Err(Error::new(ErrorKind::Other, "PRG ROM not found or incomplete!"));
+			return Err(RomError::TruncatedPrg { expected: prg_size, found: data.len() }.into());
 		}
 		let (prg, data) = data.split_at(prg_size);
 
 		// Load CHR data
-		let chr_size = header.chr_rom_size as usize * CHR_ROM_UNIT_SIZE;
+		let chr_size = header.chr_rom_size;
 		if  chr_size > data.len() {
-			return Err(Error::new(ErrorKind::Other, "CHR ROM not found or incomplete!"));
+			return Err(RomError::TruncatedChr { expected: chr_size, found: data.len() }.into());
+		}
+		let (chr, data) = data.split_at(chr_size);
+
+		// Load PlayChoice-10 data. Plenty of dumps set the flag without including it, so it's
+		// only taken when it's all there.
+		let (mut playchoice_inst_rom, mut playchoice_prom) = (None, None);
+		let playchoice = header.console_type == ConsoleType::PlayChoice10;
+		if playchoice && data.len() >= PLAYCHOICE_INST_ROM_SIZE {
+			let (inst_rom, data) = data.split_at(PLAYCHOICE_INST_ROM_SIZE);
+			playchoice_inst_rom = Some(inst_rom.to_vec());
+			if data.len() >= PLAYCHOICE_PROM_SIZE {
+				playchoice_prom = Some(data[..PLAYCHOICE_PROM_SIZE].to_vec());
+			}
 		}
-		let (chr, _) = data.split_at(chr_size);
 
 		Ok(Rom {
-			header: header,
-			prg: Box::new(prg.to_vec()),
-			chr: Box::new(chr.to_vec())
+			header,
+			trainer,
+			prg: prg.to_vec(),
+			chr: chr.to_vec(),
+			playchoice_inst_rom,
+			playchoice_prom,
+			title: None,
+			corrections: Vec::new()
 		})
 	}
 }
+
+#[test]
+fn test_invalid_identifier() {
+	let mut data = [0; 16];
+	data[0..4].copy_from_slice(b"UNIF");
+	match Header::new(&data) {
+		Err(EmuError::Rom(RomError::InvalidIdentifier)) => (),
+		other => panic!("Expected an invalid identifier, got {:?}", other)
+	}
+}
+
+// iNES header with the given bytes 6-15
+#[cfg(test)]
+fn ines_header(rest: &[u8; 10]) -> Header {
+	let mut data = [0; 16];
+	data[0..4].copy_from_slice(&IDENTIFIER);
+	data[4..6].copy_from_slice(&[2, 1]);
+	data[6..16].copy_from_slice(rest);
+	Header::new(&data).unwrap()
+}
+
+#[test]
+fn test_mapper_number() {
+	let header = ines_header(&[0x41, 0x10, 0, 0, 0, 0, 0, 0, 0, 0]);
+	assert_eq!(header.mapper_number, 0x14);
+	assert_eq!(header.mirroring(), Mirroring::Vertical);
+	assert!(header.warnings.is_empty());
+
+	// NES 2.0 adds 4 more bits
+	let header = nes_2_header(2, 1, 0x40, 0x10, [0x52, 0, 0, 0, 0, 0, 0, 0]);
+	assert_eq!(header.mapper_number, 0x214);
+	assert_eq!(header.submapper, 5);
+}
+
+#[test]
+fn test_diskdude() {
+	let mut rest = [0; 10];
+	rest[0] = 0x14;
+	rest[1..10].copy_from_slice(b"DiskDude!");
+	let header = ines_header(&rest);
+	assert_eq!(header.warnings, vec![HeaderWarning::DiskDude]);
+	// Only flags 6 is used
+	assert_eq!(header.mapper_number, 0x01);
+	assert_eq!(header.prg_ram_size, PRG_RAM_UNIT_SIZE);
+	assert!(!header.flags7.vs_unisystem);
+	assert!(header.flags6.trainer);
+
+	let header = ines_header(&[0x10, 0x04, 0x03, 0, 0, 0, 0, 0, 0, 0]);
+	assert_eq!(header.warnings, vec![HeaderWarning::ArchaicFormat]);
+	assert_eq!(header.prg_ram_size, PRG_RAM_UNIT_SIZE);
+}
+
+#[test]
+fn test_dirty_header() {
+	let header = ines_header(&[0x10, 0x21, 0x02, 0, 0, 0, 0, 0, b'a', b'b']);
+	assert_eq!(header.warnings, vec![HeaderWarning::DirtyHeader]);
+	assert_eq!(header.mapper_number, 0x01);
+	assert_eq!(header.prg_ram_size, PRG_RAM_UNIT_SIZE);
+	assert!(header.flags7.vs_unisystem);
+
+	// Bits 2-3 of flags 7 set to 3 aren't a format either
+	let header = ines_header(&[0x10, 0x2C, 0, 0, 0, 0, 0, 0, 0, 0]);
+	assert_eq!(header.warnings, vec![HeaderWarning::DirtyHeader]);
+	assert_eq!(header.mapper_number, 0x01);
+
+	// NES 2.0 uses bytes 12-15
+	let header = nes_2_header(2, 1, 0x10, 0x20, [0, 0, 0, 0, 0x01, 0, 0x01, 0x01]);
+	assert!(header.warnings.is_empty());
+	assert_eq!(header.mapper_number, 0x21);
+}
+
+// NES 2.0 header with bytes 8-15 as given
+#[cfg(test)]
+fn nes_2_header(prg_units: u8, chr_units: u8, flags6: u8, flags7: u8, rest: [u8; 8]) -> Header {
+	let mut data = [0; 16];
+	data[0..4].copy_from_slice(&IDENTIFIER);
+	data[4..8].copy_from_slice(&[prg_units, chr_units, flags6, flags7 | 0b1000]);
+	data[8..16].copy_from_slice(&rest);
+	Header::new(&data).unwrap()
+}
+
+#[test]
+fn test_ines_defaults() {
+	let mut data = [0; 16];
+	data[0..4].copy_from_slice(&IDENTIFIER);
+	data[4..8].copy_from_slice(&[2, 0, 0b10, 0b01]);
+	let header = Header::new(&data).unwrap();
+	assert_eq!(header.prg_rom_size, 2 * PRG_ROM_UNIT_SIZE);
+	assert_eq!((header.prg_ram_size, header.prg_nvram_size), (0, 0x2000));
+	assert_eq!((header.chr_rom_size, header.chr_ram_size), (0, 0x2000));
+	assert_eq!(header.timing, Timing::Ntsc);
+	assert_eq!(header.console_type, ConsoleType::VsSystem);
+	assert_eq!(header.vs_system, None);
+}
+
+#[test]
+fn test_nes_2() {
+	let header = nes_2_header(0x02, 0x01, 0b10, 0x01,
+		[0x31, 0x11, 0x97, 0x07, 0x01, 0x53, 0x01, 0x08]);
+	assert_eq!(header.mapper_number, 0x100);
+	assert_eq!(header.submapper, 3);
+	assert_eq!(header.prg_rom_size, 0x102 * PRG_ROM_UNIT_SIZE);
+	assert_eq!(header.chr_rom_size, 0x101 * CHR_ROM_UNIT_SIZE);
+	assert_eq!((header.prg_ram_size, header.prg_nvram_size), (0x2000, 0x8000));
+	assert_eq!((header.chr_ram_size, header.chr_nvram_size), (0x2000, 0));
+	assert_eq!(header.prg_ram_total(), 0xA000);
+	assert_eq!(header.timing, Timing::Pal);
+	assert_eq!(header.console_type, ConsoleType::VsSystem);
+	let vs_system = VsSystem { ppu: VsPpu::Rp2c04_0002, hardware: VsHardware::DualSystem };
+	assert_eq!(header.vs_system, Some(vs_system));
+	assert_eq!(header.misc_roms, 1);
+	assert_eq!(header.expansion_device, ExpansionDevice::Zapper);
+
+	let header = nes_2_header(0, 0, 0, 0x03, [0, 0, 0, 0, 0x03, 0x0A, 0, 0x2A]);
+	assert_eq!(header.timing, Timing::Dendy);
+	assert_eq!(header.console_type, ConsoleType::Vt369);
+	assert_eq!(header.vs_system, None);
+	assert_eq!(header.expansion_device, ExpansionDevice::Other(0x2A));
+}
+
+#[test]
+fn test_nes_2_exponent_sizes() {
+	// 2^20 * 3 bytes of PRG ROM, 2^7 * 1 bytes of CHR ROM
+	let header = nes_2_header(20 << 2 | 1, 7 << 2, 0, 0, [0, 0xFF, 0, 0, 0, 0, 0, 0]);
+	assert_eq!(header.prg_rom_size, 3 << 20);
+	assert_eq!(header.chr_rom_size, 128);
+	let header = nes_2_header(0xFF, 0, 0, 0, [0, 0x0F, 0, 0, 0, 0, 0, 0]);
+	assert_eq!(header.prg_rom_size, usize::MAX);
+}
+
+// iNES file with the given header flags, where each byte of PRG and CHR data is its unit number
+// and the trainer and anything after CHR is 0xEE
+#[cfg(test)]
+fn test_file(prg_units: u8, chr_units: u8, flags6: u8, flags7: u8, extra: usize) -> Vec<u8> {
+	let mut data = IDENTIFIER.to_vec();
+	data.extend_from_slice(&[prg_units, chr_units, flags6, flags7, 0, 0, 0, 0, 0, 0, 0, 0]);
+	if flags6 & 0b100 != 0 {
+		data.extend(vec![0xEE; TRAINER_SIZE]);
+	}
+	for unit in 0..prg_units {
+		data.extend(vec![unit; PRG_ROM_UNIT_SIZE]);
+	}
+	for unit in 0..chr_units {
+		data.extend(vec![unit; CHR_ROM_UNIT_SIZE]);
+	}
+	data.extend(vec![0xEE; extra]);
+	data
+}
+
+#[test]
+fn test_parse() {
+	let rom = Rom::parse(&test_file(2, 1, 0, 0, 0)).unwrap();
+	assert_eq!(rom.prg.len(), 2 * PRG_ROM_UNIT_SIZE);
+	assert_eq!(rom.prg[PRG_ROM_UNIT_SIZE], 1);
+	assert_eq!(rom.chr.len(), CHR_ROM_UNIT_SIZE);
+	assert!(rom.trainer.is_none());
+
+	match Rom::parse(&test_file(2, 1, 0, 0, 0)[..0x8000]) {
+		Err(EmuError::Rom(RomError::TruncatedPrg { expected: 0x8000, found: 0x7FF0 })) => (),
+		other => panic!("Expected truncated PRG, got {:?}", other)
+	}
+}
+
+#[test]
+fn test_trainer() {
+	let rom = Rom::parse(&test_file(2, 1, 0b100, 0, 0)).unwrap();
+	assert_eq!(rom.trainer, Some(vec![0xEE; TRAINER_SIZE]));
+	// PRG starts after the trainer rather than with it
+	assert_eq!(rom.prg[0], 0);
+	assert_eq!(rom.prg[PRG_ROM_UNIT_SIZE], 1);
+	assert_eq!(rom.chr, vec![0; CHR_ROM_UNIT_SIZE]);
+
+	match Rom::parse(&test_file(0, 0, 0b100, 0, 0)[..0x100]) {
+		Err(EmuError::Rom(RomError::TruncatedTrainer { expected: 512, found: 0xF0 })) => (),
+		other => panic!("Expected a truncated trainer, got {:?}", other)
+	}
+}
+
+#[test]
+fn test_playchoice_10() {
+	let rom = Rom::parse(&test_file(2, 1, 0, 0b10, PLAYCHOICE_INST_ROM_SIZE + 32)).unwrap();
+	assert_eq!(rom.playchoice_inst_rom, Some(vec![0xEE; PLAYCHOICE_INST_ROM_SIZE]));
+	assert_eq!(rom.playchoice_prom, Some(vec![0xEE; PLAYCHOICE_PROM_SIZE]));
+
+	// The flag without the data
+	let rom = Rom::parse(&test_file(2, 1, 0, 0b10, 0)).unwrap();
+	assert!(rom.playchoice_inst_rom.is_none());
+	assert!(rom.playchoice_prom.is_none());
+}
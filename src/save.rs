@@ -0,0 +1,112 @@
+use error::Result;
+use mapper::Mapper;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{ Path, PathBuf };
+#[cfg(test)]
+use mapper::{ test_header, NRom };
+#[cfg(test)]
+use rom::PRG_ROM_UNIT_SIZE;
+#[cfg(test)]
+use std::env;
+#[cfg(test)]
+use std::process;
+
+/// CPU cycles between flushes of the save file, about a second of NTSC emulation
+pub const FLUSH_INTERVAL: u64 = 1_789_773;
+
+/// Battery backed PRG RAM, kept in a .sav file so that it outlives the session. Flushing every so
+/// often as well as on exit means a crash loses at most the last interval of progress.
+pub struct SaveFile {
+	path: PathBuf,
+	saved: Vec<u8> // PRG RAM as of the last load or flush, to skip writes when nothing changed
+}
+
+impl SaveFile {
+	pub fn new<P: Into<PathBuf>>(path: P) -> SaveFile {
+		SaveFile {
+			path: path.into(),
+			saved: Vec::new()
+		}
+	}
+
+	/// Save file next to a ROM, with the same name and a .sav extension
+	pub fn for_rom<P: AsRef<Path>>(rom_path: P) -> SaveFile {
+		SaveFile::new(rom_path.as_ref().with_extension("sav"))
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// Copy the file into the mapper's PRG RAM. A missing file is a new game and leaves the RAM
+	/// as it is.
+	pub fn load(&mut self, mapper: &mut dyn Mapper) -> Result<()> {
+		let data = match fs::read(&self.path) {
+			Ok(data) => data,
+			Err(ref e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+			Err(e) => return Err(e.into())
+		};
+		if let Some(ram) = mapper.prg_ram_mut() {
+			// Files from other emulators may be padded or cut short, so copy whatever fits
+			let length = ram.len().min(data.len());
+			ram[..length].copy_from_slice(&data[..length]);
+			self.saved = ram.to_vec();
+		}
+		Ok(())
+	}
+
+	/// Write the mapper's PRG RAM to the file if it changed. The new contents are written beside
+	/// the file then renamed over it, so a crash part way through leaves the previous save.
+	pub fn flush(&mut self, mapper: &dyn Mapper) -> Result<()> {
+		let ram = match mapper.prg_ram() {
+			Some(ram) if ram != &self.saved[..] => ram,
+			_ => return Ok(())
+		};
+		let temp = self.path.with_extension("sav.tmp");
+		fs::write(&temp, ram)?;
+		fs::rename(&temp, &self.path)?;
+		self.saved = ram.to_vec();
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+fn test_nrom() -> NRom {
+	NRom::new(test_header(1, 1, 0b10), vec![0; PRG_ROM_UNIT_SIZE], Vec::new())
+}
+
+#[test]
+fn test_save_and_load() {
+	let path = env::temp_dir().join(format!("jane-{}-save-and-load.sav", process::id()));
+	let _ = fs::remove_file(&path);
+
+	// Nothing is written until the RAM changes
+	let mut nrom = test_nrom();
+	let mut save = SaveFile::new(&path);
+	save.load(&mut nrom).unwrap();
+	save.flush(&nrom).unwrap();
+	assert!(!path.exists());
+
+	nrom.cpu_write(0x6000, 0x12).unwrap();
+	nrom.cpu_write(0x7FFF, 0x34).unwrap();
+	save.flush(&nrom).unwrap();
+	assert_eq!(fs::read(&path).unwrap().len(), 0x2000);
+
+	let mut nrom = test_nrom();
+	SaveFile::new(&path).load(&mut nrom).unwrap();
+	assert_eq!(nrom.cpu_read(0x6000).unwrap(), 0x12);
+	assert_eq!(nrom.cpu_read(0x7FFF).unwrap(), 0x34);
+	fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_short_save_file() {
+	let path = env::temp_dir().join(format!("jane-{}-short-save-file.sav", process::id()));
+	fs::write(&path, [0x56, 0x78]).unwrap();
+	let mut nrom = test_nrom();
+	SaveFile::new(&path).load(&mut nrom).unwrap();
+	assert_eq!(nrom.cpu_read(0x6001).unwrap(), 0x78);
+	assert_eq!(nrom.cpu_read(0x6002).unwrap(), 0x00);
+	fs::remove_file(&path).unwrap();
+}
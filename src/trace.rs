@@ -0,0 +1,255 @@
+use cpu::CPU;
+use opcode::Mode;
+use std::io::{ Result, Write };
+
+/// Dots per scanline and scanlines per frame of the NTSC PPU
+const DOTS_PER_SCANLINE: u64 = 341;
+const SCANLINES_PER_FRAME: u64 = 262;
+
+/// Writes one line per instruction, before it executes, in the format of nestest.log
+///     http://www.qmtpro.com/~nes/misc/nestest.log
+///
+/// Example:
+///     C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+pub struct Tracer {
+	output: Box<dyn Write>
+}
+
+impl Tracer {
+	pub fn new(output: Box<dyn Write>) -> Tracer {
+		Tracer {
+			output
+		}
+	}
+
+	pub fn trace(&mut self, cpu: &CPU) -> Result<()> {
+		writeln!(self.output, "{}", format_line(cpu))
+	}
+}
+
+/// Format the instruction at PC along with the current CPU state
+pub fn format_line(cpu: &CPU) -> String {
+	let registers = cpu.registers();
+	let pc = registers.pc;
+	let opcode = &cpu.variant().opcodes()[cpu.peek(pc) as usize];
+
+	let bytes: Vec<String> = (0..opcode.length() as u16)
+		.map(|i| format!("{:02X}", cpu.peek(pc.wrapping_add(i))))
+		.collect();
+	let operand = format_operand(cpu, opcode.mnemonic, opcode.mode);
+	let disassembly = if operand.is_empty() {
+		opcode.mnemonic.to_string()
+	} else {
+		format!("{} {}", opcode.mnemonic, operand)
+	};
+
+	// The PPU runs 3 dots for every CPU cycle
+	let dots = cpu.cycles() * 3;
+	let dot = dots % DOTS_PER_SCANLINE;
+	let scanline = (dots / DOTS_PER_SCANLINE) % SCANLINES_PER_FRAME;
+
+	// nestest.log marks unofficial opcodes with a '*' in place of the space before the mnemonic
+	let marker = if opcode.official { ' ' } else { '*' };
+
+	format!("{:04X}  {:<8} {}{:<32}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
+		pc, bytes.join(" "), marker, disassembly, registers.a, registers.x, registers.y, registers.status,
+		registers.s, scanline, dot, cpu.cycles())
+}
+
+// Format the operand of the instruction at PC, resolving the effective address and the value
+// currently stored there
+fn format_operand(cpu: &CPU, mnemonic: &str, mode: Mode) -> String {
+	let registers = cpu.registers();
+	let pc = registers.pc;
+	let low = cpu.peek(pc.wrapping_add(1));
+	let word = low as u16 | (cpu.peek(pc.wrapping_add(2)) as u16) << 8;
+	let peekw_zero_page = |address: u8| {
+		cpu.peek(address as u16) as u16 | (cpu.peek(address.wrapping_add(1) as u16) as u16) << 8
+	};
+
+	match mode {
+		Mode::Implied => String::new(),
+		Mode::Accumulator => "A".to_string(),
+		Mode::Immediate => format!("#${:02X}", low),
+		Mode::ZeroPage => format!("${:02X} = {:02X}", low, cpu.peek(low as u16)),
+		Mode::ZeroPageX => {
+			let address = low.wrapping_add(registers.x);
+			format!("${:02X},X @ {:02X} = {:02X}", low, address, cpu.peek(address as u16))
+		},
+		Mode::ZeroPageY => {
+			let address = low.wrapping_add(registers.y);
+			format!("${:02X},Y @ {:02X} = {:02X}", low, address, cpu.peek(address as u16))
+		},
+		Mode::Absolute => {
+			if mnemonic == "JMP" || mnemonic == "JSR" {
+				format!("${:04X}", word)
+			} else {
+				format!("${:04X} = {:02X}", word, cpu.peek(word))
+			}
+		},
+		Mode::AbsoluteX => {
+			let address = word.wrapping_add(registers.x as u16);
+			format!("${:04X},X @ {:04X} = {:02X}", word, address, cpu.peek(address))
+		},
+		Mode::AbsoluteY => {
+			let address = word.wrapping_add(registers.y as u16);
+			format!("${:04X},Y @ {:04X} = {:02X}", word, address, cpu.peek(address))
+		},
+		Mode::Indirect => {
+			// Same page wrapping bug as the CPU, on the chips that have it
+			let high_address = if cpu.variant().has_jmp_indirect_bug() {
+				(word & 0xFF00) | (word as u8).wrapping_add(1) as u16
+			} else {
+				word.wrapping_add(1)
+			};
+			let target = cpu.peek(word) as u16 | (cpu.peek(high_address) as u16) << 8;
+			format!("(${:04X}) = {:04X}", word, target)
+		},
+		Mode::IndirectX => {
+			let pointer = low.wrapping_add(registers.x);
+			let address = peekw_zero_page(pointer);
+			format!("(${:02X},X) @ {:02X} = {:04X} = {:02X}", low, pointer, address, cpu.peek(address))
+		},
+		Mode::IndirectY => {
+			let base = peekw_zero_page(low);
+			let address = base.wrapping_add(registers.y as u16);
+			format!("(${:02X}),Y = {:04X} @ {:04X} = {:02X}", low, base, address, cpu.peek(address))
+		},
+		Mode::Relative => {
+			let target = pc.wrapping_add(2).wrapping_add(low as i8 as u16);
+			format!("${:04X}", target)
+		},
+		Mode::ZeroPageIndirect => {
+			let address = peekw_zero_page(low);
+			format!("(${:02X}) = {:04X} = {:02X}", low, address, cpu.peek(address))
+		},
+		Mode::AbsoluteIndexedIndirect => {
+			let pointer = word.wrapping_add(registers.x as u16);
+			let target = cpu.peek(pointer) as u16 | (cpu.peek(pointer.wrapping_add(1)) as u16) << 8;
+			format!("(${:04X},X) @ {:04X} = {:04X}", word, pointer, target)
+		}
+	}
+}
+
+#[cfg(test)]
+use cpu::{ CpuVariant, TestCartridge };
+#[cfg(test)]
+use error::EmuError;
+#[cfg(test)]
+use memory::Memory;
+#[cfg(test)]
+use std::io;
+
+// Output that fails every write, like a full disk
+#[cfg(test)]
+struct FailingOutput;
+
+#[cfg(test)]
+impl Write for FailingOutput {
+	fn write(&mut self, _buf: &[u8]) -> Result<usize> {
+		Err(io::Error::other("disk full"))
+	}
+
+	fn flush(&mut self) -> Result<()> {
+		Ok(())
+	}
+}
+
+#[test]
+fn test_nestest_lines() {
+	// The first few instructions of nestest's automated mode
+	let mut data = vec![0; 0x10000];
+	let code: [(u16, &[u8]); 7] = [
+		(0xC000, &[0x4C, 0xF5, 0xC5]),
+		(0xC5F5, &[0xA2, 0x00]),
+		(0xC5F7, &[0x86, 0x00]),
+		(0xC5F9, &[0x86, 0x10]),
+		(0xC5FB, &[0x86, 0x11]),
+		(0xC5FD, &[0x20, 0x2D, 0xC7]),
+		(0xC72D, &[0xEA])
+	];
+	for &(address, bytes) in code.iter() {
+		for (i, &byte) in bytes.iter().enumerate() {
+			data[address as usize + i] = byte;
+		}
+	}
+
+	let mut cpu = CPU::new(TestCartridge::new(data), CpuVariant::Ricoh2A03);
+	cpu.power_up_with_pc_override(0xC000);
+
+	let expected = [
+		"C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7",
+		"C5F5  A2 00     LDX #$00                        A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 30 CYC:10",
+		"C5F7  86 00     STX $00 = 00                    A:00 X:00 Y:00 P:26 SP:FD PPU:  0, 36 CYC:12",
+		"C5F9  86 10     STX $10 = 00                    A:00 X:00 Y:00 P:26 SP:FD PPU:  0, 45 CYC:15",
+		"C5FB  86 11     STX $11 = 00                    A:00 X:00 Y:00 P:26 SP:FD PPU:  0, 54 CYC:18",
+		"C5FD  20 2D C7  JSR $C72D                       A:00 X:00 Y:00 P:26 SP:FD PPU:  0, 63 CYC:21",
+		"C72D  EA        NOP                             A:00 X:00 Y:00 P:26 SP:FB PPU:  0, 81 CYC:27"
+	];
+	for line in expected.iter() {
+		assert_eq!(format_line(&cpu), *line);
+		cpu.step().unwrap();
+	}
+}
+
+#[test]
+fn test_indexed_operands() {
+	// LDA ($80,X), LDA ($89),Y, LDA $0300,X, JMP ($02FF)
+	let mut data = vec![0; 0x10000];
+	for (i, &byte) in [0xA1, 0x80, 0xB1, 0x89, 0xBD, 0x00, 0x03, 0x6C, 0xFF, 0x02].iter().enumerate() {
+		data[0x8000 + i] = byte;
+	}
+
+	let mut cpu = CPU::new(TestCartridge::new(data), CpuVariant::Ricoh2A03);
+	// LDX #$02, LDY #$34 run from RAM to set up the index registers
+	for (i, &byte) in [0xA2, 0x02, 0xA0, 0x34, 0x4C, 0x00, 0x80].iter().enumerate() {
+		cpu.store(0x0400 + i as u16, byte).unwrap();
+	}
+	cpu.storew(0x82, 0x0200).unwrap();
+	cpu.storew(0x89, 0x0300).unwrap();
+	cpu.store(0x0200, 0x5A).unwrap();
+	cpu.store(0x0334, 0x89).unwrap();
+	cpu.store(0x0302, 0x11).unwrap();
+	cpu.store(0x02FF, 0x7E).unwrap();
+	cpu.power_up_with_pc_override(0x0400);
+	cpu.run_for_cycles(7).unwrap();
+
+	assert!(format_line(&cpu).starts_with("8000  A1 80     LDA ($80,X) @ 82 = 0200 = 5A    A:00"));
+	cpu.step().unwrap();
+	assert!(format_line(&cpu).starts_with("8002  B1 89     LDA ($89),Y = 0300 @ 0334 = 89  A:5A"));
+	cpu.step().unwrap();
+	assert!(format_line(&cpu).starts_with("8004  BD 00 03  LDA $0300,X @ 0302 = 11         A:89"));
+	cpu.step().unwrap();
+	assert!(format_line(&cpu).starts_with("8007  6C FF 02  JMP ($02FF) = 5A7E              A:11"));
+}
+
+#[test]
+fn test_unofficial_marker() {
+	// Lines taken from nestest.log
+	let mut data = vec![0; 0x10000];
+	for (i, &byte) in [0x04, 0xA9, 0xA7, 0x4D].iter().enumerate() {
+		data[0xC000 + i] = byte;
+	}
+
+	let mut cpu = CPU::new(TestCartridge::new(data), CpuVariant::Ricoh2A03);
+	cpu.store(0xA9, 0x55).unwrap();
+	cpu.store(0x4D, 0x55).unwrap();
+	cpu.power_up_with_pc_override(0xC000);
+
+	assert!(format_line(&cpu).starts_with("C000  04 A9    *NOP $A9 = 55                    A:00"));
+	cpu.step().unwrap();
+	assert!(format_line(&cpu).starts_with("C002  A7 4D    *LAX $4D = 55                    A:00"));
+	cpu.step().unwrap();
+	assert_eq!(cpu.registers().x, 0x55);
+}
+
+#[test]
+fn test_trace_error() {
+	let mut cpu = CPU::new(TestCartridge::new(vec![0xEA; 0x10000]), CpuVariant::Ricoh2A03);
+	cpu.power_up_with_pc_override(0xC000);
+	cpu.set_tracer(Some(Tracer::new(Box::new(FailingOutput))));
+	match cpu.step() {
+		Err(EmuError::Io(ref e)) if e.to_string() == "disk full" => (),
+		other => panic!("Expected an I/O error, got {:?}", other.map(|_| ()))
+	}
+}
@@ -0,0 +1,221 @@
+use error::{ RomError, Result };
+use rom::{ self, Header, Rom, Timing };
+#[cfg(test)]
+use error::EmuError;
+#[cfg(test)]
+use mapper::Mirroring;
+#[cfg(test)]
+use rom::CHR_ROM_UNIT_SIZE;
+
+/// Identifier at the start of a UNIF file
+pub const IDENTIFIER: [u8; 4] = *b"UNIF";
+
+/// Size of the UNIF header: identifier, revision and padding
+const HEADER_SIZE: usize = 32;
+
+/// Mapper and submapper numbers for UNIF board names, without their "NES-", "HVC-" or "UNL-"
+/// style prefix. Discrete boards with bus conflicts get the submapper that turns them on.
+static BOARDS: &[(&str, u16, u8)] = &[
+	("NROM", 0, 0), ("NROM-128", 0, 0), ("NROM-256", 0, 0), ("RROM", 0, 0), ("RROM-128", 0, 0),
+	("SAROM", 1, 0), ("SBROM", 1, 0), ("SCROM", 1, 0), ("SEROM", 1, 0), ("SFROM", 1, 0),
+	("SGROM", 1, 0), ("SHROM", 1, 0), ("SJROM", 1, 0), ("SKROM", 1, 0), ("SLROM", 1, 0),
+	("SL1ROM", 1, 0), ("SNROM", 1, 0), ("SOROM", 1, 0), ("SUROM", 1, 0), ("SXROM", 1, 0),
+	("UNROM", 2, 2), ("UOROM", 2, 2),
+	("CNROM", 3, 2),
+	("TBROM", 4, 0), ("TEROM", 4, 0), ("TFROM", 4, 0), ("TGROM", 4, 0), ("TKROM", 4, 0),
+	("TLROM", 4, 0), ("TL1ROM", 4, 0), ("TNROM", 4, 0), ("TR1ROM", 4, 0), ("TSROM", 4, 0),
+	("TVROM", 4, 0), ("HKROM", 4, 1),
+	("EKROM", 5, 0), ("ELROM", 5, 0), ("ETROM", 5, 0), ("EWROM", 5, 0),
+	("AMROM", 7, 2), ("ANROM", 7, 1), ("AN1ROM", 7, 1), ("AOROM", 7, 1),
+	("PEEOROM", 9, 0), ("PNROM", 9, 0),
+	("FJROM", 10, 0), ("FKROM", 10, 0),
+	("GNROM", 66, 0), ("MHROM", 66, 0),
+	("BTR", 69, 0), ("JLROM", 69, 0), ("JSROM", 69, 0)
+];
+
+/// Mapper and submapper for a UNIF board name
+pub fn board(name: &str) -> Option<(u16, u8)> {
+	// Drop the maker prefix, e.g. NES-, HVC-, UNL- or BMC-
+	let name = name.split_once('-')
+		.filter(|(prefix, _)| prefix.len() == 3 && prefix.chars().all(|c| c.is_ascii_uppercase()))
+		.map_or(name, |(_, board)| board);
+	BOARDS.iter().find(|b| b.0 == name).map(|b| (b.1, b.2))
+}
+
+/// Read a UNIF file. It's a 32 byte header followed by chunks, each a 4 byte ID, a 32 bit little
+/// endian length and then the data:
+///     MAPR:         Board name, null terminated
+///     PRG0 -> PRGF: PRG ROM, put together in order
+///     CHR0 -> CHRF: CHR ROM, put together in order
+///     MIRR:         Mirroring, 0 horizontal, 1 vertical, 4 four screen, others set by the mapper
+///     BATR:         Battery backed PRG RAM, present or not
+///     TVCI:         0 NTSC, 1 PAL, 2 either
+/// Other chunks are ignored. The header is made up to match, as if it were an iNES file.
+///     http://wiki.nesdev.com/w/index.php/UNIF
+pub fn parse(data: &[u8]) -> Result<Rom> {
+	if data.len() < HEADER_SIZE {
+		return Err(RomError::TruncatedHeader.into());
+	}
+	if data[0..4] != IDENTIFIER {
+		return Err(RomError::InvalidIdentifier.into());
+	}
+
+	let mut board_name = None;
+	let mut prg: [Option<&[u8]>; 16] = [None; 16];
+	let mut chr: [Option<&[u8]>; 16] = [None; 16];
+	let (mut mirroring, mut battery, mut timing) = (None, false, Timing::Ntsc);
+	let mut data = &data[HEADER_SIZE..];
+	while !data.is_empty() {
+		if data.len() < 8 {
+			return Err(RomError::TruncatedChunk { id: chunk_name(data) }.into());
+		}
+		let (id, length) = (&data[0..4], u32::from_le_bytes([data[4], data[5], data[6], data[7]]));
+		let chunk = match data[8..].get(..length as usize) {
+			Some(chunk) => chunk,
+			None => return Err(RomError::TruncatedChunk { id: chunk_name(id) }.into())
+		};
+		data = &data[8 + chunk.len()..];
+
+		// The last character of PRGn and CHRn is the chunk number in hex. Chunks with anything else
+		// there are ignored.
+		let number = (id[3] as char).to_digit(16).map(|n| n as usize);
+		match &id[0..3] {
+			b"MAP" if id[3] == b'R' => {
+				let end = chunk.iter().position(|&b| b == 0).unwrap_or(chunk.len());
+				board_name = Some(String::from_utf8_lossy(&chunk[..end]).into_owned());
+			},
+			b"PRG" => if let Some(n) = number { prg[n] = Some(chunk) },
+			b"CHR" => if let Some(n) = number { chr[n] = Some(chunk) },
+			b"MIR" if id[3] == b'R' => mirroring = chunk.first().cloned(),
+			b"BAT" if id[3] == b'R' => battery = true,
+			b"TVC" if id[3] == b'I' => {
+				timing = match chunk.first() {
+					Some(1) => Timing::Pal,
+					Some(2) => Timing::MultipleRegion,
+					_ => Timing::Ntsc
+				};
+			},
+			_ => ()
+		}
+	}
+
+	let board_name = board_name.ok_or(RomError::MissingBoard)?;
+	let (mapper, submapper) = match board(&board_name) {
+		Some(board) => board,
+		None => return Err(RomError::UnsupportedBoard { name: board_name }.into())
+	};
+	let prg: Vec<u8> = prg.iter().flatten().flat_map(|chunk| chunk.iter().cloned()).collect();
+	let chr: Vec<u8> = chr.iter().flatten().flat_map(|chunk| chunk.iter().cloned()).collect();
+
+	// Make up the iNES header, then fill in what it can't hold
+	let mut flags6 = (mapper as u8 & 0x0F) << 4;
+	match mirroring {
+		Some(1) => flags6 |= 0b0001,
+		Some(4) => flags6 |= 0b1000,
+		_ => ()
+	}
+	if battery {
+		flags6 |= 0b0010;
+	}
+	let mut header_data = [0; 16];
+	header_data[0..4].copy_from_slice(&rom::IDENTIFIER);
+	header_data[5] = if chr.is_empty() { 0 } else { 1 };
+	header_data[6] = flags6;
+	header_data[7] = mapper as u8 & 0xF0;
+	let mut header = Header::new(&header_data)?;
+	header.prg_rom_size = prg.len();
+	header.chr_rom_size = chr.len();
+	header.submapper = submapper;
+	header.timing = timing;
+
+	Ok(Rom {
+		header,
+		trainer: None,
+		prg,
+		chr,
+		playchoice_inst_rom: None,
+		playchoice_prom: None,
+		title: None,
+		corrections: Vec::new()
+	})
+}
+
+fn chunk_name(id: &[u8]) -> String {
+	String::from_utf8_lossy(&id[..id.len().min(4)]).into_owned()
+}
+
+// UNIF file made of the given chunks
+#[cfg(test)]
+fn test_file(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+	let mut data = IDENTIFIER.to_vec();
+	data.extend_from_slice(&[7, 0, 0, 0]);
+	data.extend(vec![0; HEADER_SIZE - 8]);
+	for &(id, chunk) in chunks {
+		data.extend_from_slice(id);
+		data.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+		data.extend_from_slice(chunk);
+	}
+	data
+}
+
+#[test]
+fn test_board() {
+	assert_eq!(board("NES-SLROM"), Some((1, 0)));
+	assert_eq!(board("HVC-UNROM"), Some((2, 2)));
+	assert_eq!(board("TLROM"), Some((4, 0)));
+	assert_eq!(board("NES-HKROM"), Some((4, 1)));
+	assert_eq!(board("NES-AOROM"), Some((7, 1)));
+	assert_eq!(board("UNL-SOMETHING"), None);
+}
+
+#[test]
+fn test_parse() {
+	let data = test_file(&[
+		(b"MAPR", b"NES-TLROM\0"),
+		(b"NAME", b"Test\0"),
+		(b"PRG1", &[2; 0x4000]),
+		(b"PRG0", &[1; 0x4000]),
+		(b"CHR0", &[3; 0x2000]),
+		(b"CHRX", &[5; 0x10]),
+		(b"MIRR", &[4]),
+		(b"BATR", &[1]),
+		(b"TVCI", &[1])
+	]);
+	let rom = Rom::parse(&data).unwrap();
+	assert_eq!(rom.header.mapper_number, 4);
+	assert_eq!(rom.header.mirroring(), Mirroring::FourScreen);
+	assert!(rom.header.flags6.battery_backed_prg_ram);
+	assert_eq!(rom.header.timing, Timing::Pal);
+	// PRG chunks go in order of their number rather than their order in the file
+	assert_eq!(rom.prg.len(), 0x8000);
+	assert_eq!((rom.prg[0], rom.prg[0x4000]), (1, 2));
+	assert_eq!(rom.header.prg_rom_size, 0x8000);
+	// CHRX isn't numbered, so it doesn't replace CHR0
+	assert_eq!(rom.chr, vec![3; 0x2000]);
+	assert_eq!(rom.header.chr_ram_size, 0);
+
+	// CHR RAM, and a mapper number above 15
+	let data = test_file(&[(b"MAPR", b"NES-GNROM\0"), (b"PRG0", &[0; 0x8000])]);
+	let rom = Rom::parse(&data).unwrap();
+	assert_eq!(rom.header.mapper_number, 66);
+	assert_eq!(rom.header.mirroring(), Mirroring::Horizontal);
+	assert_eq!(rom.header.chr_ram_size, CHR_ROM_UNIT_SIZE);
+}
+
+#[test]
+fn test_errors() {
+	match Rom::parse(&test_file(&[(b"MAPR", b"UNL-NOTHING\0")])) {
+		Err(EmuError::Rom(RomError::UnsupportedBoard { ref name })) if name == "UNL-NOTHING" => (),
+		other => panic!("Expected an unsupported board, got {:?}", other)
+	}
+	match Rom::parse(&test_file(&[(b"PRG0", &[0; 0x10])])) {
+		Err(EmuError::Rom(RomError::MissingBoard)) => (),
+		other => panic!("Expected a missing board, got {:?}", other)
+	}
+	let mut data = test_file(&[(b"MAPR", b"NES-NROM\0"), (b"PRG0", &[0; 0x10])]);
+	data.truncate(data.len() - 1);
+	match Rom::parse(&data) {
+		Err(EmuError::Rom(RomError::TruncatedChunk { ref id })) if id == "PRG0" => (),
+		other => panic!("Expected a truncated chunk, got {:?}", other)
+	}
+}
@@ -0,0 +1,39 @@
+use std::io::{ self, Write };
+
+/// Write samples from -1.0 to 1.0 as a mono, 16 bit PCM WAV file
+///     http://soundfile.sapp.org/doc/WaveFormat/
+pub fn write<W: Write>(output: &mut W, sample_rate: u32, samples: &[f32]) -> io::Result<()> {
+	let data_size = samples.len() as u32 * 2;
+	output.write_all(b"RIFF")?;
+	output.write_all(&(36 + data_size).to_le_bytes())?;
+	output.write_all(b"WAVE")?;
+
+	output.write_all(b"fmt ")?;
+	output.write_all(&16u32.to_le_bytes())?;
+	output.write_all(&1u16.to_le_bytes())?;            // PCM
+	output.write_all(&1u16.to_le_bytes())?;            // Channels
+	output.write_all(&sample_rate.to_le_bytes())?;
+	output.write_all(&(sample_rate * 2).to_le_bytes())?; // Bytes per second
+	output.write_all(&2u16.to_le_bytes())?;            // Bytes per sample
+	output.write_all(&16u16.to_le_bytes())?;           // Bits per sample
+
+	output.write_all(b"data")?;
+	output.write_all(&data_size.to_le_bytes())?;
+	for &sample in samples {
+		let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+		output.write_all(&value.to_le_bytes())?;
+	}
+	Ok(())
+}
+
+#[test]
+fn test_write() {
+	let mut data = Vec::new();
+	write(&mut data, 44100, &[0.0, 1.0, -1.0, 2.0]).unwrap();
+	assert_eq!(data.len(), 44 + 8);
+	assert_eq!(&data[0..4], b"RIFF");
+	assert_eq!(&data[4..8], &(36u32 + 8).to_le_bytes());
+	assert_eq!(&data[24..28], &44100u32.to_le_bytes());
+	assert_eq!(&data[40..44], &8u32.to_le_bytes());
+	assert_eq!(&data[44..], &[0x00, 0x00, 0xFF, 0x7F, 0x01, 0x80, 0xFF, 0x7F]);
+}